@@ -0,0 +1,81 @@
+//! Measures parser throughput on streams that mostly use running status
+//!
+//! Run with `cargo bench --bench running_status`
+
+// Benchmarks are not built with the minimum supported rust version
+#![allow(clippy::incompatible_msrv)]
+
+use embedded_midi::MidiParser;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const ROUNDS: u32 = 200;
+
+/// A mod wheel sweep, control changes on a single running status byte
+fn control_sweep() -> Vec<u8> {
+    let mut stream = vec![0xB0];
+    for round in 0..64 {
+        for value in 0..128u8 {
+            stream.extend_from_slice(&[0x01, value ^ (round & 0x7F)]);
+        }
+    }
+    stream
+}
+
+/// A pitch bend sweep with timing clock bytes interleaved
+fn pitch_bend_sweep() -> Vec<u8> {
+    let mut stream = vec![0xE0];
+    for value in 0..16384u16 {
+        stream.extend_from_slice(&[(value & 0x7F) as u8, (value >> 7) as u8]);
+        if value % 64 == 0 {
+            stream.push(0xF8);
+        }
+    }
+    stream
+}
+
+/// Note messages on different channels, every message has its own status byte
+fn notes() -> Vec<u8> {
+    let mut stream = Vec::new();
+    for note in 0..8192u16 {
+        let channel = (note % 16) as u8;
+        stream.extend_from_slice(&[0x90 | channel, (note & 0x7F) as u8, 0x40]);
+    }
+    stream
+}
+
+fn measure(stream: &[u8]) -> (Duration, usize) {
+    let mut messages = 0;
+    let start = Instant::now();
+
+    for _ in 0..ROUNDS {
+        let mut parser = MidiParser::new();
+        for byte in black_box(stream) {
+            if black_box(parser.parse_byte(*byte)).is_some() {
+                messages += 1;
+            }
+        }
+    }
+
+    (start.elapsed(), messages)
+}
+
+fn main() {
+    let streams = [
+        ("control change sweep", control_sweep()),
+        ("pitch bend sweep", pitch_bend_sweep()),
+        ("notes without running status", notes()),
+    ];
+
+    for (name, stream) in streams.iter() {
+        let (elapsed, messages) = measure(stream);
+        let bytes = stream.len() as f64 * ROUNDS as f64;
+
+        println!(
+            "{:<30} {:>8.2} ns/byte {:>10} messages",
+            name,
+            elapsed.as_nanos() as f64 / bytes,
+            messages
+        );
+    }
+}
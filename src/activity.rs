@@ -0,0 +1,135 @@
+//! Activity indication for front panel leds
+//!
+//! Ports record the classes of the messages that pass them in a byte of flags. The user interface
+//! takes the flags when it updates the leds, which clears them, so a led blinks for every period
+//! with activity without the application looking at the messages.
+
+use crate::io::{MidiSink, MidiSource};
+use crate::midi_types::MidiMessage;
+use crate::router::MessageClass;
+
+/// The classes of the messages that passed a port since the flags were last taken
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Activity(u8);
+
+impl Activity {
+    pub const fn new() -> Self {
+        Activity(0)
+    }
+
+    pub fn record(&mut self, message: &MidiMessage) {
+        self.0 |= MessageClass::of(message).bit();
+    }
+
+    /// True when any message passed
+    pub fn is_active(&self) -> bool {
+        self.0 != 0
+    }
+
+    /// True when a message of the class passed
+    pub fn has(&self, class: MessageClass) -> bool {
+        self.0 & class.bit() != 0
+    }
+
+    /// True when a message other than clock or active sensing passed, leds usually ignore
+    /// those because they are sent all the time
+    pub fn has_messages(&self) -> bool {
+        self.0 & !(MessageClass::Clock.bit() | MessageClass::SystemRealTime.bit()) != 0
+    }
+
+    /// Return the flags and clear them
+    pub fn take(&mut self) -> Activity {
+        core::mem::take(self)
+    }
+}
+
+/// Records the activity of a source or sink
+#[derive(Debug)]
+pub struct ActivityMonitor<T> {
+    inner: T,
+    activity: Activity,
+}
+
+impl<T> ActivityMonitor<T> {
+    pub fn new(inner: T) -> Self {
+        ActivityMonitor {
+            inner,
+            activity: Activity::new(),
+        }
+    }
+
+    pub fn activity(&self) -> Activity {
+        self.activity
+    }
+
+    /// Return the activity since it was last taken and clear it
+    pub fn take_activity(&mut self) -> Activity {
+        self.activity.take()
+    }
+
+    pub fn release(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: MidiSource> MidiSource for ActivityMonitor<T> {
+    type Error = T::Error;
+
+    fn poll(&mut self) -> nb::Result<MidiMessage, Self::Error> {
+        let message = self.inner.poll()?;
+        self.activity.record(&message);
+        Ok(message)
+    }
+}
+
+impl<T: MidiSink> MidiSink for ActivityMonitor<T> {
+    type Error = T::Error;
+
+    fn send(&mut self, message: &MidiMessage) -> Result<(), Self::Error> {
+        self.inner.send(message)?;
+        self.activity.record(message);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::midi_types::{Channel, Note, Value7};
+
+    #[test]
+    fn should_record_and_take_activity() {
+        let mut activity = Activity::new();
+        activity.record(&MidiMessage::TimingClock);
+        assert!(activity.is_active());
+        assert!(!activity.has_messages());
+
+        activity.record(&MidiMessage::NoteOn(
+            Channel::C1,
+            Note::C3,
+            Value7::new(100),
+        ));
+        let taken = activity.take();
+        assert!(taken.has(MessageClass::Notes));
+        assert!(taken.has_messages());
+        assert!(!activity.is_active());
+    }
+
+    #[test]
+    fn should_monitor_sinks() {
+        struct Discard;
+
+        impl MidiSink for Discard {
+            type Error = ();
+
+            fn send(&mut self, _: &MidiMessage) -> Result<(), Self::Error> {
+                Ok(())
+            }
+        }
+
+        let mut sink = ActivityMonitor::new(Discard);
+        sink.send(&MidiMessage::Start).unwrap();
+        assert!(sink.take_activity().has(MessageClass::Clock));
+        assert!(!sink.activity().is_active());
+    }
+}
@@ -0,0 +1,152 @@
+//! Converting analog readings from pots and faders into control changes
+
+use crate::hires::HighResControl;
+use crate::midi_types::{Channel, Control, MidiMessage};
+
+/// Sends control changes for raw analog readings
+///
+/// Readings only count when they move more than the hysteresis away from the last reading that
+/// counted, so a noisy reading on the edge between two values does not flood the output. Readings
+/// between `min` and `max` are scaled to the full control range.
+#[derive(Debug, Clone)]
+pub struct AdcControl {
+    channel: Channel,
+    control: Control,
+    min: u16,
+    max: u16,
+    hysteresis: u16,
+    high_resolution: Option<HighResControl>,
+    reading: Option<u16>,
+    sent: Option<u8>,
+}
+
+impl AdcControl {
+    /// Create a converter for readings with a resolution of `bits`, up to 16 bits
+    pub fn new(channel: Channel, control: Control, bits: u8) -> Self {
+        let max = match bits {
+            0 => 0,
+            bits => u16::MAX >> 16u8.saturating_sub(bits),
+        };
+        AdcControl {
+            channel,
+            control,
+            min: 0,
+            max,
+            hysteresis: 0,
+            high_resolution: None,
+            reading: None,
+            sent: None,
+        }
+    }
+
+    /// Calibrate the readings at both ends of the control
+    pub fn set_range(&mut self, min: u16, max: u16) {
+        self.min = min;
+        self.max = max;
+    }
+
+    /// Ignore readings that are within `hysteresis` of the last reading that counted
+    pub fn set_hysteresis(&mut self, hysteresis: u16) {
+        self.hysteresis = hysteresis;
+    }
+
+    /// Send 14 bit values as a pair of control changes, returns false when the controller has no
+    /// matching least significant controller
+    pub fn set_high_resolution(&mut self, high_resolution: bool) -> bool {
+        self.high_resolution = if high_resolution {
+            match HighResControl::new(self.channel, self.control) {
+                Some(control) => Some(control),
+                None => return false,
+            }
+        } else {
+            None
+        };
+        self.sent = None;
+        true
+    }
+
+    /// Handle a new reading, sends a control change when the value changed
+    pub fn update(&mut self, reading: u16, mut emit: impl FnMut(MidiMessage)) {
+        if let Some(last) = self.reading {
+            if reading.abs_diff(last) <= self.hysteresis {
+                return;
+            }
+        }
+        self.reading = Some(reading);
+
+        let value = self.scale(reading);
+        match &mut self.high_resolution {
+            Some(control) => control.send(value, emit),
+            None => {
+                let value = (value >> 7) as u8;
+                if self.sent != Some(value) {
+                    self.sent = Some(value);
+                    emit(MidiMessage::ControlChange(
+                        self.channel,
+                        self.control,
+                        value.into(),
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Scale a reading to a 14 bit value
+    fn scale(&self, reading: u16) -> u16 {
+        let (low, high) = (self.min.min(self.max), self.min.max(self.max));
+        let span = (high - low) as u32;
+        if span == 0 {
+            return 0;
+        }
+
+        let value = (reading.clamp(low, high) - low) as u32 * 0x3fff / span;
+        if self.min > self.max {
+            0x3fff - value as u16
+        } else {
+            value as u16
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::vec::Vec;
+
+    fn update(control: &mut AdcControl, readings: &[u16]) -> Vec<(u8, u8)> {
+        let mut sent = Vec::new();
+        for reading in readings {
+            control.update(*reading, |message| {
+                if let MidiMessage::ControlChange(_, control, value) = message {
+                    sent.push((control.into(), value.into()))
+                }
+            });
+        }
+        sent
+    }
+
+    #[test]
+    fn should_scale_and_filter_noise() {
+        let mut control = AdcControl::new(Channel::C1, Control::new(7), 12);
+        control.set_hysteresis(4);
+
+        assert_eq!(update(&mut control, &[0, 3, 1, 4095]), [(7, 0), (7, 127)]);
+        // Moving less than one control change step sends nothing
+        assert_eq!(update(&mut control, &[4080]), []);
+        assert_eq!(update(&mut control, &[2048]), [(7, 64)]);
+    }
+
+    #[test]
+    fn should_send_high_resolution_values() {
+        let mut control = AdcControl::new(Channel::C1, Control::new(7), 12);
+        control.set_range(4095, 0);
+        assert!(control.set_high_resolution(true));
+
+        assert_eq!(update(&mut control, &[0]), [(7, 0x7f), (39, 0x7f)]);
+        assert_eq!(update(&mut control, &[1]), [(39, 0x7b)]);
+
+        let mut control = AdcControl::new(Channel::C1, Control::new(70), 12);
+        assert!(!control.set_high_resolution(true));
+    }
+}
@@ -0,0 +1,352 @@
+//! Converting between polyphonic key pressure and channel pressure
+//!
+//! Controllers with polyphonic aftertouch can play synths that only respond to channel pressure
+//! by folding the pressure of all keys into one channel pressure. The other way around, channel
+//! pressure is spread over the held notes for synths that only respond to key pressure.
+//!
+//! Many synths only respond to control changes like modulation or filter cutoff, pressure is
+//! converted to a control change for those.
+
+use crate::mapping::Curve;
+use crate::midi_types::{Channel, Control, MidiMessage, Note, Value7};
+use crate::process::Processor;
+use crate::value::U7;
+
+/// How the pressure of several keys is folded into channel pressure
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PressurePolicy {
+    /// The highest pressure of all keys on the channel
+    #[default]
+    Max,
+
+    /// The pressure of the key that changed last
+    MostRecent,
+}
+
+/// Folds key pressure into channel pressure, keeping track of the pressure of up to `N` keys
+///
+/// Key pressure messages are replaced by channel pressure, channel pressure is only sent when it
+/// changes. Releasing the last pressed key of a channel sends a channel pressure of 0.
+#[derive(Debug, Clone)]
+pub struct PolyToChannel<const N: usize> {
+    policy: PressurePolicy,
+    keys: [Option<(Channel, Note, u8)>; N],
+
+    /// The last channel pressure sent on every channel
+    sent: [u8; 16],
+}
+
+impl<const N: usize> Default for PolyToChannel<N> {
+    fn default() -> Self {
+        Self::new(PressurePolicy::default())
+    }
+}
+
+impl<const N: usize> PolyToChannel<N> {
+    pub fn new(policy: PressurePolicy) -> Self {
+        PolyToChannel {
+            policy,
+            keys: [None; N],
+            sent: [0; 16],
+        }
+    }
+
+    pub fn policy(&self) -> PressurePolicy {
+        self.policy
+    }
+
+    pub fn set_policy(&mut self, policy: PressurePolicy) {
+        self.policy = policy;
+    }
+
+    fn press(&mut self, channel: Channel, note: Note, pressure: u8) {
+        let index = self
+            .keys
+            .iter()
+            .position(|key| matches!(key, Some((c, n, _)) if *c == channel && *n == note))
+            .or_else(|| self.keys.iter().position(Option::is_none));
+        if let Some(slot) = index.and_then(|index| self.keys.get_mut(index)) {
+            *slot = Some((channel, note, pressure));
+        }
+    }
+
+    /// Forget a key, returns true when it was pressed
+    fn release(&mut self, channel: Channel, note: Note) -> bool {
+        match self
+            .keys
+            .iter_mut()
+            .find(|key| matches!(key, Some((c, n, _)) if *c == channel && *n == note))
+        {
+            Some(slot) => {
+                *slot = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Send the channel pressure when it changed, `recent` is the pressure of the key that
+    /// changed last if it is still pressed
+    fn update(&mut self, channel: Channel, recent: Option<u8>, emit: &mut impl FnMut(MidiMessage)) {
+        let mut pressures = self
+            .keys
+            .iter()
+            .flatten()
+            .filter(|(c, ..)| *c == channel)
+            .map(|(.., pressure)| *pressure);
+        let sent = match self.sent.get_mut(u8::from(channel) as usize) {
+            Some(sent) => sent,
+            None => return,
+        };
+
+        let pressure = match self.policy {
+            PressurePolicy::Max => pressures.max().unwrap_or(0),
+            PressurePolicy::MostRecent => match pressures.next() {
+                Some(_) => recent.unwrap_or(*sent),
+                None => 0,
+            },
+        };
+
+        if pressure != *sent {
+            *sent = pressure;
+            emit(MidiMessage::ChannelPressure(channel, Value7::new(pressure)));
+        }
+    }
+}
+
+impl<const N: usize> Processor for PolyToChannel<N> {
+    fn process(&mut self, message: MidiMessage, emit: &mut impl FnMut(MidiMessage)) {
+        match message {
+            MidiMessage::KeyPressure(channel, note, pressure) => {
+                self.press(channel, note, pressure.into());
+                self.update(channel, Some(pressure.into()), emit);
+            }
+            MidiMessage::NoteOn(channel, note, velocity) if u8::from(velocity) == 0 => {
+                emit(message);
+                if self.release(channel, note) {
+                    self.update(channel, None, emit);
+                }
+            }
+            MidiMessage::NoteOff(channel, note, _) => {
+                emit(message);
+                if self.release(channel, note) {
+                    self.update(channel, None, emit);
+                }
+            }
+            message => emit(message),
+        }
+    }
+}
+
+/// Spreads channel pressure over the held notes as key pressure, keeping track of up to `N`
+/// held notes
+///
+/// Channel pressure messages are replaced by key pressure for every held note on the channel.
+#[derive(Debug, Clone)]
+pub struct ChannelToPoly<const N: usize> {
+    held: [Option<(Channel, Note)>; N],
+}
+
+impl<const N: usize> Default for ChannelToPoly<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> ChannelToPoly<N> {
+    pub fn new() -> Self {
+        ChannelToPoly { held: [None; N] }
+    }
+}
+
+impl<const N: usize> Processor for ChannelToPoly<N> {
+    fn process(&mut self, message: MidiMessage, emit: &mut impl FnMut(MidiMessage)) {
+        match message {
+            MidiMessage::ChannelPressure(channel, pressure) => {
+                for (_, note) in self.held.iter().flatten().filter(|(c, _)| *c == channel) {
+                    emit(MidiMessage::KeyPressure(channel, *note, pressure));
+                }
+            }
+            MidiMessage::NoteOn(channel, note, velocity) if u8::from(velocity) > 0 => {
+                if !self.held.contains(&Some((channel, note))) {
+                    if let Some(slot) = self.held.iter_mut().find(|slot| slot.is_none()) {
+                        *slot = Some((channel, note));
+                    }
+                }
+                emit(message);
+            }
+            MidiMessage::NoteOn(channel, note, _) | MidiMessage::NoteOff(channel, note, _) => {
+                if let Some(slot) = self
+                    .held
+                    .iter_mut()
+                    .find(|slot| **slot == Some((channel, note)))
+                {
+                    *slot = None;
+                }
+                emit(message);
+            }
+            message => emit(message),
+        }
+    }
+}
+
+/// Converts pressure to a control change, scaling the pressure along a curve
+///
+/// Channel pressure is always converted, key pressure only when enabled with
+/// `with_key_pressure`. Other messages are passed unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PressureToControl {
+    control: Control,
+    curve: Curve,
+    min: U7,
+    max: U7,
+    key_pressure: bool,
+}
+
+impl PressureToControl {
+    /// Convert pressure to the full range of a controller, for instance modulation `Control::new(1)`
+    pub const fn new(control: Control) -> Self {
+        PressureToControl {
+            control,
+            curve: Curve::Linear,
+            min: U7::MIN,
+            max: U7::MAX,
+            key_pressure: false,
+        }
+    }
+
+    pub const fn with_curve(mut self, curve: Curve) -> Self {
+        self.curve = curve;
+        self
+    }
+
+    /// Scale pressure to the control values from `min` to `max`, `min` may be larger
+    /// than `max` to invert the pressure
+    pub const fn with_range(mut self, min: U7, max: U7) -> Self {
+        self.min = min;
+        self.max = max;
+        self
+    }
+
+    /// Also convert key pressure, the pressure of every key sets the same controller
+    pub const fn with_key_pressure(mut self) -> Self {
+        self.key_pressure = true;
+        self
+    }
+
+    pub fn control(&self) -> Control {
+        self.control
+    }
+
+    /// The control value for a pressure
+    pub fn map(&self, pressure: Value7) -> Value7 {
+        let value = self.curve.map(
+            pressure.into(),
+            self.min.get() as i64,
+            self.max.get() as i64,
+        );
+        Value7::new(value.clamp(0, 127) as u8)
+    }
+}
+
+impl Processor for PressureToControl {
+    fn process(&mut self, message: MidiMessage, emit: &mut impl FnMut(MidiMessage)) {
+        match message {
+            MidiMessage::ChannelPressure(channel, pressure) => emit(MidiMessage::ControlChange(
+                channel,
+                self.control,
+                self.map(pressure),
+            )),
+            MidiMessage::KeyPressure(channel, _, pressure) if self.key_pressure => emit(
+                MidiMessage::ControlChange(channel, self.control, self.map(pressure)),
+            ),
+            message => emit(message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{off, on, process};
+
+    fn key(note: u8, pressure: u8) -> MidiMessage {
+        MidiMessage::KeyPressure(Channel::C1, note.into(), pressure.into())
+    }
+
+    fn channel(pressure: u8) -> MidiMessage {
+        MidiMessage::ChannelPressure(Channel::C1, pressure.into())
+    }
+
+    #[test]
+    fn should_fold_key_pressure_into_channel_pressure() {
+        let messages = [key(60, 40), key(64, 80), key(64, 30), off(60), off(64)];
+
+        let mut max = PolyToChannel::<4>::new(PressurePolicy::Max);
+        assert_eq!(
+            process(&mut max, &messages),
+            [
+                channel(40),
+                channel(80),
+                channel(40),
+                off(60),
+                channel(30),
+                off(64),
+                channel(0)
+            ]
+        );
+
+        let mut recent = PolyToChannel::<4>::new(PressurePolicy::MostRecent);
+        assert_eq!(
+            process(&mut recent, &messages),
+            [
+                channel(40),
+                channel(80),
+                channel(30),
+                off(60),
+                off(64),
+                channel(0)
+            ]
+        );
+    }
+
+    #[test]
+    fn should_convert_pressure_to_control_changes() {
+        let cc =
+            |value: u8| MidiMessage::ControlChange(Channel::C1, Control::new(74), value.into());
+        let mut linear = PressureToControl::new(Control::new(74));
+        assert_eq!(
+            process(&mut linear, &[channel(0), channel(64), key(60, 127)]),
+            [cc(0), cc(64), key(60, 127)]
+        );
+
+        let mut curved = PressureToControl::new(Control::new(74))
+            .with_curve(Curve::Exponential)
+            .with_range(U7::new(20), U7::new(120))
+            .with_key_pressure();
+        assert_eq!(
+            process(&mut curved, &[channel(0), channel(64), key(60, 127)]),
+            [cc(20), cc(45), cc(120)]
+        );
+    }
+
+    #[test]
+    fn should_spread_channel_pressure_over_held_notes() {
+        let mut poly = ChannelToPoly::<4>::new();
+
+        assert_eq!(
+            process(
+                &mut poly,
+                &[on(60), on(64), channel(50), off(60), channel(20)]
+            ),
+            [
+                on(60),
+                on(64),
+                key(60, 50),
+                key(64, 50),
+                off(60),
+                key(64, 20)
+            ]
+        );
+    }
+}
@@ -0,0 +1,350 @@
+//! Arpeggiator driven by midi clock ticks
+
+use crate::midi_types::{Channel, MidiMessage, Note, Value7};
+use crate::notes::HeldNotes;
+use crate::random::Random;
+
+/// The order the arpeggiator plays the held notes in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArpPattern {
+    /// From the lowest to the highest note
+    Up,
+
+    /// From the highest to the lowest note
+    Down,
+
+    /// Up and back down, without repeating the highest and lowest notes
+    UpDown,
+
+    /// A random note for every step
+    Random,
+
+    /// In the order the notes were pressed
+    Played,
+}
+
+/// Plays held notes one after the other, a step every `division` clock ticks. Keeps up to `N`
+/// notes.
+#[derive(Debug, Clone)]
+pub struct Arpeggiator<const N: usize> {
+    channel: Channel,
+    pattern: ArpPattern,
+    octaves: u8,
+    division: u8,
+    gate: u8,
+    latch: bool,
+
+    /// Keys that are physically held
+    held: HeldNotes<N>,
+
+    /// Notes being arpeggiated, these stay when keys are released in latch mode
+    notes: HeldNotes<N>,
+
+    position: usize,
+    ticks: u8,
+    random: Random,
+    playing: Option<Note>,
+}
+
+impl<const N: usize> Arpeggiator<N> {
+    /// Create an arpeggiator playing on a channel, by default it plays sixteenth notes going up
+    /// over one octave with a gate of half a step
+    pub fn new(channel: Channel) -> Self {
+        Arpeggiator {
+            channel,
+            pattern: ArpPattern::Up,
+            octaves: 1,
+            division: 6,
+            gate: 3,
+            latch: false,
+            held: HeldNotes::new(),
+            notes: HeldNotes::new(),
+            position: 0,
+            ticks: 0,
+            random: Random::new(),
+            playing: None,
+        }
+    }
+
+    pub fn pattern(&self) -> ArpPattern {
+        self.pattern
+    }
+
+    pub fn set_pattern(&mut self, pattern: ArpPattern) {
+        self.pattern = pattern;
+    }
+
+    pub fn octaves(&self) -> u8 {
+        self.octaves
+    }
+
+    /// Set the number of octaves the notes are repeated over, at least one
+    pub fn set_octaves(&mut self, octaves: u8) {
+        self.octaves = octaves.max(1);
+    }
+
+    pub fn division(&self) -> u8 {
+        self.division
+    }
+
+    /// Set the number of clock ticks per step, at 24 ticks per quarter note 6 plays sixteenth
+    /// notes
+    pub fn set_division(&mut self, division: u8) {
+        self.division = division.max(1);
+    }
+
+    pub fn gate(&self) -> u8 {
+        self.gate
+    }
+
+    /// Set the number of clock ticks a note sounds, notes with a gate of at least the division
+    /// sound until the next step
+    pub fn set_gate(&mut self, gate: u8) {
+        self.gate = gate.max(1);
+    }
+
+    pub fn latch(&self) -> bool {
+        self.latch
+    }
+
+    /// Keep playing notes after their keys are released, until new keys are pressed. Turning
+    /// latch off drops the notes that are not held.
+    pub fn set_latch(&mut self, latch: bool) {
+        self.latch = latch;
+        if !latch {
+            self.notes = self.held.clone();
+        }
+    }
+
+    /// Seed the generator used by the random pattern
+    pub fn set_seed(&mut self, seed: u32) {
+        self.random.seed(seed);
+    }
+
+    /// The notes being arpeggiated
+    pub fn notes(&self) -> &HeldNotes<N> {
+        &self.notes
+    }
+
+    /// The note that is currently sounding
+    pub fn playing(&self) -> Option<Note> {
+        self.playing
+    }
+
+    pub fn note_on(&mut self, note: Note, velocity: Value7) {
+        if self.latch && self.held.is_empty() {
+            // New keys replace the latched notes
+            self.notes.clear();
+        }
+
+        self.held.press(note, velocity);
+        self.notes.press(note, velocity);
+    }
+
+    pub fn note_off(&mut self, note: Note) {
+        self.held.release(note);
+        if !self.latch {
+            self.notes.release(note);
+        }
+    }
+
+    /// Advance the arpeggiator by one clock tick, emits the note messages due on this tick
+    pub fn tick(&mut self, mut emit: impl FnMut(MidiMessage)) {
+        if self.ticks >= self.gate {
+            self.stop_note(&mut emit);
+        }
+
+        if self.ticks == 0 {
+            self.stop_note(&mut emit);
+            self.step(&mut emit);
+        }
+
+        self.ticks = (self.ticks + 1) % self.division;
+    }
+
+    /// Start the pattern from the beginning on the next tick
+    pub fn restart(&mut self) {
+        self.position = 0;
+        self.ticks = 0;
+    }
+
+    /// Stop the sounding note and start the pattern from the beginning
+    pub fn stop(&mut self, mut emit: impl FnMut(MidiMessage)) {
+        self.stop_note(&mut emit);
+        self.restart();
+    }
+
+    /// Handle a midi message. Note messages change the held notes, timing clock messages advance
+    /// the arpeggiator, start and stop messages restart it. Other messages are ignored.
+    pub fn handle(&mut self, message: &MidiMessage, mut emit: impl FnMut(MidiMessage)) {
+        match *message {
+            MidiMessage::NoteOn(_, note, velocity) if u8::from(velocity) > 0 => {
+                self.note_on(note, velocity)
+            }
+            MidiMessage::NoteOn(_, note, _) | MidiMessage::NoteOff(_, note, _) => {
+                self.note_off(note)
+            }
+            MidiMessage::TimingClock => self.tick(emit),
+            MidiMessage::Start => self.stop(&mut emit),
+            MidiMessage::Stop => self.stop(&mut emit),
+            _ => {}
+        }
+    }
+
+    fn stop_note(&mut self, emit: &mut impl FnMut(MidiMessage)) {
+        if let Some(note) = self.playing.take() {
+            emit(MidiMessage::NoteOff(self.channel, note, Value7::new(0)));
+        }
+    }
+
+    fn step(&mut self, emit: &mut impl FnMut(MidiMessage)) {
+        let len = self.notes.len();
+        if len == 0 {
+            return;
+        }
+
+        // Sort a copy of the notes so patterns see them from low to high
+        let mut sorted = [(Note::MIN, Value7::new(0)); N];
+        let sorted = match sorted.get_mut(..len) {
+            Some(sorted) => sorted,
+            None => return,
+        };
+        sorted.copy_from_slice(self.notes.as_slice());
+        if self.pattern != ArpPattern::Played {
+            sorted.sort_unstable_by_key(|(note, _)| u8::from(*note));
+        }
+
+        let total = len * self.octaves as usize;
+        let index = match self.pattern {
+            ArpPattern::Up | ArpPattern::Played => self.position % total,
+            ArpPattern::Down => total - 1 - self.position % total,
+            ArpPattern::UpDown if total > 1 => {
+                let position = self.position % (2 * total - 2);
+                if position < total {
+                    position
+                } else {
+                    2 * total - 2 - position
+                }
+            }
+            ArpPattern::UpDown => 0,
+            ArpPattern::Random => self.random.below(total as u32) as usize,
+        };
+        self.position = self.position.wrapping_add(1);
+
+        if let Some((note, velocity)) = sorted.get(index % len) {
+            let octave = (index / len) as u8;
+            let transposed = u8::from(*note).saturating_add(octave.saturating_mul(12));
+            // Notes transposed out of range play untransposed
+            let note = if transposed < 0x80 {
+                transposed.into()
+            } else {
+                *note
+            };
+
+            self.playing = Some(note);
+            emit(MidiMessage::NoteOn(self.channel, note, *velocity));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::vec::Vec;
+
+    fn notes_played<const N: usize>(arp: &mut Arpeggiator<N>, ticks: usize) -> Vec<u8> {
+        let mut played = Vec::new();
+        for _ in 0..ticks {
+            arp.tick(|message| {
+                if let MidiMessage::NoteOn(_, note, _) = message {
+                    played.push(note.into())
+                }
+            });
+        }
+        played
+    }
+
+    fn chord(arp: &mut Arpeggiator<8>) {
+        arp.note_on(64.into(), 100.into());
+        arp.note_on(60.into(), 100.into());
+        arp.note_on(67.into(), 100.into());
+    }
+
+    #[test]
+    fn should_play_patterns() {
+        let mut arp = Arpeggiator::<8>::new(Channel::C1);
+        chord(&mut arp);
+        assert_eq!(notes_played(&mut arp, 6 * 4), [60, 64, 67, 60]);
+
+        arp.restart();
+        arp.set_pattern(ArpPattern::Down);
+        assert_eq!(notes_played(&mut arp, 6 * 4), [67, 64, 60, 67]);
+
+        arp.restart();
+        arp.set_pattern(ArpPattern::UpDown);
+        assert_eq!(notes_played(&mut arp, 6 * 5), [60, 64, 67, 64, 60]);
+
+        arp.restart();
+        arp.set_pattern(ArpPattern::Played);
+        assert_eq!(notes_played(&mut arp, 6 * 3), [64, 60, 67]);
+
+        arp.set_pattern(ArpPattern::Random);
+        assert!(notes_played(&mut arp, 6 * 8)
+            .iter()
+            .all(|note| [60, 64, 67].contains(note)));
+    }
+
+    #[test]
+    fn should_repeat_over_octaves() {
+        let mut arp = Arpeggiator::<8>::new(Channel::C1);
+        arp.set_octaves(2);
+        arp.note_on(60.into(), 100.into());
+        arp.note_on(64.into(), 100.into());
+
+        assert_eq!(notes_played(&mut arp, 6 * 5), [60, 64, 72, 76, 60]);
+    }
+
+    #[test]
+    fn should_end_notes_after_gate() {
+        let mut arp = Arpeggiator::<8>::new(Channel::C2);
+        arp.set_division(4);
+        arp.set_gate(2);
+        arp.note_on(60.into(), 100.into());
+
+        let mut messages = Vec::new();
+        for _ in 0..4 {
+            arp.tick(|message| messages.push(message));
+            messages.push(MidiMessage::TimingClock);
+        }
+
+        assert_eq!(
+            messages,
+            [
+                MidiMessage::NoteOn(Channel::C2, 60.into(), 100.into()),
+                MidiMessage::TimingClock,
+                MidiMessage::TimingClock,
+                MidiMessage::NoteOff(Channel::C2, 60.into(), 0.into()),
+                MidiMessage::TimingClock,
+                MidiMessage::TimingClock,
+            ]
+        );
+    }
+
+    #[test]
+    fn should_keep_latched_notes_until_new_keys_are_pressed() {
+        let mut arp = Arpeggiator::<8>::new(Channel::C1);
+        arp.set_latch(true);
+        chord(&mut arp);
+        arp.note_off(60.into());
+        arp.note_off(64.into());
+        arp.note_off(67.into());
+        assert_eq!(arp.notes().len(), 3);
+
+        arp.note_on(72.into(), 100.into());
+        assert_eq!(notes_played(&mut arp, 6 * 2), [72, 72]);
+
+        arp.set_latch(false);
+        assert!(arp.notes().is_held(72.into()));
+    }
+}
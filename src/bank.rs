@@ -0,0 +1,252 @@
+//! Banks of mappings for physical controls
+
+use crate::midi_types::{Channel, Control, MidiMessage, Value7};
+use crate::takeover::SoftTakeover;
+
+/// Maps `C` physical controls to control changes through one of `B` banks
+///
+/// Every bank remembers the values of its parameters. After switching banks the controls use soft
+/// takeover, so a control only changes a parameter after it crossed the value the parameter had.
+/// Switching banks sends the stored values of the new bank, so the receiver matches the bank.
+#[derive(Debug, Clone)]
+pub struct ControlBanks<const C: usize, const B: usize> {
+    targets: [[(Channel, Control); C]; B],
+    values: [[u8; C]; B],
+    bank: usize,
+    takeover: SoftTakeover<C>,
+    program_channel: Option<Channel>,
+}
+
+impl<const C: usize, const B: usize> ControlBanks<C, B> {
+    /// Create banks sending to the channel and controller in `targets`, all values start at 0 and
+    /// the first bank is selected
+    pub fn new(targets: [[(Channel, Control); C]; B]) -> Self {
+        ControlBanks {
+            targets,
+            values: [[0; C]; B],
+            bank: 0,
+            takeover: SoftTakeover::new(),
+            program_channel: None,
+        }
+    }
+
+    pub fn bank(&self) -> usize {
+        self.bank
+    }
+
+    /// Select a bank and send the values of its parameters, banks that do not exist are ignored
+    pub fn set_bank(&mut self, bank: usize, mut emit: impl FnMut(MidiMessage)) {
+        let targets = match self.targets.get(bank) {
+            Some(targets) => targets,
+            None => return,
+        };
+        let values = self.values.get(bank).copied().unwrap_or([0; C]);
+
+        self.bank = bank;
+        self.takeover.clear();
+        for ((channel, control), value) in targets.iter().zip(values.iter()) {
+            self.takeover
+                .set_value(*channel, *control, Value7::new(*value));
+            emit(MidiMessage::ControlChange(
+                *channel,
+                *control,
+                Value7::new(*value),
+            ));
+        }
+    }
+
+    pub fn next_bank(&mut self, emit: impl FnMut(MidiMessage)) {
+        self.set_bank((self.bank + 1) % B.max(1), emit);
+    }
+
+    pub fn previous_bank(&mut self, emit: impl FnMut(MidiMessage)) {
+        self.set_bank(
+            self.bank.checked_sub(1).unwrap_or(B.saturating_sub(1)),
+            emit,
+        );
+    }
+
+    /// Switch banks on program changes received on `channel`, program `n` selects bank `n`
+    pub fn set_program_channel(&mut self, channel: Option<Channel>) {
+        self.program_channel = channel;
+    }
+
+    /// The value a parameter has in a bank
+    pub fn value(&self, bank: usize, index: usize) -> Option<Value7> {
+        let value = self.values.get(bank)?.get(index)?;
+        Some(Value7::new(*value))
+    }
+
+    /// Set the value a parameter has in a bank, the control has to pick it up again when the bank
+    /// is selected
+    pub fn set_value(&mut self, bank: usize, index: usize, value: Value7) {
+        if let Some(slot) = self
+            .values
+            .get_mut(bank)
+            .and_then(|bank| bank.get_mut(index))
+        {
+            *slot = value.into();
+        }
+
+        if bank == self.bank {
+            if let Some((channel, control)) = self.target(index) {
+                self.takeover.set_value(channel, control, value);
+            }
+        }
+    }
+
+    /// Handle a physical control moving, sends a control change for the selected bank once the
+    /// control picked up the parameter
+    pub fn control(&mut self, index: usize, value: Value7, mut emit: impl FnMut(MidiMessage)) {
+        let (channel, control) = match self.target(index) {
+            Some(target) => target,
+            None => return,
+        };
+
+        if let Some(value) = self.takeover.control(channel, control, value) {
+            if let Some(slot) = self
+                .values
+                .get_mut(self.bank)
+                .and_then(|bank| bank.get_mut(index))
+            {
+                *slot = value.into();
+            }
+            emit(MidiMessage::ControlChange(channel, control, value));
+        }
+    }
+
+    /// Handle a received message, program changes on the program channel select a bank and
+    /// control changes for parameters in the selected bank update their value
+    pub fn handle(&mut self, message: &MidiMessage, emit: impl FnMut(MidiMessage)) {
+        match *message {
+            MidiMessage::ProgramChange(channel, program)
+                if Some(channel) == self.program_channel =>
+            {
+                self.set_bank(u8::from(program) as usize, emit)
+            }
+            MidiMessage::ControlChange(channel, control, value) => {
+                let targets = match self.targets.get(self.bank) {
+                    Some(targets) => *targets,
+                    None => return,
+                };
+                for (index, target) in targets.iter().enumerate() {
+                    // Echoes of sent values do not make the control pick up the parameter again
+                    if *target == (channel, control) && self.value(self.bank, index) != Some(value)
+                    {
+                        self.set_value(self.bank, index, value);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn target(&self, index: usize) -> Option<(Channel, Control)> {
+        self.targets.get(self.bank)?.get(index).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::vec::Vec;
+
+    fn banks() -> ControlBanks<2, 2> {
+        ControlBanks::new([
+            [
+                (Channel::C1, Control::new(1)),
+                (Channel::C1, Control::new(2)),
+            ],
+            [
+                (Channel::C2, Control::new(1)),
+                (Channel::C2, Control::new(2)),
+            ],
+        ])
+    }
+
+    fn sweep(banks: &mut ControlBanks<2, 2>, index: usize, values: &[u8]) -> Vec<MidiMessage> {
+        let mut messages = Vec::new();
+        for value in values {
+            banks.control(index, Value7::new(*value), |message| messages.push(message));
+        }
+        messages
+    }
+
+    #[test]
+    fn should_send_to_selected_bank() {
+        let mut banks = banks();
+        assert_eq!(
+            sweep(&mut banks, 1, &[10]),
+            [MidiMessage::ControlChange(
+                Channel::C1,
+                Control::new(2),
+                10.into()
+            )]
+        );
+
+        banks.next_bank(|_| {});
+        assert_eq!(banks.bank(), 1);
+        assert_eq!(
+            sweep(&mut banks, 1, &[0]),
+            [MidiMessage::ControlChange(
+                Channel::C2,
+                Control::new(2),
+                0.into()
+            )]
+        );
+        assert_eq!(banks.value(0, 1), Some(10.into()));
+    }
+
+    #[test]
+    fn should_pick_up_after_switching_banks() {
+        let mut banks = banks();
+        sweep(&mut banks, 0, &[100]);
+        banks.set_bank(1, |_| {});
+        sweep(&mut banks, 0, &[0, 20]);
+
+        // The control is at 20 but the parameter in the first bank is still at 100
+        banks.previous_bank(|_| {});
+        assert_eq!(sweep(&mut banks, 0, &[40, 90]), []);
+        assert_eq!(sweep(&mut banks, 0, &[110]).len(), 1);
+    }
+
+    #[test]
+    fn should_send_values_of_new_bank() {
+        let mut banks = banks();
+        banks.set_value(1, 0, 30.into());
+
+        let mut messages = Vec::new();
+        banks.set_bank(1, |message| messages.push(message));
+        assert_eq!(
+            messages,
+            [
+                MidiMessage::ControlChange(Channel::C2, Control::new(1), 30.into()),
+                MidiMessage::ControlChange(Channel::C2, Control::new(2), 0.into()),
+            ]
+        );
+
+        messages.clear();
+        banks.set_bank(2, |message| messages.push(message));
+        assert_eq!(messages, []);
+    }
+
+    #[test]
+    fn should_switch_on_program_change() {
+        let mut banks = banks();
+        banks.handle(&MidiMessage::ProgramChange(Channel::C16, 1.into()), |_| {});
+        assert_eq!(banks.bank(), 0);
+
+        banks.set_program_channel(Some(Channel::C16));
+        banks.handle(&MidiMessage::ProgramChange(Channel::C16, 1.into()), |_| {});
+        assert_eq!(banks.bank(), 1);
+        banks.handle(&MidiMessage::ProgramChange(Channel::C16, 2.into()), |_| {});
+        assert_eq!(banks.bank(), 1);
+
+        banks.handle(
+            &MidiMessage::ControlChange(Channel::C2, Control::new(2), 64.into()),
+            |_| {},
+        );
+        assert_eq!(banks.value(1, 1), Some(64.into()));
+    }
+}
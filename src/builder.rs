@@ -0,0 +1,218 @@
+//! Building channel messages with validated values
+//!
+//! The builder checks values when the message is built instead of clamping them like the
+//! `midi_types` conversions do, and fills in defaults for values that are not set: channel 1,
+//! note C3, a note-on velocity of 100, a note-off velocity of 64, and centered pitch bends.
+//!
+//! ```
+//! # use embedded_midi::{MidiEvent, midi_types::{MidiMessage, Note}};
+//! # fn main() -> Result<(), embedded_midi::value::OutOfRange> {
+//! let event = MidiEvent::builder()
+//!     .note_on()
+//!     .channel(3)?
+//!     .note(Note::C4)
+//!     .velocity(100)
+//!     .build()?;
+//!
+//! assert_eq!(event, MidiEvent::Message(MidiMessage::NoteOn(3.into(), Note::C4, 100.into())));
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::midi_types::{Channel, MidiMessage, Note};
+use crate::parser::MidiEvent;
+use crate::value::{OutOfRange, U14, U7};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    NoteOn,
+    NoteOff,
+    KeyPressure,
+    ControlChange(u8),
+    ProgramChange,
+    ChannelPressure,
+    PitchBend,
+}
+
+/// Builds a channel message, created by `MidiEvent::builder`
+#[derive(Debug, Clone)]
+pub struct MessageBuilder {
+    kind: Kind,
+    channel: Channel,
+    note: Note,
+    value: Option<u8>,
+    bend: i16,
+}
+
+impl MidiEvent<'static> {
+    /// Build a channel message, without selecting a message type a note-on is built
+    pub fn builder() -> MessageBuilder {
+        MessageBuilder::new()
+    }
+}
+
+impl Default for MessageBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MessageBuilder {
+    pub fn new() -> Self {
+        MessageBuilder {
+            kind: Kind::NoteOn,
+            channel: Channel::C1,
+            note: Note::C3,
+            value: None,
+            bend: 0,
+        }
+    }
+
+    pub fn note_on(self) -> Self {
+        self.kind(Kind::NoteOn)
+    }
+
+    pub fn note_off(self) -> Self {
+        self.kind(Kind::NoteOff)
+    }
+
+    pub fn key_pressure(self) -> Self {
+        self.kind(Kind::KeyPressure)
+    }
+
+    /// A control change for a controller number from 0 to 127
+    pub fn control_change(self, control: u8) -> Self {
+        self.kind(Kind::ControlChange(control))
+    }
+
+    pub fn program_change(self) -> Self {
+        self.kind(Kind::ProgramChange)
+    }
+
+    pub fn channel_pressure(self) -> Self {
+        self.kind(Kind::ChannelPressure)
+    }
+
+    pub fn pitch_bend(self) -> Self {
+        self.kind(Kind::PitchBend)
+    }
+
+    /// Set the channel from 0 to 15, like `Channel::from`
+    pub fn channel(mut self, channel: u8) -> Result<Self, OutOfRange> {
+        if channel > 15 {
+            return Err(OutOfRange);
+        }
+        self.channel = channel.into();
+        Ok(self)
+    }
+
+    pub fn note(mut self, note: Note) -> Self {
+        self.note = note;
+        self
+    }
+
+    /// Set the velocity of a note message
+    pub fn velocity(self, velocity: u8) -> Self {
+        self.value(velocity)
+    }
+
+    /// Set the pressure of a pressure message
+    pub fn pressure(self, pressure: u8) -> Self {
+        self.value(pressure)
+    }
+
+    pub fn program(self, program: u8) -> Self {
+        self.value(program)
+    }
+
+    /// Set the value of a control change, or any other 7 bit value of the message
+    pub fn value(mut self, value: u8) -> Self {
+        self.value = Some(value);
+        self
+    }
+
+    /// Set a pitch bend from -8192 to 8191
+    pub fn bend(mut self, bend: i16) -> Self {
+        self.bend = bend;
+        self
+    }
+
+    /// Build the message, returns `OutOfRange` when a value does not fit the message
+    pub fn build(self) -> Result<MidiEvent<'static>, OutOfRange> {
+        let channel = self.channel;
+        let note = self.note;
+        let value = |default| U7::checked(self.value.unwrap_or(default)).ok_or(OutOfRange);
+
+        let message = match self.kind {
+            Kind::NoteOn => MidiMessage::NoteOn(channel, note, value(100)?.into()),
+            Kind::NoteOff => MidiMessage::NoteOff(channel, note, value(64)?.into()),
+            Kind::KeyPressure => MidiMessage::KeyPressure(channel, note, value(0)?.into()),
+            Kind::ControlChange(control) => MidiMessage::ControlChange(
+                channel,
+                U7::checked(control).ok_or(OutOfRange)?.into(),
+                value(0)?.into(),
+            ),
+            Kind::ProgramChange => MidiMessage::ProgramChange(channel, value(0)?.into()),
+            Kind::ChannelPressure => MidiMessage::ChannelPressure(channel, value(0)?.into()),
+            Kind::PitchBend => {
+                if !(-0x2000..0x2000).contains(&self.bend) {
+                    return Err(OutOfRange);
+                }
+                MidiMessage::PitchBendChange(channel, U14::from_signed(self.bend).into())
+            }
+        };
+
+        Ok(MidiEvent::Message(message))
+    }
+
+    fn kind(mut self, kind: Kind) -> Self {
+        self.kind = kind;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::midi_types::{Control, Value14};
+
+    #[test]
+    fn should_fill_in_defaults() {
+        assert_eq!(
+            MidiEvent::builder().note_off().build(),
+            Ok(MidiEvent::Message(MidiMessage::NoteOff(
+                Channel::C1,
+                Note::C3,
+                64.into()
+            )))
+        );
+        assert_eq!(
+            MidiEvent::builder().pitch_bend().build(),
+            Ok(MidiEvent::Message(MidiMessage::PitchBendChange(
+                Channel::C1,
+                Value14::from(0x2000u16)
+            )))
+        );
+    }
+
+    #[test]
+    fn should_validate_values() {
+        assert_eq!(MidiEvent::builder().channel(16).err(), Some(OutOfRange));
+        assert_eq!(
+            MidiEvent::builder().note_on().velocity(128).build(),
+            Err(OutOfRange)
+        );
+        assert_eq!(
+            MidiEvent::builder().pitch_bend().bend(8192).build(),
+            Err(OutOfRange)
+        );
+        assert_eq!(
+            MidiEvent::builder().control_change(7).value(90).build(),
+            Ok(MidiEvent::Message(MidiMessage::ControlChange(
+                Channel::C1,
+                Control::new(7),
+                90.into()
+            )))
+        );
+    }
+}
@@ -0,0 +1,207 @@
+//! Buttons sending control changes or notes
+
+use crate::midi_types::{Channel, Control, MidiMessage, Note};
+use crate::value::U7;
+
+/// How a button changes its value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ButtonMode {
+    /// The on value is sent while the button is held, the off value when it is released
+    Momentary,
+
+    /// Every press switches between the on and off value
+    Toggle,
+
+    /// Every press moves the value `step` from the off value towards the on value, wrapping back to
+    /// the off value after passing the on value
+    Increment { step: U7 },
+}
+
+/// The message a button sends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ButtonTarget {
+    /// Sends a control change with the value
+    Control(Channel, Control),
+
+    /// Sends a note-on with the value as velocity, or a note-off when the value is zero
+    Note(Channel, Note),
+}
+
+/// Maps button presses and releases to midi messages
+#[derive(Debug, Clone)]
+pub struct Button {
+    target: ButtonTarget,
+    mode: ButtonMode,
+    off: U7,
+    on: U7,
+    value: U7,
+    pressed: bool,
+}
+
+impl Button {
+    /// Create a button sending 127 when on and 0 when off
+    pub fn new(target: ButtonTarget, mode: ButtonMode) -> Self {
+        Button {
+            target,
+            mode,
+            off: U7::MIN,
+            on: U7::MAX,
+            value: U7::MIN,
+            pressed: false,
+        }
+    }
+
+    /// Set the values sent when on and off, the current value is reset to off
+    pub fn set_values(&mut self, off: U7, on: U7) {
+        self.off = off;
+        self.on = on;
+        self.value = self.off;
+    }
+
+    pub fn mode(&self) -> ButtonMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: ButtonMode) {
+        self.mode = mode;
+    }
+
+    /// The last value sent
+    pub fn value(&self) -> U7 {
+        self.value
+    }
+
+    pub fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+
+    /// Update the button state, sends a message when the state changes
+    pub fn update(&mut self, pressed: bool, emit: impl FnMut(MidiMessage)) {
+        if pressed {
+            self.press(emit);
+        } else {
+            self.release(emit);
+        }
+    }
+
+    pub fn press(&mut self, emit: impl FnMut(MidiMessage)) {
+        if self.pressed {
+            return;
+        }
+        self.pressed = true;
+
+        let value = match self.mode {
+            ButtonMode::Momentary => self.on,
+            ButtonMode::Toggle if self.value == self.on => self.off,
+            ButtonMode::Toggle => self.on,
+            ButtonMode::Increment { step } => {
+                let (value, step) = (self.value.get(), step.get());
+                let next = if self.off <= self.on {
+                    value
+                        .checked_add(step)
+                        .and_then(U7::checked)
+                        .filter(|next| *next <= self.on)
+                } else {
+                    value
+                        .checked_sub(step)
+                        .map(U7::new)
+                        .filter(|next| *next >= self.on)
+                };
+                next.unwrap_or(self.off)
+            }
+        };
+        self.send(value, emit);
+    }
+
+    pub fn release(&mut self, emit: impl FnMut(MidiMessage)) {
+        if !self.pressed {
+            return;
+        }
+        self.pressed = false;
+
+        if self.mode == ButtonMode::Momentary {
+            self.send(self.off, emit);
+        }
+    }
+
+    fn send(&mut self, value: U7, mut emit: impl FnMut(MidiMessage)) {
+        self.value = value;
+        emit(match self.target {
+            ButtonTarget::Control(channel, control) => {
+                MidiMessage::ControlChange(channel, control, value.into())
+            }
+            ButtonTarget::Note(channel, note) if value > U7::MIN => {
+                MidiMessage::NoteOn(channel, note, value.into())
+            }
+            ButtonTarget::Note(channel, note) => MidiMessage::NoteOff(channel, note, 0.into()),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::vec::Vec;
+
+    fn presses(button: &mut Button, states: &[bool]) -> Vec<u8> {
+        let mut values = Vec::new();
+        for pressed in states {
+            button.update(*pressed, |message| match message {
+                MidiMessage::ControlChange(_, _, value) | MidiMessage::NoteOn(_, _, value) => {
+                    values.push(value.into())
+                }
+                _ => values.push(0),
+            });
+        }
+        values
+    }
+
+    const CONTROL: ButtonTarget = ButtonTarget::Control(Channel::C1, Control::new(64));
+
+    #[test]
+    fn should_send_momentary_values() {
+        let mut button = Button::new(CONTROL, ButtonMode::Momentary);
+        button.set_values(U7::new(10), U7::new(100));
+        assert_eq!(presses(&mut button, &[true, true, false, false]), [100, 10]);
+    }
+
+    #[test]
+    fn should_toggle_on_press() {
+        let mut button = Button::new(
+            ButtonTarget::Note(Channel::C1, Note::C3),
+            ButtonMode::Toggle,
+        );
+
+        let mut messages = Vec::new();
+        for pressed in [true, false, true, false].iter() {
+            button.update(*pressed, |message| messages.push(message));
+        }
+        assert_eq!(
+            messages,
+            [
+                MidiMessage::NoteOn(Channel::C1, Note::C3, 127.into()),
+                MidiMessage::NoteOff(Channel::C1, Note::C3, 0.into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_increment_and_wrap() {
+        let mut button = Button::new(CONTROL, ButtonMode::Increment { step: U7::new(50) });
+        assert_eq!(
+            presses(&mut button, &[true, false, true, false, true, false, true]),
+            [50, 100, 0, 50]
+        );
+
+        // With the off value above the on value the value steps down
+        button.set_values(U7::new(100), U7::new(0));
+        assert_eq!(
+            presses(
+                &mut button,
+                &[false, true, false, true, false, true, false, true]
+            ),
+            [50, 0, 100, 50]
+        );
+    }
+}
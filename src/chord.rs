@@ -0,0 +1,447 @@
+//! Chord memory expanding single notes into chords and detection of played chords
+
+use crate::midi_types::{Channel, MidiMessage, Note, Value7};
+use crate::notes::HeldNotes;
+use crate::process::Processor;
+use crate::value::U7;
+use core::convert::TryFrom;
+
+/// A chord shape of up to `N` intervals in semitones relative to the played note
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chord<const N: usize> {
+    intervals: [i8; N],
+    len: usize,
+}
+
+impl<const N: usize> Chord<N> {
+    /// Create a chord from intervals, intervals beyond `N` are ignored
+    pub fn new(intervals: &[i8]) -> Self {
+        let mut chord = Chord {
+            intervals: [0; N],
+            len: 0,
+        };
+        for (slot, interval) in chord.intervals.iter_mut().zip(intervals) {
+            *slot = *interval;
+            chord.len += 1;
+        }
+        chord
+    }
+
+    /// Create a chord from played notes, relative to the lowest note
+    pub fn from_notes(notes: impl IntoIterator<Item = Note>) -> Self {
+        let mut notes_played = [0u8; N];
+        let mut len = 0;
+        for (slot, note) in notes_played.iter_mut().zip(notes) {
+            *slot = note.into();
+            len += 1;
+        }
+
+        let played = notes_played.get_mut(..len).unwrap_or_default();
+        played.sort_unstable();
+        let lowest = played.first().copied().unwrap_or_default();
+
+        let mut chord = Chord {
+            intervals: [0; N],
+            len,
+        };
+        for (slot, note) in chord.intervals.iter_mut().zip(played.iter()) {
+            *slot = (note - lowest) as i8;
+        }
+        chord
+    }
+
+    pub fn intervals(&self) -> &[i8] {
+        self.intervals.get(..self.len).unwrap_or_default()
+    }
+
+    /// The notes of the chord played from a root note, notes out of range are skipped
+    pub fn notes(&self, root: Note) -> impl Iterator<Item = Note> + '_ {
+        self.intervals().iter().filter_map(move |interval| {
+            let note = u8::from(root) as i16 + *interval as i16;
+            u8::try_from(note)
+                .ok()
+                .and_then(U7::checked)
+                .map(Note::from)
+        })
+    }
+}
+
+/// Plays a stored chord for every note, chords of up to `N` notes can be played from up to `R`
+/// held notes at the same time. Every note remembers the chord it started so changing the chord
+/// never leaves notes hanging.
+#[derive(Debug, Clone)]
+pub struct ChordMemory<const N: usize, const R: usize> {
+    chord: Chord<N>,
+    max_voices: usize,
+    /// Notes played while learning and the number of keys still held
+    learning: Option<(HeldNotes<N>, usize)>,
+    sounding: [Option<(Channel, Note, Chord<N>)>; R],
+}
+
+impl<const N: usize, const R: usize> ChordMemory<N, R> {
+    pub fn new(chord: Chord<N>) -> Self {
+        ChordMemory {
+            chord,
+            max_voices: N,
+            learning: None,
+            sounding: [None; R],
+        }
+    }
+
+    pub fn chord(&self) -> &Chord<N> {
+        &self.chord
+    }
+
+    /// Change the chord, notes that are held keep playing their chord
+    pub fn set_chord(&mut self, chord: Chord<N>) {
+        self.chord = chord;
+    }
+
+    /// Limit the number of notes played for every chord
+    pub fn set_max_voices(&mut self, max_voices: usize) {
+        self.max_voices = max_voices;
+    }
+
+    /// Learn a new chord from the next notes played, notes are passed unchanged while learning.
+    /// The chord is stored when all keys are released.
+    pub fn learn(&mut self) {
+        self.learning = Some((HeldNotes::new(), 0));
+    }
+
+    pub fn is_learning(&self) -> bool {
+        self.learning.is_some()
+    }
+
+    fn learn_note(&mut self, message: &MidiMessage) {
+        let (played, held) = match &mut self.learning {
+            Some(learning) => learning,
+            None => return,
+        };
+
+        match *message {
+            MidiMessage::NoteOn(_, note, velocity) if u8::from(velocity) > 0 => {
+                played.press(note, velocity);
+                *held += 1;
+            }
+            MidiMessage::NoteOn(..) | MidiMessage::NoteOff(..) => {
+                // Released keys stay part of the chord until all keys are released
+                *held = held.saturating_sub(1);
+                if *held == 0 && !played.is_empty() {
+                    self.chord = Chord::from_notes(played.iter().map(|(note, _)| *note));
+                    self.learning = None;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn note_on(
+        &mut self,
+        channel: Channel,
+        root: Note,
+        velocity: Value7,
+        emit: &mut impl FnMut(MidiMessage),
+    ) {
+        let mut chord = self.chord;
+        chord.len = chord.len.min(self.max_voices);
+
+        let index = self
+            .sounding
+            .iter()
+            .position(|slot| matches!(slot, Some((c, n, _)) if *c == channel && *n == root))
+            .or_else(|| self.sounding.iter().position(Option::is_none));
+        let slot = match index.and_then(|index| self.sounding.get_mut(index)) {
+            Some(slot) => slot,
+            // Without room to remember the chord only the note itself is played
+            None => return emit(MidiMessage::NoteOn(channel, root, velocity)),
+        };
+
+        *slot = Some((channel, root, chord));
+        for note in chord.notes(root) {
+            emit(MidiMessage::NoteOn(channel, note, velocity));
+        }
+    }
+
+    fn note_off(
+        &mut self,
+        channel: Channel,
+        root: Note,
+        velocity: Value7,
+        emit: &mut impl FnMut(MidiMessage),
+    ) {
+        let sounding = self
+            .sounding
+            .iter_mut()
+            .find(|slot| matches!(slot, Some((c, n, _)) if *c == channel && *n == root))
+            .and_then(Option::take);
+
+        match sounding {
+            Some((_, _, chord)) => {
+                for note in chord.notes(root) {
+                    emit(MidiMessage::NoteOff(channel, note, velocity));
+                }
+            }
+            None => emit(MidiMessage::NoteOff(channel, root, velocity)),
+        }
+    }
+}
+
+impl<const N: usize, const R: usize> Processor for ChordMemory<N, R> {
+    fn process(&mut self, message: MidiMessage, emit: &mut impl FnMut(MidiMessage)) {
+        if self.learning.is_some() {
+            self.learn_note(&message);
+            return emit(message);
+        }
+
+        match message {
+            MidiMessage::NoteOn(channel, note, velocity) if u8::from(velocity) > 0 => {
+                self.note_on(channel, note, velocity, emit)
+            }
+            MidiMessage::NoteOn(channel, note, velocity)
+            | MidiMessage::NoteOff(channel, note, velocity) => {
+                self.note_off(channel, note, velocity, emit)
+            }
+            message => emit(message),
+        }
+    }
+}
+
+/// The quality of a detected chord
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChordQuality {
+    Major,
+    Minor,
+    Diminished,
+    Augmented,
+    Sus2,
+    Sus4,
+    Major6,
+    Minor6,
+    Dominant7,
+    Major7,
+    Minor7,
+    MinorMajor7,
+    HalfDiminished7,
+    Diminished7,
+    /// A root and fifth without a third
+    Power,
+}
+
+impl ChordQuality {
+    /// Every quality with its pitch classes relative to the root, bit `n` is `n` semitones up
+    const ALL: [(ChordQuality, u16); 15] = [
+        (ChordQuality::Major, 0b0000_1001_0001),
+        (ChordQuality::Minor, 0b0000_1000_1001),
+        (ChordQuality::Diminished, 0b0000_0100_1001),
+        (ChordQuality::Augmented, 0b0001_0001_0001),
+        (ChordQuality::Sus2, 0b0000_1000_0101),
+        (ChordQuality::Sus4, 0b0000_1010_0001),
+        (ChordQuality::Major6, 0b0010_1001_0001),
+        (ChordQuality::Minor6, 0b0010_1000_1001),
+        (ChordQuality::Dominant7, 0b0100_1001_0001),
+        (ChordQuality::Major7, 0b1000_1001_0001),
+        (ChordQuality::Minor7, 0b0100_1000_1001),
+        (ChordQuality::MinorMajor7, 0b1000_1000_1001),
+        (ChordQuality::HalfDiminished7, 0b0100_0100_1001),
+        (ChordQuality::Diminished7, 0b0010_0100_1001),
+        (ChordQuality::Power, 0b0000_1000_0001),
+    ];
+}
+
+/// A chord recognized from played notes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DetectedChord {
+    /// The root as a pitch class from 0 (C) to 11 (B)
+    pub root: u8,
+    pub quality: ChordQuality,
+    /// The lowest played note, differs from the root for inversions
+    pub bass: Note,
+}
+
+impl DetectedChord {
+    /// Recognize the chord formed by a set of notes, octaves and doubled notes are ignored.
+    /// When notes form more than one chord the chord with the lowest note as its root is used.
+    pub fn detect(notes: impl IntoIterator<Item = Note>) -> Option<Self> {
+        let (mask, bass) = notes.into_iter().map(u8::from).fold(
+            (0u16, None),
+            |(mask, bass): (u16, Option<u8>), note| {
+                let lowest = bass.map_or(note, |bass| bass.min(note));
+                (mask | 1 << (note % 12), Some(lowest))
+            },
+        );
+        let bass = bass?;
+
+        let lowest = bass % 12;
+        (0..12)
+            .map(|offset| (lowest + offset) % 12)
+            .filter(|root| mask >> root & 1 == 1)
+            .find_map(|root| {
+                // The pitch classes relative to the root
+                let relative = (mask >> root | mask << (12 - root)) & 0xFFF;
+                ChordQuality::ALL
+                    .iter()
+                    .find(|(_, intervals)| *intervals == relative)
+                    .map(|(quality, _)| DetectedChord {
+                        root,
+                        quality: *quality,
+                        bass: Note::new(bass),
+                    })
+            })
+    }
+}
+
+/// Detects the chord played by up to `N` held notes
+#[derive(Debug, Clone)]
+pub struct ChordDetector<const N: usize> {
+    channel: Option<Channel>,
+    held: HeldNotes<N>,
+    chord: Option<DetectedChord>,
+}
+
+impl<const N: usize> ChordDetector<N> {
+    /// Create a detector for notes on `channel`, or all channels when `channel` is `None`
+    pub const fn new(channel: Option<Channel>) -> Self {
+        ChordDetector {
+            channel,
+            held: HeldNotes::new(),
+            chord: None,
+        }
+    }
+
+    /// The chord formed by the held notes
+    pub fn chord(&self) -> Option<DetectedChord> {
+        self.chord
+    }
+
+    pub fn held(&self) -> &HeldNotes<N> {
+        &self.held
+    }
+
+    /// Handle a received message, returns true when the detected chord changed
+    pub fn handle(&mut self, message: &MidiMessage) -> bool {
+        match *message {
+            MidiMessage::NoteOn(channel, note, velocity)
+                if u8::from(velocity) > 0 && self.listens(channel) =>
+            {
+                self.held.press(note, velocity)
+            }
+            MidiMessage::NoteOn(channel, note, _) | MidiMessage::NoteOff(channel, note, _)
+                if self.listens(channel) =>
+            {
+                self.held.release(note);
+            }
+            _ => return false,
+        }
+
+        let chord = DetectedChord::detect(self.held.iter().map(|(note, _)| *note));
+        let changed = chord != self.chord;
+        self.chord = chord;
+        changed
+    }
+
+    fn listens(&self, channel: Channel) -> bool {
+        self.channel.map_or(true, |own| own == channel)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use crate::test_util::{off, on};
+    use std::vec::Vec;
+
+    fn process(memory: &mut impl Processor, message: MidiMessage) -> Vec<u8> {
+        let mut notes = Vec::new();
+        memory.process(message, &mut |message| match message {
+            MidiMessage::NoteOn(_, note, _) | MidiMessage::NoteOff(_, note, _) => {
+                notes.push(note.into())
+            }
+            _ => {}
+        });
+        notes
+    }
+
+    #[test]
+    fn should_expand_notes_into_chords() {
+        let mut memory = ChordMemory::<4, 4>::new(Chord::new(&[0, 4, 7]));
+
+        assert_eq!(process(&mut memory, on(60)), [60, 64, 67]);
+        memory.set_chord(Chord::new(&[0, 3, 7]));
+        assert_eq!(process(&mut memory, on(62)), [62, 65, 69]);
+
+        // Notes release the chord they started with
+        assert_eq!(process(&mut memory, off(60)), [60, 64, 67]);
+        assert_eq!(process(&mut memory, off(62)), [62, 65, 69]);
+    }
+
+    #[test]
+    fn should_learn_chords_from_played_notes() {
+        let mut memory = ChordMemory::<4, 4>::new(Chord::new(&[0]));
+        memory.learn();
+
+        for message in [on(67), on(60), on(64), off(60), off(64), off(67)].iter() {
+            assert_eq!(process(&mut memory, *message).len(), 1);
+        }
+
+        assert!(!memory.is_learning());
+        assert_eq!(memory.chord().intervals(), [0, 4, 7]);
+    }
+
+    #[test]
+    fn should_limit_voices() {
+        let mut memory = ChordMemory::<4, 1>::new(Chord::new(&[0, 4, 7, 11]));
+        memory.set_max_voices(2);
+
+        assert_eq!(process(&mut memory, on(60)), [60, 64]);
+        // Without a free slot only the note itself is played
+        assert_eq!(process(&mut memory, on(50)), [50]);
+        assert_eq!(process(&mut memory, off(50)), [50]);
+        assert_eq!(process(&mut memory, off(60)), [60, 64]);
+    }
+
+    #[test]
+    fn should_detect_chords() {
+        let chord = |notes: &[u8]| DetectedChord::detect(notes.iter().map(|note| (*note).into()));
+
+        let c_major = chord(&[60, 64, 67]).unwrap();
+        assert_eq!((c_major.root, c_major.quality), (0, ChordQuality::Major));
+
+        // C six and A minor seven share their notes, the lowest note decides
+        let c_6 = chord(&[48, 52, 55, 57, 69]).unwrap();
+        assert_eq!((c_6.root, c_6.quality), (0, ChordQuality::Major6));
+        let a_minor7 = chord(&[45, 52, 55, 60]).unwrap();
+        assert_eq!((a_minor7.root, a_minor7.quality), (9, ChordQuality::Minor7));
+
+        let inversion = chord(&[52, 55, 60]).unwrap();
+        assert_eq!(
+            (inversion.root, inversion.quality),
+            (0, ChordQuality::Major)
+        );
+        assert_eq!(inversion.bass, Note::new(52));
+
+        assert_eq!(chord(&[60, 61, 62]), None);
+        assert_eq!(chord(&[]), None);
+    }
+
+    #[test]
+    fn should_follow_held_notes() {
+        let mut detector = ChordDetector::<8>::new(Some(Channel::C1));
+
+        assert!(!detector.handle(&on(62)));
+        assert!(!detector.handle(&on(66)));
+        assert!(detector.handle(&on(69)));
+        assert_eq!(
+            detector.chord().map(|chord| chord.quality),
+            Some(ChordQuality::Major)
+        );
+        assert!(detector.handle(&on(72)));
+        assert_eq!(
+            detector.chord().map(|chord| chord.quality),
+            Some(ChordQuality::Dominant7)
+        );
+        assert!(!detector.handle(&MidiMessage::NoteOff(Channel::C2, 72.into(), 0.into())));
+        assert!(detector.handle(&off(66)));
+        assert_eq!(detector.chord(), None);
+    }
+}
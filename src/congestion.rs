@@ -0,0 +1,358 @@
+//! Output scheduling that thins continuous controllers when the output is congested
+//!
+//! A DIN output sends about one byte every 320 microseconds. When more messages are sent than the
+//! output can carry, for instance when a busy sequencer stream is merged into one output, a plain
+//! queue falls further and further behind. This queue paces messages at the speed of the output
+//! and replaces continuous control change, pressure and pitch bend messages that are still
+//! waiting with newer values for the same controller, so only the latest value is sent. A waiting
+//! value is not replaced once another message for its channel was queued after it, so controllers
+//! keep their order relative to notes and program changes. Notes, switches like sustain, bank and
+//! parameter number selects and other messages are never replaced, and clock and other real-time
+//! messages skip ahead of the queue. When the queue is full new messages are refused, no waiting
+//! message is dropped.
+//!
+//! The output can also send active sensing when it was idle for a while, so receivers notice when
+//! the connection is lost. Active sensing is only sent between messages, never inside one.
+//!
+//! Times are plain `u32` values in the unit of the byte time, for instance microseconds.
+
+use crate::mapping::is_continuous;
+use crate::midi_types::MidiMessage;
+use crate::packed::PackedMidiEvent;
+use crate::parser::is_real_time;
+
+/// The time to send a byte on a DIN output in microseconds, 10 bits at 31250 baud
+pub const DIN_BYTE_TIME: u32 = 320;
+
+/// The idle time after which active sensing is sent in microseconds, receivers time out after
+/// 300 milliseconds without messages
+pub const ACTIVE_SENSING_INTERVAL: u32 = 270_000;
+
+/// The status and first data byte of messages that replace each other, `None` for messages that
+/// are never replaced
+fn superseding_key(event: PackedMidiEvent) -> Option<(u8, u8)> {
+    let [status, first, _] = event.to_bytes();
+    match status & 0xF0 {
+        0xA0 => Some((status, first)),
+        0xB0 if is_continuous(first.into()) => Some((status, first)),
+        0xD0 | 0xE0 => Some((status, 0)),
+        _ => None,
+    }
+}
+
+/// True when the event is a channel message on the channel of a status byte
+fn same_channel(event: PackedMidiEvent, status: u8) -> bool {
+    let waiting = event.status();
+    waiting < 0xF0 && waiting & 0x0F == status & 0x0F
+}
+
+/// Paces up to `N` messages at the speed of the output
+#[derive(Debug, Clone)]
+pub struct ThrottledOutput<const N: usize> {
+    events: [PackedMidiEvent; N],
+    len: usize,
+    byte_time: u32,
+
+    /// The time the output started sending the last message
+    sent_at: u32,
+
+    /// The time it takes to send the last message, 0 when the output was idle since
+    busy_for: u32,
+
+    /// The number of messages that were replaced by newer values
+    thinned: u32,
+
+    /// Send active sensing after the output was idle for this long
+    sensing: Option<u32>,
+}
+
+impl<const N: usize> Default for ThrottledOutput<N> {
+    fn default() -> Self {
+        Self::new(DIN_BYTE_TIME)
+    }
+}
+
+impl<const N: usize> ThrottledOutput<N> {
+    pub const fn new(byte_time: u32) -> Self {
+        ThrottledOutput {
+            events: [PackedMidiEvent::from_raw(0); N],
+            len: 0,
+            byte_time,
+            sent_at: 0,
+            busy_for: 0,
+            thinned: 0,
+            sensing: None,
+        }
+    }
+
+    /// The number of messages waiting to be sent
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// True when messages are waiting because the output is busy
+    pub fn is_congested(&self) -> bool {
+        !self.is_empty()
+    }
+
+    /// The number of messages that were replaced by newer values
+    pub fn thinned(&self) -> u32 {
+        self.thinned
+    }
+
+    /// Send active sensing after the output was idle for `interval`, for instance
+    /// `ACTIVE_SENSING_INTERVAL`. `None` to stop sending active sensing.
+    pub fn set_active_sensing(&mut self, interval: Option<u32>) {
+        self.sensing = interval;
+    }
+
+    pub fn active_sensing(&self) -> Option<u32> {
+        self.sensing
+    }
+
+    /// Queue a message, replacing a waiting value for the same continuous controller. Returns
+    /// the message back when the queue is full.
+    pub fn push(&mut self, message: MidiMessage) -> Result<(), MidiMessage> {
+        let event = PackedMidiEvent::from(message);
+
+        if let Some(key) = superseding_key(event) {
+            // Search back to the last message for the channel that can not be replaced
+            let channel = event.status();
+            if let Some(waiting) = self
+                .waiting_mut()
+                .iter_mut()
+                .rev()
+                .take_while(|waiting| {
+                    superseding_key(**waiting).is_some() || !same_channel(**waiting, channel)
+                })
+                .find(|waiting| superseding_key(**waiting) == Some(key))
+            {
+                *waiting = event;
+                self.thinned += 1;
+                return Ok(());
+            }
+        }
+
+        if self.len == N {
+            return Err(message);
+        }
+
+        // Real-time messages skip ahead of other messages
+        let index = if is_real_time(event.status()) {
+            self.waiting()
+                .iter()
+                .take_while(|waiting| is_real_time(waiting.status()))
+                .count()
+        } else {
+            self.len
+        };
+        self.insert(index, event);
+        Ok(())
+    }
+
+    /// Returns the next message when the output finished sending the previous message at `now`,
+    /// or active sensing when the output was idle long enough
+    pub fn poll(&mut self, now: u32) -> Option<MidiMessage> {
+        let elapsed = now.wrapping_sub(self.sent_at);
+        if elapsed < self.busy_for {
+            return None;
+        }
+
+        if let Some(event) = self.remove(0) {
+            self.start_sending(now, event.len());
+            return event.message().ok();
+        }
+
+        let idle = elapsed - self.busy_for;
+        match self.sensing {
+            Some(interval) if idle >= interval => {
+                self.start_sending(now, 1);
+                Some(MidiMessage::ActiveSensing)
+            }
+            Some(_) => None,
+            None => {
+                // Nothing is due, forget the last message so long idle times can not wrap around
+                self.sent_at = now;
+                self.busy_for = 0;
+                None
+            }
+        }
+    }
+
+    /// Drop all waiting messages
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    fn waiting(&self) -> &[PackedMidiEvent] {
+        self.events.get(..self.len).unwrap_or_default()
+    }
+
+    fn waiting_mut(&mut self) -> &mut [PackedMidiEvent] {
+        self.events.get_mut(..self.len).unwrap_or_default()
+    }
+
+    fn insert(&mut self, index: usize, event: PackedMidiEvent) {
+        if let Some(events) = self.events.get_mut(index..=self.len) {
+            events.rotate_right(1);
+            if let Some(slot) = events.first_mut() {
+                *slot = event;
+            }
+            self.len += 1;
+        }
+    }
+
+    fn remove(&mut self, index: usize) -> Option<PackedMidiEvent> {
+        let events = self.events.get_mut(index..self.len)?;
+        let event = events.first().copied()?;
+        events.rotate_left(1);
+        self.len -= 1;
+        Some(event)
+    }
+
+    fn start_sending(&mut self, now: u32, len: usize) {
+        self.sent_at = now;
+        self.busy_for = (len as u32).saturating_mul(self.byte_time);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use crate::midi_types::{Channel, Control, Note, Value7};
+    use std::vec::Vec;
+
+    fn control(value: u8) -> MidiMessage {
+        MidiMessage::ControlChange(Channel::C1, Control::new(1), Value7::new(value))
+    }
+
+    const NOTE: MidiMessage = MidiMessage::NoteOn(Channel::C1, Note::C3, Value7::new(100));
+
+    #[test]
+    fn should_pace_messages_and_replace_waiting_controllers() {
+        let mut output = ThrottledOutput::<8>::default();
+        output.push(NOTE).unwrap();
+        output.push(control(10)).unwrap();
+        output.push(control(20)).unwrap();
+        output.push(MidiMessage::TimingClock).unwrap();
+
+        let sent: Vec<_> = [0, 319, 320, 1000, 1280, 2240]
+            .iter()
+            .map(|now| output.poll(*now))
+            .collect();
+        assert_eq!(
+            sent,
+            [
+                Some(MidiMessage::TimingClock),
+                None,
+                Some(NOTE),
+                None,
+                Some(control(20)),
+                None
+            ]
+        );
+        assert_eq!(output.thinned(), 1);
+        assert!(!output.is_congested());
+    }
+
+    fn cc(control: u8, value: u8) -> MidiMessage {
+        MidiMessage::ControlChange(Channel::C1, Control::new(control), Value7::new(value))
+    }
+
+    fn program(program: u8) -> MidiMessage {
+        MidiMessage::ProgramChange(Channel::C1, program.into())
+    }
+
+    fn drain<const N: usize>(output: &mut ThrottledOutput<N>) -> Vec<MidiMessage> {
+        (1..=N as u32)
+            .filter_map(|index| output.poll(index * 10_000))
+            .collect()
+    }
+
+    #[test]
+    fn should_refuse_messages_when_full() {
+        let mut output = ThrottledOutput::<2>::default();
+        output.push(cc(123, 0)).unwrap();
+        output.push(control(10)).unwrap();
+
+        assert_eq!(output.push(NOTE), Err(NOTE));
+        assert_eq!(
+            output.push(MidiMessage::TimingClock),
+            Err(MidiMessage::TimingClock)
+        );
+        assert_eq!(output.push(control(20)), Ok(()));
+        assert_eq!(drain(&mut output), [cc(123, 0), control(20)]);
+    }
+
+    #[test]
+    fn should_keep_bank_selects_with_their_program_changes() {
+        let mut output = ThrottledOutput::<8>::default();
+        let messages = [cc(0, 1), program(5), cc(0, 2), program(7)];
+        for message in messages {
+            output.push(message).unwrap();
+        }
+        assert_eq!(drain(&mut output), messages);
+
+        // Continuous controllers are not replaced across a program change either
+        let messages = [control(10), program(5), control(20)];
+        for message in messages {
+            output.push(message).unwrap();
+        }
+        assert_eq!(drain(&mut output), messages);
+        assert_eq!(output.thinned(), 0);
+    }
+
+    #[test]
+    fn should_keep_parameter_number_sequences() {
+        let mut output = ThrottledOutput::<8>::default();
+        let messages = [
+            cc(101, 0),
+            cc(100, 0),
+            cc(6, 12),
+            cc(101, 0),
+            cc(100, 1),
+            cc(6, 64),
+        ];
+        for message in messages {
+            output.push(message).unwrap();
+        }
+        assert_eq!(drain(&mut output), messages);
+    }
+
+    #[test]
+    fn should_send_after_long_idle_times() {
+        let mut output = ThrottledOutput::<4>::default();
+        output.push(NOTE).unwrap();
+        assert_eq!(output.poll(0), Some(NOTE));
+
+        // Not polled while idle
+        output.push(NOTE).unwrap();
+        assert_eq!(output.poll(960 + 0x8000_0000), Some(NOTE));
+
+        // Polled while idle with an empty queue
+        assert_eq!(output.poll(1920 + 0x8000_0000), None);
+        output.push(NOTE).unwrap();
+        assert_eq!(output.poll(0x8000_0000u32.wrapping_mul(2)), Some(NOTE));
+    }
+
+    #[test]
+    fn should_send_active_sensing_when_idle() {
+        let mut output = ThrottledOutput::<4>::default();
+        assert_eq!(output.poll(300_000), None);
+
+        output.set_active_sensing(Some(ACTIVE_SENSING_INTERVAL));
+        output.push(NOTE).unwrap();
+        assert_eq!(output.poll(300_000), Some(NOTE));
+        assert_eq!(output.poll(300_960 + 269_999), None);
+        assert_eq!(
+            output.poll(300_960 + 270_000),
+            Some(MidiMessage::ActiveSensing)
+        );
+        assert_eq!(output.poll(600_000), None);
+    }
+}
@@ -0,0 +1,368 @@
+//! Converting between notes and DAC or ADC codes for control voltages
+
+use crate::midi_types::{Channel, MidiMessage, Note, Value14, Value7};
+use crate::tuning::TuningTable;
+use core::convert::TryFrom;
+
+/// 2^(n/12) for every semitone of an octave in 16.16 fixed point
+const SEMITONES: [u32; 13] = [
+    65536, 69433, 73562, 77936, 82570, 87480, 92682, 98193, 104032, 110218, 116772, 123715, 131072,
+];
+
+/// How a pitch is represented as a voltage
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CvScale {
+    /// Every octave adds one volt, `codes_per_volt` is the calibrated number of DAC codes for a
+    /// volt
+    VoltPerOctave { codes_per_volt: u32 },
+
+    /// The voltage is proportional to the frequency, every octave doubles the voltage
+    HertzPerVolt,
+}
+
+/// The number of DAC codes for a volt on a DAC with a resolution of `bits` and a full scale
+/// output of `full_scale_millivolts`
+pub fn codes_per_volt(bits: u8, full_scale_millivolts: u32) -> u32 {
+    let codes = 1u64 << bits.min(32);
+    (codes * 1000 / full_scale_millivolts.max(1) as u64) as u32
+}
+
+/// Converts notes with pitch bend and tuning into DAC codes using integer math
+///
+/// `base_code` is the calibrated code for `base_note`, other notes are relative to that. Codes are
+/// clamped to the range of the DAC.
+#[derive(Debug, Clone)]
+pub struct CvConverter {
+    scale: CvScale,
+    max_code: u32,
+    base_note: Note,
+    base_code: u32,
+    bend_range: u8,
+    tuning: i16,
+}
+
+impl CvConverter {
+    /// Create a converter for a DAC with a resolution of `bits`, up to 32 bits
+    pub fn new(scale: CvScale, bits: u8, base_note: Note, base_code: u32) -> Self {
+        CvConverter {
+            scale,
+            max_code: u32::MAX >> 32u8.saturating_sub(bits.min(32)),
+            base_note,
+            base_code,
+            bend_range: 2,
+            tuning: 0,
+        }
+    }
+
+    /// Set the pitch bend range in semitones
+    pub fn set_bend_range(&mut self, semitones: u8) {
+        self.bend_range = semitones;
+    }
+
+    /// Tune all notes by a number of cents
+    pub fn set_tuning(&mut self, cents: i16) {
+        self.tuning = cents;
+    }
+
+    /// The code for a note without pitch bend
+    pub fn note_code(&self, note: Note) -> u32 {
+        self.code(note, Value14::from(0i16))
+    }
+
+    /// The code for a note bent by a pitch bend value
+    pub fn code(&self, note: Note, bend: Value14) -> u32 {
+        let bend = i16::from(bend) as i32 * self.bend_range as i32 * 100 / 8192;
+        self.cents_code(u8::from(note) as i32 * 100 + bend)
+    }
+
+    /// The code for a note tuned by a tuning table and bent by a pitch bend value
+    pub fn tuned_code(&self, table: &TuningTable, note: Note, bend: Value14) -> u32 {
+        let bend = i16::from(bend) as i32 * self.bend_range as i32 * 100 / 8192;
+        self.cents_code(table.cents(note) + bend)
+    }
+
+    /// The code for a pitch in cents, note 0 is at 0 cents
+    pub fn cents_code(&self, cents: i32) -> u32 {
+        let cents = cents as i64 - u8::from(self.base_note) as i64 * 100 + self.tuning as i64;
+
+        let code = match self.scale {
+            CvScale::VoltPerOctave { codes_per_volt } => {
+                self.base_code as i64 + cents * codes_per_volt as i64 / 1200
+            }
+            CvScale::HertzPerVolt => {
+                let code = (self.base_code as u64).saturating_mul(exp2_cents(cents)) >> 16;
+                code.min(self.max_code as u64) as i64
+            }
+        };
+        code.clamp(0, self.max_code as i64) as u32
+    }
+
+    /// The pitch in cents for a code, the inverse of `cents_code` for pitches from note 0 to 127
+    pub fn code_cents(&self, code: u32) -> i32 {
+        let first = self.first_cents(|found| found >= code);
+        let past = self.first_cents(|found| found > code);
+
+        if first < past {
+            // Several pitches give this code, use the one in the middle
+            return (first + past - 1) / 2;
+        }
+
+        let below = (first - 1).max(0);
+        let above = first.min(12700);
+        if self.cents_code(above).abs_diff(code) < self.cents_code(below).abs_diff(code) {
+            above
+        } else {
+            below
+        }
+    }
+
+    /// The lowest pitch from 0 to 12700 cents with a code matching `predicate`, 12701 if there is
+    /// none. Codes rise with the pitch so this is a binary search.
+    fn first_cents(&self, predicate: impl Fn(u32) -> bool) -> i32 {
+        let (mut low, mut high) = (0, 12701);
+        while low < high {
+            let middle = (low + high) / 2;
+            if predicate(self.cents_code(middle)) {
+                high = middle;
+            } else {
+                low = middle + 1;
+            }
+        }
+        low
+    }
+}
+
+/// Converts sampled pitch control voltages into notes, for a pitch and gate input
+///
+/// The pitch is quantized to the nearest note, the note only changes when the pitch moves more
+/// than the hysteresis past the middle between two notes. The rest of the pitch can be sent as
+/// pitch bend.
+#[derive(Debug, Clone)]
+pub struct CvToMidi {
+    converter: CvConverter,
+    channel: Channel,
+    velocity: Value7,
+    hysteresis: u16,
+    bend: bool,
+    gate: bool,
+    cents: i32,
+    note: Option<Note>,
+    sent_bend: Option<i16>,
+}
+
+impl CvToMidi {
+    /// Create a converter reading codes calibrated like the codes of `converter`, the pitch bend
+    /// range of `converter` is used for the pitch bend sent
+    pub fn new(converter: CvConverter, channel: Channel) -> Self {
+        CvToMidi {
+            converter,
+            channel,
+            velocity: Value7::new(100),
+            hysteresis: 10,
+            bend: false,
+            gate: false,
+            cents: 0,
+            note: None,
+            sent_bend: None,
+        }
+    }
+
+    pub fn set_velocity(&mut self, velocity: Value7) {
+        self.velocity = velocity;
+    }
+
+    /// Set how many cents the pitch has to move past the middle between two notes to change notes
+    pub fn set_hysteresis(&mut self, cents: u16) {
+        self.hysteresis = cents;
+    }
+
+    /// Send the difference between the pitch and the note as pitch bend
+    pub fn set_pitch_bend(&mut self, bend: bool) {
+        self.bend = bend;
+    }
+
+    /// The note that is playing
+    pub fn note(&self) -> Option<Note> {
+        self.note
+    }
+
+    /// Handle a new pitch reading, changes the playing note when the gate is high
+    pub fn update(&mut self, code: u32, mut emit: impl FnMut(MidiMessage)) {
+        self.cents = self.converter.code_cents(code);
+        if !self.gate {
+            return;
+        }
+
+        if let Some(note) = self.note {
+            let offset = self.cents - u8::from(note) as i32 * 100;
+            if offset.unsigned_abs() > 50 + self.hysteresis as u32 {
+                // The new note starts before the old note ends so mono synths can slide
+                let new = self.nearest();
+                emit(MidiMessage::NoteOn(self.channel, new, self.velocity));
+                emit(MidiMessage::NoteOff(self.channel, note, Value7::new(0)));
+                self.note = Some(new);
+            }
+        }
+        self.send_bend(&mut emit);
+    }
+
+    /// Handle the gate input, starting a note at the current pitch when it goes high
+    pub fn set_gate(&mut self, gate: bool, mut emit: impl FnMut(MidiMessage)) {
+        if gate == self.gate {
+            return;
+        }
+        self.gate = gate;
+
+        if gate {
+            let note = self.nearest();
+            self.send_bend(&mut emit);
+            emit(MidiMessage::NoteOn(self.channel, note, self.velocity));
+            self.note = Some(note);
+        } else if let Some(note) = self.note.take() {
+            emit(MidiMessage::NoteOff(self.channel, note, Value7::new(0)));
+        }
+    }
+
+    fn nearest(&self) -> Note {
+        Note::new(((self.cents + 50) / 100).clamp(0, 127) as u8)
+    }
+
+    fn send_bend(&mut self, emit: &mut impl FnMut(MidiMessage)) {
+        if !self.bend {
+            return;
+        }
+
+        let note = self.note.unwrap_or_else(|| self.nearest());
+        let offset = (self.cents - u8::from(note) as i32 * 100) as i64;
+        let range = (self.converter.bend_range as i64 * 100).max(1);
+        let bend = (offset * 8192 / range).clamp(-8192, 8191) as i16;
+
+        if self.sent_bend != Some(bend) {
+            self.sent_bend = Some(bend);
+            emit(MidiMessage::PitchBendChange(self.channel, bend.into()));
+        }
+    }
+}
+
+/// 2^(cents/1200) in 16.16 fixed point, interpolating linearly between semitones
+pub(crate) fn exp2_cents(cents: i64) -> u64 {
+    let octaves = cents.div_euclid(1200);
+    let cents = cents.rem_euclid(1200);
+    let semitone = (cents / 100) as usize;
+    let fraction = (cents % 100) as u64;
+
+    let low = SEMITONES.get(semitone).copied().unwrap_or_default() as u64;
+    let high = SEMITONES.get(semitone + 1).copied().unwrap_or_default() as u64;
+    let ratio = low + (high - low) * fraction / 100;
+
+    match octaves {
+        // Saturate instead of shifting bits out
+        octaves if octaves >= 0 => u32::try_from(octaves)
+            .ok()
+            .filter(|octaves| *octaves <= ratio.leading_zeros())
+            .and_then(|octaves| ratio.checked_shl(octaves))
+            .unwrap_or(u64::MAX),
+        octaves => ratio
+            .checked_shr(octaves.unsigned_abs() as u32)
+            .unwrap_or(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::vec::Vec;
+
+    #[test]
+    fn should_convert_volt_per_octave() {
+        // A 16 bit DAC with a 10 volt output, 0 volt is note 24
+        let codes_per_volt = codes_per_volt(16, 10_000);
+        assert_eq!(codes_per_volt, 6553);
+
+        let mut cv = CvConverter::new(CvScale::VoltPerOctave { codes_per_volt }, 16, 24.into(), 0);
+        assert_eq!(cv.note_code(24.into()), 0);
+        assert_eq!(cv.note_code(36.into()), 6553);
+        assert_eq!(cv.note_code(30.into()), 3276);
+        assert_eq!(cv.note_code(12.into()), 0);
+        assert_eq!(cv.note_code(127.into()), 56246);
+
+        cv.set_tuning(-50);
+        assert_eq!(cv.code(36.into(), 2048i16.into()), 6553);
+    }
+
+    #[test]
+    fn should_convert_codes_to_cents() {
+        let volt_per_octave = CvConverter::new(
+            CvScale::VoltPerOctave {
+                codes_per_volt: 6553,
+            },
+            16,
+            24.into(),
+            0,
+        );
+        let hertz_per_volt = CvConverter::new(CvScale::HertzPerVolt, 12, 48.into(), 1000);
+
+        for cents in [2500, 3650, 6000, 9999].iter() {
+            let code = volt_per_octave.cents_code(*cents);
+            assert_eq!(volt_per_octave.code_cents(code), *cents);
+        }
+        for cents in [4800, 6000, 7000].iter() {
+            let code = hertz_per_volt.cents_code(*cents);
+            assert!((hertz_per_volt.code_cents(code) - cents).abs() <= 1);
+        }
+    }
+
+    #[test]
+    fn should_quantize_pitch_to_notes() {
+        let converter = CvConverter::new(
+            CvScale::VoltPerOctave {
+                codes_per_volt: 1200,
+            },
+            16,
+            0.into(),
+            0,
+        );
+        let mut cv = CvToMidi::new(converter, Channel::C1);
+        cv.set_pitch_bend(true);
+
+        let mut messages = Vec::new();
+        cv.update(6020, |message| messages.push(message));
+        assert_eq!(messages, []);
+        cv.set_gate(true, |message| messages.push(message));
+        // Within the hysteresis the note does not change
+        cv.update(6055, |message| messages.push(message));
+        cv.update(6070, |message| messages.push(message));
+        cv.set_gate(false, |message| messages.push(message));
+
+        assert_eq!(
+            messages,
+            [
+                MidiMessage::PitchBendChange(Channel::C1, 819i16.into()),
+                MidiMessage::NoteOn(Channel::C1, 60.into(), 100.into()),
+                MidiMessage::PitchBendChange(Channel::C1, 2252i16.into()),
+                MidiMessage::NoteOn(Channel::C1, 61.into(), 100.into()),
+                MidiMessage::NoteOff(Channel::C1, 60.into(), 0.into()),
+                MidiMessage::PitchBendChange(Channel::C1, (-1228i16).into()),
+                MidiMessage::NoteOff(Channel::C1, 61.into(), 0.into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_convert_hertz_per_volt() {
+        let cv = CvConverter::new(CvScale::HertzPerVolt, 12, 48.into(), 1000);
+
+        assert_eq!(cv.note_code(48.into()), 1000);
+        assert_eq!(cv.note_code(60.into()), 2000);
+        assert_eq!(cv.note_code(36.into()), 500);
+        assert_eq!(cv.note_code(55.into()), 1498);
+        assert_eq!(cv.note_code(96.into()), 4095);
+
+        // Large pitches saturate
+        let cv = CvConverter::new(CvScale::HertzPerVolt, 16, 0.into(), u32::MAX);
+        assert_eq!(cv.cents_code(48000), 0xFFFF);
+        assert_eq!(exp2_cents(1200 * 47), 1 << 63);
+        assert_eq!(exp2_cents(1200 * 48), u64::MAX);
+    }
+}
@@ -0,0 +1,133 @@
+//! Suppressing duplicate note-ons from senders that trigger notes twice
+
+use crate::midi_types::{Channel, MidiMessage, Note, Value7};
+use crate::process::Processor;
+
+/// What to do with a note-on for a note that is already sounding
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicatePolicy {
+    /// Drop the note-on
+    #[default]
+    Drop,
+
+    /// End the sounding note and play it again, so there is never more than one note-on
+    /// without a note-off
+    Retrigger,
+}
+
+/// Drops or retriggers duplicate note-ons, keeping track of up to `N` sounding notes
+///
+/// When more than `N` notes are sounding, note-ons for notes that are not tracked are passed on.
+/// Note-offs are always passed on.
+#[derive(Debug, Clone)]
+pub struct DuplicateFilter<const N: usize> {
+    policy: DuplicatePolicy,
+    sounding: [Option<(Channel, Note)>; N],
+}
+
+impl<const N: usize> Default for DuplicateFilter<N> {
+    fn default() -> Self {
+        Self::new(DuplicatePolicy::default())
+    }
+}
+
+impl<const N: usize> DuplicateFilter<N> {
+    pub fn new(policy: DuplicatePolicy) -> Self {
+        DuplicateFilter {
+            policy,
+            sounding: [None; N],
+        }
+    }
+
+    pub fn policy(&self) -> DuplicatePolicy {
+        self.policy
+    }
+
+    pub fn set_policy(&mut self, policy: DuplicatePolicy) {
+        self.policy = policy;
+    }
+
+    /// True when a note-on for the note was passed on and it was not released yet
+    pub fn is_sounding(&self, channel: Channel, note: Note) -> bool {
+        self.sounding.contains(&Some((channel, note)))
+    }
+
+    /// Forget all sounding notes, for instance after sending all notes off
+    pub fn clear(&mut self) {
+        self.sounding = [None; N];
+    }
+
+    fn note_on(
+        &mut self,
+        channel: Channel,
+        note: Note,
+        velocity: Value7,
+        emit: &mut impl FnMut(MidiMessage),
+    ) {
+        if self.is_sounding(channel, note) {
+            match self.policy {
+                DuplicatePolicy::Drop => return,
+                DuplicatePolicy::Retrigger => {
+                    emit(MidiMessage::NoteOff(channel, note, Value7::new(0)))
+                }
+            }
+        } else if let Some(slot) = self.sounding.iter_mut().find(|slot| slot.is_none()) {
+            *slot = Some((channel, note));
+        }
+
+        emit(MidiMessage::NoteOn(channel, note, velocity));
+    }
+
+    fn note_off(&mut self, channel: Channel, note: Note) {
+        if let Some(slot) = self
+            .sounding
+            .iter_mut()
+            .find(|slot| **slot == Some((channel, note)))
+        {
+            *slot = None;
+        }
+    }
+}
+
+impl<const N: usize> Processor for DuplicateFilter<N> {
+    fn process(&mut self, message: MidiMessage, emit: &mut impl FnMut(MidiMessage)) {
+        match message {
+            MidiMessage::NoteOn(channel, note, velocity) if u8::from(velocity) > 0 => {
+                self.note_on(channel, note, velocity, emit)
+            }
+            MidiMessage::NoteOn(channel, note, _) | MidiMessage::NoteOff(channel, note, _) => {
+                self.note_off(channel, note);
+                emit(message);
+            }
+            message => emit(message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{off, on, process};
+
+    #[test]
+    fn should_drop_duplicate_note_ons() {
+        let mut filter = DuplicateFilter::<4>::new(DuplicatePolicy::Drop);
+
+        assert_eq!(
+            process(&mut filter, &[on(60), on(60), on(62), off(60), on(60)]),
+            [on(60), on(62), off(60), on(60)]
+        );
+        assert!(filter.is_sounding(Channel::C1, 62.into()));
+    }
+
+    #[test]
+    fn should_retrigger_duplicate_note_ons() {
+        let mut filter = DuplicateFilter::<4>::new(DuplicatePolicy::Retrigger);
+
+        assert_eq!(
+            process(&mut filter, &[on(60), on(60), off(60)]),
+            [on(60), off(60), on(60), off(60)]
+        );
+        assert!(!filter.is_sounding(Channel::C1, 60.into()));
+    }
+}
@@ -0,0 +1,227 @@
+//! Measuring how long notes are held
+
+use crate::midi_types::{Channel, MidiMessage, Note, Value7};
+use crate::notes::NoteOffPolicy;
+
+/// A note that was played and released
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NoteDuration {
+    pub channel: Channel,
+    pub note: Note,
+    pub velocity: Value7,
+    /// The time of the note-on
+    pub start: u32,
+    /// The time between the note-on and the note-off
+    pub duration: u32,
+}
+
+/// Pairs note-ons with their note-offs to measure the duration of up to `N` held notes
+///
+/// Times are in any unit, like clock ticks or microseconds, and may wrap around. When more than
+/// `N` notes are held the oldest note is forgotten. The note-off policy decides which press of a
+/// note that is pressed again before it is released a note-off ends.
+#[derive(Debug, Clone)]
+pub struct DurationTracker<const N: usize> {
+    /// Held notes ordered from the oldest to the most recent press
+    held: [Option<Press>; N],
+    policy: NoteOffPolicy,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Press {
+    channel: Channel,
+    note: Note,
+    velocity: Value7,
+    start: u32,
+    /// The number of note-ons counted with the `Count` policy
+    count: u8,
+}
+
+impl Press {
+    fn is(&self, channel: Channel, note: Note) -> bool {
+        self.channel == channel && self.note == note
+    }
+}
+
+impl<const N: usize> Default for DurationTracker<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> DurationTracker<N> {
+    pub const fn new() -> Self {
+        DurationTracker {
+            held: [None; N],
+            policy: NoteOffPolicy::FirstOff,
+        }
+    }
+
+    pub fn set_note_off_policy(&mut self, policy: NoteOffPolicy) {
+        self.policy = policy;
+    }
+
+    /// The number of notes that are held
+    pub fn len(&self) -> usize {
+        self.held.iter().flatten().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Forget all held notes
+    pub fn clear(&mut self) {
+        self.held = [None; N];
+    }
+
+    /// Register a note-on at a time
+    pub fn note_on(&mut self, channel: Channel, note: Note, velocity: Value7, now: u32) {
+        if self.policy == NoteOffPolicy::Count {
+            if let Some(press) = self.find_mut(channel, note) {
+                press.count = press.count.saturating_add(1);
+                return;
+            }
+        }
+
+        if self.held.last().map_or(false, Option::is_some) {
+            self.remove(0);
+        }
+        if let Some(slot) = self.held.iter_mut().find(|slot| slot.is_none()) {
+            *slot = Some(Press {
+                channel,
+                note,
+                velocity,
+                start: now,
+                count: 1,
+            });
+        }
+    }
+
+    /// Register a note-off at a time, returns `None` when the note was not held or is still held
+    /// by another press
+    pub fn note_off(&mut self, channel: Channel, note: Note, now: u32) -> Option<NoteDuration> {
+        let index = self.policy.release(&self.held, |slot| {
+            slot.map_or(false, |press| press.is(channel, note))
+        })?;
+
+        let press = self.held.get_mut(index)?.as_mut()?;
+        press.count = press.count.saturating_sub(1);
+        if press.count > 0 {
+            return None;
+        }
+
+        let press = self.remove(index)?;
+        Some(NoteDuration {
+            channel,
+            note,
+            velocity: press.velocity,
+            start: press.start,
+            duration: now.wrapping_sub(press.start),
+        })
+    }
+
+    /// The time a held note has been held so far, since its earliest press
+    pub fn elapsed(&self, channel: Channel, note: Note, now: u32) -> Option<u32> {
+        self.held
+            .iter()
+            .flatten()
+            .find(|press| press.is(channel, note))
+            .map(|press| now.wrapping_sub(press.start))
+    }
+
+    /// Handle a received message at a time, returns the duration of released notes
+    pub fn handle(&mut self, message: &MidiMessage, now: u32) -> Option<NoteDuration> {
+        match *message {
+            MidiMessage::NoteOn(channel, note, velocity) if u8::from(velocity) > 0 => {
+                self.note_on(channel, note, velocity, now);
+                None
+            }
+            MidiMessage::NoteOn(channel, note, _) | MidiMessage::NoteOff(channel, note, _) => {
+                self.note_off(channel, note, now)
+            }
+            _ => None,
+        }
+    }
+
+    fn find_mut(&mut self, channel: Channel, note: Note) -> Option<&mut Press> {
+        self.held
+            .iter_mut()
+            .flatten()
+            .find(|press| press.is(channel, note))
+    }
+
+    /// Remove a press, keeping the others in order
+    fn remove(&mut self, index: usize) -> Option<Press> {
+        let press = self.held.get_mut(index)?.take();
+        if let Some(held) = self.held.get_mut(index..) {
+            held.rotate_left(1);
+        }
+        press
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::on;
+
+    fn off(note: u8) -> MidiMessage {
+        MidiMessage::NoteOn(Channel::C1, note.into(), 0.into())
+    }
+
+    #[test]
+    fn should_measure_note_durations() {
+        let mut tracker = DurationTracker::<4>::new();
+
+        assert_eq!(tracker.handle(&on(60), 10), None);
+        assert_eq!(tracker.handle(&on(64), 20), None);
+        assert_eq!(tracker.elapsed(Channel::C1, 60.into(), 25), Some(15));
+        assert_eq!(
+            tracker.handle(&off(60), 40),
+            Some(NoteDuration {
+                channel: Channel::C1,
+                note: 60.into(),
+                velocity: 100.into(),
+                start: 10,
+                duration: 30,
+            })
+        );
+        assert_eq!(tracker.handle(&off(60), 50), None);
+        assert_eq!(tracker.len(), 1);
+    }
+
+    #[test]
+    fn should_forget_oldest_note_when_full() {
+        let mut tracker = DurationTracker::<2>::new();
+
+        tracker.handle(&on(60), u32::MAX - 5);
+        tracker.handle(&on(62), 0);
+        tracker.handle(&on(64), 10);
+
+        assert_eq!(tracker.handle(&off(60), 20), None);
+        assert_eq!(
+            tracker.handle(&off(62), 20).map(|note| note.duration),
+            Some(20)
+        );
+        assert_eq!(
+            tracker.handle(&off(64), 20).map(|note| note.duration),
+            Some(10)
+        );
+    }
+
+    #[test]
+    fn should_pair_repeated_presses_by_policy() {
+        let durations = |policy| {
+            let mut tracker = DurationTracker::<4>::new();
+            tracker.set_note_off_policy(policy);
+            tracker.handle(&on(60), 0);
+            tracker.handle(&on(60), 10);
+            [20, 40].map(|now| tracker.handle(&off(60), now).map(|note| note.duration))
+        };
+
+        assert_eq!(durations(NoteOffPolicy::FirstOff), [Some(20), Some(30)]);
+        assert_eq!(durations(NoteOffPolicy::LastOff), [Some(10), Some(40)]);
+        assert_eq!(durations(NoteOffPolicy::Count), [None, Some(40)]);
+    }
+}
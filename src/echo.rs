@@ -0,0 +1,191 @@
+//! Echo effect repeating notes after a delay
+//!
+//! The delay is in the unit used to advance the echo, advancing by clock ticks syncs the echo to
+//! the midi clock while advancing by elapsed milliseconds gives a free delay time.
+
+use crate::midi_types::{Channel, MidiMessage, Note, Value7};
+use crate::process::Processor;
+use crate::scheduler::Scheduler;
+
+/// Repeats notes with decaying velocity, using a scheduler holding up to `N` pending messages
+#[derive(Debug, Clone)]
+pub struct Echo<const N: usize> {
+    scheduler: Scheduler<N>,
+    delay: u32,
+    repeats: u8,
+    decay: Value7,
+    now: u32,
+}
+
+impl<const N: usize> Echo<N> {
+    /// Create an echo repeating notes `repeats` times, every repeat has its velocity scaled by
+    /// `decay / 127`
+    pub fn new(delay: u32, repeats: u8, decay: Value7) -> Self {
+        Echo {
+            scheduler: Scheduler::new(),
+            delay,
+            repeats,
+            decay,
+            now: 0,
+        }
+    }
+
+    pub fn delay(&self) -> u32 {
+        self.delay
+    }
+
+    /// Change the delay, pending repeats keep their time
+    pub fn set_delay(&mut self, delay: u32) {
+        self.delay = delay;
+    }
+
+    pub fn set_repeats(&mut self, repeats: u8) {
+        self.repeats = repeats;
+    }
+
+    pub fn set_decay(&mut self, decay: Value7) {
+        self.decay = decay;
+    }
+
+    /// The number of pending repeats
+    pub fn pending(&self) -> usize {
+        self.scheduler.len()
+    }
+
+    /// Advance time and emit the repeats that are due
+    pub fn advance(&mut self, elapsed: u32, emit: impl FnMut(MidiMessage)) {
+        self.now = self.now.wrapping_add(elapsed);
+        self.scheduler.poll(self.now, emit);
+    }
+
+    /// Advance time by one clock tick
+    pub fn tick(&mut self, emit: impl FnMut(MidiMessage)) {
+        self.advance(1, emit);
+    }
+
+    /// Drop all pending repeats, sending note-off messages for notes that could still be
+    /// sounding
+    pub fn flush(&mut self, mut emit: impl FnMut(MidiMessage)) {
+        self.scheduler.cancel(|message| {
+            if let MidiMessage::NoteOff(..) = message {
+                emit(*message);
+            }
+            true
+        });
+    }
+
+    fn repeat_note_on(&mut self, channel: Channel, note: Note, velocity: Value7) {
+        let mut velocity = u8::from(velocity) as u32;
+
+        for repeat in 1..=self.repeats as u32 {
+            velocity = velocity * u8::from(self.decay) as u32 / 127;
+            if velocity == 0 {
+                break;
+            }
+
+            let time = self.now.wrapping_add(self.delay.wrapping_mul(repeat));
+            let message = MidiMessage::NoteOn(channel, note, Value7::new(velocity as u8));
+            if self.scheduler.schedule(time, message).is_err() {
+                break;
+            }
+        }
+    }
+
+    fn repeat_note_off(
+        &mut self,
+        channel: Channel,
+        note: Note,
+        velocity: Value7,
+        emit: &mut impl FnMut(MidiMessage),
+    ) {
+        for repeat in 1..=self.repeats as u32 {
+            let time = self.now.wrapping_add(self.delay.wrapping_mul(repeat));
+            let message = MidiMessage::NoteOff(channel, note, velocity);
+
+            if self.scheduler.schedule(time, message).is_err() {
+                // Without room for the note-off, drop the pending repeats of this note and end
+                // the ones that already sound so no notes get stuck
+                self.scheduler.cancel(|pending| {
+                    matches!(*pending, MidiMessage::NoteOn(c, n, _) if c == channel && n == note)
+                });
+                emit(message);
+                break;
+            }
+        }
+    }
+}
+
+impl<const N: usize> Processor for Echo<N> {
+    fn process(&mut self, message: MidiMessage, emit: &mut impl FnMut(MidiMessage)) {
+        emit(message);
+
+        match message {
+            MidiMessage::NoteOn(channel, note, velocity) if u8::from(velocity) > 0 => {
+                self.repeat_note_on(channel, note, velocity)
+            }
+            MidiMessage::NoteOn(channel, note, velocity)
+            | MidiMessage::NoteOff(channel, note, velocity) => {
+                self.repeat_note_off(channel, note, velocity, emit)
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::vec::Vec;
+
+    fn note_on(velocity: u8) -> MidiMessage {
+        MidiMessage::NoteOn(Channel::C1, Note::C4, velocity.into())
+    }
+
+    fn note_off() -> MidiMessage {
+        MidiMessage::NoteOff(Channel::C1, Note::C4, 0.into())
+    }
+
+    fn run<const N: usize>(echo: &mut Echo<N>, ticks: u32) -> Vec<(u32, MidiMessage)> {
+        let mut messages = Vec::new();
+        for tick in 1..=ticks {
+            echo.tick(|message| messages.push((tick, message)));
+        }
+        messages
+    }
+
+    #[test]
+    fn should_repeat_notes_with_decaying_velocity() {
+        let mut echo = Echo::<8>::new(10, 2, Value7::new(64));
+
+        let mut output = Vec::new();
+        echo.process(note_on(100), &mut |message| output.push(message));
+        assert_eq!(run(&mut echo, 2), []);
+        echo.process(note_off(), &mut |message| output.push(message));
+        assert_eq!(output, [note_on(100), note_off()]);
+
+        // Two ticks have passed since the note-on
+        assert_eq!(
+            run(&mut echo, 20),
+            [
+                (8, note_on(50)),
+                (10, note_off()),
+                (18, note_on(25)),
+                (20, note_off()),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_not_leave_stuck_notes_when_full() {
+        let mut echo = Echo::<3>::new(10, 3, Value7::new(127));
+
+        let mut output = Vec::new();
+        echo.process(note_on(100), &mut |message| output.push(message));
+        assert_eq!(echo.pending(), 3);
+
+        echo.process(note_off(), &mut |message| output.push(message));
+        assert_eq!(output, [note_on(100), note_off(), note_off()]);
+        assert_eq!(run(&mut echo, 40), []);
+    }
+}
@@ -0,0 +1,236 @@
+//! Relative control change encodings used by endless encoders
+
+use crate::midi_types::{Channel, Control, MidiMessage, Value7};
+
+/// How a relative control change value encodes an increment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelativeEncoding {
+    /// 7 bit two's complement, 1 is +1 and 127 is -1
+    TwosComplement,
+
+    /// Offset from 64, 65 is +1 and 63 is -1
+    BinaryOffset,
+
+    /// Bit 6 is the sign, 1 is +1 and 65 is -1
+    SignMagnitude,
+}
+
+impl RelativeEncoding {
+    /// Decode a control change value into an increment
+    pub fn decode(self, value: Value7) -> i8 {
+        let value = u8::from(value);
+        match self {
+            RelativeEncoding::TwosComplement => ((value << 1) as i8) >> 1,
+            RelativeEncoding::BinaryOffset => value as i8 - 64,
+            RelativeEncoding::SignMagnitude if value & 0x40 != 0 => -((value & 0x3f) as i8),
+            RelativeEncoding::SignMagnitude => value as i8,
+        }
+    }
+
+    /// Encode an increment into a control change value, increments outside of the range of the
+    /// encoding are clamped
+    pub fn encode(self, increment: i8) -> Value7 {
+        let value = match self {
+            RelativeEncoding::TwosComplement => increment.clamp(-64, 63) as u8 & 0x7f,
+            RelativeEncoding::BinaryOffset => (increment.clamp(-64, 63) + 64) as u8,
+            RelativeEncoding::SignMagnitude if increment < 0 => {
+                0x40 | increment.max(-63).unsigned_abs()
+            }
+            RelativeEncoding::SignMagnitude => increment.min(63) as u8,
+        };
+        Value7::new(value)
+    }
+}
+
+/// Decodes relative control changes into increments with an encoding per controller number
+#[derive(Debug, Clone)]
+pub struct RelativeDecoder {
+    encodings: [Option<RelativeEncoding>; 128],
+}
+
+impl Default for RelativeDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RelativeDecoder {
+    /// Create a decoder without relative controls
+    pub fn new() -> Self {
+        RelativeDecoder {
+            encodings: [None; 128],
+        }
+    }
+
+    pub fn encoding(&self, control: Control) -> Option<RelativeEncoding> {
+        self.encodings
+            .get(u8::from(control) as usize)
+            .copied()
+            .flatten()
+    }
+
+    /// Set the encoding of a controller, `None` makes it an absolute control
+    pub fn set_encoding(&mut self, control: Control, encoding: Option<RelativeEncoding>) {
+        if let Some(slot) = self.encodings.get_mut(u8::from(control) as usize) {
+            *slot = encoding;
+        }
+    }
+
+    /// Decode a control change from a relative control into an increment
+    pub fn decode(&self, message: &MidiMessage) -> Option<(Channel, Control, i8)> {
+        match *message {
+            MidiMessage::ControlChange(channel, control, value) => {
+                let encoding = self.encoding(control)?;
+                Some((channel, control, encoding.decode(value)))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Sends relative control changes for the detents an encoder is turned
+///
+/// Acceleration multiplies the increment when the encoder is turned again within an interval,
+/// times are in the unit used to advance the encoder.
+#[derive(Debug, Clone)]
+pub struct RelativeEncoder {
+    channel: Channel,
+    control: Control,
+    encoding: RelativeEncoding,
+    interval: u32,
+    factor: u8,
+    since_turn: u32,
+}
+
+impl RelativeEncoder {
+    pub fn new(channel: Channel, control: Control, encoding: RelativeEncoding) -> Self {
+        RelativeEncoder {
+            channel,
+            control,
+            encoding,
+            interval: 0,
+            factor: 1,
+            since_turn: u32::MAX,
+        }
+    }
+
+    pub fn encoding(&self) -> RelativeEncoding {
+        self.encoding
+    }
+
+    pub fn set_encoding(&mut self, encoding: RelativeEncoding) {
+        self.encoding = encoding;
+    }
+
+    /// Multiply increments by `factor` when turns follow each other within `interval`, a factor
+    /// of 1 disables acceleration
+    pub fn set_acceleration(&mut self, interval: u32, factor: u8) {
+        self.interval = interval;
+        self.factor = factor.max(1);
+    }
+
+    /// Advance time used for acceleration
+    pub fn advance(&mut self, elapsed: u32) {
+        self.since_turn = self.since_turn.saturating_add(elapsed);
+    }
+
+    /// Turn the encoder by a number of detents, sends nothing when `detents` is zero
+    pub fn turn(&mut self, detents: i32, mut emit: impl FnMut(MidiMessage)) {
+        if detents == 0 {
+            return;
+        }
+
+        let accelerated = self.since_turn < self.interval;
+        self.since_turn = 0;
+
+        let factor = if accelerated { self.factor as i32 } else { 1 };
+        let increment = detents.saturating_mul(factor).clamp(-64, 63) as i8;
+        emit(MidiMessage::ControlChange(
+            self.channel,
+            self.control,
+            self.encoding.encode(increment),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_decode_encodings() {
+        let cases = [
+            (
+                RelativeEncoding::TwosComplement,
+                [(1, 1), (63, 63), (127, -1), (64, -64)],
+            ),
+            (
+                RelativeEncoding::BinaryOffset,
+                [(65, 1), (127, 63), (63, -1), (0, -64)],
+            ),
+            (
+                RelativeEncoding::SignMagnitude,
+                [(1, 1), (63, 63), (65, -1), (127, -63)],
+            ),
+        ];
+
+        for (encoding, values) in cases.iter() {
+            for (value, increment) in values.iter() {
+                assert_eq!(encoding.decode(Value7::new(*value)), *increment);
+            }
+        }
+    }
+
+    #[test]
+    fn should_encode_increments() {
+        let encodings = [
+            RelativeEncoding::TwosComplement,
+            RelativeEncoding::BinaryOffset,
+            RelativeEncoding::SignMagnitude,
+        ];
+
+        for encoding in encodings.iter() {
+            for increment in -63..=63 {
+                assert_eq!(encoding.decode(encoding.encode(increment)), increment);
+            }
+            assert_eq!(encoding.decode(encoding.encode(100)), 63);
+        }
+        assert_eq!(u8::from(RelativeEncoding::SignMagnitude.encode(-100)), 127);
+    }
+
+    #[test]
+    fn should_accelerate_fast_turns() {
+        let mut encoder = RelativeEncoder::new(
+            Channel::C1,
+            Control::new(16),
+            RelativeEncoding::BinaryOffset,
+        );
+        encoder.set_acceleration(10, 4);
+
+        let mut values = [0u8; 3];
+        for (value, elapsed) in values.iter_mut().zip([100, 5, 20].iter()) {
+            encoder.advance(*elapsed);
+            encoder.turn(-1, |message| {
+                if let MidiMessage::ControlChange(_, _, cc) = message {
+                    *value = cc.into();
+                }
+            });
+        }
+
+        assert_eq!(values, [63, 60, 63]);
+    }
+
+    #[test]
+    fn should_decode_configured_controls() {
+        let mut decoder = RelativeDecoder::new();
+        decoder.set_encoding(Control::new(16), Some(RelativeEncoding::BinaryOffset));
+
+        let relative = MidiMessage::ControlChange(Channel::C2, Control::new(16), 60.into());
+        let absolute = MidiMessage::ControlChange(Channel::C2, Control::new(17), 60.into());
+        assert_eq!(
+            decoder.decode(&relative),
+            Some((Channel::C2, Control::new(16), -4))
+        );
+        assert_eq!(decoder.decode(&absolute), None);
+    }
+}
@@ -0,0 +1,290 @@
+//! Envelope generator emitting control change or channel pressure messages
+//!
+//! The envelope is triggered by notes and advanced by the time that passed. A message is only
+//! sent when the output value changes and no more often than the configured interval.
+
+use crate::midi_types::{Channel, Control, MidiMessage, Note, Value7};
+
+/// The internal level of a fully open envelope
+const LEVEL_MAX: u32 = 1 << 24;
+
+/// The shape of the envelope
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnvelopeMode {
+    /// Attack and decay to zero, releasing the note has no effect
+    AttackDecay,
+
+    /// Attack, decay to the sustain level while the note is held and release when it is released
+    Adsr,
+}
+
+/// The stage the envelope is in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// Where the envelope output is sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnvelopeTarget {
+    Control(Channel, Control),
+    ChannelPressure(Channel),
+}
+
+/// An envelope generator sending its output as midi messages, times are in milliseconds
+#[derive(Debug, Clone)]
+pub struct Envelope {
+    mode: EnvelopeMode,
+    target: EnvelopeTarget,
+    attack_ms: u32,
+    decay_ms: u32,
+    sustain: Value7,
+    release_ms: u32,
+    interval_us: u32,
+
+    stage: Stage,
+    level: u32,
+    note: Option<Note>,
+    since_sent_us: u32,
+    last: Option<u8>,
+}
+
+impl Envelope {
+    /// Create an envelope with short attack, decay and release times, sending at most a message
+    /// every millisecond
+    pub fn new(mode: EnvelopeMode, target: EnvelopeTarget) -> Self {
+        Envelope {
+            mode,
+            target,
+            attack_ms: 10,
+            decay_ms: 100,
+            sustain: Value7::new(100),
+            release_ms: 200,
+            interval_us: 1000,
+            stage: Stage::Idle,
+            level: 0,
+            note: None,
+            since_sent_us: u32::MAX,
+            last: None,
+        }
+    }
+
+    pub fn mode(&self) -> EnvelopeMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: EnvelopeMode) {
+        self.mode = mode;
+    }
+
+    pub fn target(&self) -> EnvelopeTarget {
+        self.target
+    }
+
+    pub fn set_target(&mut self, target: EnvelopeTarget) {
+        self.target = target;
+        self.last = None;
+    }
+
+    pub fn set_attack(&mut self, attack_ms: u32) {
+        self.attack_ms = attack_ms;
+    }
+
+    pub fn set_decay(&mut self, decay_ms: u32) {
+        self.decay_ms = decay_ms;
+    }
+
+    /// Set the level held while the note is held, only used in ADSR mode
+    pub fn set_sustain(&mut self, sustain: Value7) {
+        self.sustain = sustain;
+    }
+
+    pub fn set_release(&mut self, release_ms: u32) {
+        self.release_ms = release_ms;
+    }
+
+    /// Set the minimum time between messages in microseconds
+    pub fn set_interval(&mut self, interval_us: u32) {
+        self.interval_us = interval_us;
+    }
+
+    pub fn stage(&self) -> Stage {
+        self.stage
+    }
+
+    /// The current output between 0 and 127
+    pub fn value(&self) -> u8 {
+        (self.level * 127 / LEVEL_MAX) as u8
+    }
+
+    /// Start the envelope from its current level
+    pub fn trigger(&mut self) {
+        self.stage = Stage::Attack;
+    }
+
+    /// Release the envelope
+    pub fn release(&mut self) {
+        if self.mode == EnvelopeMode::Adsr && self.stage != Stage::Idle {
+            self.stage = Stage::Release;
+        }
+    }
+
+    /// Advance the envelope by the elapsed time in microseconds
+    pub fn advance(&mut self, elapsed_us: u32, mut emit: impl FnMut(MidiMessage)) {
+        self.update(elapsed_us);
+
+        self.since_sent_us = self.since_sent_us.saturating_add(elapsed_us);
+        let value = self.value();
+        if self.since_sent_us >= self.interval_us && self.last != Some(value) {
+            self.last = Some(value);
+            self.since_sent_us = 0;
+            emit(match self.target {
+                EnvelopeTarget::Control(channel, control) => {
+                    MidiMessage::ControlChange(channel, control, value.into())
+                }
+                EnvelopeTarget::ChannelPressure(channel) => {
+                    MidiMessage::ChannelPressure(channel, value.into())
+                }
+            });
+        }
+    }
+
+    /// Handle a midi message, note-on messages trigger the envelope and releasing the last
+    /// triggered note releases it. Other messages are ignored.
+    pub fn handle(&mut self, message: &MidiMessage) {
+        match *message {
+            MidiMessage::NoteOn(_, note, velocity) if u8::from(velocity) > 0 => {
+                self.note = Some(note);
+                self.trigger();
+            }
+            MidiMessage::NoteOn(_, note, _) | MidiMessage::NoteOff(_, note, _)
+                if self.note == Some(note) =>
+            {
+                self.note = None;
+                self.release();
+            }
+            _ => {}
+        }
+    }
+
+    fn update(&mut self, elapsed_us: u32) {
+        let sustain = match self.mode {
+            EnvelopeMode::AttackDecay => 0,
+            // Round up so the sustain level is output as the sustain value
+            EnvelopeMode::Adsr => (u8::from(self.sustain) as u32 * LEVEL_MAX + 126) / 127,
+        };
+
+        match self.stage {
+            Stage::Attack => {
+                self.level = self
+                    .level
+                    .saturating_add(step(elapsed_us, self.attack_ms))
+                    .min(LEVEL_MAX);
+                if self.level == LEVEL_MAX {
+                    self.stage = Stage::Decay;
+                }
+            }
+            Stage::Decay => {
+                self.level = self
+                    .level
+                    .saturating_sub(step(elapsed_us, self.decay_ms))
+                    .max(sustain);
+                if self.level == sustain {
+                    self.stage = match self.mode {
+                        EnvelopeMode::AttackDecay => Stage::Idle,
+                        EnvelopeMode::Adsr => Stage::Sustain,
+                    };
+                }
+            }
+            Stage::Sustain => self.level = sustain,
+            Stage::Release => {
+                self.level = self.level.saturating_sub(step(elapsed_us, self.release_ms));
+                if self.level == 0 {
+                    self.stage = Stage::Idle;
+                }
+            }
+            Stage::Idle => {}
+        }
+    }
+}
+
+/// The level change over the elapsed time for a stage taking `time_ms` to cover the full range
+fn step(elapsed_us: u32, time_ms: u32) -> u32 {
+    match time_ms {
+        0 => LEVEL_MAX,
+        time_ms => (LEVEL_MAX as u64 * elapsed_us as u64 / (time_ms as u64 * 1000))
+            .min(LEVEL_MAX as u64) as u32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::vec::Vec;
+
+    fn run(envelope: &mut Envelope, ms: u32) -> Vec<u8> {
+        let mut values = Vec::new();
+        for _ in 0..ms {
+            envelope.advance(1000, |message| {
+                if let MidiMessage::ChannelPressure(_, value) = message {
+                    values.push(value.into())
+                }
+            });
+        }
+        values
+    }
+
+    #[test]
+    fn should_run_through_adsr_stages() {
+        let mut envelope = Envelope::new(
+            EnvelopeMode::Adsr,
+            EnvelopeTarget::ChannelPressure(Channel::C1),
+        );
+        envelope.set_attack(4);
+        envelope.set_decay(4);
+        envelope.set_sustain(Value7::new(64));
+        envelope.set_release(2);
+
+        envelope.handle(&MidiMessage::NoteOn(Channel::C1, Note::C4, 100.into()));
+        assert_eq!(run(&mut envelope, 4), [31, 63, 95, 127]);
+        assert_eq!(run(&mut envelope, 8), [95, 64]);
+        assert_eq!(envelope.stage(), Stage::Sustain);
+
+        envelope.handle(&MidiMessage::NoteOff(Channel::C1, Note::C4, 0.into()));
+        assert_eq!(run(&mut envelope, 4), [0]);
+        assert_eq!(envelope.stage(), Stage::Idle);
+    }
+
+    #[test]
+    fn should_decay_to_zero_in_attack_decay_mode() {
+        let mut envelope = Envelope::new(
+            EnvelopeMode::AttackDecay,
+            EnvelopeTarget::ChannelPressure(Channel::C1),
+        );
+        envelope.set_attack(0);
+        envelope.set_decay(2);
+
+        envelope.trigger();
+        envelope.release();
+        assert_eq!(run(&mut envelope, 4), [127, 63, 0]);
+        assert_eq!(envelope.stage(), Stage::Idle);
+    }
+
+    #[test]
+    fn should_limit_message_rate() {
+        let mut envelope = Envelope::new(
+            EnvelopeMode::Adsr,
+            EnvelopeTarget::ChannelPressure(Channel::C1),
+        );
+        envelope.set_attack(100);
+        envelope.set_interval(10_000);
+        envelope.trigger();
+
+        assert_eq!(run(&mut envelope, 100).len(), 10);
+    }
+}
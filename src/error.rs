@@ -0,0 +1,102 @@
+//! A crate wide error type
+//!
+//! Modules return their own specific errors, these convert into `Error` so applications can
+//! propagate and log midi errors of different modules uniformly. With the `std` feature `Error`
+//! implements `std::error::Error`, the trait is only available in core from Rust 1.81 which is
+//! newer than the minimum supported Rust version.
+
+use crate::name::InvalidNoteName;
+use crate::packed::PackError;
+use crate::parser::checked::ParseError;
+use crate::sysex::SysExError;
+use crate::value::OutOfRange;
+use core::fmt;
+
+/// Errors of all modules of the crate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The bytes are not a valid midi message
+    Parse,
+
+    /// The buffer is too small to render the message into
+    BufferTooSmall,
+
+    /// A queue is full
+    QueueFull,
+
+    /// A value is out of range or does not fit the type it is converted to
+    InvalidValue,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Error::Parse => "invalid midi message",
+            Error::BufferTooSmall => "buffer too small",
+            Error::QueueFull => "queue full",
+            Error::InvalidValue => "invalid value",
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+impl From<SysExError> for Error {
+    fn from(error: SysExError) -> Self {
+        match error {
+            SysExError::BufferTooSmall => Error::BufferTooSmall,
+            SysExError::Malformed | SysExError::Mismatch | SysExError::Checksum => Error::Parse,
+        }
+    }
+}
+
+impl From<PackError> for Error {
+    fn from(error: PackError) -> Self {
+        match error {
+            PackError::SysEx => Error::InvalidValue,
+            PackError::Invalid => Error::Parse,
+        }
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(_: ParseError) -> Self {
+        Error::Parse
+    }
+}
+
+impl From<InvalidNoteName> for Error {
+    fn from(_: InvalidNoteName) -> Self {
+        Error::Parse
+    }
+}
+
+impl From<OutOfRange> for Error {
+    fn from(_: OutOfRange) -> Self {
+        Error::InvalidValue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::string::ToString;
+
+    #[test]
+    fn should_convert_module_errors() {
+        fn render() -> Result<(), Error> {
+            Err(SysExError::BufferTooSmall)?
+        }
+
+        assert_eq!(render(), Err(Error::BufferTooSmall));
+        assert_eq!(Error::from(PackError::Invalid), Error::Parse);
+    }
+
+    #[test]
+    fn should_display_errors() {
+        assert_eq!(Error::QueueFull.to_string(), "queue full");
+    }
+}
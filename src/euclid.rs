@@ -0,0 +1,214 @@
+//! Euclidean rhythms, pulses spread as evenly as possible over a number of steps
+
+use crate::midi_types::{Channel, MidiMessage, Note, Value7};
+
+/// The maximum number of steps in a rhythm
+pub const MAX_STEPS: u8 = 64;
+
+/// A rhythm of `pulses` spread evenly over `steps` steps, rotated by `rotation` steps. Without
+/// rotation the first step always has a pulse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EuclideanRhythm {
+    steps: u8,
+    pulses: u8,
+    rotation: u8,
+    mask: u64,
+}
+
+impl EuclideanRhythm {
+    /// Create a rhythm, the number of steps is limited to `MAX_STEPS` and the number of pulses
+    /// to the number of steps
+    pub fn new(pulses: u8, steps: u8, rotation: u8) -> Self {
+        let steps = steps.clamp(1, MAX_STEPS);
+        let pulses = pulses.min(steps);
+        let rotation = rotation % steps;
+
+        let (n, k) = (steps as u32, pulses as u32);
+        let mask = (0..n)
+            .filter(|step| step * k % n < k)
+            .map(|step| (step + rotation as u32) % n)
+            .fold(0u64, |mask, step| mask | 1 << step);
+
+        EuclideanRhythm {
+            steps,
+            pulses,
+            rotation,
+            mask,
+        }
+    }
+
+    pub fn steps(&self) -> u8 {
+        self.steps
+    }
+
+    pub fn pulses(&self) -> u8 {
+        self.pulses
+    }
+
+    pub fn rotation(&self) -> u8 {
+        self.rotation
+    }
+
+    /// Check if a step has a pulse, steps wrap around
+    pub fn is_pulse(&self, step: usize) -> bool {
+        self.mask >> (step % self.steps as usize) & 1 == 1
+    }
+}
+
+/// Plays a note on every pulse of a euclidean rhythm, advancing a step every `division` clock
+/// ticks. Notes end on the next tick so they can be used as triggers for drum sounds.
+#[derive(Debug, Clone)]
+pub struct EuclideanTrack {
+    rhythm: EuclideanRhythm,
+    channel: Channel,
+    note: Note,
+    velocity: Value7,
+    division: u8,
+    position: usize,
+    ticks: u8,
+    playing: bool,
+}
+
+impl EuclideanTrack {
+    /// Create a track playing sixteenth notes
+    pub fn new(rhythm: EuclideanRhythm, channel: Channel, note: Note, velocity: Value7) -> Self {
+        EuclideanTrack {
+            rhythm,
+            channel,
+            note,
+            velocity,
+            division: 6,
+            position: 0,
+            ticks: 0,
+            playing: false,
+        }
+    }
+
+    pub fn rhythm(&self) -> &EuclideanRhythm {
+        &self.rhythm
+    }
+
+    /// Change the rhythm, playback continues at the same position
+    pub fn set_rhythm(&mut self, rhythm: EuclideanRhythm) {
+        self.rhythm = rhythm;
+    }
+
+    /// Change the note that is played, takes effect on the next pulse
+    pub fn set_note(&mut self, note: Note, velocity: Value7) {
+        self.note = note;
+        self.velocity = velocity;
+    }
+
+    pub fn division(&self) -> u8 {
+        self.division
+    }
+
+    /// Set the number of clock ticks per step, at 24 ticks per quarter note 6 plays sixteenth
+    /// notes
+    pub fn set_division(&mut self, division: u8) {
+        self.division = division.max(1);
+    }
+
+    /// Advance the track by one clock tick, emits the note messages due on this tick
+    pub fn tick(&mut self, mut emit: impl FnMut(MidiMessage)) {
+        self.stop_note(&mut emit);
+
+        if self.ticks == 0 {
+            if self.rhythm.is_pulse(self.position) {
+                self.playing = true;
+                emit(MidiMessage::NoteOn(self.channel, self.note, self.velocity));
+            }
+            self.position = (self.position + 1) % self.rhythm.steps() as usize;
+        }
+
+        self.ticks = (self.ticks + 1) % self.division;
+    }
+
+    /// Start the rhythm from the first step on the next tick
+    pub fn restart(&mut self) {
+        self.position = 0;
+        self.ticks = 0;
+    }
+
+    /// Handle a midi message. Timing clock messages advance the track, start and stop messages
+    /// restart it. Other messages are ignored.
+    pub fn handle(&mut self, message: &MidiMessage, mut emit: impl FnMut(MidiMessage)) {
+        match message {
+            MidiMessage::TimingClock => self.tick(emit),
+            MidiMessage::Start | MidiMessage::Stop => {
+                self.stop_note(&mut emit);
+                self.restart();
+            }
+            _ => {}
+        }
+    }
+
+    fn stop_note(&mut self, emit: &mut impl FnMut(MidiMessage)) {
+        if self.playing {
+            self.playing = false;
+            emit(MidiMessage::NoteOff(
+                self.channel,
+                self.note,
+                Value7::new(0),
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::vec::Vec;
+
+    fn pattern(rhythm: &EuclideanRhythm) -> Vec<bool> {
+        (0..rhythm.steps() as usize)
+            .map(|step| rhythm.is_pulse(step))
+            .collect()
+    }
+
+    #[test]
+    fn should_spread_pulses_evenly() {
+        let (x, o) = (true, false);
+
+        assert_eq!(
+            pattern(&EuclideanRhythm::new(3, 8, 0)),
+            [x, o, o, x, o, o, x, o]
+        );
+        assert_eq!(
+            pattern(&EuclideanRhythm::new(5, 8, 0)),
+            [x, o, x, o, x, x, o, x]
+        );
+        assert_eq!(
+            pattern(&EuclideanRhythm::new(3, 8, 1)),
+            [o, x, o, o, x, o, o, x]
+        );
+        assert_eq!(pattern(&EuclideanRhythm::new(9, 4, 0)), [x, x, x, x]);
+    }
+
+    #[test]
+    fn should_trigger_notes_on_pulses() {
+        let mut track = EuclideanTrack::new(
+            EuclideanRhythm::new(2, 4, 0),
+            Channel::C10,
+            Note::C2,
+            Value7::new(127),
+        );
+        track.set_division(2);
+
+        let mut messages = Vec::new();
+        for tick in 0..8 {
+            track.tick(|message| messages.push((tick, message)));
+        }
+
+        assert_eq!(
+            messages,
+            [
+                (0, MidiMessage::NoteOn(Channel::C10, Note::C2, 127.into())),
+                (1, MidiMessage::NoteOff(Channel::C10, Note::C2, 0.into())),
+                (4, MidiMessage::NoteOn(Channel::C10, Note::C2, 127.into())),
+                (5, MidiMessage::NoteOff(Channel::C10, Note::C2, 0.into())),
+            ]
+        );
+    }
+}
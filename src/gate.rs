@@ -0,0 +1,232 @@
+//! Gate and trigger signals from note input
+
+use crate::midi_types::{Channel, MidiMessage, Note};
+use crate::mono::{MonoEvent, MonoNoteHandler, NotePriority};
+
+/// What happens to the gate and trigger when the note changes while the gate is high
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Retrigger {
+    /// The gate stays high and no trigger is sent
+    Legato,
+
+    /// The gate stays high and a trigger is sent
+    Trigger,
+
+    /// The gate goes low for the retrigger time before going high again, and a trigger is sent
+    Gate,
+}
+
+/// Changes of the gate and trigger outputs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GateEvent {
+    GateHigh,
+    GateLow,
+    TriggerHigh,
+    TriggerLow,
+}
+
+/// Turns the note activity of a monophonic voice into gate and trigger signals for driving
+/// outputs, keeping track of up to `N` held notes
+///
+/// Times are in the unit used to advance the gate.
+#[derive(Debug, Clone)]
+pub struct GateOutput<const N: usize> {
+    channel: Option<Channel>,
+    mono: MonoNoteHandler<N>,
+    retrigger: Retrigger,
+    trigger_length: u32,
+    retrigger_time: u32,
+    gate: bool,
+    /// Time left before the trigger goes low
+    trigger: Option<u32>,
+    /// Time left before the gate goes high after a retrigger
+    gate_delay: Option<u32>,
+}
+
+impl<const N: usize> GateOutput<N> {
+    /// Create a gate for notes on `channel`, or all channels when `channel` is `None`
+    pub fn new(channel: Option<Channel>, priority: NotePriority) -> Self {
+        GateOutput {
+            channel,
+            mono: MonoNoteHandler::new(priority),
+            retrigger: Retrigger::Trigger,
+            trigger_length: 1,
+            retrigger_time: 1,
+            gate: false,
+            trigger: None,
+            gate_delay: None,
+        }
+    }
+
+    pub fn retrigger(&self) -> Retrigger {
+        self.retrigger
+    }
+
+    pub fn set_retrigger(&mut self, retrigger: Retrigger) {
+        self.retrigger = retrigger;
+    }
+
+    /// Set how long triggers stay high
+    pub fn set_trigger_length(&mut self, length: u32) {
+        self.trigger_length = length;
+    }
+
+    /// Set how long the gate goes low when retriggering
+    pub fn set_retrigger_time(&mut self, time: u32) {
+        self.retrigger_time = time;
+    }
+
+    /// The note that is playing
+    pub fn note(&self) -> Option<Note> {
+        self.mono.current()
+    }
+
+    pub fn gate(&self) -> bool {
+        self.gate
+    }
+
+    pub fn trigger(&self) -> bool {
+        self.trigger.is_some()
+    }
+
+    /// Handle a received message, note messages on other channels are ignored
+    pub fn handle(&mut self, message: &MidiMessage, mut emit: impl FnMut(GateEvent)) {
+        let channel = match *message {
+            MidiMessage::NoteOn(channel, ..)
+            | MidiMessage::NoteOff(channel, ..)
+            | MidiMessage::ControlChange(channel, ..) => channel,
+            _ => return,
+        };
+        if self.channel.map_or(false, |own| own != channel) {
+            return;
+        }
+
+        match self.mono.handle(message) {
+            Some(MonoEvent::NoteOn(..)) if !self.gate => {
+                self.set_gate(true, &mut emit);
+                self.start_trigger(&mut emit);
+            }
+            Some(MonoEvent::NoteOn(..)) => match self.retrigger {
+                Retrigger::Legato => {}
+                Retrigger::Trigger => self.start_trigger(&mut emit),
+                Retrigger::Gate => {
+                    self.set_gate(false, &mut emit);
+                    self.gate_delay = Some(self.retrigger_time);
+                    self.start_trigger(&mut emit);
+                }
+            },
+            Some(MonoEvent::NoteOff(..)) => {
+                self.gate_delay = None;
+                self.set_gate(false, &mut emit);
+            }
+            None => {}
+        }
+    }
+
+    /// Advance time, ending triggers and retriggers that are due
+    pub fn advance(&mut self, elapsed: u32, mut emit: impl FnMut(GateEvent)) {
+        if let Some(remaining) = self.trigger {
+            self.trigger = remaining
+                .checked_sub(elapsed)
+                .filter(|remaining| *remaining > 0);
+            if self.trigger.is_none() {
+                emit(GateEvent::TriggerLow);
+            }
+        }
+
+        if let Some(remaining) = self.gate_delay {
+            self.gate_delay = remaining
+                .checked_sub(elapsed)
+                .filter(|remaining| *remaining > 0);
+            if self.gate_delay.is_none() {
+                self.set_gate(true, &mut emit);
+            }
+        }
+    }
+
+    fn set_gate(&mut self, gate: bool, emit: &mut impl FnMut(GateEvent)) {
+        if self.gate != gate {
+            self.gate = gate;
+            emit(if gate {
+                GateEvent::GateHigh
+            } else {
+                GateEvent::GateLow
+            });
+        }
+    }
+
+    fn start_trigger(&mut self, emit: &mut impl FnMut(GateEvent)) {
+        if self.trigger.is_none() {
+            emit(GateEvent::TriggerHigh);
+        }
+        self.trigger = Some(self.trigger_length);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use crate::test_util::{off, on};
+    use std::vec::Vec;
+
+    fn play(gate: &mut GateOutput<4>, messages: &[MidiMessage]) -> Vec<GateEvent> {
+        let mut events = Vec::new();
+        for message in messages {
+            gate.handle(message, |event| events.push(event));
+            gate.advance(1, |event| events.push(event));
+        }
+        events
+    }
+
+    #[test]
+    fn should_retrigger_on_note_changes() {
+        use GateEvent::*;
+
+        let mut gate = GateOutput::<4>::new(None, NotePriority::Last);
+        assert_eq!(
+            play(&mut gate, &[on(60), on(64), off(64), off(60)]),
+            [
+                GateHigh,
+                TriggerHigh,
+                TriggerLow,
+                TriggerHigh,
+                TriggerLow,
+                TriggerHigh,
+                TriggerLow,
+                GateLow
+            ]
+        );
+        assert_eq!(gate.note(), None);
+
+        gate.set_retrigger(Retrigger::Legato);
+        assert_eq!(
+            play(&mut gate, &[on(60), on(64), off(64), off(60)]),
+            [GateHigh, TriggerHigh, TriggerLow, GateLow]
+        );
+    }
+
+    #[test]
+    fn should_drop_gate_when_retriggering() {
+        use GateEvent::*;
+
+        let mut gate = GateOutput::<4>::new(Some(Channel::C1), NotePriority::Last);
+        gate.set_retrigger(Retrigger::Gate);
+        gate.set_trigger_length(2);
+
+        let other_channel = MidiMessage::NoteOn(Channel::C2, 70.into(), 100.into());
+        assert_eq!(
+            play(&mut gate, &[on(60), other_channel, on(64)]),
+            [
+                GateHigh,
+                TriggerHigh,
+                TriggerLow,
+                GateLow,
+                TriggerHigh,
+                GateHigh
+            ]
+        );
+        assert!(gate.gate());
+        assert!(gate.trigger());
+    }
+}
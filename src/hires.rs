@@ -0,0 +1,126 @@
+//! Sending 14 bit high resolution control changes
+
+use crate::midi_types::{Channel, Control, MidiMessage};
+use crate::value::U14;
+
+/// The order the two control changes of a 14 bit value are sent in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ByteOrder {
+    /// Send the most significant byte first, receivers apply the value on the least significant
+    /// byte
+    #[default]
+    MsbFirst,
+
+    /// Send the least significant byte first, for receivers that apply the value on the most
+    /// significant byte
+    LsbFirst,
+}
+
+/// Sends 14 bit values as a pair of control changes, controller `n` carries the most significant
+/// 7 bits and controller `n + 32` the least significant 7 bits
+///
+/// Only the least significant byte is sent when the most significant byte did not change.
+#[derive(Debug, Clone)]
+pub struct HighResControl {
+    channel: Channel,
+    msb: Control,
+    lsb: Control,
+    order: ByteOrder,
+    last: Option<U14>,
+}
+
+impl HighResControl {
+    /// Create a sender for a 14 bit controller, returns `None` if `control` is not one of the
+    /// controllers 0 to 31 that have a matching least significant controller
+    pub fn new(channel: Channel, control: Control) -> Option<Self> {
+        let control = u8::from(control);
+        if control >= 32 {
+            return None;
+        }
+
+        Some(HighResControl {
+            channel,
+            msb: control.into(),
+            lsb: (control + 32).into(),
+            order: ByteOrder::default(),
+            last: None,
+        })
+    }
+
+    pub fn order(&self) -> ByteOrder {
+        self.order
+    }
+
+    pub fn set_order(&mut self, order: ByteOrder) {
+        self.order = order;
+    }
+
+    /// Forget the last value sent so the next value is sent in full
+    pub fn reset(&mut self) {
+        self.last = None;
+    }
+
+    /// Send a value from 0 to 0x3fff, larger values are clamped. Nothing is sent when the value
+    /// did not change.
+    pub fn send(&mut self, value: u16, mut emit: impl FnMut(MidiMessage)) {
+        let value = U14::new(value);
+        if self.last == Some(value) {
+            return;
+        }
+
+        let lsb = MidiMessage::ControlChange(self.channel, self.lsb, value.lsb().into());
+        let msb = MidiMessage::ControlChange(self.channel, self.msb, value.msb().into());
+
+        match self.last.map(|last| last.msb() == value.msb()) {
+            Some(true) => emit(lsb),
+            _ if self.order == ByteOrder::LsbFirst => {
+                emit(lsb);
+                emit(msb);
+            }
+            _ => {
+                emit(msb);
+                emit(lsb);
+            }
+        }
+        self.last = Some(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::vec::Vec;
+
+    fn send(control: &mut HighResControl, value: u16) -> Vec<(u8, u8)> {
+        let mut sent = Vec::new();
+        control.send(value, |message| {
+            if let MidiMessage::ControlChange(_, control, value) = message {
+                sent.push((control.into(), value.into()))
+            }
+        });
+        sent
+    }
+
+    #[test]
+    fn should_only_send_changed_bytes() {
+        let mut control = HighResControl::new(Channel::C1, Control::new(1)).unwrap();
+
+        assert_eq!(send(&mut control, 0x1234), [(1, 0x24), (33, 0x34)]);
+        assert_eq!(send(&mut control, 0x1235), [(33, 0x35)]);
+        assert_eq!(send(&mut control, 0x1235), []);
+        assert_eq!(send(&mut control, 0x1335), [(1, 0x26), (33, 0x35)]);
+
+        control.reset();
+        assert_eq!(send(&mut control, 0x1335), [(1, 0x26), (33, 0x35)]);
+    }
+
+    #[test]
+    fn should_send_in_configured_order() {
+        let mut control = HighResControl::new(Channel::C1, Control::new(7)).unwrap();
+        control.set_order(ByteOrder::LsbFirst);
+
+        assert_eq!(send(&mut control, 0xffff), [(39, 0x7f), (7, 0x7f)]);
+        assert!(HighResControl::new(Channel::C1, Control::new(32)).is_none());
+    }
+}
@@ -0,0 +1,165 @@
+//! Humanizer adding random variation to velocity and timing of notes
+
+use crate::midi_types::{Channel, MidiMessage, Note, Value7};
+use crate::process::Processor;
+use crate::random::Random;
+use crate::scheduler::Scheduler;
+use crate::value::U7;
+
+/// Varies note velocities and delays notes by a random amount, using a scheduler holding up to
+/// `N` delayed messages. Timing is only varied when `N` is not zero.
+///
+/// Note-off messages are delayed by the full timing range so they always follow their note-on.
+/// Times are in the unit used to advance the humanizer.
+#[derive(Debug, Clone)]
+pub struct Humanizer<const N: usize> {
+    velocity_range: U7,
+    timing_range: u32,
+    random: Random,
+    scheduler: Scheduler<N>,
+    now: u32,
+}
+
+impl<const N: usize> Humanizer<N> {
+    /// Create a humanizer changing velocities by up to `velocity_range` in either direction and
+    /// delaying notes by up to `timing_range`
+    pub fn new(velocity_range: U7, timing_range: u32) -> Self {
+        Humanizer {
+            velocity_range,
+            timing_range,
+            random: Random::new(),
+            scheduler: Scheduler::new(),
+            now: 0,
+        }
+    }
+
+    pub fn set_velocity_range(&mut self, velocity_range: U7) {
+        self.velocity_range = velocity_range;
+    }
+
+    /// Change the timing range, this should only be done when no notes are pending
+    pub fn set_timing_range(&mut self, timing_range: u32) {
+        self.timing_range = timing_range;
+    }
+
+    /// Seed the random generator, the same seed gives the same variations
+    pub fn set_seed(&mut self, seed: u32) {
+        self.random.seed(seed);
+    }
+
+    /// Advance time and emit the delayed messages that are due
+    pub fn advance(&mut self, elapsed: u32, emit: impl FnMut(MidiMessage)) {
+        self.now = self.now.wrapping_add(elapsed);
+        self.scheduler.poll(self.now, emit);
+    }
+
+    fn vary_velocity(&mut self, velocity: Value7) -> Value7 {
+        let range = self.velocity_range.get() as i32;
+        let offset = self.random.below(2 * range as u32 + 1) as i32 - range;
+        Value7::new((u8::from(velocity) as i32 + offset).clamp(1, 127) as u8)
+    }
+
+    fn delay(&mut self, delay: u32, message: MidiMessage, emit: &mut impl FnMut(MidiMessage)) {
+        if N == 0 || self.timing_range == 0 {
+            return emit(message);
+        }
+
+        let time = self.now.wrapping_add(delay);
+        if let Err(message) = self.scheduler.schedule(time, message) {
+            if let MidiMessage::NoteOff(channel, note, _) = message {
+                self.cancel_note(channel, note);
+            }
+            emit(message);
+        }
+    }
+
+    /// Drop a pending note-on so it can not follow a note-off that is sent right away
+    fn cancel_note(&mut self, channel: Channel, note: Note) {
+        self.scheduler.cancel(
+            |pending| matches!(*pending, MidiMessage::NoteOn(c, n, _) if c == channel && n == note),
+        );
+    }
+}
+
+impl<const N: usize> Processor for Humanizer<N> {
+    fn process(&mut self, message: MidiMessage, emit: &mut impl FnMut(MidiMessage)) {
+        match message {
+            MidiMessage::NoteOn(channel, note, velocity) if u8::from(velocity) > 0 => {
+                let velocity = self.vary_velocity(velocity);
+                let delay = self.random.below(self.timing_range.saturating_add(1));
+                self.delay(delay, MidiMessage::NoteOn(channel, note, velocity), emit);
+            }
+            MidiMessage::NoteOn(channel, note, velocity) => {
+                let message = MidiMessage::NoteOff(channel, note, velocity);
+                self.delay(self.timing_range, message, emit);
+            }
+            MidiMessage::NoteOff(..) => self.delay(self.timing_range, message, emit),
+            message => emit(message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::vec::Vec;
+
+    fn humanize<const N: usize>(humanizer: &mut Humanizer<N>) -> Vec<(u32, MidiMessage)> {
+        let mut messages = Vec::new();
+        for tick in 0..20 {
+            let input = match tick {
+                0 => Some(MidiMessage::NoteOn(Channel::C1, Note::C4, 100.into())),
+                1 => Some(MidiMessage::NoteOff(Channel::C1, Note::C4, 0.into())),
+                _ => None,
+            };
+            if let Some(message) = input {
+                humanizer.process(message, &mut |message| messages.push((tick, message)));
+            }
+            humanizer.advance(1, |message| messages.push((tick, message)));
+        }
+        messages
+    }
+
+    #[test]
+    fn should_vary_velocity_within_range() {
+        let mut humanizer = Humanizer::<0>::new(U7::new(10), 0);
+
+        for _ in 0..100 {
+            let mut velocity = 0;
+            humanizer.process(
+                MidiMessage::NoteOn(Channel::C1, Note::C4, 120.into()),
+                &mut |message| {
+                    if let MidiMessage::NoteOn(_, _, value) = message {
+                        velocity = value.into()
+                    }
+                },
+            );
+            assert!((110..=127).contains(&velocity));
+        }
+    }
+
+    #[test]
+    fn should_delay_note_off_after_note_on() {
+        let mut humanizer = Humanizer::<4>::new(U7::MIN, 8);
+        humanizer.set_seed(1234);
+
+        let messages = humanize(&mut humanizer);
+        assert_eq!(messages.len(), 2);
+        assert!(matches!(messages[0].1, MidiMessage::NoteOn(..)));
+        assert_eq!(
+            messages[1],
+            (8, MidiMessage::NoteOff(Channel::C1, Note::C4, 0.into()))
+        );
+    }
+
+    #[test]
+    fn should_be_reproducible_with_a_seed() {
+        let mut first = Humanizer::<4>::new(U7::new(20), 8);
+        let mut second = Humanizer::<4>::new(U7::new(20), 8);
+        first.set_seed(42);
+        second.set_seed(42);
+
+        assert_eq!(humanize(&mut first), humanize(&mut second));
+    }
+}
@@ -0,0 +1,7 @@
+//! Conversions to and from the types of other midi crates, each behind a feature with the name
+//! of the crate
+
+#[cfg(feature = "midly")]
+mod midly;
+#[cfg(feature = "wmidi")]
+mod wmidi;
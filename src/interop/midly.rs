@@ -0,0 +1,154 @@
+//! Conversions to and from `midly` events and values
+//!
+//! Channel messages convert to and from the `TrackEventKind` of standard midi files, so songs
+//! parsed with `midly` can be played through the processors and outputs of this crate. All
+//! messages convert to and from a `LiveEvent`. `midly` stores system exclusive messages without
+//! their start byte, so they can only be converted to `midly`.
+
+use crate::packed::{PackError, PackedMidiEvent};
+use crate::parser::MidiEvent;
+use crate::value::{U14, U7};
+use core::convert::TryFrom;
+use midly::io::Cursor;
+use midly::live::{LiveEvent, SystemCommon};
+use midly::num::{u14, u7};
+use midly::TrackEventKind;
+
+impl<'a> TryFrom<MidiEvent<'a>> for LiveEvent<'a> {
+    type Error = PackError;
+
+    fn try_from(event: MidiEvent<'a>) -> Result<Self, Self::Error> {
+        if let MidiEvent::SysEx(message) = event {
+            return message
+                .get(1..message.len().saturating_sub(1))
+                .and_then(u7::slice_try_from_int)
+                .map(|data| LiveEvent::Common(SystemCommon::SysEx(data)))
+                .ok_or(PackError::Invalid);
+        }
+
+        let packed = PackedMidiEvent::try_from(event)?;
+        let bytes = packed.to_bytes();
+        bytes
+            .get(..packed.len())
+            .and_then(|bytes| LiveEvent::parse(bytes).ok())
+            .map(|event| event.to_static())
+            .ok_or(PackError::Invalid)
+    }
+}
+
+impl TryFrom<LiveEvent<'_>> for MidiEvent<'static> {
+    type Error = PackError;
+
+    fn try_from(event: LiveEvent<'_>) -> Result<Self, Self::Error> {
+        if let LiveEvent::Common(SystemCommon::SysEx(_)) = event {
+            return Err(PackError::SysEx);
+        }
+
+        let mut bytes = [0; 3];
+        event
+            .write(&mut Cursor::new(&mut bytes))
+            .map_err(|_| PackError::Invalid)?;
+        let [status, first, second] = bytes;
+        MidiEvent::try_from(PackedMidiEvent::from_bytes(status, first, second))
+    }
+}
+
+/// Only channel messages convert, standard midi files can not contain system common and real
+/// time messages
+impl TryFrom<MidiEvent<'_>> for TrackEventKind<'static> {
+    type Error = PackError;
+
+    fn try_from(event: MidiEvent<'_>) -> Result<Self, Self::Error> {
+        match LiveEvent::try_from(event)? {
+            LiveEvent::Midi { channel, message } => Ok(TrackEventKind::Midi { channel, message }),
+            LiveEvent::Common(SystemCommon::SysEx(_)) => Err(PackError::SysEx),
+            _ => Err(PackError::Invalid),
+        }
+    }
+}
+
+/// Only channel messages convert, meta messages have no midi message to convert to
+impl TryFrom<TrackEventKind<'_>> for MidiEvent<'static> {
+    type Error = PackError;
+
+    fn try_from(event: TrackEventKind<'_>) -> Result<Self, Self::Error> {
+        match event {
+            TrackEventKind::Midi { channel, message } => {
+                MidiEvent::try_from(LiveEvent::Midi { channel, message })
+            }
+            TrackEventKind::SysEx(_) | TrackEventKind::Escape(_) => Err(PackError::SysEx),
+            TrackEventKind::Meta(_) => Err(PackError::Invalid),
+        }
+    }
+}
+
+impl From<U7> for u7 {
+    fn from(value: U7) -> Self {
+        u7::from_int_lossy(value.get())
+    }
+}
+
+impl From<u7> for U7 {
+    fn from(value: u7) -> Self {
+        U7::new(value.as_int())
+    }
+}
+
+impl From<U14> for u14 {
+    fn from(value: U14) -> Self {
+        u14::from_int_lossy(value.get())
+    }
+}
+
+impl From<u14> for U14 {
+    fn from(value: u14) -> Self {
+        U14::new(value.as_int())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::midi_types::{Channel, MidiMessage, Note, Value7};
+    use midly::num::u4;
+
+    #[test]
+    fn should_convert_track_events() {
+        let event =
+            MidiEvent::Message(MidiMessage::NoteOn(Channel::C2, Note::C3, Value7::new(100)));
+        let kind = TrackEventKind::Midi {
+            channel: u4::new(1),
+            message: midly::MidiMessage::NoteOn {
+                key: u7::new(60),
+                vel: u7::new(100),
+            },
+        };
+
+        assert_eq!(TrackEventKind::try_from(event), Ok(kind));
+        assert_eq!(MidiEvent::try_from(kind), Ok(event));
+        assert_eq!(
+            TrackEventKind::try_from(MidiEvent::Message(MidiMessage::TimingClock)),
+            Err(PackError::Invalid)
+        );
+    }
+
+    #[test]
+    fn should_convert_live_events() {
+        let event = MidiEvent::Message(MidiMessage::SongPositionPointer(0x1234u16.into()));
+        let live = LiveEvent::try_from(event);
+
+        assert_eq!(
+            live,
+            Ok(LiveEvent::Common(SystemCommon::SongPosition(u14::new(
+                0x1234
+            ))))
+        );
+        assert_eq!(live.map(MidiEvent::try_from), Ok(Ok(event)));
+
+        let sysex = LiveEvent::try_from(MidiEvent::SysEx(&[0xF0, 0x7E, 0x01, 0xF7]));
+        assert!(
+            matches!(sysex, Ok(LiveEvent::Common(SystemCommon::SysEx(data))) if data.len() == 2)
+        );
+        assert_eq!(U14::from(u14::from(U14::CENTER)), U14::CENTER);
+    }
+}
@@ -0,0 +1,106 @@
+//! Conversions to and from `wmidi` messages and values
+//!
+//! Messages are converted through their bytes. `wmidi` stores system exclusive messages without
+//! their start and end bytes, so they can only be converted to `wmidi`.
+
+use crate::packed::{PackError, PackedMidiEvent};
+use crate::parser::MidiEvent;
+use crate::value::{U14, U7};
+use core::convert::TryFrom;
+
+impl<'a> TryFrom<MidiEvent<'a>> for wmidi::MidiMessage<'a> {
+    type Error = PackError;
+
+    fn try_from(event: MidiEvent<'a>) -> Result<Self, Self::Error> {
+        if let MidiEvent::SysEx(message) = event {
+            return wmidi::MidiMessage::try_from(message).map_err(|_| PackError::Invalid);
+        }
+
+        let packed = PackedMidiEvent::try_from(event)?;
+        let bytes = packed.to_bytes();
+        bytes
+            .get(..packed.len())
+            .and_then(|bytes| wmidi::MidiMessage::try_from(bytes).ok())
+            .and_then(wmidi::MidiMessage::drop_unowned_sysex)
+            .ok_or(PackError::Invalid)
+    }
+}
+
+impl TryFrom<&wmidi::MidiMessage<'_>> for MidiEvent<'static> {
+    type Error = PackError;
+
+    fn try_from(message: &wmidi::MidiMessage<'_>) -> Result<Self, Self::Error> {
+        if let wmidi::MidiMessage::SysEx(_) = message {
+            return Err(PackError::SysEx);
+        }
+
+        let mut bytes = [0; 3];
+        message
+            .copy_to_slice(&mut bytes)
+            .map_err(|_| PackError::Invalid)?;
+        let [status, first, second] = bytes;
+        MidiEvent::try_from(PackedMidiEvent::from_bytes(status, first, second))
+    }
+}
+
+impl TryFrom<wmidi::MidiMessage<'_>> for MidiEvent<'static> {
+    type Error = PackError;
+
+    fn try_from(message: wmidi::MidiMessage<'_>) -> Result<Self, Self::Error> {
+        MidiEvent::try_from(&message)
+    }
+}
+
+impl From<U7> for wmidi::U7 {
+    fn from(value: U7) -> Self {
+        wmidi::U7::from_u8_lossy(value.get())
+    }
+}
+
+impl From<wmidi::U7> for U7 {
+    fn from(value: wmidi::U7) -> Self {
+        U7::new(value.into())
+    }
+}
+
+impl From<U14> for wmidi::U14 {
+    fn from(value: U14) -> Self {
+        wmidi::U14::try_from(value.get()).unwrap_or(wmidi::U14::MAX)
+    }
+}
+
+impl From<wmidi::U14> for U14 {
+    fn from(value: wmidi::U14) -> Self {
+        U14::new(value.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::midi_types::{Channel, MidiMessage, Note, Value7};
+
+    #[test]
+    fn should_convert_messages() {
+        let event =
+            MidiEvent::Message(MidiMessage::NoteOn(Channel::C2, Note::C3, Value7::new(100)));
+        let message = wmidi::MidiMessage::NoteOn(
+            wmidi::Channel::Ch2,
+            wmidi::Note::from_u8_lossy(60),
+            wmidi::U7::from_u8_lossy(100),
+        );
+
+        assert_eq!(wmidi::MidiMessage::try_from(event), Ok(message.clone()));
+        assert_eq!(MidiEvent::try_from(message), Ok(event));
+    }
+
+    #[test]
+    fn should_convert_system_exclusive_messages_to_wmidi() {
+        let event = MidiEvent::SysEx(&[0xF0, 0x7E, 0x01, 0xF7]);
+        let message = wmidi::MidiMessage::try_from(event);
+
+        assert!(matches!(message, Ok(wmidi::MidiMessage::SysEx(data)) if data.len() == 2));
+        assert_eq!(message.map(MidiEvent::try_from), Ok(Err(PackError::SysEx)));
+        assert_eq!(U14::from(wmidi::U14::from(U14::CENTER)), U14::CENTER);
+    }
+}
@@ -0,0 +1,99 @@
+//! Transport independent midi input and output
+//!
+//! Routers and processors written against `MidiSource` and `MidiSink` work with any transport
+//! that implements them, like the uart based `MidiIn` and `MidiOut` or test doubles.
+
+#[cfg(feature = "async")]
+pub mod futures;
+
+use crate::midi_types::MidiMessage;
+use crate::{MidiIn, MidiOut};
+use core::fmt::Debug;
+use embedded_hal_nb::serial;
+
+/// Receives midi messages
+pub trait MidiSource {
+    type Error: Debug;
+
+    /// Poll for the next message, returns `WouldBlock` when no complete message was received
+    fn poll(&mut self) -> nb::Result<MidiMessage, Self::Error>;
+}
+
+/// Sends midi messages
+pub trait MidiSink {
+    type Error: Debug;
+
+    fn send(&mut self, message: &MidiMessage) -> Result<(), Self::Error>;
+}
+
+impl<T: MidiSource> MidiSource for &mut T {
+    type Error = T::Error;
+
+    fn poll(&mut self) -> nb::Result<MidiMessage, Self::Error> {
+        (**self).poll()
+    }
+}
+
+impl<T: MidiSink> MidiSink for &mut T {
+    type Error = T::Error;
+
+    fn send(&mut self, message: &MidiMessage) -> Result<(), Self::Error> {
+        (**self).send(message)
+    }
+}
+
+impl<RX, E> MidiSource for MidiIn<RX>
+where
+    RX: serial::Read<u8, Error = E>,
+    E: Debug,
+{
+    type Error = E;
+
+    fn poll(&mut self) -> nb::Result<MidiMessage, Self::Error> {
+        self.read()
+    }
+}
+
+impl<TX, E> MidiSink for MidiOut<TX>
+where
+    TX: serial::Write<u8, Error = E>,
+    E: Debug,
+{
+    type Error = E;
+
+    fn send(&mut self, message: &MidiMessage) -> Result<(), Self::Error> {
+        self.write(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use embedded_hal_mock::eh1::serial::{Mock, Transaction};
+
+    /// Forward all messages that are available, written once for any transport
+    fn forward(mut source: impl MidiSource, mut sink: impl MidiSink) {
+        loop {
+            match source.poll() {
+                Ok(message) => sink.send(&message).unwrap(),
+                Err(nb::Error::WouldBlock) => continue,
+                Err(nb::Error::Other(_)) => break,
+            }
+        }
+    }
+
+    #[test]
+    fn should_forward_between_transports() {
+        let mut rx = Mock::new(&[
+            Transaction::read_many([0x90, 0x40, 0x7F, 0xF8]),
+            Transaction::read_error(nb::Error::Other(serial::ErrorKind::Other)),
+        ]);
+        let mut tx = Mock::new(&[Transaction::write_many([0x90, 0x40, 0x7F, 0xF8])]);
+
+        forward(MidiIn::new(&mut rx), MidiOut::new(&mut tx));
+
+        rx.done();
+        tx.done();
+    }
+}
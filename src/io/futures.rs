@@ -0,0 +1,473 @@
+//! `Stream` and `Sink` adapters for async code
+//!
+//! The adapters are built on byte sources and sinks that wake their task, like a uart driven by
+//! interrupts or DMA. `AsyncByteRead` and `AsyncByteWrite` are poll based in the style of
+//! `futures::io`, `embedded-io-async` needs async functions in traits which are not available on
+//! the supported compiler versions. Implementations store the waker of the context when no bytes
+//! can be read or written and wake it from the interrupt, the adapters never wake themselves.
+//!
+//! `MidiStream` parses received bytes into `StreamEvent`s, which own the system exclusive data
+//! they carry so they outlive the receive buffer. `EventSink` accepts any `MidiEvent`, including
+//! system exclusive messages, and only accepts the next event once the previous one was written.
+
+use crate::midi_types::MidiMessage;
+use crate::parser::{is_real_time, MidiEvent, MidiParser};
+use crate::running::{RunningStatus, StatusRefresh};
+use crate::sysex::{SYSEX_END, SYSEX_START};
+use core::fmt::Debug;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use futures_core::Stream;
+use futures_sink::Sink;
+
+/// Reads bytes, waking the task when more bytes can be read
+pub trait AsyncByteRead {
+    type Error: Debug;
+
+    /// Read received bytes into the buffer, returns the number of bytes read and 0 when the
+    /// source is closed. Returns `Pending` and wakes the task later when no bytes were received.
+    fn poll_read(
+        &mut self,
+        cx: &mut Context<'_>,
+        buffer: &mut [u8],
+    ) -> Poll<Result<usize, Self::Error>>;
+}
+
+/// Writes bytes, waking the task when more bytes can be written
+pub trait AsyncByteWrite {
+    type Error: Debug;
+
+    /// Write some of the bytes, returns the number of bytes written. Returns `Pending` and wakes
+    /// the task later when no bytes can be written.
+    fn poll_write(
+        &mut self,
+        cx: &mut Context<'_>,
+        bytes: &[u8],
+    ) -> Poll<Result<usize, Self::Error>>;
+
+    /// Wait until all written bytes were sent
+    fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>>;
+}
+
+/// An event received by a `MidiStream`, system exclusive data is copied out of the receive
+/// buffer in chunks of up to `N` bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamEvent<const N: usize> {
+    message: Option<MidiMessage>,
+    data: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> StreamEvent<N> {
+    fn message(message: MidiMessage) -> Self {
+        StreamEvent {
+            message: Some(message),
+            data: [0; N],
+            len: 0,
+        }
+    }
+
+    /// The event as it would have been returned by the parser, a system exclusive message that
+    /// fits in one chunk is a `SysEx` event, others are split into `SysExChunk` events
+    pub fn event(&self) -> MidiEvent<'_> {
+        if let Some(message) = self.message {
+            return MidiEvent::Message(message);
+        }
+        let data = self.data.get(..self.len).unwrap_or_default();
+        match (data.first(), data.last()) {
+            (Some(&SYSEX_START), Some(&SYSEX_END)) => MidiEvent::SysEx(data),
+            _ => MidiEvent::SysExChunk(data),
+        }
+    }
+}
+
+/// A stream of the events received by a byte source, reading up to `N` bytes at a time
+///
+/// Source errors are passed on as items, the stream ends when the source is closed.
+#[derive(Debug)]
+pub struct MidiStream<R, const N: usize> {
+    reader: R,
+    parser: MidiParser,
+    buffer: [u8; N],
+    start: usize,
+    end: usize,
+
+    /// The system exclusive data received since the last chunk, `None` outside system exclusive
+    sysex: Option<StreamEvent<N>>,
+}
+
+impl<R: AsyncByteRead, const N: usize> MidiStream<R, N> {
+    pub fn new(reader: R) -> Self {
+        MidiStream {
+            reader,
+            parser: MidiParser::new(),
+            buffer: [0; N],
+            start: 0,
+            end: 0,
+            sysex: None,
+        }
+    }
+
+    /// Returns the reader, bytes that were read but not parsed yet are dropped
+    pub fn release(self) -> R {
+        self.reader
+    }
+
+    /// Handle a received byte, returns false when the byte has to be handled again after the
+    /// returned event
+    fn handle(&mut self, byte: u8) -> (bool, Option<StreamEvent<N>>) {
+        if let Some(chunk) = self.sysex.as_mut() {
+            if byte < 0x80 || byte == SYSEX_END {
+                match chunk.data.get_mut(chunk.len) {
+                    Some(slot) => *slot = byte,
+                    None => return (false, self.take_chunk()),
+                }
+                chunk.len += 1;
+                if byte == SYSEX_END {
+                    self.parser.parse_byte(byte);
+                    let chunk = self.sysex.take();
+                    return (true, chunk);
+                }
+                return (true, None);
+            }
+
+            // Status and real-time bytes end the current chunk
+            if chunk.len > 0 {
+                return (false, self.take_chunk());
+            }
+            if !is_real_time(byte) {
+                self.sysex = None;
+            }
+        }
+
+        if byte == SYSEX_START && N > 0 {
+            let mut chunk = StreamEvent {
+                message: None,
+                data: [0; N],
+                len: 1,
+            };
+            if let Some(slot) = chunk.data.first_mut() {
+                *slot = byte;
+            }
+            self.sysex = Some(chunk);
+            self.parser.parse_byte(byte);
+            return (true, None);
+        }
+
+        (true, self.parser.parse_byte(byte).map(StreamEvent::message))
+    }
+
+    /// Take the system exclusive data received so far, leaving an empty chunk
+    fn take_chunk(&mut self) -> Option<StreamEvent<N>> {
+        let chunk = self.sysex?;
+        if let Some(sysex) = self.sysex.as_mut() {
+            sysex.len = 0;
+        }
+        Some(chunk)
+    }
+}
+
+impl<R: AsyncByteRead + Unpin, const N: usize> Stream for MidiStream<R, N> {
+    type Item = Result<StreamEvent<N>, R::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let stream = self.get_mut();
+        loop {
+            while let Some(&byte) = stream
+                .buffer
+                .get(stream.start..stream.end)
+                .and_then(<[u8]>::first)
+            {
+                let (consumed, event) = stream.handle(byte);
+                if consumed {
+                    stream.start += 1;
+                }
+                if let Some(event) = event {
+                    return Poll::Ready(Some(Ok(event)));
+                }
+            }
+
+            match stream.reader.poll_read(cx, &mut stream.buffer) {
+                Poll::Ready(Ok(0)) => {
+                    let chunk = stream.sysex.take().filter(|chunk| chunk.len > 0);
+                    return Poll::Ready(chunk.map(Ok));
+                }
+                Poll::Ready(Ok(len)) => {
+                    stream.start = 0;
+                    stream.end = len.min(N);
+                }
+                Poll::Ready(Err(error)) => return Poll::Ready(Some(Err(error))),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// Errors of an `EventSink`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SinkError<E> {
+    /// The byte sink failed
+    Io(E),
+
+    /// The event does not fit in the buffer of the sink
+    BufferTooSmall,
+
+    /// The byte sink did not accept any bytes
+    WriteZero,
+}
+
+/// A sink writing events to a byte sink through a buffer of `N` bytes
+///
+/// Channel messages are sent with running status. The sink is ready for the next event once the
+/// previous one was written to the byte sink, so a slow output holds back the task sending to it.
+#[derive(Debug)]
+pub struct EventSink<W, const N: usize> {
+    writer: W,
+    running: RunningStatus,
+    buffer: [u8; N],
+    start: usize,
+    end: usize,
+}
+
+impl<W: AsyncByteWrite, const N: usize> EventSink<W, N> {
+    pub fn new(writer: W) -> Self {
+        EventSink {
+            writer,
+            running: RunningStatus::default(),
+            buffer: [0; N],
+            start: 0,
+            end: 0,
+        }
+    }
+
+    /// Send the status byte again after a number of bytes, see `StatusRefresh`
+    pub fn set_status_refresh(&mut self, refresh: StatusRefresh) {
+        self.running.set_refresh(refresh);
+    }
+
+    /// Returns the writer, bytes that were not written yet are dropped
+    pub fn release(self) -> W {
+        self.writer
+    }
+
+    /// Append bytes to the buffer
+    fn push(&mut self, bytes: &[u8]) -> Result<(), SinkError<W::Error>> {
+        let end = self
+            .end
+            .checked_add(bytes.len())
+            .ok_or(SinkError::BufferTooSmall)?;
+        self.buffer
+            .get_mut(self.end..end)
+            .ok_or(SinkError::BufferTooSmall)?
+            .copy_from_slice(bytes);
+        self.end = end;
+        Ok(())
+    }
+
+    /// Write the buffer to the byte sink
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), SinkError<W::Error>>> {
+        while let Some(bytes) = self
+            .buffer
+            .get(self.start..self.end)
+            .filter(|bytes| !bytes.is_empty())
+        {
+            match self.writer.poll_write(cx, bytes) {
+                Poll::Ready(Ok(0)) => return Poll::Ready(Err(SinkError::WriteZero)),
+                Poll::Ready(Ok(len)) => self.start += len.min(bytes.len()),
+                Poll::Ready(Err(error)) => return Poll::Ready(Err(SinkError::Io(error))),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        self.start = 0;
+        self.end = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<'a, W: AsyncByteWrite + Unpin, const N: usize> Sink<MidiEvent<'a>> for EventSink<W, N> {
+    type Error = SinkError<W::Error>;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().poll_drain(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, event: MidiEvent<'a>) -> Result<(), Self::Error> {
+        let sink = self.get_mut();
+        match event {
+            MidiEvent::Message(message) => {
+                let mut bytes = [0; 3];
+                let bytes = sink.running.render(&message, 0, &mut bytes);
+                sink.push(bytes)
+            }
+            MidiEvent::SysEx(data) | MidiEvent::SysExChunk(data) => {
+                sink.running.reset();
+                sink.push(data)
+            }
+            #[cfg(feature = "unknown-messages")]
+            MidiEvent::Unknown { status, data } => {
+                sink.running.reset();
+                sink.push(&[status])?;
+                sink.push(data)
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let sink = self.get_mut();
+        match sink.poll_drain(cx) {
+            Poll::Ready(Ok(())) => sink.writer.poll_flush(cx).map_err(SinkError::Io),
+            poll => poll,
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.poll_flush(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use crate::midi_types::{Channel, Note, Value7};
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use core::task::{RawWaker, RawWakerVTable, Waker};
+    use std::vec::Vec;
+
+    static WAKES: AtomicUsize = AtomicUsize::new(0);
+
+    /// A waker counting how often it was woken
+    fn counting_waker() -> Waker {
+        const VTABLE: RawWakerVTable = RawWakerVTable::new(
+            |_| RawWaker::new(core::ptr::null(), &VTABLE),
+            |_| {
+                WAKES.fetch_add(1, Ordering::SeqCst);
+            },
+            |_| {
+                WAKES.fetch_add(1, Ordering::SeqCst);
+            },
+            |_| {},
+        );
+        unsafe { Waker::from_raw(RawWaker::new(core::ptr::null(), &VTABLE)) }
+    }
+
+    /// Bytes arriving in bursts, `None` when nothing arrived yet
+    struct Bursts(Vec<Option<&'static [u8]>>);
+
+    impl AsyncByteRead for Bursts {
+        type Error = ();
+
+        fn poll_read(&mut self, _: &mut Context<'_>, buffer: &mut [u8]) -> Poll<Result<usize, ()>> {
+            if self.0.is_empty() {
+                return Poll::Ready(Ok(0));
+            }
+            match self.0.remove(0) {
+                Some(bytes) => {
+                    let len = bytes.len().min(buffer.len());
+                    buffer[..len].copy_from_slice(&bytes[..len]);
+                    if len < bytes.len() {
+                        self.0.insert(0, Some(&bytes[len..]));
+                    }
+                    Poll::Ready(Ok(len))
+                }
+                None => Poll::Pending,
+            }
+        }
+    }
+
+    /// Accepts up to `space` bytes until it is given more space
+    struct Output {
+        written: Vec<u8>,
+        space: usize,
+    }
+
+    impl AsyncByteWrite for Output {
+        type Error = ();
+
+        fn poll_write(&mut self, _: &mut Context<'_>, bytes: &[u8]) -> Poll<Result<usize, ()>> {
+            let len = bytes.len().min(self.space);
+            if len == 0 {
+                return Poll::Pending;
+            }
+            self.written.extend_from_slice(&bytes[..len]);
+            self.space -= len;
+            Poll::Ready(Ok(len))
+        }
+
+        fn poll_flush(&mut self, _: &mut Context<'_>) -> Poll<Result<(), ()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn should_stream_messages_and_system_exclusive_chunks() {
+        let mut stream = MidiStream::<_, 4>::new(Bursts(std::vec![
+            None,
+            Some(&[0x90, 0x40, 0x7F, 0xF0, 0x7E, 0x01, 0xF8, 0x02, 0xF7]),
+            Some(&[0xF0, 0x01, 0xF7]),
+        ]));
+        let waker = counting_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        // The source wakes the task, the stream does not wake itself
+        assert!(Pin::new(&mut stream).poll_next(&mut cx).is_pending());
+        assert_eq!(WAKES.load(Ordering::SeqCst), 0);
+
+        let mut events = Vec::new();
+        while let Poll::Ready(Some(event)) = Pin::new(&mut stream).poll_next(&mut cx) {
+            events.push(event.unwrap());
+        }
+        let events: Vec<_> = events.iter().map(StreamEvent::event).collect();
+        assert_eq!(
+            events,
+            [
+                MidiEvent::Message(MidiMessage::NoteOn(Channel::C1, 0x40.into(), 0x7F.into())),
+                MidiEvent::SysExChunk(&[0xF0, 0x7E, 0x01]),
+                MidiEvent::Message(MidiMessage::TimingClock),
+                MidiEvent::SysExChunk(&[0x02, 0xF7]),
+                MidiEvent::SysEx(&[0xF0, 0x01, 0xF7]),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_hold_back_events_until_written() {
+        let note = |note: Note| MidiMessage::NoteOn(Channel::C1, note, Value7::new(100));
+        let mut sink = EventSink::<_, 8>::new(Output {
+            written: Vec::new(),
+            space: 5,
+        });
+        let waker = counting_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        assert!(Pin::new(&mut sink).poll_ready(&mut cx).is_ready());
+        Pin::new(&mut sink)
+            .start_send(MidiEvent::Message(note(Note::C3)))
+            .unwrap();
+        assert!(Pin::new(&mut sink).poll_ready(&mut cx).is_ready());
+        Pin::new(&mut sink)
+            .start_send(MidiEvent::Message(note(Note::D3)))
+            .unwrap();
+        assert!(Pin::new(&mut sink).poll_ready(&mut cx).is_ready());
+        Pin::new(&mut sink)
+            .start_send(MidiEvent::SysEx(&[0xF0, 0x01, 0xF7]))
+            .unwrap();
+
+        // The output is full, the sink is not ready until it accepted the system exclusive
+        assert!(Pin::new(&mut sink).poll_ready(&mut cx).is_pending());
+        sink.writer.space = 8;
+        assert!(Pin::new(&mut sink).poll_flush(&mut cx).is_ready());
+        assert_eq!(
+            sink.release().written,
+            [0x90, 60, 100, 62, 100, 0xF0, 0x01, 0xF7]
+        );
+        assert_eq!(
+            Pin::new(&mut EventSink::<_, 2>::new(Output {
+                written: Vec::new(),
+                space: 8
+            }))
+            .start_send(MidiEvent::SysEx(&[0xF0, 0x01, 0xF7])),
+            Err(SinkError::BufferTooSmall)
+        );
+    }
+}
@@ -0,0 +1,112 @@
+//! Latency compensation for output ports
+//!
+//! A device sending to outputs with different latencies, like a DIN port and an internal sound
+//! engine, keeps them aligned by giving every output its own queue with the latency of that
+//! output. Messages are scheduled for the moment they should be heard and sent early by the
+//! latency of the output.
+
+use crate::midi_types::MidiMessage;
+use crate::scheduler::Scheduler;
+
+/// A queue of up to `N` messages for one output, offsetting transmit times by the latency of the
+/// output
+///
+/// Times are plain `u32` values in any unit, as for the scheduler. A positive latency sends
+/// messages earlier, a negative latency delays them.
+#[derive(Debug, Clone, Default)]
+pub struct CompensatedOutput<const N: usize> {
+    scheduler: Scheduler<N>,
+    latency: i32,
+}
+
+impl<const N: usize> CompensatedOutput<N> {
+    pub const fn new(latency: i32) -> Self {
+        CompensatedOutput {
+            scheduler: Scheduler::new(),
+            latency,
+        }
+    }
+
+    pub fn latency(&self) -> i32 {
+        self.latency
+    }
+
+    /// Change the latency, messages that were already scheduled keep their transmit time
+    pub fn set_latency(&mut self, latency: i32) {
+        self.latency = latency;
+    }
+
+    /// The time a message that should be heard at `time` is sent
+    pub fn transmit_time(&self, time: u32) -> u32 {
+        time.wrapping_sub(self.latency as u32)
+    }
+
+    /// Schedule a message to be heard at `time`, returns the message back when the queue is
+    /// full
+    pub fn schedule(&mut self, time: u32, message: MidiMessage) -> Result<(), MidiMessage> {
+        self.scheduler.schedule(self.transmit_time(time), message)
+    }
+
+    /// The number of messages waiting to be sent
+    pub fn len(&self) -> usize {
+        self.scheduler.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scheduler.is_empty()
+    }
+
+    /// Drop all messages that were not sent yet
+    pub fn clear(&mut self) {
+        self.scheduler.clear();
+    }
+
+    /// Pass all messages that should be sent at `now` to `emit`, messages that should have been
+    /// sent before they were scheduled are sent right away
+    pub fn poll(&mut self, now: u32, emit: impl FnMut(MidiMessage)) {
+        self.scheduler.poll(now, emit);
+    }
+
+    /// Remove and return the first message that should be sent at `now`
+    pub fn pop_due(&mut self, now: u32) -> Option<MidiMessage> {
+        self.scheduler.pop_due(now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::vec::Vec;
+
+    #[test]
+    fn should_align_outputs_with_different_latency() {
+        let mut din = CompensatedOutput::<4>::new(3);
+        let mut engine = CompensatedOutput::<4>::new(-2);
+        din.schedule(10, MidiMessage::Start).unwrap();
+        engine.schedule(10, MidiMessage::Start).unwrap();
+
+        let mut sent = Vec::new();
+        for now in 0..20 {
+            din.poll(now, |message| sent.push(("din", now, message)));
+            engine.poll(now, |message| sent.push(("engine", now, message)));
+        }
+
+        assert_eq!(
+            sent,
+            [
+                ("din", 7, MidiMessage::Start),
+                ("engine", 12, MidiMessage::Start),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_send_late_messages_right_away() {
+        let mut output = CompensatedOutput::<4>::new(10);
+        output.schedule(105, MidiMessage::Stop).unwrap();
+
+        assert_eq!(output.pop_due(100), Some(MidiMessage::Stop));
+        assert!(output.is_empty());
+    }
+}
@@ -0,0 +1,313 @@
+//! Midi learn binding incoming messages to parameter slots
+
+use crate::midi_types::{Channel, Control, MidiMessage, Note};
+use crate::value::{U14, U7};
+
+const DATA_ENTRY_MSB: u8 = 6;
+const DATA_ENTRY_LSB: u8 = 38;
+const NRPN_LSB: u8 = 98;
+const NRPN_MSB: u8 = 99;
+const RPN_LSB: u8 = 100;
+const RPN_MSB: u8 = 101;
+
+/// Scale a 7 bit value to the full 14 bit range
+fn scale(value: u8) -> u16 {
+    U14::from_parts(U7::masked(value), U7::masked(value)).get()
+}
+
+/// The message a parameter slot is bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binding {
+    Control(Channel, Control),
+
+    /// Note velocities, note-off sets the value to zero
+    Note(Channel, Note),
+
+    /// A non-registered parameter number set through data entry
+    Nrpn(Channel, u16),
+
+    PitchBend(Channel),
+}
+
+impl Binding {
+    /// The size of a serialized binding
+    pub const SIZE: usize = 3;
+
+    /// Serialize a binding, `None` is serialized as zeros
+    pub fn to_bytes(binding: Option<Binding>) -> [u8; Binding::SIZE] {
+        match binding {
+            None => [0; 3],
+            Some(Binding::Control(channel, control)) => {
+                [0x10 | u8::from(channel), control.into(), 0]
+            }
+            Some(Binding::Note(channel, note)) => [0x20 | u8::from(channel), note.into(), 0],
+            Some(Binding::Nrpn(channel, number)) => {
+                let [lsb, msb] = U14::masked(number).to_bytes();
+                [0x30 | u8::from(channel), msb, lsb]
+            }
+            Some(Binding::PitchBend(channel)) => [0x40 | u8::from(channel), 0, 0],
+        }
+    }
+
+    /// Deserialize a binding, returns `None` for empty or invalid bytes
+    pub fn from_bytes(bytes: [u8; Binding::SIZE]) -> Option<Binding> {
+        let [kind, first, second] = bytes;
+        let channel = Channel::from(kind & 0x0f);
+        if first > 0x7f || second > 0x7f {
+            return None;
+        }
+
+        match kind >> 4 {
+            1 => Some(Binding::Control(channel, first.into())),
+            2 => Some(Binding::Note(channel, first.into())),
+            3 => Some(Binding::Nrpn(channel, U14::from_bytes(second, first).get())),
+            4 => Some(Binding::PitchBend(channel)),
+            _ => None,
+        }
+    }
+}
+
+/// Parameter selection for data entry on a channel
+#[derive(Debug, Clone, Copy, Default)]
+struct DataEntry {
+    nrpn: Option<u16>,
+    registered: bool,
+    number_msb: u8,
+    value_msb: u8,
+}
+
+/// Binds up to `N` parameter slots to incoming messages
+///
+/// After `learn` is called for a slot the next control change, note-on, NRPN data entry or pitch
+/// bend is bound to that slot. Matching messages are then routed to the slot with a 14 bit value,
+/// 7 bit values are scaled to the full 14 bit range.
+#[derive(Debug, Clone)]
+pub struct MidiLearn<const N: usize> {
+    bindings: [Option<Binding>; N],
+    learning: Option<usize>,
+    data_entry: [DataEntry; 16],
+}
+
+impl<const N: usize> Default for MidiLearn<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> MidiLearn<N> {
+    pub fn new() -> Self {
+        MidiLearn {
+            bindings: [None; N],
+            learning: None,
+            data_entry: [DataEntry::default(); 16],
+        }
+    }
+
+    /// Bind the next suitable message to a slot
+    pub fn learn(&mut self, slot: usize) {
+        if slot < N {
+            self.learning = Some(slot);
+        }
+    }
+
+    pub fn cancel_learn(&mut self) {
+        self.learning = None;
+    }
+
+    /// The slot that is waiting for a message to be bound
+    pub fn learning(&self) -> Option<usize> {
+        self.learning
+    }
+
+    pub fn binding(&self, slot: usize) -> Option<Binding> {
+        self.bindings.get(slot).copied().flatten()
+    }
+
+    pub fn set_binding(&mut self, slot: usize, binding: Option<Binding>) {
+        if let Some(bound) = self.bindings.get_mut(slot) {
+            *bound = binding;
+        }
+    }
+
+    /// Serialize the bindings into `buffer`, returns the number of bytes written or `None` if the
+    /// buffer is smaller than `N * Binding::SIZE` bytes
+    pub fn save(&self, buffer: &mut [u8]) -> Option<usize> {
+        let len = N * Binding::SIZE;
+        let buffer = buffer.get_mut(..len)?;
+        for (chunk, binding) in buffer
+            .chunks_exact_mut(Binding::SIZE)
+            .zip(self.bindings.iter())
+        {
+            chunk.copy_from_slice(&Binding::to_bytes(*binding));
+        }
+        Some(len)
+    }
+
+    /// Load bindings saved with `save`, slots missing from `bytes` are cleared
+    pub fn load(&mut self, bytes: &[u8]) {
+        let mut chunks = bytes.chunks_exact(Binding::SIZE);
+        for binding in self.bindings.iter_mut() {
+            *binding = match chunks.next() {
+                Some(&[kind, first, second]) => Binding::from_bytes([kind, first, second]),
+                _ => None,
+            };
+        }
+    }
+
+    /// Handle an incoming message, learning it when a slot is waiting for a binding. Calls `route`
+    /// with the slot and value for every slot bound to the message.
+    pub fn handle(&mut self, message: &MidiMessage, mut route: impl FnMut(usize, u16)) {
+        let (binding, value) = match self.binding_value(message) {
+            Some(bound) => bound,
+            None => return,
+        };
+
+        if let Some(slot) = self.learning.take() {
+            self.set_binding(slot, Some(binding));
+        }
+
+        for (slot, bound) in self.bindings.iter().enumerate() {
+            if *bound == Some(binding) {
+                route(slot, value);
+            }
+        }
+    }
+
+    /// The binding a message matches and its 14 bit value
+    fn binding_value(&mut self, message: &MidiMessage) -> Option<(Binding, u16)> {
+        match *message {
+            MidiMessage::ControlChange(channel, control, value) => {
+                self.control_change(channel, control.into(), value.into())
+            }
+            MidiMessage::NoteOn(channel, note, velocity) => {
+                Some((Binding::Note(channel, note), scale(velocity.into())))
+            }
+            MidiMessage::NoteOff(channel, note, _) => Some((Binding::Note(channel, note), 0)),
+            MidiMessage::PitchBendChange(channel, value) => {
+                Some((Binding::PitchBend(channel), value.into()))
+            }
+            _ => None,
+        }
+        .filter(|(binding, value)| {
+            // Only note-ons are learned, a note-off would bind the key that was just released
+            self.learning.is_none() || !matches!(binding, Binding::Note(..)) || *value > 0
+        })
+    }
+
+    fn control_change(
+        &mut self,
+        channel: Channel,
+        control: u8,
+        value: u8,
+    ) -> Option<(Binding, u16)> {
+        let entry = self.data_entry.get_mut(u8::from(channel) as usize)?;
+
+        match control {
+            NRPN_MSB => entry.number_msb = value,
+            NRPN_LSB => {
+                entry.nrpn = Some(U14::from_bytes(value, entry.number_msb).get());
+                entry.registered = false;
+            }
+            RPN_MSB | RPN_LSB => {
+                entry.nrpn = None;
+                entry.registered = true;
+            }
+            DATA_ENTRY_MSB if entry.nrpn.is_some() => {
+                entry.value_msb = value;
+                let number = entry.nrpn?;
+                return Some((
+                    Binding::Nrpn(channel, number),
+                    U14::from_bytes(0, value).get(),
+                ));
+            }
+            DATA_ENTRY_LSB if entry.nrpn.is_some() => {
+                let number = entry.nrpn?;
+                return Some((
+                    Binding::Nrpn(channel, number),
+                    U14::from_bytes(value, entry.value_msb).get(),
+                ));
+            }
+            // Data entry for registered parameters is not bound
+            DATA_ENTRY_MSB | DATA_ENTRY_LSB if entry.registered => {}
+            control => {
+                return Some((Binding::Control(channel, control.into()), scale(value)));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::vec::Vec;
+
+    fn cc(control: u8, value: u8) -> MidiMessage {
+        MidiMessage::ControlChange(Channel::C3, control.into(), value.into())
+    }
+
+    fn handle<const N: usize>(learn: &mut MidiLearn<N>, message: MidiMessage) -> Vec<(usize, u16)> {
+        let mut routed = Vec::new();
+        learn.handle(&message, |slot, value| routed.push((slot, value)));
+        routed
+    }
+
+    #[test]
+    fn should_learn_and_route_messages() {
+        let mut learn = MidiLearn::<4>::new();
+        assert_eq!(handle(&mut learn, cc(74, 10)), []);
+
+        learn.learn(1);
+        assert_eq!(handle(&mut learn, cc(74, 127)), [(1, 0x3fff)]);
+        assert_eq!(learn.learning(), None);
+        assert_eq!(handle(&mut learn, cc(74, 0)), [(1, 0)]);
+
+        learn.learn(2);
+        let note = MidiMessage::NoteOff(Channel::C1, Note::C3, 0.into());
+        assert_eq!(handle(&mut learn, note), []);
+        let bend = MidiMessage::PitchBendChange(Channel::C1, 0i16.into());
+        assert_eq!(handle(&mut learn, bend), [(2, 0x2000)]);
+        assert_eq!(learn.binding(2), Some(Binding::PitchBend(Channel::C1)));
+    }
+
+    #[test]
+    fn should_learn_nrpn() {
+        let mut learn = MidiLearn::<2>::new();
+        learn.learn(0);
+
+        assert_eq!(handle(&mut learn, cc(NRPN_MSB, 1)), []);
+        assert_eq!(handle(&mut learn, cc(NRPN_LSB, 2)), []);
+        assert_eq!(handle(&mut learn, cc(DATA_ENTRY_MSB, 3)), [(0, 3 << 7)]);
+        assert_eq!(handle(&mut learn, cc(DATA_ENTRY_LSB, 4)), [(0, 3 << 7 | 4)]);
+        assert_eq!(
+            learn.binding(0),
+            Some(Binding::Nrpn(Channel::C3, 1 << 7 | 2))
+        );
+
+        // Data entry for registered parameters is not learned
+        handle(&mut learn, cc(RPN_MSB, 0));
+        learn.learn(1);
+        assert_eq!(handle(&mut learn, cc(DATA_ENTRY_MSB, 3)), []);
+        assert_eq!(learn.learning(), Some(1));
+    }
+
+    #[test]
+    fn should_save_and_load_bindings() {
+        let mut learn = MidiLearn::<5>::new();
+        learn.set_binding(0, Some(Binding::Control(Channel::C2, Control::new(7))));
+        learn.set_binding(1, Some(Binding::Note(Channel::C16, Note::C3)));
+        learn.set_binding(2, Some(Binding::Nrpn(Channel::C1, 0x3fff)));
+        learn.set_binding(4, Some(Binding::PitchBend(Channel::C10)));
+
+        let mut buffer = [0u8; 16];
+        assert_eq!(learn.save(&mut buffer[..14]), None);
+        assert_eq!(learn.save(&mut buffer), Some(15));
+
+        let mut loaded = MidiLearn::<5>::new();
+        loaded.load(&buffer);
+        for slot in 0..5 {
+            assert_eq!(loaded.binding(slot), learn.binding(slot));
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! Legato and retrigger detection for note input
+
+use crate::midi_types::{MidiMessage, Note, Value7};
+use crate::notes::HeldNotes;
+
+/// How a note-on relates to the notes that were already held
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Articulation {
+    /// No other notes were held, envelopes should be retriggered
+    Retrigger,
+
+    /// Another note was still held, the note should be played legato
+    Legato,
+}
+
+/// Note events annotated with their articulation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LegatoEvent {
+    /// A note was pressed
+    NoteOn {
+        note: Note,
+        velocity: Value7,
+        articulation: Articulation,
+    },
+
+    /// A held note was released, `on_velocity` is the velocity of the matching note-on and
+    /// `remaining` is the number of notes still held after this release
+    NoteOff {
+        note: Note,
+        on_velocity: Value7,
+        off_velocity: Value7,
+        remaining: usize,
+    },
+}
+
+/// Classifies note-ons as legato or retriggered and pairs note-offs with their note-ons. Keeps
+/// track of up to `N` held notes.
+#[derive(Debug, Clone, Default)]
+pub struct LegatoDetector<const N: usize> {
+    held: HeldNotes<N>,
+}
+
+impl<const N: usize> LegatoDetector<N> {
+    pub fn new() -> Self {
+        LegatoDetector {
+            held: HeldNotes::new(),
+        }
+    }
+
+    /// The notes that are currently held
+    pub fn held(&self) -> &HeldNotes<N> {
+        &self.held
+    }
+
+    pub fn note_on(&mut self, note: Note, velocity: Value7) -> LegatoEvent {
+        let articulation = if self.held.iter().any(|(held, _)| *held != note) {
+            Articulation::Legato
+        } else {
+            Articulation::Retrigger
+        };
+
+        self.held.press(note, velocity);
+
+        LegatoEvent::NoteOn {
+            note,
+            velocity,
+            articulation,
+        }
+    }
+
+    /// Release a note, returns `None` when the note was not held
+    pub fn note_off(&mut self, note: Note, velocity: Value7) -> Option<LegatoEvent> {
+        let (_, on_velocity) = self.held.iter().copied().find(|(held, _)| *held == note)?;
+        self.held.release(note);
+
+        Some(LegatoEvent::NoteOff {
+            note,
+            on_velocity,
+            off_velocity: velocity,
+            remaining: self.held.len(),
+        })
+    }
+
+    /// Forget all held notes
+    pub fn reset(&mut self) {
+        self.held.clear();
+    }
+
+    /// Handle a midi message, note-on messages with velocity 0 are treated as note-off. Other
+    /// messages are ignored.
+    pub fn handle(&mut self, message: &MidiMessage) -> Option<LegatoEvent> {
+        match *message {
+            MidiMessage::NoteOn(_, note, velocity) if u8::from(velocity) > 0 => {
+                Some(self.note_on(note, velocity))
+            }
+            MidiMessage::NoteOn(_, note, velocity) | MidiMessage::NoteOff(_, note, velocity) => {
+                self.note_off(note, velocity)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_detect_legato_while_other_note_held() {
+        let mut detector = LegatoDetector::<8>::new();
+
+        assert_eq!(
+            detector.note_on(60.into(), 100.into()),
+            LegatoEvent::NoteOn {
+                note: 60.into(),
+                velocity: 100.into(),
+                articulation: Articulation::Retrigger
+            }
+        );
+        assert_eq!(
+            detector.note_on(62.into(), 80.into()),
+            LegatoEvent::NoteOn {
+                note: 62.into(),
+                velocity: 80.into(),
+                articulation: Articulation::Legato
+            }
+        );
+    }
+
+    #[test]
+    fn should_pair_note_off_with_note_on() {
+        let mut detector = LegatoDetector::<8>::new();
+
+        detector.note_on(60.into(), 100.into());
+        detector.note_on(62.into(), 80.into());
+
+        assert_eq!(
+            detector.note_off(60.into(), 10.into()),
+            Some(LegatoEvent::NoteOff {
+                note: 60.into(),
+                on_velocity: 100.into(),
+                off_velocity: 10.into(),
+                remaining: 1
+            })
+        );
+        assert_eq!(detector.note_off(60.into(), 10.into()), None);
+    }
+
+    #[test]
+    fn should_retrigger_after_all_notes_released() {
+        let mut detector = LegatoDetector::<8>::new();
+
+        detector.handle(&MidiMessage::NoteOn(0.into(), 60.into(), 100.into()));
+        detector.handle(&MidiMessage::NoteOn(0.into(), 60.into(), 0.into()));
+
+        assert!(matches!(
+            detector.note_on(62.into(), 80.into()),
+            LegatoEvent::NoteOn {
+                articulation: Articulation::Retrigger,
+                ..
+            }
+        ));
+    }
+}
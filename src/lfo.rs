@@ -0,0 +1,321 @@
+//! Low frequency oscillator emitting control change or pitch bend messages
+//!
+//! The oscillator runs either at a free rate, advanced by the time that passed, or synced to the
+//! midi clock, advanced by timing clock messages. A message is only sent when the output value
+//! changes and no more often than the configured interval.
+
+use crate::midi_types::{Channel, Control, MidiMessage, Value14, Value7};
+use crate::random::Random;
+
+/// Midi clock ticks per quarter note
+const TICKS_PER_QUARTER: u32 = 24;
+
+/// A quarter of a sine wave scaled to 8191
+static QUARTER_SINE: [u16; 65] = [
+    0, 201, 402, 603, 803, 1003, 1202, 1400, 1598, 1795, 1990, 2185, 2378, 2569, 2759, 2948, 3135,
+    3319, 3502, 3683, 3861, 4037, 4211, 4382, 4551, 4716, 4879, 5039, 5196, 5350, 5501, 5648, 5792,
+    5932, 6069, 6202, 6332, 6457, 6579, 6697, 6811, 6920, 7026, 7127, 7224, 7316, 7405, 7488, 7567,
+    7642, 7712, 7778, 7838, 7894, 7946, 7992, 8034, 8070, 8102, 8129, 8152, 8169, 8181, 8189, 8191,
+];
+
+/// The shape of the oscillator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Waveform {
+    Sine,
+    Triangle,
+
+    /// A rising ramp
+    Saw,
+    Square,
+
+    /// A new random value every cycle
+    SampleAndHold,
+}
+
+/// How fast the oscillator runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LfoRate {
+    /// A free running rate in thousandths of a hertz
+    Free { millihertz: u32 },
+
+    /// Synced to the midi clock, one cycle takes `quarters` quarter notes divided by `divisor`
+    Synced { quarters: u8, divisor: u8 },
+}
+
+/// Where the oscillator output is sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LfoTarget {
+    Control(Channel, Control),
+    PitchBend(Channel),
+}
+
+/// A low frequency oscillator sending its output as midi messages
+#[derive(Debug, Clone)]
+pub struct Lfo {
+    waveform: Waveform,
+    rate: LfoRate,
+    target: LfoTarget,
+    depth: Value7,
+    interval_us: u32,
+
+    phase: u32,
+    held: i16,
+    random: Random,
+    since_sent_us: u32,
+    last: Option<u16>,
+}
+
+impl Lfo {
+    /// Create an oscillator at full depth, sending at most a message every millisecond
+    pub fn new(waveform: Waveform, rate: LfoRate, target: LfoTarget) -> Self {
+        Lfo {
+            waveform,
+            rate,
+            target,
+            depth: Value7::new(127),
+            interval_us: 1000,
+            phase: 0,
+            held: 0,
+            random: Random::new(),
+            since_sent_us: u32::MAX,
+            last: None,
+        }
+    }
+
+    pub fn waveform(&self) -> Waveform {
+        self.waveform
+    }
+
+    pub fn set_waveform(&mut self, waveform: Waveform) {
+        self.waveform = waveform;
+    }
+
+    pub fn rate(&self) -> LfoRate {
+        self.rate
+    }
+
+    pub fn set_rate(&mut self, rate: LfoRate) {
+        self.rate = rate;
+    }
+
+    pub fn target(&self) -> LfoTarget {
+        self.target
+    }
+
+    pub fn set_target(&mut self, target: LfoTarget) {
+        self.target = target;
+        self.last = None;
+    }
+
+    pub fn depth(&self) -> Value7 {
+        self.depth
+    }
+
+    /// Set how far the output swings around the center value
+    pub fn set_depth(&mut self, depth: Value7) {
+        self.depth = depth;
+    }
+
+    /// Set the minimum time between messages in microseconds, this only limits free running
+    /// oscillators
+    pub fn set_interval(&mut self, interval_us: u32) {
+        self.interval_us = interval_us;
+    }
+
+    /// Seed the generator used by the sample and hold waveform
+    pub fn set_seed(&mut self, seed: u32) {
+        self.random.seed(seed);
+    }
+
+    /// Start the cycle from the beginning
+    pub fn reset(&mut self) {
+        self.phase = 0;
+    }
+
+    /// Advance a free running oscillator by the elapsed time in microseconds
+    pub fn advance(&mut self, elapsed_us: u32, emit: impl FnMut(MidiMessage)) {
+        if let LfoRate::Free { millihertz } = self.rate {
+            // A full cycle is 2^32, one hertz advances 2^32 per second
+            let step = (millihertz as u128 * elapsed_us as u128) << 32;
+            self.step((step / 1_000_000_000) as u32);
+
+            self.since_sent_us = self.since_sent_us.saturating_add(elapsed_us);
+            if self.since_sent_us >= self.interval_us {
+                self.send(emit);
+            }
+        }
+    }
+
+    /// Advance a synced oscillator by one clock tick
+    pub fn tick(&mut self, emit: impl FnMut(MidiMessage)) {
+        if let LfoRate::Synced { quarters, divisor } = self.rate {
+            let ticks = (quarters.max(1) as u32 * TICKS_PER_QUARTER / divisor.max(1) as u32).max(1);
+            self.send(emit);
+            self.step(((1u64 << 32) / ticks as u64) as u32);
+        }
+    }
+
+    /// Handle a midi message, timing clock messages advance synced oscillators and start
+    /// messages reset them. Other messages are ignored.
+    pub fn handle(&mut self, message: &MidiMessage, emit: impl FnMut(MidiMessage)) {
+        match message {
+            MidiMessage::TimingClock => self.tick(emit),
+            MidiMessage::Start => self.reset(),
+            _ => {}
+        }
+    }
+
+    /// The current output of the waveform between -8191 and 8191
+    pub fn value(&self) -> i16 {
+        let phase = self.phase;
+        match self.waveform {
+            Waveform::Sine => sine(phase),
+            Waveform::Triangle => {
+                // Rise over the first and last quarter, fall in between
+                let shifted = phase.wrapping_add(1 << 30);
+                let ramp = ((shifted & 0x7FFF_FFFF) >> 16) as i32 - 16384;
+                let value = if shifted < 1 << 31 { ramp } else { -ramp };
+                (value / 2).clamp(-8191, 8191) as i16
+            }
+            Waveform::Saw => ((phase >> 18) as i32 - 8192).max(-8191) as i16,
+            Waveform::Square if phase < 1 << 31 => 8191,
+            Waveform::Square => -8191,
+            Waveform::SampleAndHold => self.held,
+        }
+    }
+
+    fn step(&mut self, increment: u32) {
+        let (phase, wrapped) = self.phase.overflowing_add(increment);
+        self.phase = phase;
+
+        if wrapped {
+            self.held = (self.random.below(16383) as i32 - 8191) as i16;
+        }
+    }
+
+    fn send(&mut self, mut emit: impl FnMut(MidiMessage)) {
+        let swing = self.value() as i32 * u8::from(self.depth) as i32 / 127;
+
+        let (value, message) = match self.target {
+            LfoTarget::Control(channel, control) => {
+                let value = ((swing + 8192) >> 7) as u8;
+                (
+                    value as u16,
+                    MidiMessage::ControlChange(channel, control, Value7::new(value)),
+                )
+            }
+            LfoTarget::PitchBend(channel) => {
+                let value = (swing + 8192) as u16;
+                (
+                    value,
+                    MidiMessage::PitchBendChange(channel, Value14::from(value)),
+                )
+            }
+        };
+
+        if self.last != Some(value) {
+            self.last = Some(value);
+            self.since_sent_us = 0;
+            emit(message);
+        }
+    }
+}
+
+/// Look up a sine value from the quarter wave table
+fn sine(phase: u32) -> i16 {
+    let index = (phase >> 24) as usize;
+    let quarter = index % 64;
+    let value = match index / 64 {
+        0 | 2 => QUARTER_SINE.get(quarter),
+        _ => QUARTER_SINE.get(64 - quarter),
+    }
+    .copied()
+    .unwrap_or_default() as i16;
+
+    if index < 128 {
+        value
+    } else {
+        -value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::vec::Vec;
+
+    fn cc_values(lfo: &mut Lfo, ticks: usize) -> Vec<u8> {
+        let mut values = Vec::new();
+        for _ in 0..ticks {
+            lfo.tick(|message| {
+                if let MidiMessage::ControlChange(_, _, value) = message {
+                    values.push(value.into())
+                }
+            });
+        }
+        values
+    }
+
+    #[test]
+    fn should_produce_waveform_shapes() {
+        let mut lfo = Lfo::new(
+            Waveform::Sine,
+            LfoRate::Synced {
+                quarters: 1,
+                divisor: 1,
+            },
+            LfoTarget::PitchBend(Channel::C1),
+        );
+
+        let samples = |lfo: &mut Lfo| {
+            let mut values = [0i16; 4];
+            for value in values.iter_mut() {
+                *value = lfo.value();
+                lfo.phase = lfo.phase.wrapping_add(1 << 30);
+            }
+            values
+        };
+
+        assert_eq!(samples(&mut lfo), [0, 8191, 0, -8191]);
+        lfo.set_waveform(Waveform::Triangle);
+        assert_eq!(samples(&mut lfo), [0, 8191, 0, -8191]);
+        lfo.set_waveform(Waveform::Saw);
+        assert_eq!(samples(&mut lfo), [-8191, -4096, 0, 4096]);
+        lfo.set_waveform(Waveform::Square);
+        assert_eq!(samples(&mut lfo), [8191, 8191, -8191, -8191]);
+    }
+
+    #[test]
+    fn should_follow_the_clock() {
+        let mut lfo = Lfo::new(
+            Waveform::Square,
+            LfoRate::Synced {
+                quarters: 1,
+                divisor: 4,
+            },
+            LfoTarget::Control(Channel::C1, Control::new(74)),
+        );
+
+        // A cycle takes 6 ticks, only changes are sent
+        assert_eq!(cc_values(&mut lfo, 12), [127, 0, 127, 0]);
+    }
+
+    #[test]
+    fn should_limit_free_running_message_rate() {
+        let mut lfo = Lfo::new(
+            Waveform::Saw,
+            LfoRate::Free { millihertz: 1000 },
+            LfoTarget::Control(Channel::C1, Control::new(1)),
+        );
+        lfo.set_interval(10_000);
+
+        let mut messages = 0;
+        for _ in 0..1000 {
+            lfo.advance(1000, |_| messages += 1);
+        }
+
+        // One second of a rising ramp limited to a message every 10ms
+        assert_eq!(messages, 100);
+    }
+}
@@ -2,18 +2,112 @@
 
 #![no_std]
 #![warn(missing_debug_implementations)]
+// Clippy denies indexing, unwrapping and explicit panics outside tests. Arithmetic is not linted,
+// values computed for `midi_types` conversions are range checked through `U7` or `U14` first.
+#![cfg_attr(
+    not(test),
+    deny(
+        clippy::indexing_slicing,
+        clippy::unwrap_used,
+        clippy::expect_used,
+        clippy::panic,
+        clippy::unreachable,
+        clippy::todo,
+        clippy::unimplemented
+    )
+)]
+#[cfg(feature = "std")]
+extern crate std;
+
 use core::fmt::Debug;
 use embedded_hal_nb::serial;
 use midi_convert::midi_types::MidiMessage;
 
-use midi_convert::{
-    parse::MidiParser,
-    render::{MidiRenderer, MidiTransport},
-};
 use nb::block;
+use running::{RunningStatus, StatusRefresh};
 
+pub use error::Error;
 pub use midi_convert::midi_types;
 
+/// Check an internal invariant, only when the `debug-asserts` feature is enabled
+macro_rules! invariant {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "debug-asserts")]
+        assert!($($arg)*);
+    };
+}
+
+pub mod activity;
+pub mod adc;
+pub mod aftertouch;
+pub mod arp;
+pub mod bank;
+pub mod builder;
+pub mod button;
+pub mod chord;
+pub mod congestion;
+pub mod cv;
+pub mod dedupe;
+pub mod duration;
+pub mod echo;
+pub mod encoder;
+pub mod envelope;
+pub mod error;
+pub mod euclid;
+pub mod gate;
+pub mod hires;
+pub mod humanize;
+mod interop;
+pub mod io;
+pub mod latency;
+pub mod learn;
+pub mod legato;
+pub mod lfo;
+pub mod log;
+pub mod looper;
+pub mod mapping;
+pub mod merge;
+pub mod metronome;
+pub mod mono;
+pub mod mpe;
+pub mod name;
+pub mod noteoff;
+pub mod notes;
+pub mod packed;
+pub mod parser;
+pub mod pitch;
+pub mod port;
+pub mod preset;
+pub mod probability;
+pub mod process;
+pub mod pulse;
+pub mod quantize;
+mod random;
+pub mod router;
+pub mod running;
+pub mod scale;
+pub mod scheduler;
+pub mod sequencer;
+pub mod smooth;
+pub mod song;
+pub mod stats;
+pub mod strum;
+pub mod sysex;
+pub mod takeover;
+#[cfg(test)]
+mod test_util;
+pub mod thin;
+pub mod thru;
+pub mod timed;
+pub mod transpose;
+pub mod tuning;
+pub mod value;
+pub mod voice;
+pub mod wire;
+
+pub use io::{MidiSink, MidiSource};
+pub use parser::{MidiEvent, MidiHandler, MidiParser};
+
 #[derive(Debug)]
 pub struct MidiIn<RX> {
     rx: RX,
@@ -35,33 +129,17 @@ where
     pub fn read(&mut self) -> nb::Result<MidiMessage, E> {
         let byte = self.rx.read()?;
 
-        match self.parser.parse(byte) {
+        match self.parser.parse_byte(byte) {
             Some(event) => Ok(event),
             None => Err(nb::Error::WouldBlock),
         }
     }
 }
 
-#[derive(Debug)]
-struct SerialTransport<TX>(TX);
-
-impl<TX, E> MidiTransport for SerialTransport<TX>
-where
-    TX: serial::Write<u8, Error = E>,
-    E: Debug,
-{
-    type Error = E;
-
-    fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
-        bytes
-            .iter()
-            .try_for_each(|value| block!(self.0.write(*value)))
-    }
-}
-
 #[derive(Debug)]
 pub struct MidiOut<TX> {
-    renderer: MidiRenderer<SerialTransport<TX>, true>,
+    tx: TX,
+    running: RunningStatus,
 }
 
 impl<TX, E> MidiOut<TX>
@@ -71,16 +149,31 @@ where
 {
     pub fn new(tx: TX) -> Self {
         MidiOut {
-            renderer: MidiRenderer::new(SerialTransport(tx)),
+            tx,
+            running: RunningStatus::default(),
         }
     }
 
     pub fn release(self) -> TX {
-        self.renderer.release().0
+        self.tx
+    }
+
+    /// Send the full status byte again after a number of bytes or an interval, for receivers that
+    /// lose running status
+    pub fn set_status_refresh(&mut self, refresh: StatusRefresh) {
+        self.running.set_refresh(refresh);
     }
 
     pub fn write(&mut self, message: &MidiMessage) -> Result<(), E> {
-        self.renderer.render(message)
+        self.write_at(message, 0)
+    }
+
+    /// Write a message sent at `now`, the time is only used by `StatusRefresh::AfterTime`
+    pub fn write_at(&mut self, message: &MidiMessage, now: u32) -> Result<(), E> {
+        self.running
+            .render(message, now, &mut [0; 3])
+            .iter()
+            .try_for_each(|byte| block!(self.tx.write(*byte)))
     }
 }
 
@@ -93,13 +186,13 @@ mod tests {
 
     fn verify_writes(messages: &[MidiMessage], bytes: &[u8]) {
         let expectations: Vec<serial::Transaction<u8>> = bytes
-            .into_iter()
+            .iter()
             .map(|byte| serial::Transaction::write(*byte))
             .collect();
         let serial = serial::Mock::new(&expectations);
         let mut midi_out = MidiOut::new(serial);
         for message in messages {
-            midi_out.write(&message).unwrap();
+            midi_out.write(message).unwrap();
         }
         let mut serial = midi_out.release();
         serial.done();
@@ -0,0 +1,192 @@
+//! Ring buffer logging the last midi traffic for debugging
+//!
+//! The log keeps the most recent bytes or messages with their time so they can be dumped after a
+//! device stopped responding, for instance over semihosting or a debug uart. Anything that
+//! implements `core::fmt::Write` can be used to dump the log. With the `defmt` feature the log
+//! and its entries can also be logged with `defmt`, messages are logged as their bytes.
+
+use crate::midi_types::MidiMessage;
+use core::fmt::{self, Write};
+
+#[cfg(feature = "defmt")]
+use crate::packed::PackedMidiEvent;
+
+/// An entry in the log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogEntry {
+    /// A raw byte that was sent or received
+    Byte(u8),
+
+    /// A parsed message
+    Message(MidiMessage),
+}
+
+/// Keeps the last `N` log entries and the time they were logged, older entries are overwritten
+#[derive(Debug, Clone)]
+pub struct EventLog<const N: usize> {
+    entries: [(u32, LogEntry); N],
+    /// Index of the oldest entry
+    start: usize,
+    len: usize,
+    total: u32,
+}
+
+impl<const N: usize> Default for EventLog<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> EventLog<N> {
+    pub const fn new() -> Self {
+        EventLog {
+            entries: [(0, LogEntry::Byte(0)); N],
+            start: 0,
+            len: 0,
+            total: 0,
+        }
+    }
+
+    /// Add an entry, overwriting the oldest entry when the log is full
+    pub fn log(&mut self, time: u32, entry: LogEntry) {
+        if N == 0 {
+            return;
+        }
+
+        invariant!(
+            self.len <= N && self.start < N,
+            "log entries outside the buffer"
+        );
+        let index = (self.start + self.len) % N;
+        if let Some(slot) = self.entries.get_mut(index) {
+            *slot = (time, entry);
+        }
+        if self.len == N {
+            self.start = (self.start + 1) % N;
+        } else {
+            self.len += 1;
+        }
+        self.total = self.total.wrapping_add(1);
+    }
+
+    pub fn log_byte(&mut self, time: u32, byte: u8) {
+        self.log(time, LogEntry::Byte(byte));
+    }
+
+    pub fn log_message(&mut self, time: u32, message: MidiMessage) {
+        self.log(time, LogEntry::Message(message));
+    }
+
+    /// The number of entries in the log
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The number of entries logged since the log was created or cleared, including entries that
+    /// were overwritten
+    pub fn total(&self) -> u32 {
+        self.total
+    }
+
+    pub fn clear(&mut self) {
+        self.start = 0;
+        self.len = 0;
+        self.total = 0;
+    }
+
+    /// Iterate over the entries from the oldest to the newest
+    pub fn iter(&self) -> impl Iterator<Item = (u32, LogEntry)> + '_ {
+        (0..self.len).filter_map(move |offset| self.entries.get((self.start + offset) % N).copied())
+    }
+
+    /// Write the log from the oldest to the newest entry, one entry per line
+    pub fn dump(&self, out: &mut impl Write) -> fmt::Result {
+        let skipped = self.total.wrapping_sub(self.len as u32);
+        if skipped > 0 {
+            writeln!(out, "{} earlier entries", skipped)?;
+        }
+
+        for (time, entry) in self.iter() {
+            match entry {
+                LogEntry::Byte(byte) => writeln!(out, "{:>10} {:02X}", time, byte)?,
+                LogEntry::Message(message) => writeln!(out, "{:>10} {:?}", time, message)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for LogEntry {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            LogEntry::Byte(byte) => defmt::write!(f, "{=u8:02X}", byte),
+            LogEntry::Message(message) => {
+                let event = PackedMidiEvent::from(*message);
+                let bytes = event.to_bytes();
+                defmt::write!(
+                    f,
+                    "{=[u8]:02X}",
+                    bytes.get(..event.len()).unwrap_or_default()
+                )
+            }
+        }
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<const N: usize> defmt::Format for EventLog<N> {
+    fn format(&self, f: defmt::Formatter) {
+        let skipped = self.total.wrapping_sub(self.len as u32);
+        if skipped > 0 {
+            defmt::write!(f, "{=u32} earlier entries", skipped);
+        }
+
+        for (time, entry) in self.iter() {
+            defmt::write!(f, "\n{=u32} {}", time, entry);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::string::String;
+    use std::vec::Vec;
+
+    #[test]
+    fn should_keep_last_entries() {
+        let mut log = EventLog::<3>::new();
+        for byte in 0..5 {
+            log.log_byte(byte as u32 * 10, byte);
+        }
+
+        assert_eq!(log.len(), 3);
+        assert_eq!(log.total(), 5);
+        assert_eq!(
+            log.iter().collect::<Vec<_>>(),
+            [
+                (20, LogEntry::Byte(2)),
+                (30, LogEntry::Byte(3)),
+                (40, LogEntry::Byte(4)),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_dump_entries() {
+        let mut log = EventLog::<2>::new();
+        log.log_byte(1, 0xF8);
+        log.log_byte(2, 0x90);
+        log.log_message(3, MidiMessage::Stop);
+
+        let mut dump = String::new();
+        log.dump(&mut dump).unwrap();
+        assert_eq!(dump, "1 earlier entries\n         2 90\n         3 Stop\n");
+    }
+}
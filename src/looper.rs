@@ -0,0 +1,411 @@
+//! Midi looper recording and overdubbing layers of messages synced to the midi clock
+//!
+//! Messages are stored as packed timed events with the clock tick within the loop as their time,
+//! so a looper holding `N` messages takes a fixed amount of memory. Every overdub adds a layer
+//! that can be undone. Notes that are still held when a recording pass ends get a note-off at
+//! the tick the pass ended, so they do not hang in the loop.
+
+use crate::midi_types::{Channel, MidiMessage, Note};
+use crate::packed::PackedTimedEvent;
+
+/// What the looper is doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LooperState {
+    /// Not playing or recording
+    Stopped,
+
+    /// Recording the first layer, playback starts when the loop wraps around
+    Recording,
+
+    /// Playing the recorded layers
+    Playing,
+
+    /// Playing while recording a new layer
+    Overdubbing,
+}
+
+/// Records and plays back up to `N` messages over a loop of a number of clock ticks
+///
+/// Messages are recorded at the last clock tick that was received, so they play back at most a
+/// tick early.
+#[derive(Debug, Clone)]
+pub struct Looper<const N: usize> {
+    length: u32,
+    state: LooperState,
+    /// The last clock tick that was played, `None` before the first tick
+    position: Option<u32>,
+    events: [PackedTimedEvent; N],
+    layers: [u8; N],
+    len: usize,
+    layer: u8,
+}
+
+impl<const N: usize> Looper<N> {
+    /// Create a looper with a loop of `length` clock ticks, a bar of 4/4 is 96 ticks
+    pub fn new(length: u32) -> Self {
+        Looper {
+            length: length.max(1),
+            state: LooperState::Stopped,
+            position: None,
+            events: [PackedTimedEvent::from_raw(0); N],
+            layers: [0; N],
+            len: 0,
+            layer: 0,
+        }
+    }
+
+    pub fn state(&self) -> LooperState {
+        self.state
+    }
+
+    /// The loop length in clock ticks
+    pub fn length(&self) -> u32 {
+        self.length
+    }
+
+    /// Change the loop length, recorded messages beyond the new length are not played
+    pub fn set_length(&mut self, length: u32) {
+        self.length = length.max(1);
+        self.position = self.position.map(|position| position % self.length);
+    }
+
+    /// The number of recorded messages
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The number of recorded layers
+    pub fn layers(&self) -> u8 {
+        match self.len {
+            0 => 0,
+            _ => self.layer + 1,
+        }
+    }
+
+    /// Drop the recorded loop and record a new one from the start of the loop on the next tick
+    pub fn record(&mut self, emit: impl FnMut(MidiMessage)) {
+        self.stop(emit);
+        self.len = 0;
+        self.layer = 0;
+        self.state = LooperState::Recording;
+    }
+
+    /// Play the recorded loop, ending an overdub
+    pub fn play(&mut self) {
+        if self.state != LooperState::Playing {
+            self.finish_recording();
+            self.state = LooperState::Playing;
+        }
+    }
+
+    /// Record a new layer while playing
+    pub fn overdub(&mut self) {
+        if self.state != LooperState::Overdubbing {
+            self.finish_recording();
+            if self.len > 0 {
+                self.layer = self.layer.saturating_add(1);
+            }
+            self.state = LooperState::Overdubbing;
+        }
+    }
+
+    /// Stop and rewind to the start of the loop, ending notes that are played by the loop
+    pub fn stop(&mut self, emit: impl FnMut(MidiMessage)) {
+        self.finish_recording();
+        self.end_notes(None, emit);
+        self.state = LooperState::Stopped;
+        self.position = None;
+    }
+
+    /// Remove the last recorded layer, ending its notes. An overdub continues to play.
+    pub fn undo(&mut self, emit: impl FnMut(MidiMessage)) {
+        if self.len == 0 {
+            return;
+        }
+
+        let layer = self.layer;
+        self.end_notes(Some(layer), emit);
+        self.remove(|event_layer| event_layer == layer);
+        self.layer = layer.saturating_sub(1);
+        if self.state != LooperState::Stopped {
+            self.state = LooperState::Playing;
+        }
+    }
+
+    /// Record a message when recording, returns false when the message was not recorded
+    /// because the looper is full or not recording. Room is kept for the note-offs of held
+    /// notes, a note-on is only recorded when its note-off fits as well.
+    pub fn input(&mut self, message: &MidiMessage) -> bool {
+        if !matches!(
+            self.state,
+            LooperState::Recording | LooperState::Overdubbing
+        ) {
+            return false;
+        }
+
+        let held = self.held_notes().count();
+        let needed = match *message {
+            MidiMessage::NoteOn(_, _, velocity) if u8::from(velocity) > 0 => 2,
+            MidiMessage::NoteOn(channel, note, _) | MidiMessage::NoteOff(channel, note, _)
+                if self.is_held(channel, note) =>
+            {
+                // Takes the room kept for it
+                0
+            }
+            _ => 1,
+        };
+        if self.len + held + needed > N {
+            return false;
+        }
+        self.push(self.position.unwrap_or(0), *message)
+    }
+
+    /// Advance the loop by one clock tick, emits the recorded messages on this tick
+    pub fn tick(&mut self, mut emit: impl FnMut(MidiMessage)) {
+        if self.state == LooperState::Stopped {
+            return;
+        }
+
+        let position = match self.position {
+            Some(position) => (position + 1) % self.length,
+            None => 0,
+        };
+        if position == 0 && self.state == LooperState::Recording && self.position.is_some() {
+            self.end_held_notes();
+            self.state = LooperState::Playing;
+        }
+        self.position = Some(position);
+
+        if self.state == LooperState::Recording {
+            return;
+        }
+        for event in self.as_slice() {
+            if event.timestamp() == position {
+                if let Ok(message) = event.event().message() {
+                    emit(message);
+                }
+            }
+        }
+    }
+
+    /// Handle a midi message. Timing clock messages advance the loop, start messages rewind it,
+    /// other messages except real-time messages are recorded.
+    pub fn handle(&mut self, message: &MidiMessage, emit: impl FnMut(MidiMessage)) {
+        match message {
+            MidiMessage::TimingClock => self.tick(emit),
+            MidiMessage::Start => self.position = None,
+            MidiMessage::Continue
+            | MidiMessage::Stop
+            | MidiMessage::ActiveSensing
+            | MidiMessage::Reset => {}
+            message => {
+                self.input(message);
+            }
+        }
+    }
+
+    fn as_slice(&self) -> &[PackedTimedEvent] {
+        invariant!(self.len <= N, "more messages recorded than the capacity");
+        self.events.get(..self.len).unwrap_or_default()
+    }
+
+    /// End a recording pass, a loop that was recorded for less than its length is played from
+    /// the start
+    fn finish_recording(&mut self) {
+        if matches!(
+            self.state,
+            LooperState::Recording | LooperState::Overdubbing
+        ) {
+            self.end_held_notes();
+        }
+        if self.state == LooperState::Recording {
+            self.position = None;
+        }
+    }
+
+    fn push(&mut self, time: u32, message: MidiMessage) -> bool {
+        match (self.events.get_mut(self.len), self.layers.get_mut(self.len)) {
+            (Some(event), Some(layer)) => {
+                *event = PackedTimedEvent::new(time, message.into());
+                *layer = self.layer;
+                self.len += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The message recorded at an index when it is in the layer that is being recorded
+    fn recorded(&self, index: usize) -> Option<MidiMessage> {
+        let event = self.as_slice().get(index)?;
+        (self.layers.get(index) == Some(&self.layer))
+            .then(|| event.event().message().ok())
+            .flatten()
+    }
+
+    /// The notes of the layer that is being recorded that did not get a note-off yet
+    fn held_notes(&self) -> impl Iterator<Item = (Channel, Note)> + '_ {
+        (0..self.len).filter_map(move |index| match self.recorded(index)? {
+            MidiMessage::NoteOn(channel, note, velocity) if u8::from(velocity) > 0 => {
+                let released = (index + 1..self.len).any(|later| match self.recorded(later) {
+                    Some(MidiMessage::NoteOn(c, n, velocity)) => {
+                        c == channel && n == note && u8::from(velocity) == 0
+                    }
+                    Some(MidiMessage::NoteOff(c, n, _)) => c == channel && n == note,
+                    _ => false,
+                });
+                (!released).then_some((channel, note))
+            }
+            _ => None,
+        })
+    }
+
+    fn is_held(&self, channel: Channel, note: Note) -> bool {
+        self.held_notes().any(|held| held == (channel, note))
+    }
+
+    /// Record note-offs at the current tick for the notes that are still held
+    fn end_held_notes(&mut self) {
+        let time = self.position.unwrap_or(0);
+        loop {
+            let held = self.held_notes().next();
+            match held {
+                Some((channel, note))
+                    if self.push(time, MidiMessage::NoteOff(channel, note, 0.into())) => {}
+                _ => break,
+            }
+        }
+    }
+
+    /// Send note-offs for the recorded note-ons of a layer, or all layers
+    fn end_notes(&self, layer: Option<u8>, mut emit: impl FnMut(MidiMessage)) {
+        if self.state == LooperState::Stopped || self.state == LooperState::Recording {
+            return;
+        }
+
+        let layers = self.layers.get(..self.len).unwrap_or_default();
+        for (event, event_layer) in self.as_slice().iter().zip(layers) {
+            if layer.map_or(false, |layer| layer != *event_layer) {
+                continue;
+            }
+            if let Ok(MidiMessage::NoteOn(channel, note, _)) = event.event().message() {
+                emit(MidiMessage::NoteOff(channel, note, 0.into()));
+            }
+        }
+    }
+
+    fn remove(&mut self, mut predicate: impl FnMut(u8) -> bool) {
+        let mut kept = 0;
+        for index in 0..self.len {
+            let (event, layer) = match (self.events.get(index), self.layers.get(index)) {
+                (Some(event), Some(layer)) => (*event, *layer),
+                _ => break,
+            };
+            if predicate(layer) {
+                continue;
+            }
+            if let (Some(slot), Some(slot_layer)) =
+                (self.events.get_mut(kept), self.layers.get_mut(kept))
+            {
+                *slot = event;
+                *slot_layer = layer;
+            }
+            kept += 1;
+        }
+        self.len = kept;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use crate::test_util::{off, on};
+    use std::vec::Vec;
+
+    /// Run a loop of four ticks, playing input before the given ticks
+    fn run<const N: usize>(
+        looper: &mut Looper<N>,
+        input: &[(u32, MidiMessage)],
+    ) -> Vec<(u32, MidiMessage)> {
+        let mut output = Vec::new();
+        for tick in 0..4 {
+            looper.tick(|message| output.push((tick, message)));
+            for (_, message) in input.iter().filter(|(time, _)| *time == tick) {
+                looper.input(message);
+            }
+        }
+        output
+    }
+
+    #[test]
+    fn should_record_and_play_loop() {
+        let mut looper = Looper::<8>::new(4);
+        looper.record(|_| {});
+
+        assert_eq!(run(&mut looper, &[(0, on(60)), (2, off(60))]), []);
+        assert_eq!(run(&mut looper, &[]), [(0, on(60)), (2, off(60))]);
+        assert_eq!(looper.state(), LooperState::Playing);
+        assert_eq!(run(&mut looper, &[]), [(0, on(60)), (2, off(60))]);
+    }
+
+    #[test]
+    fn should_overdub_and_undo_layers() {
+        let mut looper = Looper::<8>::new(4);
+        looper.record(|_| {});
+        run(&mut looper, &[(0, on(60)), (2, off(60))]);
+
+        looper.overdub();
+        assert_eq!(
+            run(&mut looper, &[(1, on(64)), (3, off(64))]),
+            [(0, on(60)), (2, off(60))]
+        );
+        assert_eq!(looper.layers(), 2);
+        assert_eq!(
+            run(&mut looper, &[]),
+            [(0, on(60)), (1, on(64)), (2, off(60)), (3, off(64))]
+        );
+
+        let mut ended = Vec::new();
+        looper.undo(|message| ended.push(message));
+        assert_eq!(ended, [off(64)]);
+        assert_eq!(run(&mut looper, &[]), [(0, on(60)), (2, off(60))]);
+    }
+
+    #[test]
+    fn should_end_notes_held_at_the_end_of_the_pass() {
+        let mut looper = Looper::<8>::new(4);
+        looper.record(|_| {});
+
+        assert_eq!(run(&mut looper, &[(2, on(60))]), []);
+        assert_eq!(
+            run(&mut looper, &[(1, off(60))]),
+            [(2, on(60)), (3, off(60))]
+        );
+        assert_eq!(run(&mut looper, &[]), [(2, on(60)), (3, off(60))]);
+
+        // Ending an overdub ends its held notes as well
+        looper.overdub();
+        run(&mut looper, &[(1, on(64))]);
+        looper.play();
+        assert_eq!(
+            run(&mut looper, &[]),
+            [(1, on(64)), (2, on(60)), (3, off(60)), (3, off(64))]
+        );
+    }
+
+    #[test]
+    fn should_keep_room_for_note_offs() {
+        let mut looper = Looper::<3>::new(4);
+        looper.record(|_| {});
+        assert!(looper.input(&on(60)));
+        assert!(!looper.input(&on(62)));
+        assert!(looper.input(&MidiMessage::TuneRequest));
+        assert!(looper.input(&off(60)));
+        assert!(!looper.input(&off(62)));
+    }
+}
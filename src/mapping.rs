@@ -0,0 +1,297 @@
+//! Mapping control changes to parameter ranges
+//!
+//! Maps are saved and loaded as a `Preset` so users can store their assignments.
+
+use crate::midi_types::{Channel, Control, MidiMessage, Value7};
+use crate::preset::{Preset, PresetReader, PresetWriter};
+use crate::Error;
+
+/// Fixed point one used while mapping
+const ONE: i64 = 1 << 16;
+
+fn divide_rounded(numerator: i64, denominator: i64) -> i64 {
+    if numerator < 0 {
+        (numerator - denominator / 2) / denominator
+    } else {
+        (numerator + denominator / 2) / denominator
+    }
+}
+
+/// True for controllers that set a continuous value, where only the latest value matters
+///
+/// Bank select, data entry and increment, parameter number selects, switches like sustain and
+/// channel mode messages are not continuous. Their order relative to other messages matters, so
+/// they must not be thinned or replaced by later values.
+pub fn is_continuous(control: Control) -> bool {
+    !matches!(u8::from(control), 0 | 32 | 6 | 38 | 64..=69 | 96..=101 | 120..=127)
+}
+
+/// The taper applied to control values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Curve {
+    Linear,
+
+    /// Changes slowly near the start of the range and fast near the end, approximated by a square
+    Exponential,
+
+    /// Changes fast near the start of the range and slowly near the end, the inverse of
+    /// `Exponential`
+    Logarithmic,
+}
+
+impl Curve {
+    /// Apply the curve to a fixed point value from 0 to `ONE`
+    fn apply(self, x: i64) -> i64 {
+        match self {
+            Curve::Linear => x,
+            Curve::Exponential => x * x / ONE,
+            Curve::Logarithmic => ONE - (ONE - x) * (ONE - x) / ONE,
+        }
+    }
+
+    /// Map a 7 bit value along the curve to the range from `min` to `max`
+    pub(crate) fn map(self, value: u8, min: i64, max: i64) -> i64 {
+        min + divide_rounded(
+            (max - min) * self.apply(divide_rounded(value as i64 * ONE, 127)),
+            ONE,
+        )
+    }
+}
+
+/// Maps a control change from a channel and controller number to a parameter value from `min` to
+/// `max`, using only integer math
+///
+/// Maps are const so a table of them can bind the parameters of a synth engine:
+///
+/// ```
+/// use embedded_midi::mapping::{Curve, ParameterMap};
+/// use embedded_midi::midi_types::{Channel, Control};
+///
+/// const MAPS: [ParameterMap; 2] = [
+///     ParameterMap::new(Channel::C1, Control::new(74), 20, 20_000).with_curve(Curve::Exponential),
+///     ParameterMap::new(Channel::C1, Control::new(10), -100, 100).bipolar(),
+/// ];
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParameterMap {
+    channel: Channel,
+    control: Control,
+    min: i32,
+    max: i32,
+    curve: Curve,
+    bipolar: bool,
+}
+
+impl ParameterMap {
+    /// Create a linear map from `min` to `max`, `min` may be larger than `max` to invert the
+    /// control
+    pub const fn new(channel: Channel, control: Control, min: i32, max: i32) -> Self {
+        ParameterMap {
+            channel,
+            control,
+            min,
+            max,
+            curve: Curve::Linear,
+            bipolar: false,
+        }
+    }
+
+    pub const fn with_curve(mut self, curve: Curve) -> Self {
+        self.curve = curve;
+        self
+    }
+
+    /// Make the map bipolar, a control value of 64 maps to the center of the range and the curve
+    /// is applied in both directions from there
+    pub const fn bipolar(mut self) -> Self {
+        self.bipolar = true;
+        self
+    }
+
+    pub fn channel(&self) -> Channel {
+        self.channel
+    }
+
+    pub fn control(&self) -> Control {
+        self.control
+    }
+
+    /// Map a control value to the parameter range
+    pub fn map(&self, value: Value7) -> i32 {
+        let value = u8::from(value) as i64;
+        let (min, max) = (self.min as i64, self.max as i64);
+        let lerp = |from: i64, to: i64, x: i64| {
+            from + divide_rounded((to - from) * self.curve.apply(x), ONE)
+        };
+
+        let mapped = if !self.bipolar {
+            self.curve.map(value as u8, min, max)
+        } else {
+            let center = min + (max - min) / 2;
+            if value >= 64 {
+                lerp(center, max, divide_rounded((value - 64) * ONE, 63))
+            } else {
+                lerp(center, min, divide_rounded((64 - value) * ONE, 64))
+            }
+        };
+        mapped as i32
+    }
+
+    /// Map a message if it is a control change for this map
+    pub fn handle(&self, message: &MidiMessage) -> Option<i32> {
+        match *message {
+            MidiMessage::ControlChange(channel, control, value)
+                if channel == self.channel && control == self.control =>
+            {
+                Some(self.map(value))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Preset for ParameterMap {
+    const VERSION: u8 = 1;
+
+    fn save(&self, writer: &mut PresetWriter<'_>) -> Result<(), Error> {
+        writer.write_u8(self.channel.into())?;
+        writer.write_u8(self.control.into())?;
+        writer.write_u32(self.min as u32)?;
+        writer.write_u32(self.max as u32)?;
+        writer.write_u8(self.curve as u8)?;
+        writer.write_u8(self.bipolar as u8)
+    }
+
+    /// Fields missing from the end of the payload keep their values, the map is not changed when
+    /// a field is out of range
+    fn load(&mut self, _: u8, reader: &mut PresetReader<'_>) -> Result<(), Error> {
+        // Stop at the first missing field so a partial field is not read as the next one
+        let channel = reader.read_u8();
+        let control = channel.and_then(|_| reader.read_u8());
+        let min = control.and_then(|_| reader.read_u32());
+        let max = min.and_then(|_| reader.read_u32());
+        let curve = max.and_then(|_| reader.read_u8());
+        let bipolar = curve.and_then(|_| reader.read_u8());
+
+        let curve = curve
+            .map(|curve| match curve {
+                0 => Ok(Curve::Linear),
+                1 => Ok(Curve::Exponential),
+                2 => Ok(Curve::Logarithmic),
+                _ => Err(Error::InvalidValue),
+            })
+            .transpose()?;
+        if channel.map_or(false, |channel| channel > 15)
+            || control.map_or(false, |control| control > 127)
+        {
+            return Err(Error::InvalidValue);
+        }
+
+        self.channel = channel.map_or(self.channel, Channel::from);
+        self.control = control.map_or(self.control, Control::from);
+        self.min = min.map_or(self.min, |min| min as i32);
+        self.max = max.map_or(self.max, |max| max as i32);
+        self.curve = curve.unwrap_or(self.curve);
+        self.bipolar = bipolar.map_or(self.bipolar, |bipolar| bipolar != 0);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(map: ParameterMap, values: &[u8]) -> [i32; 5] {
+        let mut mapped = [0; 5];
+        for (slot, value) in mapped.iter_mut().zip(values) {
+            *slot = map.map(Value7::new(*value));
+        }
+        mapped
+    }
+
+    const VALUES: [u8; 5] = [0, 32, 64, 96, 127];
+
+    #[test]
+    fn should_map_with_curves() {
+        let linear = ParameterMap::new(Channel::C1, Control::new(1), 0, 1270);
+        assert_eq!(map(linear, &VALUES), [0, 320, 640, 960, 1270]);
+
+        let exponential = linear.with_curve(Curve::Exponential);
+        assert_eq!(map(exponential, &VALUES), [0, 81, 323, 726, 1270]);
+
+        let logarithmic = linear.with_curve(Curve::Logarithmic);
+        assert_eq!(map(logarithmic, &VALUES), [0, 559, 957, 1194, 1270]);
+
+        let inverted = ParameterMap::new(Channel::C1, Control::new(1), 100, 0);
+        assert_eq!(map(inverted, &[0, 127]), [100, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn should_map_bipolar_from_center() {
+        let bipolar = ParameterMap::new(Channel::C1, Control::new(1), -100, 100).bipolar();
+        assert_eq!(map(bipolar, &VALUES), [-100, -50, 0, 51, 100]);
+
+        let curved = bipolar.with_curve(Curve::Exponential);
+        assert_eq!(map(curved, &VALUES), [-100, -25, 0, 26, 100]);
+    }
+
+    #[test]
+    fn should_save_and_load_presets() {
+        let map = ParameterMap::new(Channel::C3, Control::new(74), -200, 20_000)
+            .with_curve(Curve::Logarithmic)
+            .bipolar();
+        let mut buffer = [0; 20];
+        let len = crate::preset::save(&map, &mut buffer).unwrap();
+
+        let mut loaded = ParameterMap::new(Channel::C1, Control::new(1), 0, 127);
+        assert_eq!(crate::preset::load(&mut loaded, &buffer[..len]), Ok(len));
+        assert_eq!(loaded, map);
+    }
+
+    #[test]
+    fn should_keep_fields_missing_from_presets() {
+        struct Partial(&'static [u8]);
+
+        impl Preset for Partial {
+            const VERSION: u8 = 1;
+
+            fn save(&self, writer: &mut PresetWriter<'_>) -> Result<(), Error> {
+                writer.write_bytes(self.0)
+            }
+
+            fn load(&mut self, _: u8, _: &mut PresetReader<'_>) -> Result<(), Error> {
+                Ok(())
+            }
+        }
+
+        let map = ParameterMap::new(Channel::C1, Control::new(1), 0, 127).bipolar();
+        let mut buffer = [0; 20];
+
+        // Channel, control and half of the minimum
+        let len = crate::preset::save(&Partial(&[2, 74, 0xFF, 0xFF]), &mut buffer).unwrap();
+        let mut loaded = map;
+        assert_eq!(crate::preset::load(&mut loaded, &buffer[..len]), Ok(len));
+        assert_eq!(
+            loaded,
+            ParameterMap::new(Channel::C3, Control::new(74), 0, 127).bipolar()
+        );
+
+        // A control out of range
+        let len = crate::preset::save(&Partial(&[2, 200]), &mut buffer).unwrap();
+        let mut loaded = map;
+        assert_eq!(
+            crate::preset::load(&mut loaded, &buffer[..len]),
+            Err(Error::InvalidValue)
+        );
+        assert_eq!(loaded, map);
+    }
+
+    #[test]
+    fn should_only_handle_own_control() {
+        let map = ParameterMap::new(Channel::C2, Control::new(7), 0, 127);
+        let message = MidiMessage::ControlChange(Channel::C2, Control::new(7), 42.into());
+        assert_eq!(map.handle(&message), Some(42));
+        let message = MidiMessage::ControlChange(Channel::C1, Control::new(7), 42.into());
+        assert_eq!(map.handle(&message), None);
+    }
+}
@@ -0,0 +1,253 @@
+//! Merging raw byte streams without parsing them into messages
+//!
+//! A hardware merge box forwards bytes as soon as they are received instead of waiting for
+//! complete messages. Bytes of one input are only held back while another input is in the middle
+//! of a message, so messages are never split. When the output switches between inputs a running
+//! status message of an input can no longer rely on the status byte last sent on the output, the
+//! merge inserts the status byte of the input again in that case. Real-time bytes are forwarded
+//! right away, even in the middle of a message.
+
+use crate::parser::{is_channel_status, is_real_time, status_info};
+use crate::sysex::{SYSEX_END, SYSEX_START};
+
+/// The state of one input of the merge
+#[derive(Debug, Clone, Copy)]
+struct Input<const N: usize> {
+    /// The running status of the input, 0 when there is none
+    status: u8,
+
+    /// The number of data bytes still expected for the message in progress
+    remaining: u8,
+    sysex: bool,
+
+    /// Bytes held back while another input is sending a message
+    queue: [u8; N],
+    head: usize,
+    len: usize,
+}
+
+impl<const N: usize> Input<N> {
+    const fn new() -> Self {
+        Input {
+            status: 0,
+            remaining: 0,
+            sysex: false,
+            queue: [0; N],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, byte: u8) -> Result<(), u8> {
+        if self.len == N {
+            return Err(byte);
+        }
+
+        let slot = self.queue.get_mut((self.head + self.len) % N).ok_or(byte)?;
+        *slot = byte;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let byte = self.queue.get(self.head).copied();
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        byte
+    }
+}
+
+/// Merges the byte streams of `IN` inputs into one output, holding back up to `N` bytes per input
+#[derive(Debug, Clone)]
+pub struct ByteMerge<const IN: usize, const N: usize> {
+    inputs: [Input<N>; IN],
+
+    /// The input that is in the middle of sending a message
+    owner: Option<usize>,
+
+    /// The last channel status byte sent on the output, 0 when running status was cancelled
+    output_status: u8,
+}
+
+impl<const IN: usize, const N: usize> Default for ByteMerge<IN, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const IN: usize, const N: usize> ByteMerge<IN, N> {
+    pub const fn new() -> Self {
+        ByteMerge {
+            inputs: [Input::new(); IN],
+            owner: None,
+            output_status: 0,
+        }
+    }
+
+    /// Merge a byte received on an input, passing the bytes that can be sent to `emit`. Returns
+    /// the byte back when the input is out of range or its queue is full.
+    pub fn push(&mut self, input: usize, byte: u8, emit: &mut impl FnMut(u8)) -> Result<(), u8> {
+        if is_real_time(byte) && input < IN {
+            emit(byte);
+            return Ok(());
+        }
+
+        self.inputs.get_mut(input).ok_or(byte)?.push(byte)?;
+        self.drain(emit);
+        Ok(())
+    }
+
+    /// The number of bytes held back for an input
+    pub fn pending(&self, input: usize) -> usize {
+        self.inputs.get(input).map_or(0, |input| input.len)
+    }
+
+    /// True when no input is in the middle of a message
+    pub fn is_idle(&self) -> bool {
+        self.owner.is_none()
+    }
+
+    /// Drop all held back bytes and forget the running status of all inputs
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Forward held back bytes as long as the output is not taken by another input
+    fn drain(&mut self, emit: &mut impl FnMut(u8)) {
+        let mut progressed = true;
+        while progressed {
+            progressed = false;
+            for index in 0..IN {
+                if self.owner.map_or(true, |owner| owner == index) {
+                    if let Some(byte) = self.inputs.get_mut(index).and_then(Input::pop) {
+                        self.forward(index, byte, emit);
+                        progressed = true;
+                    }
+                }
+            }
+        }
+    }
+
+    fn forward(&mut self, index: usize, byte: u8, emit: &mut impl FnMut(u8)) {
+        let ByteMerge {
+            inputs,
+            owner,
+            output_status,
+        } = self;
+        let input = match inputs.get_mut(index) {
+            Some(input) => input,
+            None => return,
+        };
+
+        if byte < 0x80 {
+            if !input.sysex {
+                if input.remaining == 0 {
+                    // A running status message, drop data without a status
+                    if input.status == 0 {
+                        return;
+                    }
+                    if *output_status != input.status {
+                        emit(input.status);
+                        *output_status = input.status;
+                    }
+                    input.remaining = status_info(input.status).len;
+                }
+                input.remaining -= 1;
+                *owner = (input.remaining > 0).then_some(index);
+            }
+            emit(byte);
+            return;
+        }
+
+        // A status byte ends unterminated system exclusive messages
+        if input.sysex {
+            input.sysex = false;
+            *owner = None;
+            emit(SYSEX_END);
+            if byte == SYSEX_END {
+                return;
+            }
+        } else if byte == SYSEX_END {
+            return;
+        }
+
+        input.status = if is_channel_status(byte) { byte } else { 0 };
+        input.remaining = status_info(byte).len;
+        input.sysex = byte == SYSEX_START;
+        *output_status = input.status;
+        *owner = (input.sysex || input.remaining > 0).then_some(index);
+        emit(byte);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::vec::Vec;
+
+    fn merge(merge: &mut ByteMerge<2, 8>, bytes: &[(usize, u8)]) -> Vec<u8> {
+        let mut output = Vec::new();
+        for (input, byte) in bytes {
+            merge
+                .push(*input, *byte, &mut |byte| output.push(byte))
+                .unwrap();
+        }
+        output
+    }
+
+    #[test]
+    fn should_insert_status_when_running_status_is_broken() {
+        let mut bytes = ByteMerge::new();
+        let output = merge(
+            &mut bytes,
+            &[
+                (0, 0x90),
+                (0, 60),
+                (1, 0x91),
+                (1, 62),
+                (0, 100),
+                (0, 64),
+                (1, 100),
+                (0, 90),
+            ],
+        );
+
+        assert_eq!(output, [0x90, 60, 100, 0x91, 62, 100, 0x90, 64, 90]);
+        assert!(bytes.is_idle());
+    }
+
+    #[test]
+    fn should_forward_real_time_bytes_and_hold_back_sysex() {
+        let mut bytes = ByteMerge::new();
+        let output = merge(
+            &mut bytes,
+            &[
+                (0, 0xF0),
+                (0, 0x7E),
+                (1, 0xC0),
+                (1, 0xF8),
+                (1, 5),
+                (0, 0xF7),
+            ],
+        );
+
+        assert_eq!(output, [0xF0, 0x7E, 0xF8, 0xF7, 0xC0, 5]);
+    }
+
+    #[test]
+    fn should_return_bytes_when_queue_is_full() {
+        let mut bytes = ByteMerge::<2, 1>::new();
+        let mut emit = |_| {};
+
+        assert_eq!(bytes.push(0, 0x90, &mut emit), Ok(()));
+        assert_eq!(bytes.push(1, 0x91, &mut emit), Ok(()));
+        assert_eq!(bytes.push(1, 60, &mut emit), Err(60));
+        assert_eq!(bytes.pending(1), 1);
+        assert_eq!(bytes.push(2, 0x90, &mut emit), Err(0x90));
+    }
+}
@@ -0,0 +1,295 @@
+//! Metronome clicks following the midi clock and counting in recordings
+
+use crate::midi_types::{Channel, MidiMessage, Note, Value7};
+
+/// A beat of the metronome, for instance to blink a led
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Beat {
+    /// The bar since the last start, starting at 0
+    pub bar: u32,
+    /// The beat in the bar, starting at 0 for the downbeat
+    pub beat: u8,
+}
+
+impl Beat {
+    pub fn is_downbeat(&self) -> bool {
+        self.beat == 0
+    }
+}
+
+/// Plays a click on every beat with an accent on the downbeat
+///
+/// By default clicks are played on channel 10 with the general midi high wood block for the
+/// downbeat and the low wood block for other beats, four beats to a bar at 24 ticks per quarter
+/// note. Clicks end on the next tick.
+#[derive(Debug, Clone)]
+pub struct Metronome {
+    channel: Channel,
+    accent: (Note, Value7),
+    click: (Note, Value7),
+    ticks_per_beat: u8,
+    beats_per_bar: u8,
+    muted: bool,
+    ticks: u8,
+    beat: Beat,
+    playing: Option<Note>,
+}
+
+impl Default for Metronome {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metronome {
+    pub fn new() -> Self {
+        Metronome {
+            channel: Channel::C10,
+            accent: (Note::new(76), Value7::new(127)),
+            click: (Note::new(77), Value7::new(100)),
+            ticks_per_beat: 24,
+            beats_per_bar: 4,
+            muted: false,
+            ticks: 0,
+            beat: Beat { bar: 0, beat: 0 },
+            playing: None,
+        }
+    }
+
+    pub fn set_channel(&mut self, channel: Channel) {
+        self.channel = channel;
+    }
+
+    /// Set the note played on the downbeat
+    pub fn set_accent(&mut self, note: Note, velocity: Value7) {
+        self.accent = (note, velocity);
+    }
+
+    /// Set the note played on other beats
+    pub fn set_click(&mut self, note: Note, velocity: Value7) {
+        self.click = (note, velocity);
+    }
+
+    /// Set the length of a beat in clock ticks, 12 gives eighth notes
+    pub fn set_ticks_per_beat(&mut self, ticks: u8) {
+        self.ticks_per_beat = ticks.max(1);
+    }
+
+    pub fn set_beats_per_bar(&mut self, beats: u8) {
+        self.beats_per_bar = beats.max(1);
+    }
+
+    /// Stop playing clicks, beats are still reported
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+
+    /// Advance the metronome by one clock tick, emits the clicks due on this tick and returns the
+    /// beat that starts on it
+    pub fn tick(&mut self, mut emit: impl FnMut(MidiMessage)) -> Option<Beat> {
+        self.stop_click(&mut emit);
+
+        let beat = match self.ticks {
+            0 => {
+                let beat = self.beat;
+                let (note, velocity) = if beat.is_downbeat() {
+                    self.accent
+                } else {
+                    self.click
+                };
+                if !self.muted {
+                    self.playing = Some(note);
+                    emit(MidiMessage::NoteOn(self.channel, note, velocity));
+                }
+
+                self.beat.beat += 1;
+                if self.beat.beat >= self.beats_per_bar {
+                    self.beat.beat = 0;
+                    self.beat.bar = self.beat.bar.wrapping_add(1);
+                }
+                Some(beat)
+            }
+            _ => None,
+        };
+
+        self.ticks = (self.ticks + 1) % self.ticks_per_beat;
+        beat
+    }
+
+    /// Start from the first beat of the first bar on the next tick
+    pub fn restart(&mut self) {
+        self.ticks = 0;
+        self.beat = Beat { bar: 0, beat: 0 };
+    }
+
+    /// Handle a midi message. Timing clock messages advance the metronome, start and stop
+    /// messages restart it. Other messages are ignored.
+    pub fn handle(
+        &mut self,
+        message: &MidiMessage,
+        mut emit: impl FnMut(MidiMessage),
+    ) -> Option<Beat> {
+        match message {
+            MidiMessage::TimingClock => self.tick(emit),
+            MidiMessage::Start | MidiMessage::Stop => {
+                self.stop_click(&mut emit);
+                self.restart();
+                None
+            }
+            _ => None,
+        }
+    }
+
+    fn stop_click(&mut self, emit: &mut impl FnMut(MidiMessage)) {
+        if let Some(note) = self.playing.take() {
+            emit(MidiMessage::NoteOff(self.channel, note, Value7::new(0)));
+        }
+    }
+}
+
+/// What happens on a tick of a count-in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CountInEvent {
+    /// A beat of the count-in was played
+    Beat(Beat),
+
+    /// The count-in finished, this tick is the downbeat recording starts on
+    Finished,
+}
+
+/// Counts in a recording with a number of bars of metronome clicks
+///
+/// A start message for slave devices is sent on the last tick of the count-in so they start on
+/// the downbeat with the next clock tick. Playback should be suppressed while counting.
+#[derive(Debug, Clone)]
+pub struct CountIn {
+    metronome: Metronome,
+    bars: u32,
+    counting: bool,
+}
+
+impl CountIn {
+    pub fn new(metronome: Metronome) -> Self {
+        CountIn {
+            metronome,
+            bars: 0,
+            counting: false,
+        }
+    }
+
+    pub fn metronome_mut(&mut self) -> &mut Metronome {
+        &mut self.metronome
+    }
+
+    /// Start counting in `bars` bars from the next tick, without bars recording starts on the
+    /// next tick
+    pub fn arm(&mut self, bars: u32) {
+        self.metronome.restart();
+        self.bars = bars;
+        self.counting = true;
+    }
+
+    /// Stop counting in, ending the click that is playing
+    pub fn disarm(&mut self, mut emit: impl FnMut(MidiMessage)) {
+        self.metronome.stop_click(&mut emit);
+        self.counting = false;
+    }
+
+    /// True while counting in
+    pub fn is_counting(&self) -> bool {
+        self.counting
+    }
+
+    /// Advance the count-in by one clock tick, emits clicks and the start message
+    pub fn tick(&mut self, mut emit: impl FnMut(MidiMessage)) -> Option<CountInEvent> {
+        if !self.counting {
+            return None;
+        }
+
+        if self.is_downbeat_after_count_in() {
+            if self.bars == 0 {
+                emit(MidiMessage::Start);
+            }
+            self.disarm(emit);
+            return Some(CountInEvent::Finished);
+        }
+
+        let beat = self.metronome.tick(&mut emit);
+        if self.is_downbeat_after_count_in() {
+            emit(MidiMessage::Start);
+        }
+        beat.map(CountInEvent::Beat)
+    }
+
+    fn is_downbeat_after_count_in(&self) -> bool {
+        let metronome = &self.metronome;
+        metronome.ticks == 0 && metronome.beat.beat == 0 && metronome.beat.bar == self.bars
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::vec::Vec;
+
+    #[test]
+    fn should_click_on_beats_with_accent() {
+        let mut metronome = Metronome::new();
+        metronome.set_ticks_per_beat(2);
+        metronome.set_beats_per_bar(3);
+
+        let mut notes = Vec::new();
+        let mut beats = Vec::new();
+        for _ in 0..8 {
+            let beat = metronome.tick(|message| {
+                if let MidiMessage::NoteOn(Channel::C10, note, _) = message {
+                    notes.push(u8::from(note))
+                }
+            });
+            beats.extend(beat.map(|beat| (beat.bar, beat.beat)));
+        }
+
+        assert_eq!(notes, [76, 77, 77, 76]);
+        assert_eq!(beats, [(0, 0), (0, 1), (0, 2), (1, 0)]);
+    }
+
+    #[test]
+    fn should_report_beats_when_muted_and_restart() {
+        let mut metronome = Metronome::new();
+        metronome.set_muted(true);
+
+        let mut messages = Vec::new();
+        let beat = metronome.handle(&MidiMessage::TimingClock, |m| messages.push(m));
+        assert_eq!(beat, Some(Beat { bar: 0, beat: 0 }));
+        metronome.handle(&MidiMessage::Start, |m| messages.push(m));
+        let beat = metronome.handle(&MidiMessage::TimingClock, |m| messages.push(m));
+        assert_eq!(beat.map(|beat| beat.is_downbeat()), Some(true));
+        assert!(messages.is_empty());
+    }
+
+    #[test]
+    fn should_count_in_and_start_on_downbeat() {
+        let mut metronome = Metronome::new();
+        metronome.set_ticks_per_beat(2);
+        metronome.set_beats_per_bar(2);
+        let mut count_in = CountIn::new(metronome);
+        count_in.arm(2);
+
+        let mut output = Vec::new();
+        let mut events = Vec::new();
+        for tick in 0..10 {
+            let event = count_in.tick(|message| match message {
+                MidiMessage::NoteOn(_, note, _) => output.push((tick, u8::from(note))),
+                MidiMessage::Start => output.push((tick, 0)),
+                _ => {}
+            });
+            events.extend(event.map(|event| (tick, event)));
+        }
+
+        assert_eq!(output, [(0, 76), (2, 77), (4, 76), (6, 77), (7, 0)]);
+        assert_eq!(events.last(), Some(&(8, CountInEvent::Finished)));
+        assert_eq!(events.len(), 5);
+        assert!(!count_in.is_counting());
+    }
+}
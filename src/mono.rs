@@ -0,0 +1,179 @@
+//! Monophonic note handling with configurable note priority
+
+use crate::midi_types::{MidiMessage, Note, Value7};
+use crate::notes::HeldNotes;
+
+/// Decides which of the held notes sounds when more than one key is pressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotePriority {
+    /// The most recently pressed note sounds
+    Last,
+
+    /// The lowest held note sounds
+    Low,
+
+    /// The highest held note sounds
+    High,
+}
+
+/// Changes to the note a monophonic voice should play
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MonoEvent {
+    /// Start playing a note, replacing the note that is currently playing if any
+    NoteOn(Note, Value7),
+
+    /// Stop playing the current note, no more notes are held
+    NoteOff(Note),
+}
+
+/// Turns polyphonic note input into a single sounding note. Keeps track of up to `N` held notes
+/// so the voice returns to a held note when the sounding note is released.
+#[derive(Debug, Clone)]
+pub struct MonoNoteHandler<const N: usize> {
+    priority: NotePriority,
+    held: HeldNotes<N>,
+    current: Option<Note>,
+}
+
+impl<const N: usize> MonoNoteHandler<N> {
+    pub fn new(priority: NotePriority) -> Self {
+        MonoNoteHandler {
+            priority,
+            held: HeldNotes::new(),
+            current: None,
+        }
+    }
+
+    pub fn priority(&self) -> NotePriority {
+        self.priority
+    }
+
+    /// Change the note priority, this can result in a different note being played
+    pub fn set_priority(&mut self, priority: NotePriority) -> Option<MonoEvent> {
+        self.priority = priority;
+        self.update()
+    }
+
+    /// The note that is currently sounding
+    pub fn current(&self) -> Option<Note> {
+        self.current
+    }
+
+    /// The notes that are currently held
+    pub fn held(&self) -> &HeldNotes<N> {
+        &self.held
+    }
+
+    pub fn note_on(&mut self, note: Note, velocity: Value7) -> Option<MonoEvent> {
+        self.held.press(note, velocity);
+        self.update()
+    }
+
+    pub fn note_off(&mut self, note: Note) -> Option<MonoEvent> {
+        self.held.release(note);
+        self.update()
+    }
+
+    /// Release all held notes
+    pub fn reset(&mut self) -> Option<MonoEvent> {
+        self.held.clear();
+        self.update()
+    }
+
+    /// Handle a midi message, note-on messages with velocity 0 are treated as note-off and
+    /// all-notes-off control changes release all held notes. Other messages are ignored.
+    pub fn handle(&mut self, message: &MidiMessage) -> Option<MonoEvent> {
+        match *message {
+            MidiMessage::NoteOn(_, note, velocity) if u8::from(velocity) > 0 => {
+                self.note_on(note, velocity)
+            }
+            MidiMessage::NoteOn(_, note, _) | MidiMessage::NoteOff(_, note, _) => {
+                self.note_off(note)
+            }
+            MidiMessage::ControlChange(_, control, _) if u8::from(control) == 123 => self.reset(),
+            _ => None,
+        }
+    }
+
+    fn update(&mut self) -> Option<MonoEvent> {
+        let target = match self.priority {
+            NotePriority::Last => self.held.last(),
+            NotePriority::Low => self.held.lowest(),
+            NotePriority::High => self.held.highest(),
+        };
+
+        match (target, self.current) {
+            (Some((note, _)), Some(current)) if note == current => None,
+            (Some((note, velocity)), _) => {
+                self.current = Some(note);
+                Some(MonoEvent::NoteOn(note, velocity))
+            }
+            (None, Some(current)) => {
+                self.current = None;
+                Some(MonoEvent::NoteOff(current))
+            }
+            (None, None) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_return_to_previous_note_with_last_priority() {
+        let mut mono = MonoNoteHandler::<8>::new(NotePriority::Last);
+
+        assert_eq!(
+            mono.note_on(60.into(), 100.into()),
+            Some(MonoEvent::NoteOn(60.into(), 100.into()))
+        );
+        assert_eq!(
+            mono.note_on(64.into(), 90.into()),
+            Some(MonoEvent::NoteOn(64.into(), 90.into()))
+        );
+        assert_eq!(
+            mono.note_off(64.into()),
+            Some(MonoEvent::NoteOn(60.into(), 100.into()))
+        );
+        assert_eq!(
+            mono.note_off(60.into()),
+            Some(MonoEvent::NoteOff(60.into()))
+        );
+    }
+
+    #[test]
+    fn should_keep_lowest_note_with_low_priority() {
+        let mut mono = MonoNoteHandler::<8>::new(NotePriority::Low);
+
+        mono.note_on(60.into(), 100.into());
+        assert_eq!(mono.note_on(64.into(), 100.into()), None);
+        assert_eq!(mono.note_off(64.into()), None);
+        assert_eq!(mono.current(), Some(60.into()));
+    }
+
+    #[test]
+    fn should_return_to_highest_note_with_high_priority() {
+        let mut mono = MonoNoteHandler::<8>::new(NotePriority::High);
+
+        mono.note_on(60.into(), 100.into());
+        mono.note_on(67.into(), 100.into());
+        mono.note_on(64.into(), 100.into());
+        assert_eq!(
+            mono.note_off(67.into()),
+            Some(MonoEvent::NoteOn(64.into(), 100.into()))
+        );
+    }
+
+    #[test]
+    fn should_treat_zero_velocity_as_note_off() {
+        let mut mono = MonoNoteHandler::<8>::new(NotePriority::Last);
+
+        mono.handle(&MidiMessage::NoteOn(0.into(), 60.into(), 100.into()));
+        assert_eq!(
+            mono.handle(&MidiMessage::NoteOn(0.into(), 60.into(), 0.into())),
+            Some(MonoEvent::NoteOff(60.into()))
+        );
+    }
+}
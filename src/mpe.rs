@@ -0,0 +1,446 @@
+//! Midi Polyphonic Expression (MPE) zone configuration and per-note expression tracking
+//!
+//! MPE assigns every sounding note its own member channel so pitch bend, channel pressure and
+//! the timbre controller (CC74) on that channel apply to that note only. Member channels are
+//! grouped in a lower zone managed from channel 1 and an upper zone managed from channel 16.
+//! Zones are configured by sending the MPE Configuration RPN on the manager channel.
+
+use crate::midi_types::{Channel, MidiMessage, Note, Value14, Value7};
+
+const RPN_MSB: u8 = 101;
+const RPN_LSB: u8 = 100;
+const DATA_ENTRY_MSB: u8 = 6;
+const TIMBRE: u8 = 74;
+
+const RPN_PITCH_BEND_RANGE: (u8, u8) = (0, 0);
+const RPN_MPE_CONFIGURATION: (u8, u8) = (0, 6);
+const RPN_NULL: (u8, u8) = (0x7f, 0x7f);
+
+/// Default pitch bend range in semitones for member channels
+pub const DEFAULT_MEMBER_BEND_RANGE: u8 = 48;
+
+/// Default pitch bend range in semitones for manager channels
+pub const DEFAULT_MANAGER_BEND_RANGE: u8 = 2;
+
+/// The two MPE zones
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Zone {
+    /// Zone managed from channel 1, with member channels counting up from channel 2
+    Lower,
+
+    /// Zone managed from channel 16, with member channels counting down from channel 15
+    Upper,
+}
+
+/// The role a channel has in the current zone configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelRole {
+    /// Manager channel, messages apply to all notes in the zone
+    Manager(Zone),
+
+    /// Member channel, messages apply to the note playing on this channel
+    Member(Zone),
+
+    /// Channel is not part of a zone
+    None,
+}
+
+/// Expression values for a single note
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NoteExpression {
+    /// Per-note pitch bend, scaled by the member pitch bend range of the zone
+    pub bend: Value14,
+
+    /// Per-note pressure
+    pub pressure: Value7,
+
+    /// Per-note timbre, sent as CC74
+    pub timbre: Value7,
+}
+
+impl Default for NoteExpression {
+    fn default() -> Self {
+        NoteExpression {
+            bend: Value14::new(0),
+            pressure: Value7::new(0),
+            timbre: Value7::new(64),
+        }
+    }
+}
+
+/// Events emitted by the MPE receiver
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MpeEvent {
+    /// A zone was (re)configured with a number of member channels, zero disables the zone
+    ZoneConfigured { zone: Zone, members: u8 },
+
+    /// A note started on a member channel, `expression` contains the expression values that were
+    /// sent on the channel before the note-on
+    NoteOn {
+        zone: Zone,
+        channel: Channel,
+        note: Note,
+        velocity: Value7,
+        expression: NoteExpression,
+    },
+
+    /// A note stopped on a member channel
+    NoteOff {
+        zone: Zone,
+        channel: Channel,
+        note: Note,
+        velocity: Value7,
+    },
+
+    /// The expression of a playing note changed
+    Expression {
+        zone: Zone,
+        channel: Channel,
+        note: Note,
+        expression: NoteExpression,
+    },
+
+    /// A message on a manager channel that applies to all notes in the zone
+    Zone { zone: Zone, message: MidiMessage },
+
+    /// A message that is not handled as MPE
+    Other(MidiMessage),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ChannelState {
+    note: Option<Note>,
+    expression: NoteExpression,
+    rpn: (u8, u8),
+}
+
+/// Keeps track of the MPE zone configuration and per-note expression, and consolidates incoming
+/// channel messages into per-note events.
+#[derive(Debug, Clone)]
+pub struct MpeReceiver {
+    lower_members: u8,
+    upper_members: u8,
+    member_bend_range: [u8; 2],
+    manager_bend_range: [u8; 2],
+    channels: [ChannelState; 16],
+}
+
+impl MpeReceiver {
+    /// Create a receiver with both zones disabled
+    pub fn new() -> Self {
+        MpeReceiver {
+            lower_members: 0,
+            upper_members: 0,
+            member_bend_range: [DEFAULT_MEMBER_BEND_RANGE; 2],
+            manager_bend_range: [DEFAULT_MANAGER_BEND_RANGE; 2],
+            channels: [ChannelState {
+                note: None,
+                expression: NoteExpression::default(),
+                rpn: RPN_NULL,
+            }; 16],
+        }
+    }
+
+    /// The number of member channels in a zone, zero when the zone is disabled
+    pub fn members(&self, zone: Zone) -> u8 {
+        match zone {
+            Zone::Lower => self.lower_members,
+            Zone::Upper => self.upper_members,
+        }
+    }
+
+    /// Configure a zone, zones that would overlap are shrunk so the most recently configured
+    /// zone gets the channels it asked for
+    pub fn configure(&mut self, zone: Zone, members: u8) {
+        let members = members.min(15);
+
+        match zone {
+            Zone::Lower => {
+                self.lower_members = members;
+                self.upper_members = self.upper_members.min(14u8.saturating_sub(members));
+            }
+            Zone::Upper => {
+                self.upper_members = members;
+                self.lower_members = self.lower_members.min(14u8.saturating_sub(members));
+            }
+        }
+
+        // Configuring a zone resets the pitch bend ranges to their defaults
+        self.set_bend_range(ChannelRole::Member(zone), DEFAULT_MEMBER_BEND_RANGE);
+        self.set_bend_range(ChannelRole::Manager(zone), DEFAULT_MANAGER_BEND_RANGE);
+    }
+
+    /// The pitch bend range in semitones for the member channels of a zone
+    pub fn member_bend_range(&self, zone: Zone) -> u8 {
+        self.member_bend_range
+            .get(zone as usize)
+            .copied()
+            .unwrap_or(DEFAULT_MEMBER_BEND_RANGE)
+    }
+
+    /// The pitch bend range in semitones for the manager channel of a zone
+    pub fn manager_bend_range(&self, zone: Zone) -> u8 {
+        self.manager_bend_range
+            .get(zone as usize)
+            .copied()
+            .unwrap_or(DEFAULT_MANAGER_BEND_RANGE)
+    }
+
+    /// The role of a channel in the current configuration
+    pub fn role(&self, channel: Channel) -> ChannelRole {
+        let channel = u8::from(channel);
+        let lower = self.lower_members;
+        let upper = self.upper_members;
+
+        if lower > 0 && channel == 0 {
+            ChannelRole::Manager(Zone::Lower)
+        } else if upper > 0 && channel == 15 {
+            ChannelRole::Manager(Zone::Upper)
+        } else if lower > 0 && channel >= 1 && channel <= lower {
+            ChannelRole::Member(Zone::Lower)
+        } else if upper > 0 && channel >= 15 - upper && channel <= 14 {
+            ChannelRole::Member(Zone::Upper)
+        } else {
+            ChannelRole::None
+        }
+    }
+
+    /// The note playing on a member channel and its expression
+    pub fn note(&self, channel: Channel) -> Option<(Note, NoteExpression)> {
+        let state = self.channels.get(u8::from(channel) as usize)?;
+        state.note.map(|note| (note, state.expression))
+    }
+
+    /// Handle an incoming message
+    pub fn handle(&mut self, message: &MidiMessage) -> Option<MpeEvent> {
+        if let MidiMessage::ControlChange(channel, control, value) = *message {
+            if let Some(event) = self.handle_rpn(channel, control.into(), value.into()) {
+                return event;
+            }
+        }
+
+        let channel = match message_channel(message) {
+            Some(channel) => channel,
+            None => return Some(MpeEvent::Other(*message)),
+        };
+
+        match self.role(channel) {
+            ChannelRole::Manager(zone) => Some(MpeEvent::Zone {
+                zone,
+                message: *message,
+            }),
+            ChannelRole::Member(zone) => self.handle_member(zone, channel, message),
+            ChannelRole::None => Some(MpeEvent::Other(*message)),
+        }
+    }
+
+    fn set_bend_range(&mut self, role: ChannelRole, range: u8) {
+        let slot = match role {
+            ChannelRole::Manager(zone) => self.manager_bend_range.get_mut(zone as usize),
+            ChannelRole::Member(zone) => self.member_bend_range.get_mut(zone as usize),
+            ChannelRole::None => None,
+        };
+
+        if let Some(slot) = slot {
+            *slot = range;
+        }
+    }
+
+    /// Track registered parameter numbers, returns `Some` when the message was consumed
+    fn handle_rpn(&mut self, channel: Channel, control: u8, value: u8) -> Option<Option<MpeEvent>> {
+        let state = self.channels.get_mut(u8::from(channel) as usize)?;
+
+        match control {
+            RPN_MSB => state.rpn.0 = value,
+            RPN_LSB => state.rpn.1 = value,
+            DATA_ENTRY_MSB if state.rpn == RPN_MPE_CONFIGURATION => {
+                let zone = match u8::from(channel) {
+                    0 => Zone::Lower,
+                    15 => Zone::Upper,
+                    _ => return None,
+                };
+                self.configure(zone, value);
+                return Some(Some(MpeEvent::ZoneConfigured {
+                    zone,
+                    members: self.members(zone),
+                }));
+            }
+            DATA_ENTRY_MSB if state.rpn == RPN_PITCH_BEND_RANGE => {
+                self.set_bend_range(self.role(channel), value)
+            }
+            _ => {}
+        }
+
+        None
+    }
+
+    fn handle_member(
+        &mut self,
+        zone: Zone,
+        channel: Channel,
+        message: &MidiMessage,
+    ) -> Option<MpeEvent> {
+        let state = self.channels.get_mut(u8::from(channel) as usize)?;
+
+        match *message {
+            MidiMessage::NoteOn(_, note, velocity) if u8::from(velocity) > 0 => {
+                state.note = Some(note);
+                Some(MpeEvent::NoteOn {
+                    zone,
+                    channel,
+                    note,
+                    velocity,
+                    expression: state.expression,
+                })
+            }
+            MidiMessage::NoteOn(_, note, velocity) | MidiMessage::NoteOff(_, note, velocity) => {
+                if state.note == Some(note) {
+                    state.note = None;
+                }
+                Some(MpeEvent::NoteOff {
+                    zone,
+                    channel,
+                    note,
+                    velocity,
+                })
+            }
+            MidiMessage::PitchBendChange(_, bend) => {
+                state.expression.bend = bend;
+                expression_event(zone, channel, state)
+            }
+            MidiMessage::ChannelPressure(_, pressure) => {
+                state.expression.pressure = pressure;
+                expression_event(zone, channel, state)
+            }
+            MidiMessage::ControlChange(_, control, value) if u8::from(control) == TIMBRE => {
+                state.expression.timbre = value;
+                expression_event(zone, channel, state)
+            }
+            _ => Some(MpeEvent::Other(*message)),
+        }
+    }
+}
+
+impl Default for MpeReceiver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn expression_event(zone: Zone, channel: Channel, state: &ChannelState) -> Option<MpeEvent> {
+    state.note.map(|note| MpeEvent::Expression {
+        zone,
+        channel,
+        note,
+        expression: state.expression,
+    })
+}
+
+fn message_channel(message: &MidiMessage) -> Option<Channel> {
+    match *message {
+        MidiMessage::NoteOff(channel, ..)
+        | MidiMessage::NoteOn(channel, ..)
+        | MidiMessage::KeyPressure(channel, ..)
+        | MidiMessage::ControlChange(channel, ..)
+        | MidiMessage::ProgramChange(channel, ..)
+        | MidiMessage::ChannelPressure(channel, ..)
+        | MidiMessage::PitchBendChange(channel, ..) => Some(channel),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn configure(mpe: &mut MpeReceiver, channel: u8, members: u8) -> Option<MpeEvent> {
+        let cc = |control: u8, value: u8| {
+            MidiMessage::ControlChange(channel.into(), control.into(), value.into())
+        };
+
+        mpe.handle(&cc(RPN_MSB, 0));
+        mpe.handle(&cc(RPN_LSB, 6));
+        mpe.handle(&cc(DATA_ENTRY_MSB, members))
+    }
+
+    #[test]
+    fn should_configure_zones_from_rpn() {
+        let mut mpe = MpeReceiver::new();
+
+        assert_eq!(
+            configure(&mut mpe, 0, 7),
+            Some(MpeEvent::ZoneConfigured {
+                zone: Zone::Lower,
+                members: 7
+            })
+        );
+        assert_eq!(mpe.role(0.into()), ChannelRole::Manager(Zone::Lower));
+        assert_eq!(mpe.role(7.into()), ChannelRole::Member(Zone::Lower));
+        assert_eq!(mpe.role(8.into()), ChannelRole::None);
+    }
+
+    #[test]
+    fn should_shrink_overlapping_zone() {
+        let mut mpe = MpeReceiver::new();
+
+        configure(&mut mpe, 0, 10);
+        configure(&mut mpe, 15, 8);
+
+        assert_eq!(mpe.members(Zone::Lower), 6);
+        assert_eq!(mpe.members(Zone::Upper), 8);
+        assert_eq!(mpe.role(7.into()), ChannelRole::Member(Zone::Upper));
+    }
+
+    #[test]
+    fn should_consolidate_per_note_expression() {
+        let mut mpe = MpeReceiver::new();
+        configure(&mut mpe, 0, 15);
+
+        mpe.handle(&MidiMessage::ControlChange(
+            2.into(),
+            TIMBRE.into(),
+            20.into(),
+        ));
+        assert_eq!(
+            mpe.handle(&MidiMessage::NoteOn(2.into(), 60.into(), 100.into())),
+            Some(MpeEvent::NoteOn {
+                zone: Zone::Lower,
+                channel: 2.into(),
+                note: 60.into(),
+                velocity: 100.into(),
+                expression: NoteExpression {
+                    timbre: 20.into(),
+                    ..NoteExpression::default()
+                }
+            })
+        );
+        assert_eq!(
+            mpe.handle(&MidiMessage::ChannelPressure(2.into(), 80.into())),
+            Some(MpeEvent::Expression {
+                zone: Zone::Lower,
+                channel: 2.into(),
+                note: 60.into(),
+                expression: NoteExpression {
+                    pressure: 80.into(),
+                    timbre: 20.into(),
+                    ..NoteExpression::default()
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn should_pass_manager_messages_as_zone_messages() {
+        let mut mpe = MpeReceiver::new();
+        configure(&mut mpe, 15, 3);
+
+        let message = MidiMessage::PitchBendChange(15.into(), 1000i16.into());
+        assert_eq!(
+            mpe.handle(&message),
+            Some(MpeEvent::Zone {
+                zone: Zone::Upper,
+                message
+            })
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! Note names like "C#3" or "Db3"
+//!
+//! There are two conventions for numbering octaves. Yamaha and `midi_types` call note 60 C3, so
+//! the lowest note is C-2. Scientific pitch notation, used by Roland and most music theory, calls
+//! note 60 C4 and the lowest note C-1. Config files, serial consoles and displays should agree on
+//! one convention, the default is the one of `Note::C3`.
+//!
+//! `Note` is a type of `midi_types`, names are parsed and formatted through the `NoteName`
+//! wrapper.
+
+use crate::midi_types::Note;
+use crate::value::U7;
+use core::convert::TryFrom;
+use core::fmt;
+use core::str::FromStr;
+
+const SHARPS: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+const FLATS: [&str; 12] = [
+    "C", "Db", "D", "Eb", "E", "F", "Gb", "G", "Ab", "A", "Bb", "B",
+];
+
+/// How octaves are numbered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Octaves {
+    /// Note 60 is C3, like `Note::C3`
+    #[default]
+    Yamaha,
+
+    /// Note 60 is C4
+    Scientific,
+}
+
+impl Octaves {
+    /// The number of the octave of note 0
+    const fn lowest(self) -> i32 {
+        match self {
+            Octaves::Yamaha => -2,
+            Octaves::Scientific => -1,
+        }
+    }
+}
+
+/// The text is not a note name or names a note outside the midi range
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidNoteName;
+
+impl fmt::Display for InvalidNoteName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid note name")
+    }
+}
+
+/// Parses and formats the name of a note
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NoteName {
+    pub note: Note,
+    pub octaves: Octaves,
+
+    /// Format black keys with flats instead of sharps
+    pub flats: bool,
+}
+
+impl NoteName {
+    pub const fn new(note: Note, octaves: Octaves) -> Self {
+        NoteName {
+            note,
+            octaves,
+            flats: false,
+        }
+    }
+
+    /// Format black keys with flats, like "Db3"
+    pub const fn with_flats(mut self) -> Self {
+        self.flats = true;
+        self
+    }
+
+    /// Parse a name like "C#3", "Db3" or "a-1" with the given octave numbering
+    pub fn parse(name: &str, octaves: Octaves) -> Result<Self, InvalidNoteName> {
+        let mut chars = name.chars();
+        let class = match chars.next().map(|letter| letter.to_ascii_uppercase()) {
+            Some('C') => 0,
+            Some('D') => 2,
+            Some('E') => 4,
+            Some('F') => 5,
+            Some('G') => 7,
+            Some('A') => 9,
+            Some('B') => 11,
+            _ => return Err(InvalidNoteName),
+        };
+        let rest = chars.as_str();
+        let (class, octave) = if let Some(octave) = rest.strip_prefix('#') {
+            (class + 1, octave)
+        } else if let Some(octave) = rest.strip_prefix('b') {
+            (class - 1, octave)
+        } else {
+            (class, rest)
+        };
+
+        let octave = i32::from_str(octave).map_err(|_| InvalidNoteName)?;
+        let note = octave
+            .checked_sub(octaves.lowest())
+            .and_then(|octave| octave.checked_mul(12))
+            .and_then(|note| note.checked_add(class))
+            .and_then(|note| u8::try_from(note).ok())
+            .and_then(U7::checked)
+            .ok_or(InvalidNoteName)?;
+
+        Ok(NoteName::new(note.into(), octaves))
+    }
+}
+
+impl From<NoteName> for Note {
+    fn from(name: NoteName) -> Self {
+        name.note
+    }
+}
+
+impl FromStr for NoteName {
+    type Err = InvalidNoteName;
+
+    /// Parse a name with the default octave numbering, where note 60 is C3
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        NoteName::parse(name, Octaves::default())
+    }
+}
+
+impl fmt::Display for NoteName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let note = u8::from(self.note);
+        let names = if self.flats { &FLATS } else { &SHARPS };
+        let name = names.get(note as usize % 12).copied().unwrap_or_default();
+        let octave = (note / 12) as i32 + self.octaves.lowest();
+        write!(f, "{}{}", name, octave)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::string::ToString;
+
+    #[test]
+    fn should_parse_note_names() {
+        assert_eq!("C3".parse::<NoteName>().map(Note::from), Ok(Note::C3));
+        assert_eq!(
+            NoteName::parse("C4", Octaves::Scientific).map(Note::from),
+            Ok(Note::C3)
+        );
+        assert_eq!("c#3".parse::<NoteName>().map(Note::from), Ok(Note::Cs3));
+        assert_eq!("Db3".parse::<NoteName>().map(Note::from), Ok(Note::Cs3));
+        assert_eq!("C-2".parse::<NoteName>().map(Note::from), Ok(0.into()));
+        assert_eq!("G8".parse::<NoteName>().map(Note::from), Ok(127.into()));
+        assert_eq!("Cb-2".parse::<NoteName>(), Err(InvalidNoteName));
+        assert_eq!("G#8".parse::<NoteName>(), Err(InvalidNoteName));
+        assert_eq!("H3".parse::<NoteName>(), Err(InvalidNoteName));
+        assert_eq!("C".parse::<NoteName>(), Err(InvalidNoteName));
+        assert_eq!("C2147483647".parse::<NoteName>(), Err(InvalidNoteName));
+    }
+
+    #[test]
+    fn should_format_note_names() {
+        assert_eq!(NoteName::new(Note::C3, Octaves::Yamaha).to_string(), "C3");
+        assert_eq!(
+            NoteName::new(Note::Cs3, Octaves::Scientific).to_string(),
+            "C#4"
+        );
+        assert_eq!(
+            NoteName::new(Note::Cs3, Octaves::Yamaha)
+                .with_flats()
+                .to_string(),
+            "Db3"
+        );
+        assert_eq!(NoteName::new(0.into(), Octaves::Yamaha).to_string(), "C-2");
+    }
+}
@@ -0,0 +1,142 @@
+//! Notes that release themselves after a duration
+
+use crate::midi_types::{Channel, MidiMessage, Note, Value7};
+use crate::scheduler::Scheduler;
+
+/// Plays notes for a fixed duration, holding up to `N` pending note-offs
+///
+/// Durations are in the unit used to advance time, like clock ticks or microseconds.
+#[derive(Debug, Clone, Default)]
+pub struct AutoNoteOff<const N: usize> {
+    scheduler: Scheduler<N>,
+    now: u32,
+}
+
+impl<const N: usize> AutoNoteOff<N> {
+    pub const fn new() -> Self {
+        AutoNoteOff {
+            scheduler: Scheduler::new(),
+            now: 0,
+        }
+    }
+
+    /// The number of notes waiting for their note-off
+    pub fn pending(&self) -> usize {
+        self.scheduler.len()
+    }
+
+    /// Play a note and release it after `duration`. A note that is still playing is released
+    /// first so it is retriggered. Returns the note-on back without playing it when no more
+    /// note-offs can be scheduled.
+    pub fn play(
+        &mut self,
+        channel: Channel,
+        note: Note,
+        velocity: Value7,
+        duration: u32,
+        mut emit: impl FnMut(MidiMessage),
+    ) -> Result<(), MidiMessage> {
+        let note_on = MidiMessage::NoteOn(channel, note, velocity);
+        let note_off = MidiMessage::NoteOff(channel, note, Value7::new(0));
+
+        if self.is_playing(channel, note) {
+            self.note_off(channel, note, &mut emit);
+        }
+        if self.scheduler.is_full() {
+            return Err(note_on);
+        }
+
+        emit(note_on);
+        self.scheduler
+            .schedule(self.now.wrapping_add(duration), note_off)
+            .map_err(|_| note_on)
+    }
+
+    /// Check if a note is waiting for its note-off
+    pub fn is_playing(&self, channel: Channel, note: Note) -> bool {
+        self.scheduler
+            .iter()
+            .any(|(_, message)| is_note_off(&message, channel, note))
+    }
+
+    /// Release a note before its duration ends, cancelling its scheduled note-off
+    pub fn note_off(&mut self, channel: Channel, note: Note, mut emit: impl FnMut(MidiMessage)) {
+        let mut released = false;
+        self.scheduler.cancel(|message| {
+            let matches = is_note_off(message, channel, note);
+            released |= matches;
+            matches
+        });
+        if released {
+            emit(MidiMessage::NoteOff(channel, note, Value7::new(0)));
+        }
+    }
+
+    /// Release all notes right away
+    pub fn all_off(&mut self, mut emit: impl FnMut(MidiMessage)) {
+        self.scheduler.cancel(|message| {
+            emit(*message);
+            true
+        });
+    }
+
+    /// Advance time and release the notes that are due
+    pub fn advance(&mut self, elapsed: u32, emit: impl FnMut(MidiMessage)) {
+        self.now = self.now.wrapping_add(elapsed);
+        self.scheduler.poll(self.now, emit);
+    }
+}
+
+fn is_note_off(message: &MidiMessage, channel: Channel, note: Note) -> bool {
+    matches!(message, MidiMessage::NoteOff(c, n, _) if *c == channel && *n == note)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use crate::test_util::{off, on};
+    use std::vec::Vec;
+
+    #[test]
+    fn should_release_notes_after_duration() {
+        let mut notes = AutoNoteOff::<4>::new();
+        let mut output = Vec::new();
+
+        notes
+            .play(Channel::C1, 60.into(), 100.into(), 10, |m| output.push(m))
+            .unwrap();
+        notes.advance(5, |m| output.push(m));
+        notes
+            .play(Channel::C1, 64.into(), 100.into(), 10, |m| output.push(m))
+            .unwrap();
+        notes.advance(5, |m| output.push(m));
+        // Retriggering a playing note releases it first
+        notes
+            .play(Channel::C1, 64.into(), 100.into(), 10, |m| output.push(m))
+            .unwrap();
+        notes.advance(10, |m| output.push(m));
+
+        assert_eq!(output, [on(60), on(64), off(60), off(64), on(64), off(64)]);
+        assert_eq!(notes.pending(), 0);
+    }
+
+    #[test]
+    fn should_cancel_scheduled_note_off_on_manual_release() {
+        let mut notes = AutoNoteOff::<1>::new();
+        let mut output = Vec::new();
+
+        notes
+            .play(Channel::C1, 60.into(), 100.into(), 10, |m| output.push(m))
+            .unwrap();
+        assert_eq!(
+            notes.play(Channel::C1, 62.into(), 100.into(), 10, |m| output.push(m)),
+            Err(on(62))
+        );
+        notes.note_off(Channel::C1, 60.into(), |m| output.push(m));
+        notes.note_off(Channel::C1, 60.into(), |m| output.push(m));
+        notes.advance(20, |m| output.push(m));
+
+        assert_eq!(output, [on(60), off(60)]);
+    }
+}
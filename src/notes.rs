@@ -0,0 +1,183 @@
+//! Tracking of currently held notes
+
+use crate::midi_types::{Note, Value7};
+
+/// Which press of a note a note-off releases when the note was pressed again before it was
+/// released, for instance by two controllers playing the same note
+///
+/// Components that remember something about every press, like its start time or the note it
+/// was transposed to, use this to pair note-offs with note-ons. `HeldNotes` always treats a
+/// repeated press as the same note.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NoteOffPolicy {
+    /// Presses of a note are counted as one note that ends when it received as many note-offs
+    /// as note-ons
+    Count,
+
+    /// A note-off releases the earliest press of the note
+    #[default]
+    FirstOff,
+
+    /// A note-off releases the most recent press of the note
+    LastOff,
+}
+
+impl NoteOffPolicy {
+    /// The index of the press a note-off releases, from presses ordered from the oldest to the
+    /// most recent. With the `Count` policy there is at most one matching press.
+    pub(crate) fn release<T>(
+        self,
+        presses: &[T],
+        pressed: impl FnMut(&T) -> bool,
+    ) -> Option<usize> {
+        match self {
+            NoteOffPolicy::Count | NoteOffPolicy::FirstOff => presses.iter().position(pressed),
+            NoteOffPolicy::LastOff => presses.iter().rposition(pressed),
+        }
+    }
+}
+
+/// A fixed capacity list of held notes and their velocities, ordered by the moment they were
+/// pressed. When the list is full the oldest note is dropped to make room for a new one.
+#[derive(Debug, Clone)]
+pub struct HeldNotes<const N: usize> {
+    notes: [(Note, Value7); N],
+    len: usize,
+}
+
+impl<const N: usize> HeldNotes<N> {
+    /// Create an empty list of held notes
+    pub const fn new() -> Self {
+        HeldNotes {
+            notes: [(Note::MIN, Value7::new(0)); N],
+            len: 0,
+        }
+    }
+
+    /// Register a pressed note, a note that was already held is moved to the end of the list
+    pub fn press(&mut self, note: Note, velocity: Value7) {
+        if N == 0 {
+            return;
+        }
+
+        self.release(note);
+
+        if self.len == N {
+            self.notes.copy_within(1.., 0);
+            self.len -= 1;
+        }
+
+        if let Some(slot) = self.notes.get_mut(self.len) {
+            *slot = (note, velocity);
+            self.len += 1;
+        }
+    }
+
+    /// Register a released note, returns true if the note was held
+    pub fn release(&mut self, note: Note) -> bool {
+        match self.position(note) {
+            Some(index) => {
+                self.notes.copy_within(index + 1..self.len, index);
+                self.len -= 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Release all notes
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Check if a note is currently held
+    pub fn is_held(&self, note: Note) -> bool {
+        self.position(note).is_some()
+    }
+
+    /// The number of held notes
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check if no notes are held
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Iterate over the held notes from the oldest to the most recently pressed
+    pub fn iter(&self) -> core::slice::Iter<'_, (Note, Value7)> {
+        self.as_slice().iter()
+    }
+
+    /// The held notes from the oldest to the most recently pressed
+    pub fn as_slice(&self) -> &[(Note, Value7)] {
+        invariant!(self.len <= N, "more notes held than the capacity");
+        &self.notes[..self.len]
+    }
+
+    /// The most recently pressed note
+    pub fn last(&self) -> Option<(Note, Value7)> {
+        self.as_slice().last().copied()
+    }
+
+    /// The lowest held note
+    pub fn lowest(&self) -> Option<(Note, Value7)> {
+        self.iter().copied().min_by_key(|(note, _)| u8::from(*note))
+    }
+
+    /// The highest held note
+    pub fn highest(&self) -> Option<(Note, Value7)> {
+        self.iter().copied().max_by_key(|(note, _)| u8::from(*note))
+    }
+
+    fn position(&self, note: Note) -> Option<usize> {
+        self.iter().position(|(held, _)| *held == note)
+    }
+}
+
+impl<const N: usize> Default for HeldNotes<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_keep_notes_in_order_pressed() {
+        let mut notes = HeldNotes::<4>::new();
+        notes.press(60.into(), 100.into());
+        notes.press(64.into(), 100.into());
+        notes.press(60.into(), 90.into());
+
+        assert_eq!(
+            notes.as_slice(),
+            &[(64.into(), 100.into()), (60.into(), 90.into())]
+        );
+    }
+
+    #[test]
+    fn should_drop_oldest_note_when_full() {
+        let mut notes = HeldNotes::<2>::new();
+        notes.press(60.into(), 100.into());
+        notes.press(62.into(), 100.into());
+        notes.press(64.into(), 100.into());
+
+        assert!(!notes.is_held(60.into()));
+        assert_eq!(notes.len(), 2);
+    }
+
+    #[test]
+    fn should_release_notes() {
+        let mut notes = HeldNotes::<4>::new();
+        notes.press(60.into(), 100.into());
+        notes.press(64.into(), 100.into());
+
+        assert!(notes.release(60.into()));
+        assert!(!notes.release(60.into()));
+        assert_eq!(notes.lowest(), Some((64.into(), 100.into())));
+    }
+}
@@ -0,0 +1,306 @@
+//! Compact 32 bit representation of midi events
+//!
+//! Packed events store the status byte and up to two data bytes of a message in a single `u32`,
+//! so long sequences and queues take up a third of the space a `MidiMessage` does. Timed events
+//! add a timestamp in the upper half of a `u64` so they sort by time.
+
+use crate::midi_types::MidiMessage;
+use crate::parser::{build_message, is_channel_status, status_info, MidiEvent};
+use crate::value::U14;
+use core::convert::TryFrom;
+
+/// Errors converting to or from packed events
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackError {
+    /// System exclusive messages do not fit a packed event
+    SysEx,
+
+    /// The packed value does not hold a valid message
+    Invalid,
+}
+
+/// A midi message packed into 32 bits, the status byte is stored in bits 16 to 23 followed by
+/// the data bytes, unused data bytes are zero
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PackedMidiEvent(u32);
+
+impl PackedMidiEvent {
+    /// Pack the bytes of a message
+    pub const fn from_bytes(status: u8, first: u8, second: u8) -> Self {
+        PackedMidiEvent((status as u32) << 16 | (first as u32) << 8 | second as u32)
+    }
+
+    /// Create a packed event from its raw value, the value is checked when it is unpacked
+    pub const fn from_raw(raw: u32) -> Self {
+        PackedMidiEvent(raw)
+    }
+
+    /// The raw packed value
+    pub const fn to_raw(self) -> u32 {
+        self.0
+    }
+
+    /// The status byte
+    pub const fn status(self) -> u8 {
+        (self.0 >> 16) as u8
+    }
+
+    /// The status byte and data bytes
+    pub const fn to_bytes(self) -> [u8; 3] {
+        [self.status(), (self.0 >> 8) as u8, self.0 as u8]
+    }
+
+    /// The number of bytes of the message when it is sent
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(self) -> usize {
+        1 + status_info(self.status()).len as usize
+    }
+
+    /// Unpack the message
+    pub fn message(self) -> Result<MidiMessage, PackError> {
+        let [status, first, second] = self.to_bytes();
+        if status < 0x80 || first >= 0x80 || second >= 0x80 || self.0 >> 24 != 0 {
+            return Err(PackError::Invalid);
+        }
+
+        build_message(status_info(status).kind, status, first, second).ok_or(PackError::Invalid)
+    }
+}
+
+impl From<MidiMessage> for PackedMidiEvent {
+    fn from(message: MidiMessage) -> Self {
+        let (status, first, second) = match message {
+            MidiMessage::NoteOff(channel, note, velocity) => {
+                (0x80 | u8::from(channel), note.into(), velocity.into())
+            }
+            MidiMessage::NoteOn(channel, note, velocity) => {
+                (0x90 | u8::from(channel), note.into(), velocity.into())
+            }
+            MidiMessage::KeyPressure(channel, note, value) => {
+                (0xA0 | u8::from(channel), note.into(), value.into())
+            }
+            MidiMessage::ControlChange(channel, control, value) => {
+                (0xB0 | u8::from(channel), control.into(), value.into())
+            }
+            MidiMessage::ProgramChange(channel, program) => {
+                (0xC0 | u8::from(channel), program.into(), 0)
+            }
+            MidiMessage::ChannelPressure(channel, value) => {
+                (0xD0 | u8::from(channel), value.into(), 0)
+            }
+            MidiMessage::PitchBendChange(channel, value) => {
+                let [lsb, msb] = U14::from(value).to_bytes();
+                (0xE0 | u8::from(channel), lsb, msb)
+            }
+            MidiMessage::QuarterFrame(frame) => (0xF1, frame.into(), 0),
+            MidiMessage::SongPositionPointer(position) => {
+                let [lsb, msb] = U14::from(position).to_bytes();
+                (0xF2, lsb, msb)
+            }
+            MidiMessage::SongSelect(song) => (0xF3, song.into(), 0),
+            MidiMessage::TuneRequest => (0xF6, 0, 0),
+            MidiMessage::TimingClock => (0xF8, 0, 0),
+            MidiMessage::Start => (0xFA, 0, 0),
+            MidiMessage::Continue => (0xFB, 0, 0),
+            MidiMessage::Stop => (0xFC, 0, 0),
+            MidiMessage::ActiveSensing => (0xFE, 0, 0),
+            MidiMessage::Reset => (0xFF, 0, 0),
+        };
+
+        Self::from_bytes(status, first, second)
+    }
+}
+
+impl TryFrom<PackedMidiEvent> for MidiMessage {
+    type Error = PackError;
+
+    fn try_from(event: PackedMidiEvent) -> Result<Self, Self::Error> {
+        event.message()
+    }
+}
+
+impl<'a> TryFrom<MidiEvent<'a>> for PackedMidiEvent {
+    type Error = PackError;
+
+    fn try_from(event: MidiEvent<'a>) -> Result<Self, Self::Error> {
+        match event {
+            MidiEvent::Message(message) => Ok(message.into()),
+            MidiEvent::SysEx(_) | MidiEvent::SysExChunk(_) => Err(PackError::SysEx),
+            #[cfg(feature = "unknown-messages")]
+            MidiEvent::Unknown { .. } => Err(PackError::Invalid),
+        }
+    }
+}
+
+impl TryFrom<PackedMidiEvent> for MidiEvent<'_> {
+    type Error = PackError;
+
+    fn try_from(event: PackedMidiEvent) -> Result<Self, Self::Error> {
+        event.message().map(MidiEvent::Message)
+    }
+}
+
+/// Channel voice messages of three bytes, like note-ons and control changes
+impl TryFrom<MidiEvent<'_>> for [u8; 3] {
+    type Error = PackError;
+
+    fn try_from(event: MidiEvent<'_>) -> Result<Self, Self::Error> {
+        let packed = PackedMidiEvent::try_from(event)?;
+        if is_channel_status(packed.status()) && packed.len() == 3 {
+            Ok(packed.to_bytes())
+        } else {
+            Err(PackError::Invalid)
+        }
+    }
+}
+
+/// Channel voice messages of two bytes, program changes and channel pressure
+impl TryFrom<MidiEvent<'_>> for [u8; 2] {
+    type Error = PackError;
+
+    fn try_from(event: MidiEvent<'_>) -> Result<Self, Self::Error> {
+        let packed = PackedMidiEvent::try_from(event)?;
+        match (is_channel_status(packed.status()), packed.to_bytes()) {
+            (true, [status, first, _]) if packed.len() == 2 => Ok([status, first]),
+            _ => Err(PackError::Invalid),
+        }
+    }
+}
+
+impl TryFrom<[u8; 3]> for MidiEvent<'static> {
+    type Error = PackError;
+
+    fn try_from([status, first, second]: [u8; 3]) -> Result<Self, Self::Error> {
+        let packed = PackedMidiEvent::from_bytes(status, first, second);
+        if is_channel_status(status) && packed.len() == 3 {
+            MidiEvent::try_from(packed)
+        } else {
+            Err(PackError::Invalid)
+        }
+    }
+}
+
+impl TryFrom<[u8; 2]> for MidiEvent<'static> {
+    type Error = PackError;
+
+    fn try_from([status, first]: [u8; 2]) -> Result<Self, Self::Error> {
+        let packed = PackedMidiEvent::from_bytes(status, first, 0);
+        if is_channel_status(status) && packed.len() == 2 {
+            MidiEvent::try_from(packed)
+        } else {
+            Err(PackError::Invalid)
+        }
+    }
+}
+
+/// A packed event with a 32 bit timestamp, timed events are ordered by their timestamp first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PackedTimedEvent(u64);
+
+impl PackedTimedEvent {
+    pub const fn new(timestamp: u32, event: PackedMidiEvent) -> Self {
+        PackedTimedEvent((timestamp as u64) << 32 | event.0 as u64)
+    }
+
+    /// Create a timed event from its raw value
+    pub const fn from_raw(raw: u64) -> Self {
+        PackedTimedEvent(raw)
+    }
+
+    /// The raw packed value
+    pub const fn to_raw(self) -> u64 {
+        self.0
+    }
+
+    pub const fn timestamp(self) -> u32 {
+        (self.0 >> 32) as u32
+    }
+
+    pub const fn event(self) -> PackedMidiEvent {
+        PackedMidiEvent(self.0 as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::midi_types::{Channel, Control, Note, Program, Value14, Value7};
+
+    #[test]
+    fn should_pack_and_unpack_losslessly() {
+        let messages = [
+            MidiMessage::NoteOn(Channel::C3, Note::C4, Value7::new(100)),
+            MidiMessage::ControlChange(Channel::C16, Control::new(1), Value7::new(127)),
+            MidiMessage::ProgramChange(Channel::C1, Program::new(5)),
+            MidiMessage::PitchBendChange(Channel::C2, 1000i16.into()),
+            MidiMessage::SongPositionPointer(Value14::from((0x12, 0x34))),
+            MidiMessage::TimingClock,
+        ];
+
+        for message in messages.iter() {
+            let packed = PackedMidiEvent::from(*message);
+            assert_eq!(MidiMessage::try_from(packed), Ok(*message));
+            assert_eq!(
+                MidiEvent::try_from(packed),
+                Ok(MidiEvent::Message(*message))
+            );
+        }
+    }
+
+    #[test]
+    fn should_store_wire_bytes() {
+        let packed =
+            PackedMidiEvent::from(MidiMessage::PitchBendChange(Channel::C1, 0x2000u16.into()));
+        assert_eq!(packed.to_raw(), 0x00E0_0040);
+        assert_eq!(packed.len(), 3);
+        assert_eq!(PackedMidiEvent::from(MidiMessage::Stop).len(), 1);
+    }
+
+    #[test]
+    fn should_reject_invalid_events() {
+        assert_eq!(
+            PackedMidiEvent::try_from(MidiEvent::SysEx(&[0xF0, 0x7E, 0xF7])),
+            Err(PackError::SysEx)
+        );
+        assert_eq!(
+            PackedMidiEvent::from_raw(0x0090_8040).message(),
+            Err(PackError::Invalid)
+        );
+        assert_eq!(
+            PackedMidiEvent::from_raw(0x00F0_0000).message(),
+            Err(PackError::Invalid)
+        );
+    }
+
+    #[test]
+    fn should_convert_channel_messages_to_byte_arrays() {
+        let note_on = MidiEvent::Message(MidiMessage::NoteOn(Channel::C2, Note::C3, 100.into()));
+        let program = MidiEvent::Message(MidiMessage::ProgramChange(Channel::C1, Program::new(5)));
+
+        assert_eq!(<[u8; 3]>::try_from(note_on), Ok([0x91, 60, 100]));
+        assert_eq!(<[u8; 2]>::try_from(program), Ok([0xC0, 5]));
+        assert_eq!(MidiEvent::try_from([0x91, 60, 100]), Ok(note_on));
+        assert_eq!(MidiEvent::try_from([0xC0, 5]), Ok(program));
+    }
+
+    #[test]
+    fn should_reject_byte_arrays_of_other_messages() {
+        assert_eq!(
+            <[u8; 2]>::try_from(MidiEvent::Message(MidiMessage::Stop)),
+            Err(PackError::Invalid)
+        );
+        assert_eq!(MidiEvent::try_from([0xC0, 5, 0]), Err(PackError::Invalid));
+        assert_eq!(MidiEvent::try_from([0xF2, 1, 2]), Err(PackError::Invalid));
+        assert_eq!(MidiEvent::try_from([0x90, 0x80]), Err(PackError::Invalid));
+    }
+
+    #[test]
+    fn should_order_timed_events_by_timestamp() {
+        let late = PackedTimedEvent::new(20, MidiMessage::Start.into());
+        let early = PackedTimedEvent::new(10, MidiMessage::Stop.into());
+
+        assert!(early < late);
+        assert_eq!(late.timestamp(), 20);
+        assert_eq!(late.event().message(), Ok(MidiMessage::Start));
+    }
+}
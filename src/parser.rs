@@ -0,0 +1,745 @@
+//! Byte by byte midi message parser
+//!
+//! The parser looks up the kind of message and the number of data bytes that follow in a table
+//! indexed by the status byte, so every message type is handled the same way.
+//!
+//! System real-time messages may appear anywhere in the stream, also inside a system exclusive
+//! message. They are returned as soon as they are received and the system exclusive message
+//! continues after them, the real-time byte never ends up in the system exclusive data.
+
+pub mod checked;
+
+use crate::midi_types::MidiMessage;
+use crate::value::U14;
+
+/// The kind of message a status byte starts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MessageKind {
+    NoteOff,
+    NoteOn,
+    KeyPressure,
+    ControlChange,
+    ProgramChange,
+    ChannelPressure,
+    PitchBend,
+    SysExStart,
+    QuarterFrame,
+    SongPosition,
+    SongSelect,
+    TuneRequest,
+    SysExEnd,
+    TimingClock,
+    Start,
+    Continue,
+    Stop,
+    ActiveSensing,
+    Reset,
+    Undefined,
+}
+
+/// What the parser needs to know about a status byte
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct StatusInfo {
+    pub kind: MessageKind,
+
+    /// The number of data bytes following the status byte
+    pub len: u8,
+}
+
+const fn info(kind: MessageKind, len: u8) -> StatusInfo {
+    StatusInfo { kind, len }
+}
+
+const fn system_info(status: u8) -> StatusInfo {
+    match status {
+        0xF0 => info(MessageKind::SysExStart, 0),
+        0xF1 => info(MessageKind::QuarterFrame, 1),
+        0xF2 => info(MessageKind::SongPosition, 2),
+        0xF3 => info(MessageKind::SongSelect, 1),
+        0xF6 => info(MessageKind::TuneRequest, 0),
+        0xF7 => info(MessageKind::SysExEnd, 0),
+        0xF8 => info(MessageKind::TimingClock, 0),
+        0xFA => info(MessageKind::Start, 0),
+        0xFB => info(MessageKind::Continue, 0),
+        0xFC => info(MessageKind::Stop, 0),
+        0xFE => info(MessageKind::ActiveSensing, 0),
+        0xFF => info(MessageKind::Reset, 0),
+        _ => info(MessageKind::Undefined, 0),
+    }
+}
+
+// Indexing is checked at compile time when building the table
+#[allow(clippy::indexing_slicing)]
+const fn build_table() -> [StatusInfo; 128] {
+    let mut table = [info(MessageKind::Undefined, 0); 128];
+    let mut index = 0;
+
+    while index < 128 {
+        let status = 0x80 | index as u8;
+        table[index] = match status >> 4 {
+            0x8 => info(MessageKind::NoteOff, 2),
+            0x9 => info(MessageKind::NoteOn, 2),
+            0xA => info(MessageKind::KeyPressure, 2),
+            0xB => info(MessageKind::ControlChange, 2),
+            0xC => info(MessageKind::ProgramChange, 1),
+            0xD => info(MessageKind::ChannelPressure, 1),
+            0xE => info(MessageKind::PitchBend, 2),
+            _ => system_info(status),
+        };
+        index += 1;
+    }
+
+    table
+}
+
+/// Message kind and length for every status byte, indexed by the status byte without its msb
+static STATUS_TABLE: [StatusInfo; 128] = build_table();
+
+/// Look up the kind and length of the message started by a status byte
+pub(crate) fn status_info(status: u8) -> StatusInfo {
+    match STATUS_TABLE.get((status & 0x7F) as usize) {
+        Some(info) => *info,
+        None => info(MessageKind::Undefined, 0),
+    }
+}
+
+/// Check if a status byte is a system real-time message, these can appear anywhere in the stream
+/// without interrupting other messages
+pub(crate) fn is_real_time(status: u8) -> bool {
+    status >= 0xF8
+}
+
+/// Check if a status byte starts a channel message, only these messages use running status
+pub(crate) fn is_channel_status(status: u8) -> bool {
+    (0x80..0xF0).contains(&status)
+}
+
+/// Build a message from a status byte and its data bytes
+pub(crate) fn build_message(
+    kind: MessageKind,
+    status: u8,
+    first: u8,
+    second: u8,
+) -> Option<MidiMessage> {
+    let channel = (status & 0x0F).into();
+
+    Some(match kind {
+        MessageKind::NoteOff => MidiMessage::NoteOff(channel, first.into(), second.into()),
+        MessageKind::NoteOn => MidiMessage::NoteOn(channel, first.into(), second.into()),
+        MessageKind::KeyPressure => MidiMessage::KeyPressure(channel, first.into(), second.into()),
+        MessageKind::ControlChange => {
+            MidiMessage::ControlChange(channel, first.into(), second.into())
+        }
+        MessageKind::ProgramChange => MidiMessage::ProgramChange(channel, first.into()),
+        MessageKind::ChannelPressure => MidiMessage::ChannelPressure(channel, first.into()),
+        MessageKind::PitchBend => {
+            MidiMessage::PitchBendChange(channel, U14::from_bytes(first, second).into())
+        }
+        MessageKind::QuarterFrame => MidiMessage::QuarterFrame(first.into()),
+        MessageKind::SongPosition => {
+            MidiMessage::SongPositionPointer(U14::from_bytes(first, second).into())
+        }
+        MessageKind::SongSelect => MidiMessage::SongSelect(first.into()),
+        MessageKind::TuneRequest => MidiMessage::TuneRequest,
+        MessageKind::TimingClock => MidiMessage::TimingClock,
+        MessageKind::Start => MidiMessage::Start,
+        MessageKind::Continue => MidiMessage::Continue,
+        MessageKind::Stop => MidiMessage::Stop,
+        MessageKind::ActiveSensing => MidiMessage::ActiveSensing,
+        MessageKind::Reset => MidiMessage::Reset,
+        MessageKind::SysExStart | MessageKind::SysExEnd | MessageKind::Undefined => return None,
+    })
+}
+
+/// Status value for a parser that has not received a status byte or can not use running status
+const NO_STATUS: u8 = 0x00;
+
+/// Status byte for system exclusive messages, the byte by byte parser skips their data
+const SYSEX_START: u8 = 0xF0;
+
+/// End of a system exclusive message
+const SYSEX_END: u8 = 0xF7;
+
+/// Events parsed from a buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MidiEvent<'a> {
+    /// A midi message
+    Message(MidiMessage),
+
+    /// A complete system exclusive message including the start and end bytes, borrowed from the
+    /// parsed buffer
+    SysEx(&'a [u8]),
+
+    /// Part of a system exclusive message that did not fit in a single buffer, or was interrupted
+    /// by a real-time message. The first chunk of a message starts with the start byte and the
+    /// last chunk ends with the end byte. A chunk that does not end with the end byte while the
+    /// next chunk does not continue it means the message was terminated by another status byte.
+    SysExChunk(&'a [u8]),
+
+    /// A message with an undefined status byte, passed on unchanged so routers can forward
+    /// messages this crate does not know. Data bytes are only included when they follow the
+    /// status byte in the same buffer. Only parsed with the `unknown-messages` feature.
+    #[cfg(feature = "unknown-messages")]
+    Unknown { status: u8, data: &'a [u8] },
+}
+
+/// Receives the events produced by the parser, this is implemented for closures taking a
+/// `MidiEvent`
+pub trait MidiHandler {
+    fn handle(&mut self, event: MidiEvent<'_>);
+}
+
+impl<F> MidiHandler for F
+where
+    F: FnMut(MidiEvent<'_>),
+{
+    fn handle(&mut self, event: MidiEvent<'_>) {
+        self(event)
+    }
+}
+
+/// Parses midi messages a byte at a time
+///
+/// The parser state is kept in three bytes: the current status byte, the first data byte of the
+/// message being received and the number of data bytes still expected. Keeping the expected
+/// number of data bytes means long runs of running status messages only need a table lookup when
+/// a message completes.
+#[derive(Debug, Clone, Default)]
+pub struct MidiParser {
+    status: u8,
+    data: u8,
+    remaining: u8,
+}
+
+impl MidiParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse a buffer, for instance filled by DMA, returns an iterator over the events in the
+    /// buffer. System exclusive data is not copied but borrowed from the buffer. Messages that
+    /// are split across buffers are completed when parsing the next buffer.
+    pub fn parse_buffer<'a>(&mut self, buffer: &'a [u8]) -> BufferEvents<'_, 'a> {
+        BufferEvents {
+            parser: self,
+            buffer,
+            position: 0,
+            sysex_start: None,
+        }
+    }
+
+    /// Parse the next byte and pass a completed message to the handler, this lets interrupt
+    /// handlers dispatch events without matching on the result for every byte. The handler is
+    /// called from the state machine where the message is completed, no `Option` is returned.
+    #[inline]
+    pub fn parse_byte_into(&mut self, byte: u8, handler: &mut impl MidiHandler) {
+        self.dispatch(byte, |message| handler.handle(MidiEvent::Message(message)));
+    }
+
+    /// Parse a buffer and pass all events in it to the handler
+    pub fn parse_buffer_into(&mut self, buffer: &[u8], handler: &mut impl MidiHandler) {
+        self.parse_buffer(buffer)
+            .for_each(|event| handler.handle(event));
+    }
+
+    /// Parse a 32 bit word holding four bytes, as read from a fifo. The first byte is stored in
+    /// the least significant bits. Words that are only partially filled can be parsed with
+    /// `parse_buffer_into` on the used bytes of `word.to_le_bytes()`.
+    #[inline]
+    pub fn parse_word(&mut self, word: u32, handler: &mut impl MidiHandler) {
+        self.parse_buffer_into(&word.to_le_bytes(), handler);
+    }
+
+    /// Parse the next byte, returns a message when this byte completes one
+    #[inline]
+    pub fn parse_byte(&mut self, byte: u8) -> Option<MidiMessage> {
+        let mut completed = None;
+        self.dispatch(byte, |message| completed = Some(message));
+        completed
+    }
+
+    /// Run the state machine on the next byte, `emit` is called when this byte completes a
+    /// message
+    #[inline]
+    fn dispatch(&mut self, byte: u8, emit: impl FnOnce(MidiMessage)) {
+        if byte < 0x80 {
+            return self.parse_data(byte, emit);
+        }
+
+        let info = status_info(byte);
+
+        // Real-time messages are handled right away without changing the parser state
+        if is_real_time(byte) {
+            if let Some(message) = build_message(info.kind, byte, 0, 0) {
+                emit(message);
+            }
+            return;
+        }
+
+        self.remaining = info.len;
+        if info.kind == MessageKind::SysExStart {
+            self.status = SYSEX_START;
+        } else if info.len == 0 {
+            self.status = NO_STATUS;
+            if let Some(message) = build_message(info.kind, byte, 0, 0) {
+                emit(message);
+            }
+        } else {
+            self.status = byte;
+        }
+    }
+
+    #[inline]
+    fn parse_data(&mut self, byte: u8, emit: impl FnOnce(MidiMessage)) {
+        invariant!(
+            self.status >= 0x80 || self.remaining == 0,
+            "data bytes expected without a status byte"
+        );
+        invariant!(
+            self.status < 0x80 || self.remaining <= status_info(self.status).len,
+            "more data bytes expected than the message has"
+        );
+
+        match self.remaining {
+            // Data bytes without a status byte are dropped, system exclusive data is skipped
+            0 => {}
+            2 => {
+                self.data = byte;
+                self.remaining = 1;
+            }
+            _ => self.complete(byte, emit),
+        }
+    }
+
+    /// Build the message completed by its last data byte
+    #[inline]
+    fn complete(&mut self, byte: u8, emit: impl FnOnce(MidiMessage)) {
+        let status = self.status;
+        let info = status_info(status);
+
+        let (first, second) = match info.len {
+            2 => (self.data, byte),
+            _ => (byte, 0),
+        };
+
+        if is_channel_status(status) {
+            // Expect the next message with the same status
+            self.remaining = info.len;
+        } else {
+            // Only channel messages support running status
+            self.status = NO_STATUS;
+            self.remaining = 0;
+        }
+
+        if let Some(message) = build_message(info.kind, status, first, second) {
+            emit(message);
+        }
+    }
+}
+
+/// Iterator over the events in a buffer, returned by `MidiParser::parse_buffer`
+#[derive(Debug)]
+pub struct BufferEvents<'p, 'a> {
+    parser: &'p mut MidiParser,
+    buffer: &'a [u8],
+    position: usize,
+
+    /// Position of a system exclusive start byte in this buffer
+    sysex_start: Option<usize>,
+}
+
+impl<'p, 'a> BufferEvents<'p, 'a> {
+    /// The bytes in a range of the buffer, empty when the range is out of bounds
+    fn bytes(&self, start: usize, end: usize) -> &'a [u8] {
+        self.buffer.get(start..end).unwrap_or_default()
+    }
+
+    fn next_sysex(&mut self) -> Option<MidiEvent<'a>> {
+        invariant!(
+            self.position <= self.buffer.len(),
+            "position beyond the buffer"
+        );
+        invariant!(
+            self.sysex_start
+                .map_or(true, |start| start <= self.position),
+            "system exclusive message starts after the position"
+        );
+        let start = self.sysex_start.take().unwrap_or(self.position);
+        let end = self
+            .bytes(self.position, self.buffer.len())
+            .iter()
+            .position(|byte| *byte >= 0x80)
+            .map_or(self.buffer.len(), |index| self.position + index);
+
+        let byte = match self.buffer.get(end) {
+            Some(byte) => *byte,
+            None => {
+                // The message continues in the next buffer
+                self.position = end;
+                return Some(MidiEvent::SysExChunk(self.bytes(start, end)));
+            }
+        };
+
+        if byte == SYSEX_END {
+            self.parser.status = NO_STATUS;
+            self.position = end + 1;
+
+            let message = self.bytes(start, end + 1);
+            return Some(match message.first() {
+                Some(&SYSEX_START) => MidiEvent::SysEx(message),
+                _ => MidiEvent::SysExChunk(message),
+            });
+        }
+
+        if is_real_time(byte) {
+            if start < end {
+                // Emit the data before the real-time message, the message continues after it
+                self.position = end;
+                return Some(MidiEvent::SysExChunk(self.bytes(start, end)));
+            }
+
+            self.position = end + 1;
+            #[cfg(feature = "unknown-messages")]
+            if status_info(byte).kind == MessageKind::Undefined {
+                return Some(MidiEvent::Unknown {
+                    status: byte,
+                    data: &[],
+                });
+            }
+            return self.parser.parse_byte(byte).map(MidiEvent::Message);
+        }
+
+        // Any other status byte ends the message, it is parsed as the start of a new message
+        self.parser.status = NO_STATUS;
+        self.position = end;
+        if start < end {
+            Some(MidiEvent::SysExChunk(self.bytes(start, end)))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(feature = "unknown-messages")]
+impl<'p, 'a> BufferEvents<'p, 'a> {
+    /// Pass on an undefined status byte with the data bytes following it in the buffer, real-time
+    /// status bytes do not have data
+    fn next_unknown(&mut self, byte: u8) -> Option<MidiEvent<'a>> {
+        if byte < 0x80 || status_info(byte).kind != MessageKind::Undefined {
+            return None;
+        }
+
+        self.parser.parse_byte(byte);
+        if is_real_time(byte) {
+            return Some(MidiEvent::Unknown {
+                status: byte,
+                data: &[],
+            });
+        }
+
+        let start = self.position;
+        let end = self
+            .bytes(start, self.buffer.len())
+            .iter()
+            .position(|byte| *byte >= 0x80)
+            .map_or(self.buffer.len(), |index| start + index);
+        self.position = end;
+        Some(MidiEvent::Unknown {
+            status: byte,
+            data: self.bytes(start, end),
+        })
+    }
+}
+
+impl<'p, 'a> Iterator for BufferEvents<'p, 'a> {
+    type Item = MidiEvent<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(&byte) = self.buffer.get(self.position) {
+            if self.parser.status == SYSEX_START {
+                if let Some(event) = self.next_sysex() {
+                    return Some(event);
+                }
+                continue;
+            }
+
+            if byte == SYSEX_START {
+                self.sysex_start = Some(self.position);
+            }
+            self.position += 1;
+
+            #[cfg(feature = "unknown-messages")]
+            if let Some(event) = self.next_unknown(byte) {
+                return Some(event);
+            }
+
+            if let Some(message) = self.parser.parse_byte(byte) {
+                return Some(MidiEvent::Message(message));
+            }
+        }
+
+        // A system exclusive message starting with the last byte continues in the next buffer
+        let start = self.sysex_start.take()?;
+        Some(MidiEvent::SysExChunk(self.bytes(start, self.buffer.len())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use crate::midi_types::{Channel, Control, Note, Value7};
+    use std::vec::Vec;
+
+    fn parse(bytes: &[u8]) -> Vec<MidiMessage> {
+        let mut parser = MidiParser::new();
+        bytes
+            .iter()
+            .filter_map(|byte| parser.parse_byte(*byte))
+            .collect()
+    }
+
+    #[test]
+    fn should_keep_parser_state_small() {
+        assert_eq!(core::mem::size_of::<MidiParser>(), 3);
+    }
+
+    #[test]
+    fn should_parse_channel_messages() {
+        assert_eq!(
+            parse(&[0x92, 0x76, 0x34, 0xC1, 0x05, 0xE0, 0x01, 0x40]),
+            [
+                MidiMessage::NoteOn(2.into(), 0x76.into(), 0x34.into()),
+                MidiMessage::ProgramChange(1.into(), 5.into()),
+                MidiMessage::PitchBendChange(0.into(), (0x40, 0x01).into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_parse_running_status() {
+        assert_eq!(
+            parse(&[0xB3, 0x01, 0x10, 0x01, 0x11, 0xD0, 0x20, 0x21]),
+            [
+                MidiMessage::ControlChange(3.into(), 1.into(), 0x10.into()),
+                MidiMessage::ControlChange(3.into(), 1.into(), 0x11.into()),
+                MidiMessage::ChannelPressure(0.into(), 0x20.into()),
+                MidiMessage::ChannelPressure(0.into(), 0x21.into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_handle_real_time_messages_inside_other_messages() {
+        assert_eq!(
+            parse(&[0x90, 0x40, 0xF8, 0x7F, 0xFA]),
+            [
+                MidiMessage::TimingClock,
+                MidiMessage::NoteOn(0.into(), 0x40.into(), 0x7F.into()),
+                MidiMessage::Start,
+            ]
+        );
+    }
+
+    #[test]
+    fn should_not_use_running_status_for_system_common_messages() {
+        assert_eq!(
+            parse(&[0xF2, 0x10, 0x20, 0x11, 0x21, 0xF3, 0x05, 0xF6]),
+            [
+                MidiMessage::SongPositionPointer((0x20, 0x10).into()),
+                MidiMessage::SongSelect(5.into()),
+                MidiMessage::TuneRequest,
+            ]
+        );
+    }
+
+    fn parse_buffers<'a>(buffers: &[&'a [u8]]) -> Vec<MidiEvent<'a>> {
+        let mut parser = MidiParser::new();
+        buffers
+            .iter()
+            .flat_map(|buffer| parser.parse_buffer(buffer).collect::<Vec<_>>())
+            .collect()
+    }
+
+    #[test]
+    fn should_borrow_complete_sysex_from_buffer() {
+        let buffer = [
+            0x90, 0x40, 0x7F, 0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7, 0x40, 0x00,
+        ];
+
+        assert_eq!(
+            parse_buffers(&[&buffer]),
+            [
+                MidiEvent::Message(MidiMessage::NoteOn(0.into(), 0x40.into(), 0x7F.into())),
+                MidiEvent::SysEx(&buffer[3..9]),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_return_chunks_for_sysex_across_buffers() {
+        let first = [0xB0, 0x07, 0x10, 0xF0, 0x43, 0x10];
+        let second = [0x01, 0x02];
+        let third = [0x03, 0xF7, 0xF8];
+
+        assert_eq!(
+            parse_buffers(&[&first, &second, &third]),
+            [
+                MidiEvent::Message(MidiMessage::ControlChange(0.into(), 7.into(), 0x10.into())),
+                MidiEvent::SysExChunk(&first[3..]),
+                MidiEvent::SysExChunk(&second),
+                MidiEvent::SysExChunk(&third[..2]),
+                MidiEvent::Message(MidiMessage::TimingClock),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_return_sysex_start_at_end_of_buffer() {
+        let first = [0x90, 0x40, 0x7F, 0xF0];
+        let second = [0x43, 0x10, 0xF7];
+
+        assert_eq!(
+            parse_buffers(&[&first, &second]),
+            [
+                MidiEvent::Message(MidiMessage::NoteOn(0.into(), 0x40.into(), 0x7F.into())),
+                MidiEvent::SysExChunk(&[0xF0]),
+                MidiEvent::SysExChunk(&second),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_emit_real_time_messages_inside_sysex() {
+        assert_eq!(
+            parse(&[0xF0, 0x43, 0xF8, 0x10, 0xFE, 0xF7, 0x90, 0x40, 0x7F]),
+            [
+                MidiMessage::TimingClock,
+                MidiMessage::ActiveSensing,
+                MidiMessage::NoteOn(0.into(), 0x40.into(), 0x7F.into()),
+            ]
+        );
+
+        let buffer = [0xF0, 0x43, 0x10, 0xF8, 0x01, 0x02, 0xFE, 0xF7];
+        assert_eq!(
+            parse_buffers(&[&buffer[..4], &buffer[4..]]),
+            [
+                MidiEvent::SysExChunk(&buffer[..3]),
+                MidiEvent::Message(MidiMessage::TimingClock),
+                MidiEvent::SysExChunk(&buffer[4..6]),
+                MidiEvent::Message(MidiMessage::ActiveSensing),
+                MidiEvent::SysExChunk(&buffer[7..]),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_end_sysex_on_status_byte() {
+        let buffer = [0xF0, 0x01, 0x02, 0x90, 0x40, 0x7F];
+
+        assert_eq!(
+            parse_buffers(&[&buffer]),
+            [
+                MidiEvent::SysExChunk(&buffer[..3]),
+                MidiEvent::Message(MidiMessage::NoteOn(0.into(), 0x40.into(), 0x7F.into())),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_pass_events_to_handler() {
+        struct Counter(usize);
+
+        impl MidiHandler for Counter {
+            fn handle(&mut self, _event: MidiEvent<'_>) {
+                self.0 += 1;
+            }
+        }
+
+        let mut parser = MidiParser::new();
+        let mut counter = Counter(0);
+        [0x90, 0x40, 0x7F, 0x41, 0x7F, 0xF8]
+            .iter()
+            .for_each(|byte| parser.parse_byte_into(*byte, &mut counter));
+        assert_eq!(counter.0, 3);
+
+        let mut events = Vec::new();
+        parser.parse_buffer_into(&[0xF0, 0x01, 0xF7], &mut |event: MidiEvent<'_>| {
+            events.push(event == MidiEvent::SysEx(&[0xF0, 0x01, 0xF7]))
+        });
+        assert_eq!(events, [true]);
+    }
+
+    #[test]
+    fn should_parse_words() {
+        let mut parser = MidiParser::new();
+        let mut events = Vec::new();
+        let mut handler = |event: MidiEvent<'_>| {
+            if let MidiEvent::Message(message) = event {
+                events.push(message)
+            }
+        };
+
+        parser.parse_word(u32::from_le_bytes([0x90, 0x3C, 0x40, 0xF8]), &mut handler);
+        parser.parse_word(u32::from_le_bytes([0x3E, 0x40, 0xB0, 0x01]), &mut handler);
+        parser.parse_word(u32::from_le_bytes([0x7F, 0x02, 0x00, 0xFE]), &mut handler);
+
+        assert_eq!(
+            events,
+            [
+                MidiMessage::NoteOn(Channel::C1, Note::C3, Value7::new(0x40)),
+                MidiMessage::TimingClock,
+                MidiMessage::NoteOn(Channel::C1, Note::D3, Value7::new(0x40)),
+                MidiMessage::ControlChange(Channel::C1, Control::new(1), Value7::new(0x7F)),
+                MidiMessage::ControlChange(Channel::C1, Control::new(2), Value7::new(0)),
+                MidiMessage::ActiveSensing,
+            ]
+        );
+    }
+
+    #[test]
+    fn should_drop_data_without_status() {
+        assert_eq!(
+            parse(&[0x40, 0x40, 0xF0, 0x01, 0x02, 0xF7, 0x03, 0x80, 0x40, 0x00]),
+            [MidiMessage::NoteOff(0.into(), 0x40.into(), 0.into())]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "debug-asserts")]
+    #[should_panic(expected = "more data bytes expected than the message has")]
+    fn should_check_parser_invariants() {
+        let mut parser = MidiParser::new();
+        parser.parse_byte(0xC0);
+        parser.remaining = 2;
+        parser.parse_byte(0x01);
+    }
+
+    #[test]
+    #[cfg(feature = "unknown-messages")]
+    fn should_pass_on_unknown_messages() {
+        let mut parser = MidiParser::new();
+        let events: Vec<_> = parser
+            .parse_buffer(&[0xF4, 0x01, 0x02, 0x90, 0x40, 0xFD, 0x7F])
+            .collect();
+
+        assert_eq!(
+            events,
+            [
+                MidiEvent::Unknown {
+                    status: 0xF4,
+                    data: &[0x01, 0x02]
+                },
+                MidiEvent::Unknown {
+                    status: 0xFD,
+                    data: &[]
+                },
+                MidiEvent::Message(MidiMessage::NoteOn(
+                    Channel::C1,
+                    Note::new(0x40),
+                    Value7::new(0x7F)
+                )),
+            ]
+        );
+    }
+}
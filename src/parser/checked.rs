@@ -0,0 +1,194 @@
+//! Parsing with deliberate handling of problems in the stream
+//!
+//! `MidiParser` silently drops what it can not use. Interface firmware that reports line problems
+//! to the user parses with a `CheckedParser` instead, which reports them as a `ParseError` or
+//! passes them on depending on its policies.
+//!
+//! Data bytes received without a status byte, for instance when the interface was powered on in
+//! the middle of a message or a status byte was lost, are orphan data. They are dropped unless
+//! reporting them is enabled.
+//!
+//! The undefined status bytes 0xF4 and 0xF5 cancel running status like other system common
+//! messages, 0xF9 and 0xFD are real-time bytes and may appear anywhere, some clocks send 0xF9 as a
+//! tick every 10 milliseconds.
+
+use super::{status_info, MessageKind, MidiEvent, MidiParser, NO_STATUS};
+use core::fmt;
+
+/// What to do with the undefined status bytes 0xF4, 0xF5, 0xF9 and 0xFD
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UndefinedPolicy {
+    /// Drop the status byte, like `MidiParser` does
+    #[default]
+    Ignore,
+
+    /// Report the status byte as `ParseError::UndefinedStatus`
+    Report,
+
+    /// Pass the status byte on as `MidiEvent::Unknown`, data bytes following it are dropped
+    #[cfg(feature = "unknown-messages")]
+    PassThrough,
+}
+
+/// Problems in the received stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ParseError {
+    /// An undefined status byte was received
+    UndefinedStatus(u8),
+
+    /// A data byte was received without a status byte
+    OrphanData(u8),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UndefinedStatus(status) => {
+                write!(f, "undefined status byte {:#04X}", status)
+            }
+            ParseError::OrphanData(data) => {
+                write!(f, "data byte {:#04X} without status byte", data)
+            }
+        }
+    }
+}
+
+/// Parses midi messages a byte at a time, reporting problems in the stream
+#[derive(Debug, Clone, Default)]
+pub struct CheckedParser {
+    parser: MidiParser,
+    undefined: UndefinedPolicy,
+    report_orphans: bool,
+}
+
+impl CheckedParser {
+    pub fn new(undefined: UndefinedPolicy) -> Self {
+        CheckedParser {
+            parser: MidiParser::new(),
+            undefined,
+            report_orphans: false,
+        }
+    }
+
+    pub fn undefined_policy(&self) -> UndefinedPolicy {
+        self.undefined
+    }
+
+    pub fn set_undefined_policy(&mut self, policy: UndefinedPolicy) {
+        self.undefined = policy;
+    }
+
+    pub fn reports_orphan_data(&self) -> bool {
+        self.report_orphans
+    }
+
+    /// Report data bytes without a status byte as `ParseError::OrphanData` instead of dropping
+    /// them
+    pub fn set_report_orphan_data(&mut self, report: bool) {
+        self.report_orphans = report;
+    }
+
+    /// Parse the next byte, returns an event when this byte completes one
+    pub fn parse_byte(&mut self, byte: u8) -> Result<Option<MidiEvent<'static>>, ParseError> {
+        // System exclusive data is kept in the status, only data without any status is orphaned
+        let orphan = byte < 0x80 && self.parser.status == NO_STATUS;
+        let message = self.parser.parse_byte(byte);
+
+        if orphan && self.report_orphans {
+            return Err(ParseError::OrphanData(byte));
+        }
+
+        if byte >= 0x80 && status_info(byte).kind == MessageKind::Undefined {
+            return match self.undefined {
+                UndefinedPolicy::Ignore => Ok(None),
+                UndefinedPolicy::Report => Err(ParseError::UndefinedStatus(byte)),
+                #[cfg(feature = "unknown-messages")]
+                UndefinedPolicy::PassThrough => Ok(Some(MidiEvent::Unknown {
+                    status: byte,
+                    data: &[],
+                })),
+            };
+        }
+
+        Ok(message.map(MidiEvent::Message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use crate::midi_types::MidiMessage;
+    use std::vec::Vec;
+
+    fn parse(
+        parser: &mut CheckedParser,
+        bytes: &[u8],
+    ) -> Vec<Result<Option<MidiEvent<'static>>, ParseError>> {
+        bytes.iter().map(|byte| parser.parse_byte(*byte)).collect()
+    }
+
+    #[test]
+    fn should_ignore_or_report_undefined_status_bytes() {
+        let mut parser = CheckedParser::default();
+        assert_eq!(
+            parse(&mut parser, &[0xF9, 0xF8]),
+            [
+                Ok(None),
+                Ok(Some(MidiEvent::Message(MidiMessage::TimingClock)))
+            ]
+        );
+
+        parser.set_undefined_policy(UndefinedPolicy::Report);
+        assert_eq!(
+            parse(&mut parser, &[0xC0, 0xF9, 0x05, 0xF4, 0x06]),
+            [
+                Ok(None),
+                Err(ParseError::UndefinedStatus(0xF9)),
+                Ok(Some(MidiEvent::Message(MidiMessage::ProgramChange(
+                    0.into(),
+                    5.into()
+                )))),
+                Err(ParseError::UndefinedStatus(0xF4)),
+                Ok(None)
+            ]
+        );
+    }
+
+    #[test]
+    fn should_report_orphan_data_when_enabled() {
+        let mut parser = CheckedParser::default();
+        assert_eq!(parse(&mut parser, &[0x40, 0x7F]), [Ok(None), Ok(None)]);
+
+        parser.set_report_orphan_data(true);
+        assert_eq!(
+            parse(&mut parser, &[0x40, 0xF0, 0x7E, 0xF7, 0x7F, 0xD0, 0x10]),
+            [
+                Err(ParseError::OrphanData(0x40)),
+                Ok(None),
+                Ok(None),
+                Ok(None),
+                Err(ParseError::OrphanData(0x7F)),
+                Ok(None),
+                Ok(Some(MidiEvent::Message(MidiMessage::ChannelPressure(
+                    0.into(),
+                    0x10.into()
+                ))))
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "unknown-messages")]
+    fn should_pass_on_undefined_status_bytes() {
+        let mut parser = CheckedParser::new(UndefinedPolicy::PassThrough);
+        assert_eq!(
+            parser.parse_byte(0xFD),
+            Ok(Some(MidiEvent::Unknown {
+                status: 0xFD,
+                data: &[]
+            }))
+        );
+    }
+}
@@ -0,0 +1,202 @@
+//! Continuous pitch from notes, pitch bend and glide
+
+use crate::cv::CvConverter;
+use crate::midi_types::{Channel, MidiMessage, Note};
+use crate::mono::{MonoEvent, MonoNoteHandler, NotePriority};
+
+const DATA_ENTRY_MSB: u8 = 6;
+const DATA_ENTRY_LSB: u8 = 38;
+const RPN_LSB: u8 = 100;
+const RPN_MSB: u8 = 101;
+/// Registered parameter 0 sets the pitch bend range
+const PITCH_BEND_SENSITIVITY: u16 = 0;
+
+/// Fractional bits of the pitch so slow glides still move every update
+const FRACTION: u32 = 8;
+
+/// Tracks the pitch of a monophonic voice as one value combining the playing note, pitch bend
+/// and glide, keeping track of up to `N` held notes
+///
+/// The pitch bend range follows registered parameter 0. Glide is only applied when moving between
+/// notes while a note is held. Times are in the unit used to advance the pitch.
+#[derive(Debug, Clone)]
+pub struct PitchTracker<const N: usize> {
+    channel: Option<Channel>,
+    mono: MonoNoteHandler<N>,
+    bend: i16,
+    bend_range: u16,
+    rpn: [u8; 2],
+    glide_time: u32,
+    /// The pitch of the note in cents with fractional bits
+    pitch: i32,
+    target: i32,
+}
+
+impl<const N: usize> PitchTracker<N> {
+    /// Create a tracker for notes on `channel`, or all channels when `channel` is `None`
+    pub fn new(channel: Option<Channel>, priority: NotePriority) -> Self {
+        PitchTracker {
+            channel,
+            mono: MonoNoteHandler::new(priority),
+            bend: 0,
+            bend_range: 200,
+            rpn: [0x7f, 0x7f],
+            glide_time: 0,
+            pitch: 0,
+            target: 0,
+        }
+    }
+
+    /// The pitch bend range in cents
+    pub fn bend_range(&self) -> u16 {
+        self.bend_range
+    }
+
+    pub fn set_bend_range(&mut self, cents: u16) {
+        self.bend_range = cents;
+    }
+
+    /// Set the time it takes to glide an octave, 0 disables glide
+    pub fn set_glide_time(&mut self, time: u32) {
+        self.glide_time = time;
+    }
+
+    /// The note that is playing
+    pub fn note(&self) -> Option<Note> {
+        self.mono.current()
+    }
+
+    /// The pitch in cents including pitch bend, note 0 is at 0 cents
+    pub fn cents(&self) -> i32 {
+        let bend = self.bend as i32 * self.bend_range as i32 / 8192;
+        (self.pitch >> FRACTION) + bend
+    }
+
+    /// The pitch as a DAC code
+    pub fn code(&self, converter: &CvConverter) -> u32 {
+        converter.cents_code(self.cents())
+    }
+
+    /// Move the pitch towards the playing note
+    pub fn advance(&mut self, elapsed: u32) {
+        let step = match self.glide_time {
+            0 => u32::MAX as i64,
+            time => (1200 << FRACTION) as i64 * elapsed as i64 / time as i64,
+        };
+        let step = step.min(i32::MAX as i64) as i32;
+
+        self.pitch = if self.pitch < self.target {
+            self.pitch.saturating_add(step).min(self.target)
+        } else {
+            self.pitch.saturating_sub(step).max(self.target)
+        };
+    }
+
+    /// Handle a received message, returns true when the pitch may have changed
+    pub fn handle(&mut self, message: &MidiMessage) -> bool {
+        let channel = match *message {
+            MidiMessage::NoteOn(channel, ..)
+            | MidiMessage::NoteOff(channel, ..)
+            | MidiMessage::ControlChange(channel, ..)
+            | MidiMessage::PitchBendChange(channel, ..) => channel,
+            _ => return false,
+        };
+        if self.channel.map_or(false, |own| own != channel) {
+            return false;
+        }
+
+        match *message {
+            MidiMessage::PitchBendChange(_, bend) => {
+                self.bend = bend.into();
+                true
+            }
+            MidiMessage::ControlChange(_, control, value) => {
+                self.control_change(control.into(), value.into());
+                false
+            }
+            _ => self.handle_note(message),
+        }
+    }
+
+    fn handle_note(&mut self, message: &MidiMessage) -> bool {
+        let was_playing = self.mono.current().is_some();
+        match self.mono.handle(message) {
+            Some(MonoEvent::NoteOn(note, _)) => {
+                self.target = (u8::from(note) as i32 * 100) << FRACTION;
+                if !was_playing || self.glide_time == 0 {
+                    self.pitch = self.target;
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn control_change(&mut self, control: u8, value: u8) {
+        match control {
+            RPN_MSB => self.rpn[0] = value,
+            RPN_LSB => self.rpn[1] = value,
+            DATA_ENTRY_MSB | DATA_ENTRY_LSB
+                if (self.rpn[0] as u16) << 7 | self.rpn[1] as u16 == PITCH_BEND_SENSITIVITY =>
+            {
+                let (semitones, cents) = (self.bend_range / 100, self.bend_range % 100);
+                self.bend_range = if control == DATA_ENTRY_MSB {
+                    value as u16 * 100 + cents
+                } else {
+                    semitones * 100 + value.min(99) as u16
+                };
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cv::CvScale;
+
+    fn cc(control: u8, value: u8) -> MidiMessage {
+        MidiMessage::ControlChange(Channel::C1, control.into(), value.into())
+    }
+
+    #[test]
+    fn should_combine_note_and_pitch_bend() {
+        let mut pitch = PitchTracker::<4>::new(Some(Channel::C1), NotePriority::Last);
+        assert!(pitch.handle(&MidiMessage::NoteOn(Channel::C1, 60.into(), 100.into())));
+        assert!(pitch.handle(&MidiMessage::PitchBendChange(Channel::C1, 4096i16.into())));
+        assert_eq!(pitch.cents(), 6100);
+
+        for message in [cc(RPN_MSB, 0), cc(RPN_LSB, 0), cc(DATA_ENTRY_MSB, 12)].iter() {
+            pitch.handle(message);
+        }
+        assert_eq!(pitch.bend_range(), 1200);
+        assert_eq!(pitch.cents(), 6600);
+
+        let converter = CvConverter::new(
+            CvScale::VoltPerOctave {
+                codes_per_volt: 1200,
+            },
+            16,
+            0.into(),
+            0,
+        );
+        assert_eq!(pitch.code(&converter), 6600);
+    }
+
+    #[test]
+    fn should_glide_between_held_notes() {
+        let mut pitch = PitchTracker::<4>::new(None, NotePriority::Last);
+        pitch.set_glide_time(120);
+
+        pitch.handle(&MidiMessage::NoteOn(Channel::C1, 60.into(), 100.into()));
+        assert_eq!(pitch.cents(), 6000);
+        pitch.handle(&MidiMessage::NoteOn(Channel::C2, 72.into(), 100.into()));
+        assert_eq!(pitch.cents(), 6000);
+
+        pitch.advance(30);
+        assert_eq!(pitch.cents(), 6300);
+        pitch.advance(300);
+        assert_eq!(pitch.cents(), 7200);
+    }
+}
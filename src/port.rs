@@ -0,0 +1,160 @@
+//! Tagging events with the port they were received on or are sent to
+//!
+//! Interfaces with more than one input or output tag every event with a port number instead of
+//! keeping a parser, queue and router state for every port. For usb midi the port is the cable
+//! number in the first byte of every event packet, so ports range from 0 to 15.
+
+use crate::io::MidiSource;
+use crate::midi_types::MidiMessage;
+use crate::packed::{PackError, PackedMidiEvent};
+use crate::parser::{MidiEvent, MidiParser};
+
+/// An event with the number of the port or usb cable it was received on or is sent to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortEvent<T> {
+    pub port: u8,
+    pub event: T,
+}
+
+impl<T> PortEvent<T> {
+    pub const fn new(port: u8, event: T) -> Self {
+        PortEvent { port, event }
+    }
+
+    /// Replace the event, keeping the port
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> PortEvent<U> {
+        PortEvent::new(self.port, f(self.event))
+    }
+}
+
+impl PortEvent<MidiMessage> {
+    /// Encode a usb midi event packet, with the lower four bits of the port as cable number
+    pub fn to_usb_packet(&self) -> [u8; 4] {
+        let [status, first, second] = PackedMidiEvent::from(self.event).to_bytes();
+        let code_index = match status {
+            0x80..=0xEF => status >> 4,
+            0xF1 | 0xF3 => 0x2,
+            0xF2 => 0x3,
+            0xF6 => 0x5,
+            _ => 0xF,
+        };
+        [self.port << 4 | code_index, status, first, second]
+    }
+
+    /// Decode a usb midi event packet, returns `PackError::SysEx` for packets carrying system
+    /// exclusive data
+    pub fn from_usb_packet(packet: [u8; 4]) -> Result<Self, PackError> {
+        let [header, status, first, second] = packet;
+        let code_index = header & 0x0F;
+        if matches!(code_index, 0x4 | 0x6 | 0x7) || (code_index == 0x5 && status != 0xF6) {
+            return Err(PackError::SysEx);
+        }
+
+        let message = PackedMidiEvent::from_bytes(status, first, second).message()?;
+        Ok(PortEvent::new(header >> 4, message))
+    }
+}
+
+/// Parses the bytes received on one port, tagging the parsed events with the port
+#[derive(Debug, Clone, Default)]
+pub struct PortParser {
+    port: u8,
+    parser: MidiParser,
+}
+
+impl PortParser {
+    pub fn new(port: u8) -> Self {
+        PortParser {
+            port,
+            parser: MidiParser::new(),
+        }
+    }
+
+    pub fn port(&self) -> u8 {
+        self.port
+    }
+
+    /// Parse the next byte, returns a message when this byte completes one
+    pub fn parse_byte(&mut self, byte: u8) -> Option<PortEvent<MidiMessage>> {
+        let port = self.port;
+        self.parser
+            .parse_byte(byte)
+            .map(|message| PortEvent::new(port, message))
+    }
+
+    /// Parse a buffer, returns an iterator over the tagged events in the buffer
+    pub fn parse_buffer<'a>(
+        &'a mut self,
+        buffer: &'a [u8],
+    ) -> impl Iterator<Item = PortEvent<MidiEvent<'a>>> + 'a {
+        let port = self.port;
+        self.parser
+            .parse_buffer(buffer)
+            .map(move |event| PortEvent::new(port, event))
+    }
+}
+
+/// Tags the messages of a source with a port
+#[derive(Debug)]
+pub struct PortSource<S> {
+    port: u8,
+    source: S,
+}
+
+impl<S: MidiSource> PortSource<S> {
+    pub fn new(port: u8, source: S) -> Self {
+        PortSource { port, source }
+    }
+
+    pub fn port(&self) -> u8 {
+        self.port
+    }
+
+    pub fn release(self) -> S {
+        self.source
+    }
+
+    /// Poll for the next message, returns `WouldBlock` when no complete message was received
+    pub fn poll(&mut self) -> nb::Result<PortEvent<MidiMessage>, S::Error> {
+        let message = self.source.poll()?;
+        Ok(PortEvent::new(self.port, message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use crate::midi_types::{Channel, Note, Value7};
+    use std::vec::Vec;
+
+    const NOTE: MidiMessage = MidiMessage::NoteOn(Channel::C2, Note::C3, Value7::new(100));
+
+    #[test]
+    fn should_tag_parsed_events() {
+        let mut parser = PortParser::new(3);
+        let events: Vec<_> = parser.parse_buffer(&[0x91, 60, 100, 0xF8]).collect();
+
+        assert_eq!(
+            events,
+            [
+                PortEvent::new(3, MidiEvent::Message(NOTE)),
+                PortEvent::new(3, MidiEvent::Message(MidiMessage::TimingClock))
+            ]
+        );
+    }
+
+    #[test]
+    fn should_convert_usb_packets() {
+        let event = PortEvent::new(2, NOTE);
+        assert_eq!(event.to_usb_packet(), [0x29, 0x91, 60, 100]);
+        assert_eq!(PortEvent::from_usb_packet([0x29, 0x91, 60, 100]), Ok(event));
+
+        let clock = PortEvent::new(1, MidiMessage::TimingClock);
+        assert_eq!(clock.to_usb_packet(), [0x1F, 0xF8, 0, 0]);
+        assert_eq!(
+            PortEvent::from_usb_packet([0x04, 0xF0, 0x7E, 0x01]),
+            Err(PackError::SysEx)
+        );
+    }
+}
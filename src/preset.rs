@@ -0,0 +1,231 @@
+//! Storing configurations in eeprom or flash
+//!
+//! Configurations are stored as records with a version and a checksum:
+//!
+//! | bytes       | contents                                                      |
+//! |-------------|---------------------------------------------------------------|
+//! | 0           | format version                                                |
+//! | 1..3        | payload length, little endian                                 |
+//! | 3..3+len    | payload                                                       |
+//! | 2 bytes     | fletcher-16 checksum of the version, length and payload, little endian |
+//!
+//! A new format version only appends fields to the payload of the previous version. Loading reads
+//! the fields it knows and ignores the rest, so presets saved by newer firmware still load in
+//! older firmware, and fields missing from presets saved by older firmware keep their current
+//! values.
+
+use crate::error::Error;
+use core::convert::TryFrom;
+
+const HEADER_LEN: usize = 3;
+const CHECKSUM_LEN: usize = 2;
+
+/// A configuration that can be stored as a preset
+pub trait Preset {
+    /// The format version written by `save`
+    const VERSION: u8;
+
+    /// Write the fields of the configuration
+    fn save(&self, writer: &mut PresetWriter<'_>) -> Result<(), Error>;
+
+    /// Read the fields of a payload of the given version, fields that are not in the payload
+    /// keep their values
+    fn load(&mut self, version: u8, reader: &mut PresetReader<'_>) -> Result<(), Error>;
+}
+
+/// Writes the payload of a preset
+#[derive(Debug)]
+pub struct PresetWriter<'a> {
+    buffer: &'a mut [u8],
+    position: usize,
+}
+
+impl<'a> PresetWriter<'a> {
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let end = self
+            .position
+            .checked_add(bytes.len())
+            .ok_or(Error::BufferTooSmall)?;
+        self.buffer
+            .get_mut(self.position..end)
+            .ok_or(Error::BufferTooSmall)?
+            .copy_from_slice(bytes);
+        self.position = end;
+        Ok(())
+    }
+
+    pub fn write_u8(&mut self, value: u8) -> Result<(), Error> {
+        self.write_bytes(&[value])
+    }
+
+    pub fn write_u16(&mut self, value: u16) -> Result<(), Error> {
+        self.write_bytes(&value.to_le_bytes())
+    }
+
+    pub fn write_u32(&mut self, value: u32) -> Result<(), Error> {
+        self.write_bytes(&value.to_le_bytes())
+    }
+}
+
+/// Reads the payload of a preset, reads past the end of the payload return `None`
+#[derive(Debug)]
+pub struct PresetReader<'a> {
+    payload: &'a [u8],
+    position: usize,
+}
+
+impl<'a> PresetReader<'a> {
+    pub fn read_bytes<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let mut bytes = [0; N];
+        let end = self.position.checked_add(N)?;
+        bytes.copy_from_slice(self.payload.get(self.position..end)?);
+        self.position = end;
+        Some(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Option<u8> {
+        self.read_bytes().map(|[value]| value)
+    }
+
+    pub fn read_u16(&mut self) -> Option<u16> {
+        self.read_bytes().map(u16::from_le_bytes)
+    }
+
+    pub fn read_u32(&mut self) -> Option<u32> {
+        self.read_bytes().map(u32::from_le_bytes)
+    }
+}
+
+/// The fletcher-16 checksum of the bytes
+fn checksum(bytes: &[u8]) -> u16 {
+    let (low, high) = bytes.iter().fold((0u16, 0u16), |(low, high), byte| {
+        let low = (low + *byte as u16) % 255;
+        (low, (high + low) % 255)
+    });
+    high << 8 | low
+}
+
+/// Save a preset to the start of a buffer, returns the number of bytes written
+pub fn save<P: Preset>(preset: &P, buffer: &mut [u8]) -> Result<usize, Error> {
+    let payload = buffer.get_mut(HEADER_LEN..).ok_or(Error::BufferTooSmall)?;
+    let mut writer = PresetWriter {
+        buffer: payload,
+        position: 0,
+    };
+    preset.save(&mut writer)?;
+
+    let payload_len = writer.position;
+    let [low, high] = u16::try_from(payload_len)
+        .map_err(|_| Error::InvalidValue)?
+        .to_le_bytes();
+    let len = HEADER_LEN + payload_len;
+    let record = buffer
+        .get_mut(..len + CHECKSUM_LEN)
+        .ok_or(Error::BufferTooSmall)?;
+    let (record, sum) = record.split_at_mut(len);
+    record
+        .get_mut(..HEADER_LEN)
+        .ok_or(Error::BufferTooSmall)?
+        .copy_from_slice(&[P::VERSION, low, high]);
+    sum.copy_from_slice(&checksum(record).to_le_bytes());
+
+    Ok(len + CHECKSUM_LEN)
+}
+
+/// Load a preset from the start of a buffer, returns the number of bytes read. The preset is not
+/// changed when the checksum does not match.
+pub fn load(preset: &mut impl Preset, buffer: &[u8]) -> Result<usize, Error> {
+    let (version, payload_len) = match *buffer.get(..HEADER_LEN).ok_or(Error::BufferTooSmall)? {
+        [version, low, high] => (version, u16::from_le_bytes([low, high]) as usize),
+        _ => return Err(Error::BufferTooSmall),
+    };
+    let len = HEADER_LEN + payload_len;
+    let record = buffer.get(..len).ok_or(Error::BufferTooSmall)?;
+    let sum = buffer
+        .get(len..len + CHECKSUM_LEN)
+        .ok_or(Error::BufferTooSmall)?;
+    if sum != checksum(record).to_le_bytes() {
+        return Err(Error::Parse);
+    }
+
+    let mut reader = PresetReader {
+        payload: record.get(HEADER_LEN..).unwrap_or_default(),
+        position: 0,
+    };
+    preset.load(version, &mut reader)?;
+
+    Ok(len + CHECKSUM_LEN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Default, PartialEq)]
+    struct Settings {
+        volume: u8,
+        tempo: u16,
+    }
+
+    impl Preset for Settings {
+        const VERSION: u8 = 2;
+
+        fn save(&self, writer: &mut PresetWriter<'_>) -> Result<(), Error> {
+            writer.write_u8(self.volume)?;
+            writer.write_u16(self.tempo)
+        }
+
+        fn load(&mut self, _: u8, reader: &mut PresetReader<'_>) -> Result<(), Error> {
+            if let Some(volume) = reader.read_u8() {
+                self.volume = volume;
+            }
+            if let Some(tempo) = reader.read_u16() {
+                self.tempo = tempo;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn should_save_and_load_presets() {
+        let settings = Settings {
+            volume: 100,
+            tempo: 1200,
+        };
+        let mut buffer = [0; 16];
+        assert_eq!(save(&settings, &mut buffer), Ok(8));
+        assert_eq!(buffer[..3], [2, 3, 0]);
+
+        let mut loaded = Settings::default();
+        assert_eq!(load(&mut loaded, &buffer), Ok(8));
+        assert_eq!(loaded, settings);
+
+        buffer[4] ^= 1;
+        assert_eq!(load(&mut loaded, &buffer), Err(Error::Parse));
+        assert_eq!(
+            save(&settings, &mut buffer[..7]),
+            Err(Error::BufferTooSmall)
+        );
+    }
+
+    #[test]
+    fn should_keep_fields_missing_from_older_versions() {
+        // A version 1 preset with only the volume
+        let mut buffer = [1, 1, 0, 90, 0, 0];
+        let sum = checksum(&buffer[..4]).to_le_bytes();
+        buffer[4..].copy_from_slice(&sum);
+
+        let mut loaded = Settings {
+            volume: 0,
+            tempo: 1200,
+        };
+        assert_eq!(load(&mut loaded, &buffer), Ok(6));
+        assert_eq!(
+            loaded,
+            Settings {
+                volume: 90,
+                tempo: 1200
+            }
+        );
+    }
+}
@@ -0,0 +1,121 @@
+//! Probability gate randomly dropping notes
+
+use crate::midi_types::{Channel, MidiMessage, Note, Value7};
+use crate::process::Processor;
+use crate::random::Random;
+
+/// Passes every note-on with a probability, the note-off of a dropped note is dropped too
+///
+/// The probability is looked up per note by calling `probability` with the channel and note, a
+/// probability of 127 always passes the note and 0 never does.
+#[derive(Debug, Clone)]
+pub struct ProbabilityGate<F> {
+    probability: F,
+    dropped: [u128; 16],
+    random: Random,
+}
+
+impl<F: FnMut(Channel, Note) -> Value7> ProbabilityGate<F> {
+    pub fn new(probability: F) -> Self {
+        ProbabilityGate {
+            probability,
+            dropped: [0; 16],
+            random: Random::new(),
+        }
+    }
+
+    /// Seed the random generator, the same seed drops the same notes
+    pub fn set_seed(&mut self, seed: u32) {
+        self.random.seed(seed);
+    }
+
+    fn set_dropped(&mut self, channel: Channel, note: Note, dropped: bool) {
+        if let Some(notes) = self.dropped.get_mut(u8::from(channel) as usize) {
+            let bit = 1u128 << u8::from(note);
+            if dropped {
+                *notes |= bit;
+            } else {
+                *notes &= !bit;
+            }
+        }
+    }
+
+    fn is_dropped(&self, channel: Channel, note: Note) -> bool {
+        self.dropped
+            .get(u8::from(channel) as usize)
+            .map_or(false, |notes| notes >> u8::from(note) & 1 == 1)
+    }
+}
+
+impl<F: FnMut(Channel, Note) -> Value7> Processor for ProbabilityGate<F> {
+    fn process(&mut self, message: MidiMessage, emit: &mut impl FnMut(MidiMessage)) {
+        match message {
+            MidiMessage::NoteOn(channel, note, velocity) if u8::from(velocity) > 0 => {
+                let probability = u8::from((self.probability)(channel, note)) as u32;
+                let pass = probability >= 127 || self.random.below(127) < probability;
+
+                self.set_dropped(channel, note, !pass);
+                if pass {
+                    emit(message);
+                }
+            }
+            MidiMessage::NoteOn(channel, note, _) | MidiMessage::NoteOff(channel, note, _) => {
+                if self.is_dropped(channel, note) {
+                    self.set_dropped(channel, note, false);
+                } else {
+                    emit(message);
+                }
+            }
+            message => emit(message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::vec::Vec;
+
+    fn play(gate: &mut impl Processor, channel: Channel, note: Note) -> Vec<MidiMessage> {
+        let mut output = Vec::new();
+        gate.process(
+            MidiMessage::NoteOn(channel, note, 100.into()),
+            &mut |message| output.push(message),
+        );
+        gate.process(
+            MidiMessage::NoteOff(channel, note, 0.into()),
+            &mut |message| output.push(message),
+        );
+        output
+    }
+
+    #[test]
+    fn should_use_probability_per_channel() {
+        let probabilities = [
+            127, 0, 127, 0, 127, 0, 127, 0, 127, 0, 127, 0, 127, 0, 127, 0,
+        ];
+        let mut gate =
+            ProbabilityGate::new(|channel, _| probabilities[u8::from(channel) as usize].into());
+
+        assert_eq!(play(&mut gate, Channel::C1, Note::C4).len(), 2);
+        assert_eq!(play(&mut gate, Channel::C2, Note::C4), []);
+    }
+
+    #[test]
+    fn should_drop_note_off_of_dropped_notes() {
+        let mut gate = ProbabilityGate::new(|_, _| Value7::new(64));
+        gate.set_seed(7);
+
+        let mut passed = 0;
+        for _ in 0..1000 {
+            match play(&mut gate, Channel::C1, Note::C4).len() {
+                0 => {}
+                2 => passed += 1,
+                _ => panic!("Note-on and note-off should be passed or dropped together"),
+            }
+        }
+
+        assert!((400..600).contains(&passed));
+    }
+}
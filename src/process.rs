@@ -0,0 +1,171 @@
+//! Midi message processors and static processor chains
+//!
+//! A processor takes a message and passes zero or more messages on. Processors are combined with
+//! `Processor::chain` or by grouping them in a tuple. Chains are fully monomorphized, there is no
+//! dynamic dispatch or allocation, so a chain compiles to straight-line code suitable for use in
+//! interrupt handlers.
+
+use crate::midi_types::MidiMessage;
+
+/// Transforms midi messages
+pub trait Processor {
+    /// Process a message, passing the resulting messages to `emit`
+    fn process(&mut self, message: MidiMessage, emit: &mut impl FnMut(MidiMessage));
+
+    /// Pass the output of this processor to another processor
+    fn chain<P: Processor>(self, next: P) -> Chain<Self, P>
+    where
+        Self: Sized,
+    {
+        Chain {
+            first: self,
+            second: next,
+        }
+    }
+}
+
+/// Two processors in series, created by `Processor::chain`
+#[derive(Debug, Clone)]
+pub struct Chain<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A, B> Chain<A, B> {
+    /// Split the chain into its processors
+    pub fn into_inner(self) -> (A, B) {
+        (self.first, self.second)
+    }
+}
+
+impl<A: Processor, B: Processor> Processor for Chain<A, B> {
+    #[inline]
+    fn process(&mut self, message: MidiMessage, emit: &mut impl FnMut(MidiMessage)) {
+        let second = &mut self.second;
+        self.first
+            .process(message, &mut |message| second.process(message, emit));
+    }
+}
+
+/// Only passes messages for which the predicate returns true
+#[derive(Debug, Clone)]
+pub struct Filter<F>(pub F);
+
+impl<F: FnMut(&MidiMessage) -> bool> Processor for Filter<F> {
+    #[inline]
+    fn process(&mut self, message: MidiMessage, emit: &mut impl FnMut(MidiMessage)) {
+        if (self.0)(&message) {
+            emit(message);
+        }
+    }
+}
+
+/// Replaces every message by the result of a function
+#[derive(Debug, Clone)]
+pub struct Map<F>(pub F);
+
+impl<F: FnMut(MidiMessage) -> MidiMessage> Processor for Map<F> {
+    #[inline]
+    fn process(&mut self, message: MidiMessage, emit: &mut impl FnMut(MidiMessage)) {
+        emit((self.0)(message));
+    }
+}
+
+/// Passes all messages unchanged
+impl Processor for () {
+    #[inline]
+    fn process(&mut self, message: MidiMessage, emit: &mut impl FnMut(MidiMessage)) {
+        emit(message);
+    }
+}
+
+macro_rules! impl_tuple_processor {
+    ($first:ident $(, $rest:ident)+) => {
+        /// Processors in a tuple are chained in order
+        impl<$first: Processor, $($rest: Processor),+> Processor for ($first, $($rest),+) {
+            #[inline]
+            #[allow(non_snake_case)]
+            fn process(&mut self, message: MidiMessage, emit: &mut impl FnMut(MidiMessage)) {
+                let ($first, $($rest),+) = self;
+                let mut rest = ($($rest,)+);
+                $first.process(message, &mut |message| rest.process(message, emit));
+            }
+        }
+    };
+}
+
+/// A single processor in a tuple, the end of a tuple chain
+impl<A: Processor> Processor for (A,) {
+    #[inline]
+    fn process(&mut self, message: MidiMessage, emit: &mut impl FnMut(MidiMessage)) {
+        self.0.process(message, emit);
+    }
+}
+
+impl<A: Processor> Processor for &mut A {
+    #[inline]
+    fn process(&mut self, message: MidiMessage, emit: &mut impl FnMut(MidiMessage)) {
+        (**self).process(message, emit);
+    }
+}
+
+impl_tuple_processor!(A, B);
+impl_tuple_processor!(A, B, C);
+impl_tuple_processor!(A, B, C, D);
+impl_tuple_processor!(A, B, C, D, E);
+impl_tuple_processor!(A, B, C, D, E, F);
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use crate::midi_types::{Channel, Note, Value7};
+    use std::vec::Vec;
+
+    fn transpose(message: MidiMessage) -> MidiMessage {
+        match message {
+            MidiMessage::NoteOn(channel, note, velocity) => {
+                MidiMessage::NoteOn(channel, (u8::from(note) + 12).into(), velocity)
+            }
+            other => other,
+        }
+    }
+
+    fn run(processor: &mut impl Processor, messages: &[MidiMessage]) -> Vec<MidiMessage> {
+        let mut output = Vec::new();
+        for message in messages {
+            processor.process(*message, &mut |message| output.push(message));
+        }
+        output
+    }
+
+    const INPUT: [MidiMessage; 2] = [
+        MidiMessage::NoteOn(Channel::C1, Note::C4, Value7::new(100)),
+        MidiMessage::TimingClock,
+    ];
+
+    #[test]
+    fn should_chain_processors() {
+        let mut chain = Filter(|message: &MidiMessage| message != &MidiMessage::TimingClock)
+            .chain(Map(transpose));
+
+        assert_eq!(
+            run(&mut chain, &INPUT),
+            [MidiMessage::NoteOn(Channel::C1, Note::C5, Value7::new(100))]
+        );
+    }
+
+    #[test]
+    fn should_chain_processors_in_tuples() {
+        let mut chain = (
+            Map(transpose),
+            Filter(|message: &MidiMessage| message != &MidiMessage::TimingClock),
+            Map(transpose),
+        );
+
+        assert_eq!(
+            run(&mut chain, &INPUT),
+            [MidiMessage::NoteOn(Channel::C1, Note::C6, Value7::new(100))]
+        );
+    }
+}
@@ -0,0 +1,158 @@
+//! Trigger pulses from the midi clock for analog clock outputs
+
+use crate::midi_types::MidiMessage;
+
+/// Changes of a pulse output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PulseEvent {
+    High,
+    Low,
+}
+
+/// Divides the midi clock into trigger pulses
+///
+/// A pulse is sent every `division` clock ticks, the default of 6 gives sixteenth notes at 24
+/// ticks per quarter note. Pulses stay high for the pulse length, in the unit used to advance the
+/// output.
+#[derive(Debug, Clone)]
+pub struct ClockPulses {
+    division: u8,
+    pulse_length: u32,
+    ticks: u8,
+    /// Time left before the pulse goes low
+    pulse: Option<u32>,
+}
+
+impl Default for ClockPulses {
+    fn default() -> Self {
+        Self::new(6)
+    }
+}
+
+impl ClockPulses {
+    pub fn new(division: u8) -> Self {
+        ClockPulses {
+            division: division.max(1),
+            pulse_length: 1,
+            ticks: 0,
+            pulse: None,
+        }
+    }
+
+    pub fn division(&self) -> u8 {
+        self.division
+    }
+
+    /// Set the number of clock ticks per pulse
+    pub fn set_division(&mut self, division: u8) {
+        self.division = division.max(1);
+    }
+
+    pub fn set_pulse_length(&mut self, length: u32) {
+        self.pulse_length = length;
+    }
+
+    /// True while a pulse is high
+    pub fn is_high(&self) -> bool {
+        self.pulse.is_some()
+    }
+
+    /// Handle a clock tick, starting a pulse on the first tick of every division. A pulse that is
+    /// still high is ended first so every pulse has an edge.
+    pub fn tick(&mut self, mut emit: impl FnMut(PulseEvent)) {
+        if self.ticks == 0 {
+            if self.pulse.is_some() {
+                emit(PulseEvent::Low);
+            }
+            emit(PulseEvent::High);
+            self.pulse = Some(self.pulse_length);
+        }
+        self.ticks = (self.ticks + 1) % self.division;
+    }
+
+    /// Advance time, ending the pulse when it is due
+    pub fn advance(&mut self, elapsed: u32, mut emit: impl FnMut(PulseEvent)) {
+        if let Some(remaining) = self.pulse {
+            self.pulse = remaining
+                .checked_sub(elapsed)
+                .filter(|remaining| *remaining > 0);
+            if self.pulse.is_none() {
+                emit(PulseEvent::Low);
+            }
+        }
+    }
+
+    /// Start pulsing from the first tick of a division on the next tick
+    pub fn restart(&mut self) {
+        self.ticks = 0;
+    }
+
+    /// Handle a midi message. Timing clock messages advance the output, start and stop messages
+    /// end the pulse and restart it. Other messages are ignored.
+    pub fn handle(&mut self, message: &MidiMessage, mut emit: impl FnMut(PulseEvent)) {
+        match message {
+            MidiMessage::TimingClock => self.tick(emit),
+            MidiMessage::Start | MidiMessage::Stop => {
+                if self.pulse.take().is_some() {
+                    emit(PulseEvent::Low);
+                }
+                self.restart();
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::vec::Vec;
+
+    fn run(pulses: &mut ClockPulses, messages: &[MidiMessage]) -> Vec<(usize, PulseEvent)> {
+        let mut events = Vec::new();
+        for (index, message) in messages.iter().enumerate() {
+            pulses.handle(message, |event| events.push((index, event)));
+            pulses.advance(1, |event| events.push((index, event)));
+        }
+        events
+    }
+
+    #[test]
+    fn should_pulse_every_division() {
+        let mut pulses = ClockPulses::new(3);
+        pulses.set_pulse_length(2);
+
+        assert_eq!(
+            run(&mut pulses, &[MidiMessage::TimingClock; 7]),
+            [
+                (0, PulseEvent::High),
+                (1, PulseEvent::Low),
+                (3, PulseEvent::High),
+                (4, PulseEvent::Low),
+                (6, PulseEvent::High),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_restart_on_start() {
+        let mut pulses = ClockPulses::new(4);
+        pulses.set_pulse_length(10);
+
+        let messages = [
+            MidiMessage::TimingClock,
+            MidiMessage::TimingClock,
+            MidiMessage::Start,
+            MidiMessage::TimingClock,
+        ];
+        assert_eq!(
+            run(&mut pulses, &messages),
+            [
+                (0, PulseEvent::High),
+                (2, PulseEvent::Low),
+                (3, PulseEvent::High),
+            ]
+        );
+    }
+}
@@ -0,0 +1,206 @@
+//! Live input quantization to the midi clock grid
+
+use crate::midi_types::{Channel, MidiMessage, Note};
+use crate::process::Processor;
+use crate::scheduler::Scheduler;
+
+/// Delays incoming notes towards the next position on a grid of midi clock ticks, holding up to
+/// `N` delayed messages
+///
+/// The grid is `division` clock ticks, the default of 6 gives sixteenth notes at 24 ticks per
+/// quarter note. The strength is the percentage of the distance to the grid a note is moved, at
+/// 0 notes pass unchanged. Note-offs follow their note-on when it is still delayed, when the
+/// note-off can not be delayed as well the note-on is sent right before it.
+#[derive(Debug, Clone)]
+pub struct InputQuantizer<const N: usize> {
+    division: u8,
+    strength: u8,
+    /// The number of the next clock tick since the last start
+    position: u32,
+    scheduler: Scheduler<N>,
+}
+
+impl<const N: usize> Default for InputQuantizer<N> {
+    fn default() -> Self {
+        Self::new(6)
+    }
+}
+
+impl<const N: usize> InputQuantizer<N> {
+    pub fn new(division: u8) -> Self {
+        InputQuantizer {
+            division: division.max(1),
+            strength: 100,
+            position: 0,
+            scheduler: Scheduler::new(),
+        }
+    }
+
+    pub fn division(&self) -> u8 {
+        self.division
+    }
+
+    /// Set the number of clock ticks per grid step
+    pub fn set_division(&mut self, division: u8) {
+        self.division = division.max(1);
+    }
+
+    pub fn strength(&self) -> u8 {
+        self.strength
+    }
+
+    /// Set how far notes are moved to the grid in percent, values above 100 are limited to 100
+    pub fn set_strength(&mut self, strength: u8) {
+        self.strength = strength.min(100);
+    }
+
+    /// Handle a clock tick, emitting the notes that are due on it
+    pub fn tick(&mut self, emit: impl FnMut(MidiMessage)) {
+        self.scheduler.poll(self.position, emit);
+        self.position = self.position.wrapping_add(1);
+    }
+
+    /// Emit all delayed notes right away
+    pub fn flush(&mut self, mut emit: impl FnMut(MidiMessage)) {
+        while let Some(message) = self
+            .scheduler
+            .pop_due(self.position.wrapping_add(i32::MAX as u32))
+        {
+            emit(message);
+        }
+    }
+
+    /// Start the grid from the next tick, emitting all delayed notes
+    pub fn restart(&mut self, emit: impl FnMut(MidiMessage)) {
+        self.flush(emit);
+        self.position = 0;
+    }
+
+    /// The tick a note played now is moved to
+    fn target(&self) -> u32 {
+        let division = self.division as u32;
+        let distance = (division - self.position % division) % division;
+        let delay = (distance * self.strength as u32 + 50) / 100;
+        self.position.wrapping_add(delay)
+    }
+
+    /// The tick a delayed note-on is sent
+    fn pending(&self, channel: Channel, note: Note) -> Option<u32> {
+        self.scheduler
+            .iter()
+            .find_map(|(time, message)| match message {
+                MidiMessage::NoteOn(c, n, _) if c == channel && n == note => Some(time),
+                _ => None,
+            })
+    }
+
+    fn delay(&mut self, time: u32, message: MidiMessage, emit: &mut impl FnMut(MidiMessage)) {
+        if let Err(message) = self.scheduler.schedule(time, message) {
+            emit(message);
+        }
+    }
+
+    /// Send a delayed note-on right away, for a note-off that can not be delayed
+    fn send_pending(&mut self, channel: Channel, note: Note, emit: &mut impl FnMut(MidiMessage)) {
+        let is_pending = |message: &MidiMessage| matches!(*message, MidiMessage::NoteOn(c, n, _) if c == channel && n == note);
+        let pending = self
+            .scheduler
+            .iter()
+            .find(|(_, message)| is_pending(message));
+        if let Some((_, message)) = pending {
+            self.scheduler.cancel(is_pending);
+            emit(message);
+        }
+    }
+}
+
+impl<const N: usize> Processor for InputQuantizer<N> {
+    fn process(&mut self, message: MidiMessage, emit: &mut impl FnMut(MidiMessage)) {
+        match message {
+            MidiMessage::NoteOn(_, _, velocity) if self.strength > 0 && u8::from(velocity) > 0 => {
+                self.delay(self.target(), message, emit)
+            }
+            MidiMessage::NoteOn(channel, note, _) | MidiMessage::NoteOff(channel, note, _) => {
+                if let Some(time) = self.pending(channel, note) {
+                    match self.scheduler.schedule(time, message) {
+                        Ok(()) => return,
+                        // The scheduler is full, play the delayed note-on first
+                        Err(_) => self.send_pending(channel, note, emit),
+                    }
+                }
+                emit(message)
+            }
+            MidiMessage::TimingClock => {
+                self.tick(&mut *emit);
+                emit(message);
+            }
+            MidiMessage::Start => {
+                self.restart(&mut *emit);
+                emit(message);
+            }
+            MidiMessage::Stop => {
+                self.flush(&mut *emit);
+                emit(message);
+            }
+            message => emit(message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use crate::test_util::{off, on};
+    use std::vec::Vec;
+
+    /// Play notes between clock ticks, returns the notes and the number of ticks before them
+    fn play<const N: usize>(
+        quantizer: &mut InputQuantizer<N>,
+        input: &[(u32, MidiMessage)],
+    ) -> Vec<(u32, MidiMessage)> {
+        let mut output = Vec::new();
+        for tick in 0..12 {
+            for (_, message) in input.iter().filter(|(time, _)| *time == tick) {
+                quantizer.process(*message, &mut |message| output.push((tick, message)));
+            }
+            quantizer.process(MidiMessage::TimingClock, &mut |message| {
+                if message != MidiMessage::TimingClock {
+                    output.push((tick, message))
+                }
+            });
+        }
+        output
+    }
+
+    #[test]
+    fn should_delay_notes_to_next_grid_position() {
+        let mut quantizer = InputQuantizer::<4>::new(6);
+
+        assert_eq!(
+            play(&mut quantizer, &[(2, on(60)), (3, off(60)), (8, off(60))]),
+            [(6, on(60)), (6, off(60)), (8, off(60))]
+        );
+    }
+
+    #[test]
+    fn should_send_delayed_note_before_note_off_when_full() {
+        let mut quantizer = InputQuantizer::<1>::new(6);
+
+        assert_eq!(
+            play(&mut quantizer, &[(2, on(60)), (3, off(60))]),
+            [(3, on(60)), (3, off(60))]
+        );
+    }
+
+    #[test]
+    fn should_apply_strength() {
+        let mut quantizer = InputQuantizer::<4>::new(6);
+        quantizer.set_strength(50);
+        assert_eq!(play(&mut quantizer, &[(2, on(60))]), [(4, on(60))]);
+
+        let mut quantizer = InputQuantizer::<4>::new(6);
+        quantizer.set_strength(0);
+        assert_eq!(play(&mut quantizer, &[(2, on(60))]), [(2, on(60))]);
+    }
+}
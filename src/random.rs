@@ -0,0 +1,32 @@
+//! Small seedable random number generator for generative features
+
+/// Xorshift generator, fast and good enough for musical randomness
+#[derive(Debug, Clone)]
+pub(crate) struct Random(u32);
+
+impl Random {
+    pub const fn new() -> Self {
+        Random(0x2545_F491)
+    }
+
+    /// Restart the sequence from a seed, a zero seed is replaced since it would only produce
+    /// zeros
+    pub fn seed(&mut self, seed: u32) {
+        self.0 = if seed == 0 { 0x2545_F491 } else { seed };
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let mut value = self.0;
+        value ^= value << 13;
+        value ^= value >> 17;
+        value ^= value << 5;
+        self.0 = value;
+        value
+    }
+
+    /// A random number in `0..bound`, returns 0 when the bound is 0
+    pub fn below(&mut self, bound: u32) -> u32 {
+        // Scale instead of using the remainder to avoid bias towards low numbers
+        ((self.next_u32() as u64 * bound as u64) >> 32) as u32
+    }
+}
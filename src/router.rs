@@ -0,0 +1,838 @@
+//! Routing messages from a number of inputs to a number of outputs
+//!
+//! A `RoutingMatrix` has a crosspoint for every input and output pair. A message received on an
+//! input is sent to every output with an enabled crosspoint whose filter passes the message, like
+//! the patchbay of a multi port midi interface.
+//!
+//! Every input and output also has a filter of its own, a message is only routed when it passes
+//! the filter of its input, the crosspoint and the filter of the output. Filters are stored in
+//! three bytes so the filters of all ports fit in a few bytes of eeprom.
+//!
+//! Clock is forwarded from every input by default. Selecting a clock source input drops clock
+//! from all other inputs, so two devices sending clock never end up on the same output. When the
+//! source stops sending clock while it is running the router can keep the clock going at the last
+//! measured tempo.
+//!
+//! `panic` sends sustain off, all notes off and all sound off on every channel of every output,
+//! to silence all connected devices with one call.
+//!
+//! The matrix is saved and loaded as a `Preset`, with its crosspoints, port filters and clock
+//! source.
+//!
+//! Messages routed with `receive` and `update` are recorded in the activity flags of their input
+//! and outputs, for front panel leds.
+
+use crate::activity::Activity;
+use crate::io::MidiSink;
+use crate::midi_types::{Channel, Control, MidiMessage, Value7};
+use crate::port::PortEvent;
+use crate::preset::{Preset, PresetReader, PresetWriter};
+use crate::Error;
+
+/// Groups of messages that can be filtered together
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageClass {
+    /// Note-on and note-off messages
+    Notes,
+
+    /// Key and channel pressure
+    Pressure,
+    ControlChange,
+    ProgramChange,
+    PitchBend,
+
+    /// Timing clock, start, continue and stop
+    Clock,
+
+    /// Time code, song position, song select and tune request
+    SystemCommon,
+
+    /// Active sensing and reset
+    SystemRealTime,
+}
+
+impl MessageClass {
+    pub fn of(message: &MidiMessage) -> Self {
+        match message {
+            MidiMessage::NoteOn(..) | MidiMessage::NoteOff(..) => MessageClass::Notes,
+            MidiMessage::KeyPressure(..) | MidiMessage::ChannelPressure(..) => {
+                MessageClass::Pressure
+            }
+            MidiMessage::ControlChange(..) => MessageClass::ControlChange,
+            MidiMessage::ProgramChange(..) => MessageClass::ProgramChange,
+            MidiMessage::PitchBendChange(..) => MessageClass::PitchBend,
+            MidiMessage::TimingClock
+            | MidiMessage::Start
+            | MidiMessage::Continue
+            | MidiMessage::Stop => MessageClass::Clock,
+            MidiMessage::QuarterFrame(_)
+            | MidiMessage::SongPositionPointer(_)
+            | MidiMessage::SongSelect(_)
+            | MidiMessage::TuneRequest => MessageClass::SystemCommon,
+            MidiMessage::ActiveSensing | MidiMessage::Reset => MessageClass::SystemRealTime,
+        }
+    }
+
+    pub(crate) const fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// The channel of a channel message, `None` for system messages
+pub fn channel_of(message: &MidiMessage) -> Option<Channel> {
+    match *message {
+        MidiMessage::NoteOn(channel, ..)
+        | MidiMessage::NoteOff(channel, ..)
+        | MidiMessage::KeyPressure(channel, ..)
+        | MidiMessage::ControlChange(channel, ..)
+        | MidiMessage::ProgramChange(channel, _)
+        | MidiMessage::ChannelPressure(channel, _)
+        | MidiMessage::PitchBendChange(channel, _) => Some(channel),
+        _ => None,
+    }
+}
+
+/// The controllers `panic` sends 0 to: sustain, all notes off and all sound off
+const PANIC_CONTROLS: [u8; 3] = [64, 123, 120];
+
+/// Pass the messages that silence a channel to `emit`: sustain off, all notes off and all sound
+/// off
+pub fn panic_messages(channel: Channel, mut emit: impl FnMut(MidiMessage)) {
+    for control in PANIC_CONTROLS {
+        emit(MidiMessage::ControlChange(
+            channel,
+            Control::new(control),
+            Value7::new(0),
+        ));
+    }
+}
+
+/// Passes messages of selected classes and channels, system messages pass regardless of the
+/// selected channels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageFilter {
+    channels: u16,
+    classes: u8,
+}
+
+impl Default for MessageFilter {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+impl MessageFilter {
+    /// Passes all messages
+    pub const ALL: MessageFilter = MessageFilter {
+        channels: 0xFFFF,
+        classes: 0xFF,
+    };
+
+    /// Blocks all messages
+    pub const NONE: MessageFilter = MessageFilter {
+        channels: 0,
+        classes: 0,
+    };
+
+    pub fn passes(&self, message: &MidiMessage) -> bool {
+        let channel = channel_of(message).map_or(true, |channel| self.passes_channel(channel));
+        channel && self.passes_class(MessageClass::of(message))
+    }
+
+    pub fn passes_channel(&self, channel: Channel) -> bool {
+        self.channels & 1 << u8::from(channel) != 0
+    }
+
+    pub fn passes_class(&self, class: MessageClass) -> bool {
+        self.classes & class.bit() != 0
+    }
+
+    pub fn allow_channel(&mut self, channel: Channel) {
+        self.channels |= 1 << u8::from(channel);
+    }
+
+    pub fn block_channel(&mut self, channel: Channel) {
+        self.channels &= !(1 << u8::from(channel));
+    }
+
+    pub fn allow(&mut self, class: MessageClass) {
+        self.classes |= class.bit();
+    }
+
+    pub fn block(&mut self, class: MessageClass) {
+        self.classes &= !class.bit();
+    }
+
+    /// True when timing clock, start, continue and stop pass
+    pub fn forwards_clock(&self) -> bool {
+        self.passes_class(MessageClass::Clock)
+    }
+
+    pub fn set_clock_forwarding(&mut self, forward: bool) {
+        if forward {
+            self.allow(MessageClass::Clock);
+        } else {
+            self.block(MessageClass::Clock);
+        }
+    }
+
+    /// The filter as the channel mask, least significant byte first, and the class mask
+    pub const fn to_bytes(self) -> [u8; 3] {
+        let [low, high] = self.channels.to_le_bytes();
+        [low, high, self.classes]
+    }
+
+    pub const fn from_bytes(bytes: [u8; 3]) -> Self {
+        let [low, high, classes] = bytes;
+        MessageFilter {
+            channels: u16::from_le_bytes([low, high]),
+            classes,
+        }
+    }
+}
+
+/// The connection between an input and an output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crosspoint {
+    pub enabled: bool,
+    pub filter: MessageFilter,
+}
+
+impl Crosspoint {
+    /// A disabled crosspoint that passes all messages once it is enabled
+    pub const DISCONNECTED: Crosspoint = Crosspoint {
+        enabled: false,
+        filter: MessageFilter::ALL,
+    };
+
+    pub fn passes(&self, message: &MidiMessage) -> bool {
+        self.enabled && self.filter.passes(message)
+    }
+}
+
+/// Selects the input clock is forwarded from and regenerates clock when that input drops
+///
+/// Times are plain `u32` values in any unit, as for the scheduler.
+#[derive(Debug, Clone, Default)]
+pub struct ClockSource {
+    source: Option<usize>,
+
+    /// How long the source may be silent before clock is regenerated, `None` to not regenerate
+    timeout: Option<u32>,
+    running: bool,
+
+    /// The time between the last two ticks received from the source
+    interval: Option<u32>,
+    last_received: Option<u32>,
+    last_sent: u32,
+}
+
+impl ClockSource {
+    pub const fn new() -> Self {
+        ClockSource {
+            source: None,
+            timeout: None,
+            running: false,
+            interval: None,
+            last_received: None,
+            last_sent: 0,
+        }
+    }
+
+    /// The input clock is forwarded from, `None` when clock is forwarded from all inputs
+    pub fn source(&self) -> Option<usize> {
+        self.source
+    }
+
+    /// Select the input clock is forwarded from, forgetting the tempo of the previous source
+    pub fn set_source(&mut self, source: Option<usize>) {
+        *self = ClockSource {
+            source,
+            timeout: self.timeout,
+            ..Self::new()
+        };
+    }
+
+    /// How long the source may be silent before clock is regenerated
+    pub fn regeneration(&self) -> Option<u32> {
+        self.timeout
+    }
+
+    /// Regenerate clock when the source is silent for `timeout` while running, `None` to stop
+    /// clock when the source drops
+    pub fn set_regeneration(&mut self, timeout: Option<u32>) {
+        self.timeout = timeout;
+    }
+
+    /// True when clock from the input is forwarded
+    pub fn accepts(&self, input: usize) -> bool {
+        self.source.map_or(true, |source| source == input)
+    }
+
+    /// True while clock is regenerated because the source dropped
+    pub fn is_regenerating(&self, now: u32) -> bool {
+        match (self.timeout, self.last_received, self.interval) {
+            (Some(timeout), Some(received), Some(_)) => {
+                self.running && now.wrapping_sub(received) >= timeout
+            }
+            _ => false,
+        }
+    }
+
+    /// Keep track of the tempo and transport state of the source
+    pub fn receive(&mut self, input: usize, message: &MidiMessage, now: u32) {
+        if self.source != Some(input) {
+            return;
+        }
+
+        match message {
+            MidiMessage::TimingClock => {
+                self.interval = self
+                    .last_received
+                    .map(|received| now.wrapping_sub(received))
+                    .filter(|interval| self.timeout.map_or(true, |timeout| *interval < timeout))
+                    .or(self.interval);
+                self.last_received = Some(now);
+                self.last_sent = now;
+            }
+            MidiMessage::Start | MidiMessage::Continue => self.running = true,
+            MidiMessage::Stop => self.running = false,
+            _ => {}
+        }
+    }
+
+    /// Pass clock ticks that are due to `emit` while regenerating
+    pub fn update(&mut self, now: u32, mut emit: impl FnMut(MidiMessage)) {
+        let interval = match self.interval {
+            Some(interval) if interval > 0 && self.is_regenerating(now) => interval,
+            _ => return,
+        };
+
+        while now.wrapping_sub(self.last_sent) >= interval {
+            self.last_sent = self.last_sent.wrapping_add(interval);
+            emit(MidiMessage::TimingClock);
+        }
+    }
+}
+
+/// Routes messages from `IN` inputs to `OUT` outputs, all crosspoints start disconnected and all
+/// port filters start passing all messages
+#[derive(Debug, Clone)]
+pub struct RoutingMatrix<const IN: usize, const OUT: usize> {
+    crosspoints: [[Crosspoint; OUT]; IN],
+    input_filters: [MessageFilter; IN],
+    output_filters: [MessageFilter; OUT],
+    clock: ClockSource,
+    input_activity: [Activity; IN],
+    output_activity: [Activity; OUT],
+}
+
+impl<const IN: usize, const OUT: usize> Default for RoutingMatrix<IN, OUT> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const IN: usize, const OUT: usize> RoutingMatrix<IN, OUT> {
+    pub fn new() -> Self {
+        RoutingMatrix {
+            crosspoints: [[Crosspoint::DISCONNECTED; OUT]; IN],
+            input_filters: [MessageFilter::ALL; IN],
+            output_filters: [MessageFilter::ALL; OUT],
+            clock: ClockSource::new(),
+            input_activity: [Activity::new(); IN],
+            output_activity: [Activity::new(); OUT],
+        }
+    }
+
+    pub fn crosspoint(&self, input: usize, output: usize) -> Option<&Crosspoint> {
+        self.crosspoints.get(input)?.get(output)
+    }
+
+    pub fn crosspoint_mut(&mut self, input: usize, output: usize) -> Option<&mut Crosspoint> {
+        self.crosspoints.get_mut(input)?.get_mut(output)
+    }
+
+    /// Enable the crosspoint between an input and an output, keeping its filter
+    pub fn connect(&mut self, input: usize, output: usize) {
+        self.set_enabled(input, output, true);
+    }
+
+    /// Disable the crosspoint between an input and an output, keeping its filter
+    pub fn disconnect(&mut self, input: usize, output: usize) {
+        self.set_enabled(input, output, false);
+    }
+
+    pub fn set_enabled(&mut self, input: usize, output: usize, enabled: bool) {
+        if let Some(crosspoint) = self.crosspoint_mut(input, output) {
+            crosspoint.enabled = enabled;
+        }
+    }
+
+    pub fn is_connected(&self, input: usize, output: usize) -> bool {
+        self.crosspoint(input, output)
+            .map_or(false, |crosspoint| crosspoint.enabled)
+    }
+
+    pub fn set_filter(&mut self, input: usize, output: usize, filter: MessageFilter) {
+        if let Some(crosspoint) = self.crosspoint_mut(input, output) {
+            crosspoint.filter = filter;
+        }
+    }
+
+    pub fn input_filter(&self, input: usize) -> Option<&MessageFilter> {
+        self.input_filters.get(input)
+    }
+
+    pub fn input_filter_mut(&mut self, input: usize) -> Option<&mut MessageFilter> {
+        self.input_filters.get_mut(input)
+    }
+
+    pub fn set_input_filter(&mut self, input: usize, filter: MessageFilter) {
+        if let Some(input) = self.input_filters.get_mut(input) {
+            *input = filter;
+        }
+    }
+
+    pub fn output_filter(&self, output: usize) -> Option<&MessageFilter> {
+        self.output_filters.get(output)
+    }
+
+    pub fn output_filter_mut(&mut self, output: usize) -> Option<&mut MessageFilter> {
+        self.output_filters.get_mut(output)
+    }
+
+    pub fn set_output_filter(&mut self, output: usize, filter: MessageFilter) {
+        if let Some(output) = self.output_filters.get_mut(output) {
+            *output = filter;
+        }
+    }
+
+    pub fn clock(&self) -> &ClockSource {
+        &self.clock
+    }
+
+    pub fn clock_mut(&mut self) -> &mut ClockSource {
+        &mut self.clock
+    }
+
+    /// Block or forward clock to an output, this changes the filter of the output
+    pub fn set_clock_output(&mut self, output: usize, forward: bool) {
+        if let Some(filter) = self.output_filters.get_mut(output) {
+            filter.set_clock_forwarding(forward);
+        }
+    }
+
+    /// The activity of an input since it was last taken
+    pub fn input_activity(&self, input: usize) -> Activity {
+        self.input_activity.get(input).copied().unwrap_or_default()
+    }
+
+    /// Return the activity of an input and clear it
+    pub fn take_input_activity(&mut self, input: usize) -> Activity {
+        self.input_activity
+            .get_mut(input)
+            .map(Activity::take)
+            .unwrap_or_default()
+    }
+
+    /// The activity of an output since it was last taken
+    pub fn output_activity(&self, output: usize) -> Activity {
+        self.output_activity
+            .get(output)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Return the activity of an output and clear it
+    pub fn take_output_activity(&mut self, output: usize) -> Activity {
+        self.output_activity
+            .get_mut(output)
+            .map(Activity::take)
+            .unwrap_or_default()
+    }
+
+    /// Disconnect all crosspoints and let the filters of all ports pass all messages
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Route a message received on an input at `now`, keeping track of the tempo of the clock
+    /// source
+    pub fn receive(
+        &mut self,
+        input: usize,
+        message: &MidiMessage,
+        now: u32,
+        mut emit: impl FnMut(usize, MidiMessage),
+    ) {
+        self.clock.receive(input, message, now);
+        if let Some(activity) = self.input_activity.get_mut(input) {
+            activity.record(message);
+        }
+
+        let mut outputs = [false; OUT];
+        self.route(input, message, |output, message| {
+            if let Some(routed) = outputs.get_mut(output) {
+                *routed = true;
+            }
+            emit(output, message)
+        });
+        self.record_outputs(&outputs, message);
+    }
+
+    /// Route clock ticks that are due while the clock source dropped, as if they were received
+    /// from the source
+    pub fn update(&mut self, now: u32, mut emit: impl FnMut(usize, MidiMessage)) {
+        if let Some(source) = self.clock.source() {
+            let mut ticks = 0;
+            self.clock.update(now, |_| ticks += 1);
+            let mut outputs = [false; OUT];
+            for _ in 0..ticks {
+                self.route(source, &MidiMessage::TimingClock, |output, message| {
+                    if let Some(routed) = outputs.get_mut(output) {
+                        *routed = true;
+                    }
+                    emit(output, message)
+                });
+            }
+            self.record_outputs(&outputs, &MidiMessage::TimingClock);
+        }
+    }
+
+    /// Pass a message received on an input to `emit` with the index of every output it is routed
+    /// to, messages from inputs outside the matrix are dropped
+    pub fn route(
+        &self,
+        input: usize,
+        message: &MidiMessage,
+        mut emit: impl FnMut(usize, MidiMessage),
+    ) {
+        let passes_clock =
+            MessageClass::of(message) != MessageClass::Clock || self.clock.accepts(input);
+        let passes_input = self
+            .input_filters
+            .get(input)
+            .map_or(false, |filter| passes_clock && filter.passes(message));
+
+        if let (true, Some(outputs)) = (passes_input, self.crosspoints.get(input)) {
+            outputs
+                .iter()
+                .zip(self.output_filters.iter())
+                .enumerate()
+                .filter(|(_, (crosspoint, filter))| {
+                    crosspoint.passes(message) && filter.passes(message)
+                })
+                .for_each(|(output, _)| emit(output, *message));
+        }
+    }
+
+    /// Route an event tagged with its input port, the events passed to `emit` are tagged with
+    /// their output port
+    pub fn route_event(
+        &self,
+        event: &PortEvent<MidiMessage>,
+        mut emit: impl FnMut(PortEvent<MidiMessage>),
+    ) {
+        self.route(event.port as usize, &event.event, |output, message| {
+            emit(PortEvent::new(output as u8, message))
+        });
+    }
+
+    fn record_outputs(&mut self, outputs: &[bool; OUT], message: &MidiMessage) {
+        self.output_activity
+            .iter_mut()
+            .zip(outputs.iter())
+            .filter(|(_, routed)| **routed)
+            .for_each(|(activity, _)| activity.record(message));
+    }
+
+    /// Pass the messages that silence all channels to `emit` for every output, skipping the
+    /// channels the filter of an output blocks
+    pub fn panic(&self, mut emit: impl FnMut(usize, MidiMessage)) {
+        for (output, filter) in self.output_filters.iter().enumerate() {
+            (0..16)
+                .map(Channel::from)
+                .filter(|channel| filter.passes_channel(*channel))
+                .for_each(|channel| panic_messages(channel, |message| emit(output, message)));
+        }
+    }
+
+    /// Send a message received on an input to the sinks of the outputs it is routed to
+    ///
+    /// The message is sent to all outputs even when sending to one of them fails, the first error
+    /// is returned.
+    pub fn dispatch<S: MidiSink>(
+        &self,
+        input: usize,
+        message: &MidiMessage,
+        sinks: &mut [S; OUT],
+    ) -> Result<(), S::Error> {
+        let mut result = Ok(());
+        self.route(input, message, |output, message| {
+            if let Some(sink) = sinks.get_mut(output) {
+                let sent = sink.send(&message);
+                if result.is_ok() {
+                    result = sent;
+                }
+            }
+        });
+        result
+    }
+}
+
+/// Stored with the number of inputs and outputs, loading fails with `Error::InvalidValue` when
+/// they differ from the matrix
+///
+/// A payload that ends partway is loaded up to the last complete field, the crosspoints, filters
+/// and clock settings after it keep their values. Loading does not report how far it got.
+impl<const IN: usize, const OUT: usize> Preset for RoutingMatrix<IN, OUT> {
+    const VERSION: u8 = 1;
+
+    fn save(&self, writer: &mut PresetWriter<'_>) -> Result<(), Error> {
+        writer.write_u8(IN as u8)?;
+        writer.write_u8(OUT as u8)?;
+        for crosspoint in self.crosspoints.iter().flatten() {
+            writer.write_u8(crosspoint.enabled as u8)?;
+            writer.write_bytes(&crosspoint.filter.to_bytes())?;
+        }
+        for filter in self.input_filters.iter().chain(self.output_filters.iter()) {
+            writer.write_bytes(&filter.to_bytes())?;
+        }
+
+        let source = self.clock.source().map_or(0xFF, |source| source as u8);
+        writer.write_u8(source)?;
+        writer.write_u8(self.clock.regeneration().is_some() as u8)?;
+        writer.write_u32(self.clock.regeneration().unwrap_or_default())
+    }
+
+    fn load(&mut self, _: u8, reader: &mut PresetReader<'_>) -> Result<(), Error> {
+        if reader.read_u8() != Some(IN as u8) || reader.read_u8() != Some(OUT as u8) {
+            return Err(Error::InvalidValue);
+        }
+
+        // Fields missing from the preset keep their values
+        let _ = self.load_fields(reader);
+        Ok(())
+    }
+}
+
+impl<const IN: usize, const OUT: usize> RoutingMatrix<IN, OUT> {
+    fn load_fields(&mut self, reader: &mut PresetReader<'_>) -> Option<()> {
+        for crosspoint in self.crosspoints.iter_mut().flatten() {
+            crosspoint.enabled = reader.read_u8()? != 0;
+            crosspoint.filter = MessageFilter::from_bytes(reader.read_bytes()?);
+        }
+        for filter in self
+            .input_filters
+            .iter_mut()
+            .chain(self.output_filters.iter_mut())
+        {
+            *filter = MessageFilter::from_bytes(reader.read_bytes()?);
+        }
+
+        let source = reader.read_u8()? as usize;
+        self.clock.set_source((source < IN).then_some(source));
+        let regenerate = reader.read_u8()? != 0;
+        let timeout = reader.read_u32()?;
+        self.clock.set_regeneration(regenerate.then_some(timeout));
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use crate::midi_types::Note;
+    use std::vec::Vec;
+
+    const NOTE: MidiMessage = MidiMessage::NoteOn(Channel::C2, Note::C3, Value7::new(100));
+
+    #[derive(Default)]
+    struct Recorder(Vec<MidiMessage>);
+
+    impl MidiSink for Recorder {
+        type Error = ();
+
+        fn send(&mut self, message: &MidiMessage) -> Result<(), Self::Error> {
+            self.0.push(*message);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn should_route_to_connected_outputs() {
+        let mut matrix = RoutingMatrix::<2, 3>::new();
+        matrix.connect(0, 0);
+        matrix.connect(0, 2);
+        matrix.connect(1, 1);
+
+        let mut outputs = Vec::new();
+        matrix.route(0, &NOTE, |output, _| outputs.push(output));
+        matrix.route(2, &NOTE, |output, _| outputs.push(output));
+        assert_eq!(outputs, [0, 2]);
+
+        let mut events = Vec::new();
+        matrix.route_event(&PortEvent::new(1, NOTE), |event| events.push(event));
+        assert_eq!(events, [PortEvent::new(1, NOTE)]);
+
+        matrix.disconnect(0, 2);
+        assert!(!matrix.is_connected(0, 2));
+        assert!(matrix.is_connected(1, 1));
+    }
+
+    #[test]
+    fn should_filter_crosspoints() {
+        let mut filter = MessageFilter::ALL;
+        filter.block_channel(Channel::C2);
+        filter.block(MessageClass::Clock);
+
+        assert!(!filter.passes(&NOTE));
+        assert!(!filter.passes(&MidiMessage::TimingClock));
+        assert!(filter.passes(&MidiMessage::ControlChange(
+            Channel::C1,
+            Control::new(7),
+            Value7::new(90)
+        )));
+        assert!(filter.passes(&MidiMessage::TuneRequest));
+        assert_eq!(MessageFilter::from_bytes(filter.to_bytes()), filter);
+    }
+
+    #[test]
+    fn should_apply_port_filters() {
+        let mut matrix = RoutingMatrix::<2, 2>::new();
+        matrix.connect(0, 0);
+        matrix.connect(0, 1);
+        matrix.connect(1, 0);
+
+        if let Some(filter) = matrix.input_filter_mut(1) {
+            filter.block_channel(Channel::C2);
+        }
+        if let Some(filter) = matrix.output_filter_mut(1) {
+            filter.set_clock_forwarding(false);
+        }
+
+        let mut outputs = Vec::new();
+        matrix.route(0, &MidiMessage::TimingClock, |output, _| {
+            outputs.push(output)
+        });
+        matrix.route(1, &NOTE, |output, _| outputs.push(output));
+        matrix.route(0, &NOTE, |output, _| outputs.push(output));
+        assert_eq!(outputs, [0, 0, 1]);
+        assert!(!matrix.output_filter(1).unwrap().forwards_clock());
+    }
+
+    #[test]
+    fn should_forward_clock_from_the_source() {
+        let mut matrix = RoutingMatrix::<2, 2>::new();
+        matrix.connect(0, 0);
+        matrix.connect(0, 1);
+        matrix.connect(1, 0);
+        matrix.clock_mut().set_source(Some(0));
+        matrix.clock_mut().set_regeneration(Some(50));
+        matrix.set_clock_output(1, false);
+
+        let mut outputs = Vec::new();
+        let mut emit = |output, _| outputs.push(output);
+        matrix.receive(1, &MidiMessage::TimingClock, 0, &mut emit);
+        matrix.receive(0, &MidiMessage::Start, 0, &mut emit);
+        matrix.receive(0, &MidiMessage::TimingClock, 0, &mut emit);
+        matrix.receive(0, &MidiMessage::TimingClock, 20, &mut emit);
+        assert_eq!(outputs, [0, 0, 0]);
+        assert!(matrix.take_input_activity(1).has(MessageClass::Clock));
+        assert!(!matrix.input_activity(1).is_active());
+        assert!(!matrix.take_output_activity(1).is_active());
+
+        let mut ticks = Vec::new();
+        matrix.update(60, |output, message| ticks.push((output, message)));
+        assert!(ticks.is_empty());
+        matrix.update(80, |output, message| ticks.push((output, message)));
+        assert_eq!(
+            ticks,
+            [
+                (0, MidiMessage::TimingClock),
+                (0, MidiMessage::TimingClock),
+                (0, MidiMessage::TimingClock)
+            ]
+        );
+    }
+
+    #[test]
+    fn should_send_panic_to_all_outputs() {
+        let mut matrix = RoutingMatrix::<1, 2>::new();
+        matrix.set_output_filter(1, MessageFilter::NONE);
+        if let Some(filter) = matrix.output_filter_mut(1) {
+            filter.allow_channel(Channel::C10);
+        }
+
+        let mut messages = Vec::new();
+        matrix.panic(|output, message| messages.push((output, message)));
+
+        assert_eq!(messages.len(), 17 * 3);
+        assert_eq!(
+            messages[48..],
+            [
+                (
+                    1,
+                    MidiMessage::ControlChange(Channel::C10, Control::new(64), Value7::new(0))
+                ),
+                (
+                    1,
+                    MidiMessage::ControlChange(Channel::C10, Control::new(123), Value7::new(0))
+                ),
+                (
+                    1,
+                    MidiMessage::ControlChange(Channel::C10, Control::new(120), Value7::new(0))
+                )
+            ]
+        );
+    }
+
+    #[test]
+    fn should_save_and_load_presets() {
+        let mut matrix = RoutingMatrix::<2, 3>::new();
+        matrix.connect(1, 2);
+        matrix.set_clock_output(0, false);
+        matrix.clock_mut().set_source(Some(1));
+        matrix.clock_mut().set_regeneration(Some(500));
+
+        let mut buffer = [0; 64];
+        let len = crate::preset::save(&matrix, &mut buffer).unwrap();
+
+        let mut loaded = RoutingMatrix::<2, 3>::new();
+        assert_eq!(crate::preset::load(&mut loaded, &buffer[..len]), Ok(len));
+        assert!(loaded.is_connected(1, 2));
+        assert!(!loaded.is_connected(0, 2));
+        assert!(!loaded.output_filter(0).unwrap().forwards_clock());
+        assert_eq!(loaded.clock().source(), Some(1));
+        assert_eq!(loaded.clock().regeneration(), Some(500));
+
+        let mut other = RoutingMatrix::<3, 3>::new();
+        assert_eq!(
+            crate::preset::load(&mut other, &buffer[..len]),
+            Err(Error::InvalidValue)
+        );
+    }
+
+    #[test]
+    fn should_dispatch_to_sinks() {
+        let mut matrix = RoutingMatrix::<1, 2>::new();
+        matrix.connect(0, 0);
+        matrix.connect(0, 1);
+        matrix.set_filter(0, 1, {
+            let mut filter = MessageFilter::NONE;
+            filter.allow(MessageClass::Clock);
+            filter
+        });
+
+        let mut sinks = [Recorder::default(), Recorder::default()];
+        matrix.dispatch(0, &NOTE, &mut sinks).unwrap();
+        matrix
+            .dispatch(0, &MidiMessage::TimingClock, &mut sinks)
+            .unwrap();
+
+        assert_eq!(sinks[0].0, [NOTE, MidiMessage::TimingClock]);
+        assert_eq!(sinks[1].0, [MidiMessage::TimingClock]);
+    }
+}
@@ -0,0 +1,161 @@
+//! Serializing messages with running status
+//!
+//! Channel messages with the same status byte as the previous channel message are sent without
+//! their status byte. Some older receivers lose running status after a dropout on the line and
+//! ignore everything until the next status byte, the serializer can send the full status byte
+//! again after a number of bytes or an interval to limit how long such a receiver stays lost.
+//!
+//! Times are plain `u32` values, for instance milliseconds.
+
+use crate::midi_types::MidiMessage;
+use crate::packed::PackedMidiEvent;
+use crate::parser::{is_channel_status, is_real_time};
+
+/// When to send the full status byte again although running status could be used
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StatusRefresh {
+    /// Use running status as long as the status byte does not change
+    #[default]
+    Never,
+
+    /// Send the status byte again once this many bytes were sent since the last status byte
+    AfterBytes(u16),
+
+    /// Send the status byte again once this much time passed since the last status byte
+    AfterTime(u32),
+}
+
+/// Tracks the running status of an output
+#[derive(Debug, Clone, Default)]
+pub struct RunningStatus {
+    /// The last channel status byte sent, 0 when running status was cancelled
+    status: u8,
+    refresh: StatusRefresh,
+
+    /// The number of bytes sent since the last status byte
+    bytes: u16,
+
+    /// The time the last status byte was sent
+    sent_at: u32,
+}
+
+impl RunningStatus {
+    pub const fn new(refresh: StatusRefresh) -> Self {
+        RunningStatus {
+            status: 0,
+            refresh,
+            bytes: 0,
+            sent_at: 0,
+        }
+    }
+
+    pub fn refresh(&self) -> StatusRefresh {
+        self.refresh
+    }
+
+    pub fn set_refresh(&mut self, refresh: StatusRefresh) {
+        self.refresh = refresh;
+    }
+
+    /// Forget the running status so the next channel message is sent with its status byte
+    pub fn reset(&mut self) {
+        self.status = 0;
+    }
+
+    /// Serialize a message sent at `now` into the buffer, returns the bytes to send
+    pub fn render<'b>(
+        &mut self,
+        message: &MidiMessage,
+        now: u32,
+        buffer: &'b mut [u8; 3],
+    ) -> &'b [u8] {
+        let event = PackedMidiEvent::from(*message);
+        let status = event.status();
+        let len = event.len();
+        *buffer = event.to_bytes();
+
+        // Real-time messages do not change the running status
+        if is_real_time(status) {
+            self.bytes = self.bytes.saturating_add(len as u16);
+            return buffer.get(..len).unwrap_or_default();
+        }
+
+        if status == self.status && !self.expired(now) {
+            self.bytes = self.bytes.saturating_add(len as u16 - 1);
+            return buffer.get(1..len).unwrap_or_default();
+        }
+
+        self.status = if is_channel_status(status) { status } else { 0 };
+        self.bytes = len as u16;
+        self.sent_at = now;
+        buffer.get(..len).unwrap_or_default()
+    }
+
+    fn expired(&self, now: u32) -> bool {
+        match self.refresh {
+            StatusRefresh::Never => false,
+            StatusRefresh::AfterBytes(bytes) => self.bytes >= bytes,
+            StatusRefresh::AfterTime(interval) => now.wrapping_sub(self.sent_at) >= interval,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use crate::midi_types::{Channel, Value7};
+    use std::vec::Vec;
+
+    fn note(note: u8) -> MidiMessage {
+        MidiMessage::NoteOn(Channel::C1, note.into(), Value7::new(100))
+    }
+
+    fn render(running: &mut RunningStatus, messages: &[(MidiMessage, u32)]) -> Vec<u8> {
+        let mut output = Vec::new();
+        for (message, now) in messages {
+            output.extend_from_slice(running.render(message, *now, &mut [0; 3]));
+        }
+        output
+    }
+
+    #[test]
+    fn should_resend_status_after_byte_count() {
+        let mut running = RunningStatus::new(StatusRefresh::AfterBytes(5));
+        let output = render(
+            &mut running,
+            &[
+                (note(60), 0),
+                (note(62), 0),
+                (MidiMessage::TimingClock, 0),
+                (note(64), 0),
+                (note(65), 0),
+            ],
+        );
+
+        assert_eq!(
+            output,
+            [0x90, 60, 100, 62, 100, 0xF8, 0x90, 64, 100, 65, 100]
+        );
+    }
+
+    #[test]
+    fn should_resend_status_after_interval() {
+        let mut running = RunningStatus::new(StatusRefresh::AfterTime(300));
+        let output = render(
+            &mut running,
+            &[
+                (note(60), 1000),
+                (note(62), 1299),
+                (note(64), 1300),
+                (MidiMessage::TuneRequest, 1310),
+                (note(65), 1320),
+            ],
+        );
+
+        assert_eq!(
+            output,
+            [0x90, 60, 100, 62, 100, 0x90, 64, 100, 0xF6, 0x90, 65, 100]
+        );
+    }
+}
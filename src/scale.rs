@@ -0,0 +1,258 @@
+//! Musical scales and quantizing notes to a scale
+
+use crate::midi_types::{Channel, MidiMessage, Note, Value7};
+use crate::process::Processor;
+
+/// A scale as the notes it contains in a key
+///
+/// The notes are a bit mask where bit `n` is the note `n` semitones above the root.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Scale {
+    root: u8,
+    mask: u16,
+}
+
+impl Scale {
+    pub const CHROMATIC: u16 = 0b1111_1111_1111;
+    pub const MAJOR: u16 = 0b1010_1011_0101;
+    pub const MINOR: u16 = 0b0101_1010_1101;
+    pub const HARMONIC_MINOR: u16 = 0b1001_1010_1101;
+    pub const DORIAN: u16 = 0b0110_1010_1101;
+    pub const PHRYGIAN: u16 = 0b0101_1010_1011;
+    pub const LYDIAN: u16 = 0b1010_1101_0101;
+    pub const MIXOLYDIAN: u16 = 0b0110_1011_0101;
+    pub const LOCRIAN: u16 = 0b0101_0110_1011;
+    pub const MAJOR_PENTATONIC: u16 = 0b0010_1001_0101;
+    pub const MINOR_PENTATONIC: u16 = 0b0100_1010_1001;
+
+    /// Create a scale from a root from 0 (C) to 11 (B) and a mask of the notes in the scale. The
+    /// root is always part of the scale.
+    pub const fn new(root: u8, mask: u16) -> Self {
+        Scale {
+            root: root % 12,
+            mask: mask & Self::CHROMATIC | 1,
+        }
+    }
+
+    pub fn root(&self) -> u8 {
+        self.root
+    }
+
+    pub fn mask(&self) -> u16 {
+        self.mask
+    }
+
+    pub fn contains(&self, note: Note) -> bool {
+        let degree = (u8::from(note) + 12 - self.root) % 12;
+        self.mask >> degree & 1 == 1
+    }
+
+    /// The nearest note in the scale, notes exactly between two notes of the scale go down
+    pub fn quantize(&self, note: Note) -> Note {
+        let note = u8::from(note);
+        for distance in 0..12u8 {
+            let candidates = [note.checked_sub(distance), note.checked_add(distance)];
+            for candidate in candidates.iter().flatten() {
+                if *candidate < 0x80 && self.contains(Note::new(*candidate)) {
+                    return Note::new(*candidate);
+                }
+            }
+        }
+        Note::new(note)
+    }
+
+    /// Move a note by a number of scale degrees, `None` when the result is out of range. Notes
+    /// outside the scale keep their distance to the scale note below them.
+    pub fn transpose(&self, note: Note, degrees: i8) -> Option<Note> {
+        let note = u8::from(note);
+        let mut base = note;
+        while !self.contains(Note::new(base)) {
+            base = base.checked_sub(1)?;
+        }
+        let offset = note - base;
+
+        for _ in 0..degrees.unsigned_abs() {
+            loop {
+                base = if degrees > 0 {
+                    base.checked_add(1).filter(|base| *base < 0x80)?
+                } else {
+                    base.checked_sub(1)?
+                };
+                if self.contains(Note::new(base)) {
+                    break;
+                }
+            }
+        }
+
+        base.checked_add(offset)
+            .filter(|note| *note < 0x80)
+            .map(Note::new)
+    }
+}
+
+/// What happens when a note is played that quantizes to a note that is already sounding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverlapPolicy {
+    /// The sounding note is kept, no new note-on is sent
+    Merge,
+
+    /// The sounding note is played again
+    Retrigger,
+}
+
+/// Quantizes notes to a scale, remembering up to `N` held notes so their note-off matches the
+/// note-on even when the scale changes
+///
+/// A quantized note sounds until all notes that quantized to it are released.
+#[derive(Debug, Clone)]
+pub struct ScaleQuantizer<const N: usize> {
+    scale: Scale,
+    policy: OverlapPolicy,
+    held: [Option<(Channel, Note, Note)>; N],
+}
+
+impl<const N: usize> ScaleQuantizer<N> {
+    pub fn new(scale: Scale) -> Self {
+        ScaleQuantizer {
+            scale,
+            policy: OverlapPolicy::Merge,
+            held: [None; N],
+        }
+    }
+
+    pub fn scale(&self) -> Scale {
+        self.scale
+    }
+
+    /// Change the scale, held notes keep sounding until they are released
+    pub fn set_scale(&mut self, scale: Scale) {
+        self.scale = scale;
+    }
+
+    pub fn set_policy(&mut self, policy: OverlapPolicy) {
+        self.policy = policy;
+    }
+
+    fn sounding(&self, channel: Channel, output: Note) -> usize {
+        self.held
+            .iter()
+            .flatten()
+            .filter(|(c, _, out)| *c == channel && *out == output)
+            .count()
+    }
+
+    fn note_on(
+        &mut self,
+        channel: Channel,
+        note: Note,
+        velocity: Value7,
+        emit: &mut impl FnMut(MidiMessage),
+    ) {
+        let output = self.scale.quantize(note);
+        let sounding = self.sounding(channel, output) > 0;
+
+        if let Some(slot) = self.held.iter_mut().find(|slot| slot.is_none()) {
+            *slot = Some((channel, note, output));
+        }
+
+        if !sounding || self.policy == OverlapPolicy::Retrigger {
+            emit(MidiMessage::NoteOn(channel, output, velocity));
+        }
+    }
+
+    fn note_off(
+        &mut self,
+        channel: Channel,
+        note: Note,
+        velocity: Value7,
+        emit: &mut impl FnMut(MidiMessage),
+    ) {
+        let output = match self
+            .held
+            .iter_mut()
+            .find(|slot| matches!(slot, Some((c, n, _)) if *c == channel && *n == note))
+            .and_then(Option::take)
+        {
+            Some((_, _, output)) => output,
+            // Without a remembered note the note-off is quantized like its note-on was
+            None => self.scale.quantize(note),
+        };
+
+        if self.sounding(channel, output) == 0 {
+            emit(MidiMessage::NoteOff(channel, output, velocity));
+        }
+    }
+}
+
+impl<const N: usize> Processor for ScaleQuantizer<N> {
+    fn process(&mut self, message: MidiMessage, emit: &mut impl FnMut(MidiMessage)) {
+        match message {
+            MidiMessage::NoteOn(channel, note, velocity) if u8::from(velocity) > 0 => {
+                self.note_on(channel, note, velocity, emit)
+            }
+            MidiMessage::NoteOn(channel, note, velocity)
+            | MidiMessage::NoteOff(channel, note, velocity) => {
+                self.note_off(channel, note, velocity, emit)
+            }
+            message => emit(message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use crate::test_util::{off, on, process};
+    use std::vec::Vec;
+
+    #[test]
+    fn should_quantize_to_scale() {
+        let d_minor = Scale::new(2, Scale::MINOR);
+        let quantized: Vec<u8> = (60..72)
+            .map(|note| d_minor.quantize(Note::new(note)).into())
+            .collect();
+
+        assert_eq!(quantized, [60, 60, 62, 62, 64, 65, 65, 67, 67, 69, 70, 70]);
+        assert!(Scale::new(0, Scale::MAJOR).contains(Note::new(71)));
+        assert!(!Scale::new(0, Scale::MAJOR).contains(Note::new(70)));
+    }
+
+    #[test]
+    fn should_transpose_by_degrees() {
+        let c_major = Scale::new(0, Scale::MAJOR);
+
+        assert_eq!(c_major.transpose(Note::new(60), 2), Some(Note::new(64)));
+        assert_eq!(c_major.transpose(Note::new(64), 2), Some(Note::new(67)));
+        assert_eq!(c_major.transpose(Note::new(60), -7), Some(Note::new(48)));
+        assert_eq!(c_major.transpose(Note::new(61), 1), Some(Note::new(63)));
+        assert_eq!(c_major.transpose(Note::new(127), 1), None);
+    }
+
+    #[test]
+    fn should_hold_notes_until_all_inputs_are_released() {
+        let mut quantizer = ScaleQuantizer::<4>::new(Scale::new(0, Scale::MAJOR));
+
+        assert_eq!(
+            process(&mut quantizer, &[on(61), on(60), off(61), off(60)]),
+            [on(60), off(60)]
+        );
+
+        quantizer.set_policy(OverlapPolicy::Retrigger);
+        assert_eq!(
+            process(&mut quantizer, &[on(61), on(60), off(60), off(61)]),
+            [on(60), on(60), off(60)]
+        );
+    }
+
+    #[test]
+    fn should_release_notes_after_scale_change() {
+        let mut quantizer = ScaleQuantizer::<4>::new(Scale::new(0, Scale::MAJOR));
+
+        let mut output = process(&mut quantizer, &[on(63)]);
+        quantizer.set_scale(Scale::new(0, Scale::MINOR));
+        output.extend(process(&mut quantizer, &[off(63)]));
+
+        assert_eq!(output, [on(62), off(62)]);
+    }
+}
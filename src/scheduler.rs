@@ -0,0 +1,245 @@
+//! Bounded scheduler for sending messages in the future
+//!
+//! Times are plain `u32` values in any unit, for instance milliseconds or clock ticks, as long as
+//! the same unit is used for scheduling and polling. Times wrap around, events can be scheduled
+//! up to `i32::MAX` units ahead.
+//!
+//! Messages can be scheduled with a tag to cancel them as a group later, for instance all notes
+//! played by one track. Messages scheduled without a tag have tag 0.
+
+use crate::midi_types::MidiMessage;
+use crate::packed::PackedTimedEvent;
+
+/// Holds up to `N` messages until they are due
+#[derive(Debug, Clone)]
+pub struct Scheduler<const N: usize> {
+    events: [PackedTimedEvent; N],
+    tags: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> Scheduler<N> {
+    pub const fn new() -> Self {
+        Scheduler {
+            events: [PackedTimedEvent::from_raw(0); N],
+            tags: [0; N],
+            len: 0,
+        }
+    }
+
+    /// The number of scheduled messages
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    /// Remove all scheduled messages
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Schedule a message, returns the message back when the scheduler is full
+    pub fn schedule(&mut self, time: u32, message: MidiMessage) -> Result<(), MidiMessage> {
+        self.schedule_tagged(time, 0, message)
+    }
+
+    /// Schedule a message with a tag, returns the message back when the scheduler is full
+    pub fn schedule_tagged(
+        &mut self,
+        time: u32,
+        tag: u8,
+        message: MidiMessage,
+    ) -> Result<(), MidiMessage> {
+        match (self.events.get_mut(self.len), self.tags.get_mut(self.len)) {
+            (Some(slot), Some(slot_tag)) => {
+                *slot = PackedTimedEvent::new(time, message.into());
+                *slot_tag = tag;
+                self.len += 1;
+                Ok(())
+            }
+            _ => Err(message),
+        }
+    }
+
+    /// The time of the first scheduled message
+    pub fn next_time(&self, now: u32) -> Option<u32> {
+        self.next(now).map(|(_, event)| event.timestamp())
+    }
+
+    /// Pass all messages that are due at `now` to `emit` in the order of their time, messages
+    /// scheduled for the same time are passed in the order they were scheduled
+    pub fn poll(&mut self, now: u32, mut emit: impl FnMut(MidiMessage)) {
+        while let Some(message) = self.pop_due(now) {
+            emit(message);
+        }
+    }
+
+    /// Remove and return the first message that is due at `now`, this takes a bounded amount of
+    /// work so it can be called once per interrupt
+    pub fn pop_due(&mut self, now: u32) -> Option<MidiMessage> {
+        loop {
+            let (index, event) = self.next(now)?;
+            if !is_due(event.timestamp(), now) {
+                return None;
+            }
+
+            self.remove(index);
+            if let Ok(message) = event.event().message() {
+                return Some(message);
+            }
+        }
+    }
+
+    /// Remove all scheduled messages for which `predicate` returns true
+    pub fn cancel(&mut self, mut predicate: impl FnMut(&MidiMessage) -> bool) {
+        let mut index = 0;
+        while let Some(event) = self.as_slice().get(index) {
+            match event.event().message() {
+                Ok(message) if predicate(&message) => self.remove(index),
+                _ => index += 1,
+            }
+        }
+    }
+
+    /// Remove all scheduled messages with a tag
+    pub fn cancel_tag(&mut self, tag: u8) {
+        let mut index = 0;
+        while let Some(event_tag) = self.tags.get(..self.len).and_then(|tags| tags.get(index)) {
+            if *event_tag == tag {
+                self.remove(index);
+            } else {
+                index += 1;
+            }
+        }
+    }
+
+    /// Iterate over the scheduled messages and their times in the order they were scheduled
+    pub fn iter(&self) -> impl Iterator<Item = (u32, MidiMessage)> + '_ {
+        self.as_slice().iter().filter_map(|event| {
+            let message = event.event().message().ok()?;
+            Some((event.timestamp(), message))
+        })
+    }
+
+    fn as_slice(&self) -> &[PackedTimedEvent] {
+        invariant!(self.len <= N, "more messages scheduled than the capacity");
+        self.events.get(..self.len).unwrap_or_default()
+    }
+
+    /// The earliest event relative to `now`, the first scheduled on ties
+    fn next(&self, now: u32) -> Option<(usize, PackedTimedEvent)> {
+        self.as_slice()
+            .iter()
+            .copied()
+            .enumerate()
+            .min_by_key(|(_, event)| event.timestamp().wrapping_sub(now) as i32)
+    }
+
+    fn remove(&mut self, index: usize) {
+        if index < self.len {
+            self.events.copy_within(index + 1..self.len, index);
+            self.tags.copy_within(index + 1..self.len, index);
+            self.len -= 1;
+        }
+    }
+}
+
+impl<const N: usize> Default for Scheduler<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Check if an event at `time` is due at `now`, allowing times to wrap around
+fn is_due(time: u32, now: u32) -> bool {
+    now.wrapping_sub(time) as i32 >= 0
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::vec::Vec;
+
+    fn poll<const N: usize>(scheduler: &mut Scheduler<N>, now: u32) -> Vec<MidiMessage> {
+        let mut messages = Vec::new();
+        scheduler.poll(now, |message| messages.push(message));
+        messages
+    }
+
+    #[test]
+    fn should_emit_due_messages_in_time_order() {
+        let mut scheduler = Scheduler::<4>::new();
+        scheduler.schedule(20, MidiMessage::Stop).unwrap();
+        scheduler.schedule(10, MidiMessage::Start).unwrap();
+        scheduler.schedule(10, MidiMessage::Continue).unwrap();
+
+        assert_eq!(poll(&mut scheduler, 5), []);
+        assert_eq!(
+            poll(&mut scheduler, 15),
+            [MidiMessage::Start, MidiMessage::Continue]
+        );
+        assert_eq!(scheduler.next_time(15), Some(20));
+        assert_eq!(poll(&mut scheduler, 25), [MidiMessage::Stop]);
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn should_handle_wrapping_time() {
+        let mut scheduler = Scheduler::<4>::new();
+        scheduler.schedule(5, MidiMessage::Stop).unwrap();
+        scheduler
+            .schedule(u32::MAX - 5, MidiMessage::Start)
+            .unwrap();
+
+        assert_eq!(poll(&mut scheduler, u32::MAX - 10), []);
+        assert_eq!(
+            poll(&mut scheduler, 10),
+            [MidiMessage::Start, MidiMessage::Stop]
+        );
+    }
+
+    #[test]
+    fn should_reject_when_full_and_cancel() {
+        let mut scheduler = Scheduler::<2>::new();
+        scheduler.schedule(1, MidiMessage::Start).unwrap();
+        scheduler.schedule(2, MidiMessage::Stop).unwrap();
+        assert_eq!(
+            scheduler.schedule(3, MidiMessage::Continue),
+            Err(MidiMessage::Continue)
+        );
+
+        scheduler.cancel(|message| *message == MidiMessage::Start);
+        assert_eq!(
+            scheduler.iter().collect::<Vec<_>>(),
+            [(2, MidiMessage::Stop)]
+        );
+    }
+
+    #[test]
+    fn should_pop_due_messages_and_cancel_by_tag() {
+        let mut scheduler = Scheduler::<4>::new();
+        scheduler
+            .schedule_tagged(10, 1, MidiMessage::Start)
+            .unwrap();
+        scheduler.schedule_tagged(20, 2, MidiMessage::Stop).unwrap();
+        scheduler
+            .schedule_tagged(30, 1, MidiMessage::Continue)
+            .unwrap();
+        scheduler.schedule(40, MidiMessage::Reset).unwrap();
+
+        scheduler.cancel_tag(1);
+        assert_eq!(scheduler.len(), 2);
+        assert_eq!(scheduler.pop_due(15), None);
+        assert_eq!(scheduler.pop_due(50), Some(MidiMessage::Stop));
+        assert_eq!(scheduler.pop_due(50), Some(MidiMessage::Reset));
+        assert_eq!(scheduler.pop_due(50), None);
+    }
+}
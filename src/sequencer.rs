@@ -0,0 +1,415 @@
+//! Step sequencer driven by midi clock ticks
+//!
+//! Patterns are plain arrays of steps that can be built in a `const` context, so they can be
+//! stored in flash and loaded into the sequencer when needed. Patterns can be queued to play one
+//! after the other, switching on a bar boundary.
+
+use crate::midi_types::{Channel, MidiMessage, Note, Value7};
+
+/// A single step of a pattern
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Step {
+    /// Play a note for `gate` clock ticks, a gate of at least the division of the sequencer
+    /// lasts until the next step
+    Note {
+        note: Note,
+        velocity: Value7,
+        gate: u8,
+    },
+
+    /// Keep the previous note sounding through this step
+    Tie,
+
+    /// Play nothing
+    #[default]
+    Rest,
+}
+
+impl Step {
+    pub const fn note(note: Note, velocity: Value7, gate: u8) -> Self {
+        Step::Note {
+            note,
+            velocity,
+            gate,
+        }
+    }
+}
+
+/// A pattern of up to `N` steps
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pattern<const N: usize> {
+    steps: [Step; N],
+    len: usize,
+}
+
+impl<const N: usize> Pattern<N> {
+    /// Create a pattern using all steps
+    pub const fn new(steps: [Step; N]) -> Self {
+        Pattern { steps, len: N }
+    }
+
+    /// Create a pattern of rests
+    pub const fn empty() -> Self {
+        Self::new([Step::Rest; N])
+    }
+
+    /// The number of steps that are played
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Set the number of steps that are played, at most `N`
+    pub fn set_len(&mut self, len: usize) {
+        self.len = len.min(N);
+    }
+
+    pub fn step(&self, index: usize) -> Option<Step> {
+        self.steps.get(index).copied()
+    }
+
+    /// Change a step, returns false when the index is out of range
+    pub fn set_step(&mut self, index: usize, step: Step) -> bool {
+        match self.steps.get_mut(index) {
+            Some(slot) => {
+                *slot = step;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl<const N: usize> Default for Pattern<N> {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+/// Plays a pattern of up to `N` steps, advancing a step every `division` clock ticks
+#[derive(Debug, Clone)]
+pub struct StepSequencer<const N: usize> {
+    channel: Channel,
+    pattern: Pattern<N>,
+    division: u8,
+    position: usize,
+    ticks: u8,
+    gate: u8,
+    playing: Option<Note>,
+}
+
+impl<const N: usize> StepSequencer<N> {
+    /// Create a sequencer playing a pattern on a channel, by default it plays sixteenth notes
+    pub fn new(channel: Channel, pattern: Pattern<N>) -> Self {
+        StepSequencer {
+            channel,
+            pattern,
+            division: 6,
+            position: 0,
+            ticks: 0,
+            gate: 0,
+            playing: None,
+        }
+    }
+
+    pub fn pattern(&self) -> &Pattern<N> {
+        &self.pattern
+    }
+
+    /// Edit the pattern, changes are played from the next step
+    pub fn pattern_mut(&mut self) -> &mut Pattern<N> {
+        &mut self.pattern
+    }
+
+    /// Replace the pattern, playback continues at the same position
+    pub fn set_pattern(&mut self, pattern: Pattern<N>) {
+        self.pattern = pattern;
+    }
+
+    pub fn division(&self) -> u8 {
+        self.division
+    }
+
+    /// Set the number of clock ticks per step, at 24 ticks per quarter note 6 plays sixteenth
+    /// notes
+    pub fn set_division(&mut self, division: u8) {
+        self.division = division.max(1);
+    }
+
+    /// The step that is played next
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// The note that is currently sounding
+    pub fn playing(&self) -> Option<Note> {
+        self.playing
+    }
+
+    /// Advance the sequencer by one clock tick, emits the note messages due on this tick
+    pub fn tick(&mut self, mut emit: impl FnMut(MidiMessage)) {
+        if self.ticks == 0 {
+            self.start_step(&mut emit);
+        } else if self.ticks >= self.gate {
+            self.stop_note(&mut emit);
+        }
+
+        self.ticks = (self.ticks + 1) % self.division;
+    }
+
+    /// Start the pattern from the first step on the next tick
+    pub fn restart(&mut self) {
+        self.position = 0;
+        self.ticks = 0;
+    }
+
+    /// Continue playing from a tick within a step on the next tick, a step is only played when
+    /// continuing from its first tick
+    pub fn locate(&mut self, step: usize, tick: u8) {
+        self.position = step;
+        self.ticks = tick % self.division;
+    }
+
+    /// Stop the sounding note and start the pattern from the first step
+    pub fn stop(&mut self, mut emit: impl FnMut(MidiMessage)) {
+        self.stop_note(&mut emit);
+        self.restart();
+    }
+
+    /// Handle a midi message. Timing clock messages advance the sequencer, start and stop
+    /// messages restart it. Other messages are ignored.
+    pub fn handle(&mut self, message: &MidiMessage, emit: impl FnMut(MidiMessage)) {
+        match message {
+            MidiMessage::TimingClock => self.tick(emit),
+            MidiMessage::Start | MidiMessage::Stop => self.stop(emit),
+            _ => {}
+        }
+    }
+
+    fn start_step(&mut self, emit: &mut impl FnMut(MidiMessage)) {
+        let len = self.pattern.len();
+        let step = match self.position < len {
+            true => self.pattern.step(self.position).unwrap_or_default(),
+            false => Step::Rest,
+        };
+        self.position = match self.position + 1 {
+            next if next < len => next,
+            _ => 0,
+        };
+
+        match step {
+            Step::Note {
+                note,
+                velocity,
+                gate,
+            } => {
+                self.stop_note(emit);
+                self.gate = gate.max(1);
+                self.playing = Some(note);
+                emit(MidiMessage::NoteOn(self.channel, note, velocity));
+            }
+            Step::Tie => self.gate = u8::MAX,
+            Step::Rest => self.stop_note(emit),
+        }
+    }
+
+    pub(crate) fn stop_note(&mut self, emit: &mut impl FnMut(MidiMessage)) {
+        if let Some(note) = self.playing.take() {
+            emit(MidiMessage::NoteOff(self.channel, note, Value7::new(0)));
+        }
+    }
+}
+
+/// Plays queued patterns one after the other on a step sequencer, holding up to `Q` patterns
+///
+/// The next pattern starts on the first bar boundary after it was queued. A bar is 16 steps by
+/// default, the end of a pattern is always a bar boundary. The last pattern keeps looping when the
+/// queue runs out.
+#[derive(Debug, Clone)]
+pub struct PatternChain<const N: usize, const Q: usize> {
+    sequencer: StepSequencer<N>,
+    bar_length: usize,
+    queue: [Pattern<N>; Q],
+    start: usize,
+    len: usize,
+}
+
+impl<const N: usize, const Q: usize> PatternChain<N, Q> {
+    pub fn new(sequencer: StepSequencer<N>) -> Self {
+        PatternChain {
+            sequencer,
+            bar_length: 16,
+            queue: [Pattern::empty(); Q],
+            start: 0,
+            len: 0,
+        }
+    }
+
+    pub fn sequencer(&self) -> &StepSequencer<N> {
+        &self.sequencer
+    }
+
+    pub fn sequencer_mut(&mut self) -> &mut StepSequencer<N> {
+        &mut self.sequencer
+    }
+
+    /// Set the number of steps in a bar
+    pub fn set_bar_length(&mut self, steps: usize) {
+        self.bar_length = steps.max(1);
+    }
+
+    /// Add a pattern to the end of the queue, returns the pattern back when the queue is full
+    pub fn queue(&mut self, pattern: Pattern<N>) -> Result<(), Pattern<N>> {
+        if self.len == Q {
+            return Err(pattern);
+        }
+
+        match self.queue.get_mut((self.start + self.len) % Q) {
+            Some(slot) => {
+                *slot = pattern;
+                self.len += 1;
+                Ok(())
+            }
+            None => Err(pattern),
+        }
+    }
+
+    /// The number of patterns waiting to be played
+    pub fn queued(&self) -> usize {
+        self.len
+    }
+
+    /// Remove all patterns from the queue, the playing pattern continues
+    pub fn clear_queue(&mut self) {
+        self.len = 0;
+    }
+
+    /// Advance by one clock tick, switching to the next pattern on a bar boundary
+    pub fn tick(&mut self, emit: impl FnMut(MidiMessage)) {
+        let sequencer = &self.sequencer;
+        if self.len > 0 && sequencer.ticks == 0 && sequencer.position % self.bar_length == 0 {
+            if let Some(pattern) = self.queue.get(self.start) {
+                self.sequencer.set_pattern(*pattern);
+                self.sequencer.position = 0;
+            }
+            self.start = (self.start + 1) % Q;
+            self.len -= 1;
+        }
+
+        self.sequencer.tick(emit);
+    }
+
+    /// Handle a midi message like the step sequencer does
+    pub fn handle(&mut self, message: &MidiMessage, emit: impl FnMut(MidiMessage)) {
+        match message {
+            MidiMessage::TimingClock => self.tick(emit),
+            message => self.sequencer.handle(message, emit),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::vec::Vec;
+
+    static PATTERN: Pattern<4> = Pattern::new([
+        Step::note(Note::C3, Value7::new(100), 1),
+        Step::note(Note::E3, Value7::new(80), 6),
+        Step::Tie,
+        Step::Rest,
+    ]);
+
+    fn run<const N: usize>(
+        sequencer: &mut StepSequencer<N>,
+        ticks: usize,
+    ) -> Vec<(usize, MidiMessage)> {
+        let mut messages = Vec::new();
+        for tick in 0..ticks {
+            sequencer.tick(|message| messages.push((tick, message)));
+        }
+        messages
+    }
+
+    #[test]
+    fn should_play_notes_ties_and_rests() {
+        let mut sequencer = StepSequencer::new(Channel::C1, PATTERN);
+        sequencer.set_division(2);
+
+        assert_eq!(
+            run(&mut sequencer, 10),
+            [
+                (0, MidiMessage::NoteOn(Channel::C1, Note::C3, 100.into())),
+                (1, MidiMessage::NoteOff(Channel::C1, Note::C3, 0.into())),
+                (2, MidiMessage::NoteOn(Channel::C1, Note::E3, 80.into())),
+                (6, MidiMessage::NoteOff(Channel::C1, Note::E3, 0.into())),
+                (8, MidiMessage::NoteOn(Channel::C1, Note::C3, 100.into())),
+                (9, MidiMessage::NoteOff(Channel::C1, Note::C3, 0.into())),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_loop_over_pattern_length() {
+        let mut sequencer = StepSequencer::new(Channel::C1, PATTERN);
+        sequencer.set_division(1);
+        sequencer.pattern_mut().set_len(1);
+
+        let notes_on = run(&mut sequencer, 3)
+            .iter()
+            .filter(|(_, message)| matches!(message, MidiMessage::NoteOn(..)))
+            .count();
+        assert_eq!(notes_on, 3);
+    }
+
+    #[test]
+    fn should_restart_on_stop() {
+        let mut sequencer = StepSequencer::new(Channel::C1, PATTERN);
+        run(&mut sequencer, 8);
+        assert_eq!(sequencer.position(), 2);
+
+        let mut messages = Vec::new();
+        sequencer.handle(&MidiMessage::Stop, |message| messages.push(message));
+        assert_eq!(
+            messages,
+            [MidiMessage::NoteOff(Channel::C1, Note::E3, 0.into())]
+        );
+        assert_eq!(sequencer.position(), 0);
+    }
+
+    #[test]
+    fn should_switch_queued_patterns_on_bar_boundary() {
+        let note = |note: Note| Step::note(note, Value7::new(100), 1);
+        let first = Pattern::new([note(Note::C3), Step::Rest, Step::Rest, Step::Rest]);
+        let second = Pattern::new([note(Note::D3), note(Note::E3), Step::Rest, Step::Rest]);
+
+        let mut sequencer = StepSequencer::new(Channel::C1, first);
+        sequencer.set_division(1);
+        let mut chain = PatternChain::<4, 2>::new(sequencer);
+        chain.set_bar_length(2);
+
+        let mut notes = Vec::new();
+        for tick in 0..8 {
+            if tick == 1 {
+                chain.queue(second).unwrap();
+                chain.queue(first).unwrap();
+                assert_eq!(chain.queue(first), Err(first));
+            }
+            chain.tick(|message| {
+                if let MidiMessage::NoteOn(_, note, _) = message {
+                    notes.push((tick, note))
+                }
+            });
+        }
+
+        assert_eq!(
+            notes,
+            [(0, Note::C3), (2, Note::D3), (3, Note::E3), (4, Note::C3)]
+        );
+        assert_eq!(chain.queued(), 0);
+    }
+}
@@ -0,0 +1,121 @@
+//! Smoothing of received control changes
+
+use crate::midi_types::{Channel, Control, MidiMessage, Value7};
+
+const MAX: u32 = 0x3fff;
+/// Fractional bits kept so slow slews still move every update
+const FRACTION: u32 = 8;
+
+/// Slew limits the value of a control so it moves smoothly between received control changes
+///
+/// The value is a 14 bit value so it can move in steps smaller than one control change value. The
+/// slew time is the time it takes to move over the full range, in the unit used to advance the
+/// smoother.
+#[derive(Debug, Clone)]
+pub struct Smoother {
+    channel: Channel,
+    control: Control,
+    time: u32,
+    value: u32,
+    target: u32,
+}
+
+impl Smoother {
+    pub fn new(channel: Channel, control: Control, time: u32) -> Self {
+        Smoother {
+            channel,
+            control,
+            time,
+            value: 0,
+            target: 0,
+        }
+    }
+
+    pub fn set_time(&mut self, time: u32) {
+        self.time = time;
+    }
+
+    /// Move towards a new control change value
+    pub fn set_target(&mut self, value: Value7) {
+        self.target = (u8::from(value) as u32 * MAX / 127) << FRACTION;
+    }
+
+    /// Jump to a control change value without smoothing
+    pub fn set_value(&mut self, value: Value7) {
+        self.set_target(value);
+        self.value = self.target;
+    }
+
+    /// The smoothed value from 0 to 0x3fff
+    pub fn value(&self) -> u16 {
+        (self.value >> FRACTION) as u16
+    }
+
+    pub fn is_settled(&self) -> bool {
+        self.value == self.target
+    }
+
+    /// Move the value towards the last received control change
+    pub fn advance(&mut self, elapsed: u32) {
+        let step = if self.time == 0 {
+            u32::MAX
+        } else {
+            let step = (MAX << FRACTION) as u64 * elapsed as u64 / self.time as u64;
+            step.min(u32::MAX as u64) as u32
+        };
+
+        self.value = if self.value < self.target {
+            self.value.saturating_add(step).min(self.target)
+        } else {
+            self.value.saturating_sub(step).max(self.target)
+        };
+    }
+
+    /// Handle a received message, returns true when it changed the target
+    pub fn handle(&mut self, message: &MidiMessage) -> bool {
+        match *message {
+            MidiMessage::ControlChange(channel, control, value)
+                if channel == self.channel && control == self.control =>
+            {
+                self.set_target(value);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CUTOFF: Control = Control::new(74);
+
+    #[test]
+    fn should_slew_to_received_values() {
+        let mut smoother = Smoother::new(Channel::C1, CUTOFF, 100);
+
+        assert!(smoother.handle(&MidiMessage::ControlChange(Channel::C1, CUTOFF, 127.into())));
+        smoother.advance(25);
+        assert_eq!(smoother.value(), 0x3fff / 4);
+        smoother.advance(50);
+        assert_eq!(smoother.value(), 0x3fff * 3 / 4);
+        smoother.advance(50);
+        assert_eq!(smoother.value(), 0x3fff);
+        assert!(smoother.is_settled());
+
+        smoother.set_target(0.into());
+        smoother.advance(1);
+        assert!(smoother.value() < 0x3fff);
+    }
+
+    #[test]
+    fn should_ignore_other_controls() {
+        let mut smoother = Smoother::new(Channel::C1, CUTOFF, 0);
+        assert!(!smoother.handle(&MidiMessage::ControlChange(Channel::C2, CUTOFF, 127.into())));
+
+        smoother.set_target(64.into());
+        smoother.advance(1);
+        assert_eq!(smoother.value(), 8256);
+    }
+}
@@ -0,0 +1,269 @@
+//! Song mode arranging step sequencer patterns
+//!
+//! A song is a list of entries that each play a pattern a number of times. Patterns and songs are
+//! borrowed so they can be stored in flash. The song position is kept in midi beats, sixteenth
+//! notes of six clock ticks, so it can be sent to and received from other devices with song
+//! position pointer messages.
+
+use crate::midi_types::{MidiMessage, Value14};
+use crate::sequencer::{Pattern, StepSequencer};
+
+/// Clock ticks in a midi beat, the unit of song position pointers
+const TICKS_PER_BEAT: u32 = 6;
+
+/// An entry of a song, playing a pattern `repeats` times
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SongEntry {
+    /// The index of the pattern
+    pub pattern: usize,
+    pub repeats: u8,
+    /// A marker for the section of the song this entry is part of, like verse or chorus
+    pub section: u8,
+}
+
+impl SongEntry {
+    pub const fn new(pattern: usize, repeats: u8, section: u8) -> Self {
+        SongEntry {
+            pattern,
+            repeats,
+            section,
+        }
+    }
+}
+
+/// Plays a song on a step sequencer
+#[derive(Debug)]
+pub struct SongPlayer<'a, const N: usize> {
+    sequencer: StepSequencer<N>,
+    patterns: &'a [Pattern<N>],
+    song: &'a [SongEntry],
+    looping: bool,
+    entry: usize,
+    /// Clock ticks since the start of the entry
+    entry_ticks: u32,
+    /// Clock ticks since the start of the song
+    ticks: u32,
+}
+
+impl<'a, const N: usize> SongPlayer<'a, N> {
+    pub fn new(
+        sequencer: StepSequencer<N>,
+        patterns: &'a [Pattern<N>],
+        song: &'a [SongEntry],
+    ) -> Self {
+        let mut player = SongPlayer {
+            sequencer,
+            patterns,
+            song,
+            looping: false,
+            entry: 0,
+            entry_ticks: 0,
+            ticks: 0,
+        };
+        player.start_entry(0);
+        player
+    }
+
+    pub fn sequencer(&self) -> &StepSequencer<N> {
+        &self.sequencer
+    }
+
+    /// Start the song again when it ends
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping;
+    }
+
+    /// The index of the playing entry, `None` when the song ended
+    pub fn entry(&self) -> Option<usize> {
+        (self.entry < self.song.len()).then_some(self.entry)
+    }
+
+    /// The section of the playing entry
+    pub fn section(&self) -> Option<u8> {
+        self.song.get(self.entry).map(|entry| entry.section)
+    }
+
+    /// The song position in midi beats since the start of the song
+    pub fn song_position(&self) -> u16 {
+        (self.ticks / TICKS_PER_BEAT).min(0x3FFF) as u16
+    }
+
+    /// A song position pointer message for the song position, for devices following the song
+    pub fn song_position_message(&self) -> MidiMessage {
+        MidiMessage::SongPositionPointer(Value14::from(self.song_position()))
+    }
+
+    /// Continue the song from a position in midi beats on the next tick, a position after the
+    /// end of the song ends it
+    pub fn locate(&mut self, position: u16, mut emit: impl FnMut(MidiMessage)) {
+        self.sequencer.stop_note(&mut emit);
+
+        let mut remaining = position as u32 * TICKS_PER_BEAT;
+        self.ticks = remaining;
+        for index in 0..self.song.len() {
+            let length = self.entry_length(index);
+            if remaining < length {
+                self.start_entry(index);
+                self.entry_ticks = remaining;
+
+                let division = self.sequencer.division() as u32;
+                let pattern_ticks = self.pattern_length(index);
+                let within = remaining % pattern_ticks.max(1);
+                self.sequencer
+                    .locate((within / division) as usize, (within % division) as u8);
+                return;
+            }
+            remaining -= length;
+        }
+
+        self.entry = self.song.len();
+    }
+
+    /// Jump to the first entry of a section, returns false when the song has no such section
+    pub fn jump_to_section(&mut self, section: u8, emit: impl FnMut(MidiMessage)) -> bool {
+        let index = match self.song.iter().position(|entry| entry.section == section) {
+            Some(index) => index,
+            None => return false,
+        };
+
+        let beats = (0..index)
+            .map(|index| self.entry_length(index))
+            .sum::<u32>()
+            / TICKS_PER_BEAT;
+        self.locate(beats.min(0x3FFF) as u16, emit);
+        true
+    }
+
+    /// Advance the song by one clock tick, emits the note messages due on this tick
+    pub fn tick(&mut self, mut emit: impl FnMut(MidiMessage)) {
+        if self.entry_ticks >= self.entry_length(self.entry) {
+            let next = self.entry + 1;
+            if next < self.song.len() {
+                self.start_entry(next);
+            } else if self.looping {
+                self.ticks = 0;
+                self.start_entry(0);
+            } else {
+                self.entry = self.song.len();
+            }
+        }
+
+        if self.entry >= self.song.len() {
+            return self.sequencer.stop_note(&mut emit);
+        }
+
+        self.sequencer.tick(emit);
+        self.entry_ticks += 1;
+        self.ticks = self.ticks.wrapping_add(1);
+    }
+
+    /// Handle a midi message. Timing clock messages advance the song, start messages start it
+    /// from the beginning, stop messages end the playing note and song position pointers move
+    /// the song. Other messages are ignored.
+    pub fn handle(&mut self, message: &MidiMessage, mut emit: impl FnMut(MidiMessage)) {
+        match *message {
+            MidiMessage::TimingClock => self.tick(emit),
+            MidiMessage::Start => self.locate(0, emit),
+            MidiMessage::Stop => self.sequencer.stop_note(&mut emit),
+            MidiMessage::SongPositionPointer(position) => self.locate(position.into(), emit),
+            _ => {}
+        }
+    }
+
+    fn start_entry(&mut self, index: usize) {
+        self.entry = index;
+        self.entry_ticks = 0;
+        if let Some(pattern) = self
+            .song
+            .get(index)
+            .and_then(|entry| self.patterns.get(entry.pattern))
+        {
+            self.sequencer.set_pattern(*pattern);
+        }
+        self.sequencer.restart();
+    }
+
+    /// The length of one repeat of the pattern of an entry in clock ticks
+    fn pattern_length(&self, index: usize) -> u32 {
+        let steps = self
+            .song
+            .get(index)
+            .and_then(|entry| self.patterns.get(entry.pattern))
+            .map_or(0, |pattern| pattern.len());
+        steps as u32 * self.sequencer.division() as u32
+    }
+
+    /// The length of an entry in clock ticks
+    fn entry_length(&self, index: usize) -> u32 {
+        let repeats = self.song.get(index).map_or(0, |entry| entry.repeats);
+        self.pattern_length(index) * repeats as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use crate::midi_types::{Channel, Note, Value7};
+    use crate::sequencer::Step;
+    use std::vec::Vec;
+
+    const fn pattern(note: Note) -> Pattern<2> {
+        Pattern::new([Step::note(note, Value7::new(100), 1), Step::Rest])
+    }
+
+    static PATTERNS: [Pattern<2>; 2] = [pattern(Note::C3), pattern(Note::D3)];
+    static SONG: [SongEntry; 2] = [SongEntry::new(0, 2, 0), SongEntry::new(1, 1, 1)];
+
+    fn run(player: &mut SongPlayer<'_, 2>, ticks: u32) -> Vec<(u32, u8)> {
+        let mut notes = Vec::new();
+        for tick in 0..ticks {
+            player.tick(|message| {
+                if let MidiMessage::NoteOn(_, note, _) = message {
+                    notes.push((tick, note.into()))
+                }
+            });
+        }
+        notes
+    }
+
+    fn player() -> SongPlayer<'static, 2> {
+        let mut sequencer = StepSequencer::new(Channel::C1, PATTERNS[0]);
+        sequencer.set_division(6);
+        SongPlayer::new(sequencer, &PATTERNS, &SONG)
+    }
+
+    #[test]
+    fn should_play_song_entries_with_repeats() {
+        let mut player = player();
+
+        assert_eq!(run(&mut player, 40), [(0, 60), (12, 60), (24, 62)]);
+        assert_eq!(player.entry(), None);
+
+        player.set_looping(true);
+        player.handle(&MidiMessage::Start, |_| {});
+        assert_eq!(
+            run(&mut player, 40),
+            [(0, 60), (12, 60), (24, 62), (36, 60)]
+        );
+    }
+
+    #[test]
+    fn should_follow_song_position() {
+        let mut player = player();
+        run(&mut player, 18);
+        assert_eq!(player.song_position(), 3);
+        assert_eq!(
+            player.song_position_message(),
+            MidiMessage::SongPositionPointer(3u16.into())
+        );
+
+        player.handle(&MidiMessage::SongPositionPointer(4u16.into()), |_| {});
+        assert_eq!(player.section(), Some(1));
+        assert_eq!(run(&mut player, 2), [(0, 62)]);
+
+        assert!(player.jump_to_section(0, |_| {}));
+        assert_eq!(player.song_position(), 0);
+        assert!(!player.jump_to_section(5, |_| {}));
+    }
+}
@@ -0,0 +1,191 @@
+//! Traffic statistics for diagnostic screens
+//!
+//! Counts the messages flowing through a port by type and estimates the bandwidth in bytes per
+//! second. Message sizes are counted without running status, so the estimate is the upper bound
+//! of what was on the wire.
+
+use crate::midi_types::MidiMessage;
+use crate::parser::MidiEvent;
+
+/// Message counts by type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TrafficCounts {
+    /// Note-on and note-off messages
+    pub notes: u32,
+    pub control_changes: u32,
+    /// Timing clock messages
+    pub clock: u32,
+    /// Bytes of system exclusive messages, including the start and end bytes
+    pub sysex_bytes: u32,
+    /// All other messages
+    pub other: u32,
+    /// All bytes
+    pub bytes: u32,
+}
+
+/// Counts traffic by message type and measures bandwidth over windows of one second
+///
+/// Times are plain `u32` values in a unit set by `ticks_per_second`, for instance 1000 for
+/// milliseconds. Counters wrap around.
+#[derive(Debug, Clone)]
+pub struct TrafficStats {
+    counts: TrafficCounts,
+    ticks_per_second: u32,
+    window_start: Option<u32>,
+    window_bytes: u32,
+    bandwidth: u32,
+}
+
+impl TrafficStats {
+    pub const fn new(ticks_per_second: u32) -> Self {
+        TrafficStats {
+            counts: TrafficCounts {
+                notes: 0,
+                control_changes: 0,
+                clock: 0,
+                sysex_bytes: 0,
+                other: 0,
+                bytes: 0,
+            },
+            ticks_per_second,
+            window_start: None,
+            window_bytes: 0,
+            bandwidth: 0,
+        }
+    }
+
+    pub fn counts(&self) -> TrafficCounts {
+        self.counts
+    }
+
+    /// The bandwidth in bytes per second measured over the last complete window
+    pub fn bandwidth(&self) -> u32 {
+        self.bandwidth
+    }
+
+    /// Count a parsed event
+    pub fn count(&mut self, event: &MidiEvent<'_>, now: u32) {
+        match event {
+            MidiEvent::Message(message) => self.count_message(message, now),
+            MidiEvent::SysEx(bytes) | MidiEvent::SysExChunk(bytes) => {
+                let len = bytes.len() as u32;
+                self.counts.sysex_bytes = self.counts.sysex_bytes.wrapping_add(len);
+                self.add_bytes(len, now);
+            }
+            #[cfg(feature = "unknown-messages")]
+            MidiEvent::Unknown { data, .. } => {
+                self.counts.other = self.counts.other.wrapping_add(1);
+                self.add_bytes(1 + data.len() as u32, now);
+            }
+        }
+    }
+
+    /// Count a message, for instance one that is sent
+    pub fn count_message(&mut self, message: &MidiMessage, now: u32) {
+        let counter = match message {
+            MidiMessage::NoteOn(..) | MidiMessage::NoteOff(..) => &mut self.counts.notes,
+            MidiMessage::ControlChange(..) => &mut self.counts.control_changes,
+            MidiMessage::TimingClock => &mut self.counts.clock,
+            _ => &mut self.counts.other,
+        };
+        *counter = counter.wrapping_add(1);
+        self.add_bytes(message_len(message), now);
+    }
+
+    /// Update the bandwidth without counting traffic, so it drops to zero when a port goes quiet
+    pub fn update(&mut self, now: u32) {
+        let start = match self.window_start {
+            Some(start) => start,
+            None => return,
+        };
+
+        let elapsed = now.wrapping_sub(start);
+        if elapsed >= self.ticks_per_second.max(1) {
+            self.bandwidth =
+                (self.window_bytes as u64 * self.ticks_per_second as u64 / elapsed as u64) as u32;
+            self.window_start = Some(now);
+            self.window_bytes = 0;
+        }
+    }
+
+    /// Clear all counters and the bandwidth
+    pub fn reset(&mut self) {
+        *self = Self::new(self.ticks_per_second);
+    }
+
+    fn add_bytes(&mut self, len: u32, now: u32) {
+        if self.window_start.is_none() {
+            self.window_start = Some(now);
+        }
+        self.update(now);
+        self.counts.bytes = self.counts.bytes.wrapping_add(len);
+        self.window_bytes = self.window_bytes.saturating_add(len);
+    }
+}
+
+/// The number of bytes of a message without running status
+fn message_len(message: &MidiMessage) -> u32 {
+    match message {
+        MidiMessage::NoteOff(..)
+        | MidiMessage::NoteOn(..)
+        | MidiMessage::KeyPressure(..)
+        | MidiMessage::ControlChange(..)
+        | MidiMessage::PitchBendChange(..)
+        | MidiMessage::SongPositionPointer(..) => 3,
+        MidiMessage::ProgramChange(..)
+        | MidiMessage::ChannelPressure(..)
+        | MidiMessage::QuarterFrame(..)
+        | MidiMessage::SongSelect(..) => 2,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::midi_types::{Channel, Control};
+    use crate::parser::MidiParser;
+
+    #[test]
+    fn should_count_messages_by_type() {
+        let mut stats = TrafficStats::new(1000);
+        let mut parser = MidiParser::new();
+        for event in parser.parse_buffer(&[
+            0x90, 0x40, 0x7F, 0x40, 0x00, 0xF8, 0xF0, 0x01, 0xF7, 0xC0, 0x01,
+        ]) {
+            stats.count(&event, 0);
+        }
+        stats.count_message(
+            &MidiMessage::ControlChange(Channel::C1, Control::new(7), 100.into()),
+            0,
+        );
+
+        assert_eq!(
+            stats.counts(),
+            TrafficCounts {
+                notes: 2,
+                control_changes: 1,
+                clock: 1,
+                sysex_bytes: 3,
+                other: 1,
+                bytes: 15,
+            }
+        );
+
+        stats.reset();
+        assert_eq!(stats.counts(), TrafficCounts::default());
+    }
+
+    #[test]
+    fn should_estimate_bandwidth() {
+        let mut stats = TrafficStats::new(1000);
+        for time in 0..100 {
+            stats.count_message(&MidiMessage::TimingClock, time * 10);
+        }
+        stats.update(1000);
+        assert_eq!(stats.bandwidth(), 100);
+
+        stats.update(2000);
+        assert_eq!(stats.bandwidth(), 0);
+    }
+}
@@ -0,0 +1,179 @@
+//! Strumming notes of a chord one after the other
+
+use crate::midi_types::{Channel, MidiMessage, Note, Value7};
+use crate::process::Processor;
+use crate::scheduler::Scheduler;
+
+/// The order the notes of a chord are strummed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StrumDirection {
+    /// From the lowest to the highest note
+    Up,
+
+    /// From the highest to the lowest note
+    Down,
+}
+
+/// Spreads the notes of chords of up to `N` notes over time
+///
+/// Notes received before time is advanced form a chord. When time is advanced the first note of
+/// the chord is played right away and the others follow `interval` apart. Releasing a note before
+/// it was strummed cancels it. Times are in the unit used to advance the strum.
+#[derive(Debug, Clone)]
+pub struct Strum<const N: usize> {
+    interval: u32,
+    direction: StrumDirection,
+    chord: [(Channel, Note, Value7); N],
+    len: usize,
+    scheduler: Scheduler<N>,
+    now: u32,
+}
+
+impl<const N: usize> Strum<N> {
+    pub fn new(interval: u32, direction: StrumDirection) -> Self {
+        Strum {
+            interval,
+            direction,
+            chord: [(Channel::C1, Note::MIN, Value7::new(0)); N],
+            len: 0,
+            scheduler: Scheduler::new(),
+            now: 0,
+        }
+    }
+
+    pub fn set_interval(&mut self, interval: u32) {
+        self.interval = interval;
+    }
+
+    pub fn direction(&self) -> StrumDirection {
+        self.direction
+    }
+
+    pub fn set_direction(&mut self, direction: StrumDirection) {
+        self.direction = direction;
+    }
+
+    /// Strum the chord received so far, advance time and emit the notes that are due
+    pub fn advance(&mut self, elapsed: u32, mut emit: impl FnMut(MidiMessage)) {
+        self.strum(&mut emit);
+        self.now = self.now.wrapping_add(elapsed);
+        self.scheduler.poll(self.now, emit);
+    }
+
+    fn strum(&mut self, emit: &mut impl FnMut(MidiMessage)) {
+        let chord = self.chord.get_mut(..self.len).unwrap_or_default();
+        match self.direction {
+            StrumDirection::Up => chord.sort_unstable_by_key(|(_, note, _)| u8::from(*note)),
+            StrumDirection::Down => {
+                chord.sort_unstable_by_key(|(_, note, _)| core::cmp::Reverse(u8::from(*note)))
+            }
+        }
+
+        let mut time = self.now;
+        for (channel, note, velocity) in chord.iter() {
+            let message = MidiMessage::NoteOn(*channel, *note, *velocity);
+            if let Err(message) = self.scheduler.schedule(time, message) {
+                emit(message);
+            }
+            time = time.wrapping_add(self.interval);
+        }
+
+        self.len = 0;
+    }
+
+    /// Remove a note that was not strummed yet, returns true if the note was found
+    fn cancel(&mut self, channel: Channel, note: Note) -> bool {
+        let chord = self.chord.get(..self.len).unwrap_or_default();
+        if let Some(index) = chord
+            .iter()
+            .position(|(c, n, _)| *c == channel && *n == note)
+        {
+            self.chord.copy_within(index + 1..self.len, index);
+            self.len -= 1;
+            return true;
+        }
+
+        let is_pending = |message: &MidiMessage| matches!(*message, MidiMessage::NoteOn(c, n, _) if c == channel && n == note);
+        let pending = self
+            .scheduler
+            .iter()
+            .any(|(_, message)| is_pending(&message));
+        self.scheduler.cancel(is_pending);
+        pending
+    }
+}
+
+impl<const N: usize> Processor for Strum<N> {
+    fn process(&mut self, message: MidiMessage, emit: &mut impl FnMut(MidiMessage)) {
+        match message {
+            MidiMessage::NoteOn(channel, note, velocity) if u8::from(velocity) > 0 => {
+                match self.chord.get_mut(self.len) {
+                    Some(slot) => {
+                        *slot = (channel, note, velocity);
+                        self.len += 1;
+                    }
+                    None => emit(message),
+                }
+            }
+            MidiMessage::NoteOn(channel, note, _) | MidiMessage::NoteOff(channel, note, _) => {
+                if !self.cancel(channel, note) {
+                    emit(message);
+                }
+            }
+            message => emit(message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use crate::test_util::{off, on};
+    use std::vec::Vec;
+
+    fn play<const N: usize>(strum: &mut Strum<N>, chord: &[u8]) -> Vec<(u32, MidiMessage)> {
+        for note in chord {
+            strum.process(on(*note), &mut |_| {});
+        }
+
+        let mut messages = Vec::new();
+        strum.advance(0, |message| messages.push((0, message)));
+        for time in 1..10 {
+            strum.advance(1, |message| messages.push((time, message)));
+        }
+        messages
+    }
+
+    #[test]
+    fn should_strum_chords_in_direction() {
+        let mut strum = Strum::<4>::new(2, StrumDirection::Up);
+        assert_eq!(
+            play(&mut strum, &[67, 60, 64]),
+            [(0, on(60)), (2, on(64)), (4, on(67))]
+        );
+
+        strum.set_direction(StrumDirection::Down);
+        assert_eq!(
+            play(&mut strum, &[67, 60, 64]),
+            [(0, on(67)), (2, on(64)), (4, on(60))]
+        );
+    }
+
+    #[test]
+    fn should_cancel_notes_released_before_they_are_strummed() {
+        let mut strum = Strum::<4>::new(4, StrumDirection::Up);
+        for note in [60, 64, 67].iter() {
+            strum.process(on(*note), &mut |_| {});
+        }
+
+        let mut messages = Vec::new();
+        strum.advance(0, |message| messages.push(message));
+        for note in [60, 64, 67].iter() {
+            strum.process(off(*note), &mut |message| messages.push(message));
+        }
+        strum.advance(10, |message| messages.push(message));
+
+        assert_eq!(messages, [on(60), off(60)]);
+    }
+}
@@ -0,0 +1,71 @@
+//! System exclusive message builders and parsers
+//!
+//! Parsers take a complete system exclusive message including the start (`0xF0`) and end
+//! (`0xF7`) bytes, builders render the complete message into a caller-provided buffer.
+
+pub mod assembler;
+pub mod filter;
+pub mod identity;
+pub mod manufacturer;
+pub mod mmc;
+pub mod msc;
+pub mod pack;
+pub mod reset;
+pub mod roland;
+pub mod tuning;
+
+/// Start of a system exclusive message
+pub const SYSEX_START: u8 = 0xF0;
+
+/// End of a system exclusive message
+pub const SYSEX_END: u8 = 0xF7;
+
+/// Id for universal non-real-time messages
+pub const UNIVERSAL_NON_REAL_TIME: u8 = 0x7E;
+
+/// Id for universal real-time messages
+pub const UNIVERSAL_REAL_TIME: u8 = 0x7F;
+
+/// Device id addressing all devices
+pub const ALL_CALL: u8 = 0x7F;
+
+/// Errors building or parsing system exclusive messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SysExError {
+    /// The buffer is too small to render the message into
+    BufferTooSmall,
+
+    /// The bytes are not a well formed system exclusive message
+    Malformed,
+
+    /// The message is well formed but not of the expected type
+    Mismatch,
+
+    /// The checksum of the message does not match its contents
+    Checksum,
+}
+
+/// Strip the start and end bytes off a system exclusive message, checking that all bytes in
+/// between are data bytes
+pub(crate) fn payload(message: &[u8]) -> Result<&[u8], SysExError> {
+    match message {
+        [SYSEX_START, payload @ .., SYSEX_END] if payload.iter().all(|byte| *byte < 0x80) => {
+            Ok(payload)
+        }
+        _ => Err(SysExError::Malformed),
+    }
+}
+
+/// Render the parts of a message into a buffer, returns the number of bytes written
+pub(crate) fn render(buffer: &mut [u8], parts: &[&[u8]]) -> Result<usize, SysExError> {
+    let len = parts.iter().map(|part| part.len()).sum();
+    let mut target = buffer.get_mut(..len).ok_or(SysExError::BufferTooSmall)?;
+
+    for part in parts {
+        let (head, tail) = target.split_at_mut(part.len());
+        head.copy_from_slice(part);
+        target = tail;
+    }
+
+    Ok(len)
+}
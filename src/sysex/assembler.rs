@@ -0,0 +1,387 @@
+//! Assembling system exclusive messages from the chunks produced by the buffer parser
+//!
+//! A message that is split across buffers or interrupted by real-time messages arrives in
+//! chunks. The assembler collects them into a buffer of `N` bytes and decides what to do with
+//! messages that do not end with an end byte:
+//!
+//! - A message ended by another status byte has an implicit end according to the midi spec, its
+//!   data is complete but the end byte is missing. The recovery policy decides if it is passed on.
+//! - Data that does not follow a start byte is corrupt, for instance because the start of the
+//!   message was lost. It is never passed on.
+//! - A message longer than the buffer is handled by the overflow policy. A librarian can stream
+//!   long dumps in chunks while a small device only needs the start of a message.
+
+use super::{SYSEX_END, SYSEX_START};
+use crate::midi_types::MidiMessage;
+use crate::parser::MidiEvent;
+
+/// What to do with a message ended by another status byte instead of an end byte
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SysExRecovery {
+    /// Pass the message on as `SysExEvent::Terminated`
+    #[default]
+    EmitPartial,
+
+    /// Drop the message
+    Discard,
+
+    /// Drop the message and report it as `SysExEvent::Error`
+    Report,
+}
+
+/// What to do with a message that does not fit the buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SysExOverflow {
+    /// Drop the message and report it as `SysExEvent::Error`
+    #[default]
+    Discard,
+
+    /// Pass on the first `N` bytes of the message as `SysExEvent::Truncated`
+    Truncate,
+
+    /// Pass on the message as `SysExEvent::Chunk` every time the buffer is full, the rest of
+    /// the message follows as a complete or terminated message
+    Stream,
+}
+
+/// Why a message was dropped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SysExFault {
+    /// The message was ended by another status byte
+    Terminated,
+
+    /// Data was received without a start byte
+    Corrupt,
+
+    /// The message did not fit the buffer
+    Overflow,
+}
+
+/// Messages produced by the assembler
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SysExEvent<'a> {
+    /// A complete message including the start and end bytes, or the rest of a streamed message
+    Complete(&'a [u8]),
+
+    /// A message including the start byte that was ended by another status byte, or the rest of
+    /// a streamed message
+    Terminated(&'a [u8]),
+
+    /// The first bytes of a message that did not fit the buffer, including the start byte
+    Truncated(&'a [u8]),
+
+    /// A full buffer of a message that is streamed, the first chunk starts with the start byte
+    Chunk(&'a [u8]),
+
+    /// A message was dropped. Overflows are reported with the `Discard` overflow policy, other
+    /// faults only with the `Report` recovery policy.
+    Error(SysExFault),
+}
+
+/// Collects system exclusive messages of up to `N` bytes from parsed events
+#[derive(Debug, Clone)]
+pub struct SysExAssembler<const N: usize> {
+    buffer: [u8; N],
+    len: usize,
+    assembling: bool,
+    overflow: bool,
+    recovery: SysExRecovery,
+    overflow_policy: SysExOverflow,
+}
+
+impl<const N: usize> Default for SysExAssembler<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> SysExAssembler<N> {
+    pub const fn new() -> Self {
+        SysExAssembler {
+            buffer: [0; N],
+            len: 0,
+            assembling: false,
+            overflow: false,
+            recovery: SysExRecovery::EmitPartial,
+            overflow_policy: SysExOverflow::Discard,
+        }
+    }
+
+    pub fn recovery(&self) -> SysExRecovery {
+        self.recovery
+    }
+
+    pub fn set_recovery(&mut self, recovery: SysExRecovery) {
+        self.recovery = recovery;
+    }
+
+    pub fn overflow_policy(&self) -> SysExOverflow {
+        self.overflow_policy
+    }
+
+    pub fn set_overflow_policy(&mut self, policy: SysExOverflow) {
+        self.overflow_policy = policy;
+    }
+
+    /// True while part of a message was received
+    pub fn is_assembling(&self) -> bool {
+        self.assembling
+    }
+
+    /// Drop the message that is being assembled
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.assembling = false;
+        self.overflow = false;
+    }
+
+    /// Handle a parsed event, passing assembled messages to `handler`. Messages that are not
+    /// system exclusive end a message that is being assembled, except real-time messages.
+    pub fn push(&mut self, event: MidiEvent<'_>, mut handler: impl FnMut(SysExEvent<'_>)) {
+        match event {
+            MidiEvent::Message(message) => {
+                if !is_real_time(&message) {
+                    self.terminate(&mut handler);
+                }
+            }
+            MidiEvent::SysEx(message) => {
+                self.terminate(&mut handler);
+                handler(SysExEvent::Complete(message));
+            }
+            MidiEvent::SysExChunk(chunk) => {
+                if chunk.first() == Some(&SYSEX_START) {
+                    self.terminate(&mut handler);
+                    self.assembling = true;
+                } else if !self.assembling {
+                    return self.report(SysExFault::Corrupt, &mut handler);
+                }
+
+                self.append(chunk, &mut handler);
+                if chunk.last() == Some(&SYSEX_END) {
+                    self.complete(&mut handler);
+                }
+            }
+            #[cfg(feature = "unknown-messages")]
+            MidiEvent::Unknown { status, .. } => {
+                if !crate::parser::is_real_time(status) {
+                    self.terminate(&mut handler);
+                }
+            }
+        }
+    }
+
+    fn append(&mut self, chunk: &[u8], handler: &mut impl FnMut(SysExEvent<'_>)) {
+        invariant!(self.len <= N, "assembled message longer than the buffer");
+        for byte in chunk {
+            if self.len == N && N > 0 && self.overflow_policy == SysExOverflow::Stream {
+                handler(SysExEvent::Chunk(&self.buffer));
+                self.len = 0;
+            }
+
+            match self.buffer.get_mut(self.len) {
+                Some(slot) => {
+                    *slot = *byte;
+                    self.len += 1;
+                }
+                None => {
+                    self.overflow = true;
+                    return;
+                }
+            }
+        }
+    }
+
+    fn complete(&mut self, handler: &mut impl FnMut(SysExEvent<'_>)) {
+        if self.overflow {
+            self.overflowed(handler);
+        } else if let Some(message) = self.buffer.get(..self.len) {
+            handler(SysExEvent::Complete(message));
+        }
+        self.reset();
+    }
+
+    /// End the message that is being assembled without an end byte
+    fn terminate(&mut self, handler: &mut impl FnMut(SysExEvent<'_>)) {
+        if !self.assembling {
+            return;
+        }
+
+        if self.overflow {
+            self.overflowed(handler);
+        } else {
+            match self.recovery {
+                SysExRecovery::EmitPartial => {
+                    if let Some(message) = self.buffer.get(..self.len) {
+                        handler(SysExEvent::Terminated(message));
+                    }
+                }
+                SysExRecovery::Discard => {}
+                SysExRecovery::Report => handler(SysExEvent::Error(SysExFault::Terminated)),
+            }
+        }
+        self.reset();
+    }
+
+    fn overflowed(&self, handler: &mut impl FnMut(SysExEvent<'_>)) {
+        match self.overflow_policy {
+            SysExOverflow::Truncate => handler(SysExEvent::Truncated(&self.buffer)),
+            _ => handler(SysExEvent::Error(SysExFault::Overflow)),
+        }
+    }
+
+    fn report(&self, fault: SysExFault, handler: &mut impl FnMut(SysExEvent<'_>)) {
+        if self.recovery == SysExRecovery::Report {
+            handler(SysExEvent::Error(fault));
+        }
+    }
+}
+
+fn is_real_time(message: &MidiMessage) -> bool {
+    matches!(
+        message,
+        MidiMessage::TimingClock
+            | MidiMessage::Start
+            | MidiMessage::Continue
+            | MidiMessage::Stop
+            | MidiMessage::ActiveSensing
+            | MidiMessage::Reset
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use crate::parser::MidiParser;
+    use std::vec::Vec;
+
+    fn assemble<const N: usize>(
+        assembler: &mut SysExAssembler<N>,
+        buffers: &[&[u8]],
+    ) -> Vec<(u8, Vec<u8>)> {
+        let mut parser = MidiParser::new();
+        let mut output = Vec::new();
+        for buffer in buffers {
+            for event in parser.parse_buffer(buffer) {
+                assembler.push(event, |event| {
+                    output.push(match event {
+                        SysExEvent::Complete(message) => (0, message.to_vec()),
+                        SysExEvent::Terminated(message) => (1, message.to_vec()),
+                        SysExEvent::Error(fault) => (2, std::vec![fault as u8]),
+                        SysExEvent::Truncated(message) => (3, message.to_vec()),
+                        SysExEvent::Chunk(chunk) => (4, chunk.to_vec()),
+                    })
+                });
+            }
+        }
+        output
+    }
+
+    #[test]
+    fn should_assemble_chunks() {
+        let mut assembler = SysExAssembler::<8>::new();
+
+        assert_eq!(
+            assemble(
+                &mut assembler,
+                &[&[0xF0, 0x01, 0xF8, 0x02], &[0x03, 0xF7, 0xF0, 0x04, 0xF7]]
+            ),
+            [
+                (0, std::vec![0xF0, 0x01, 0x02, 0x03, 0xF7]),
+                (0, std::vec![0xF0, 0x04, 0xF7]),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_not_corrupt_sysex_with_real_time_messages() {
+        let mut assembler = SysExAssembler::<8>::new();
+        let mut parser = MidiParser::new();
+        let mut events = Vec::new();
+        let mut assembled = Vec::new();
+        let buffer = [0xF0, 0xF8, 0x01, 0xFA, 0xF8, 0x02, 0xFC, 0xF7];
+
+        for event in parser.parse_buffer(&buffer) {
+            if let MidiEvent::Message(message) = event {
+                events.push(message);
+            }
+            assembler.push(event, |event| {
+                if let SysExEvent::Complete(message) = event {
+                    assembled.extend_from_slice(message);
+                }
+            });
+        }
+
+        assert_eq!(
+            events,
+            [
+                MidiMessage::TimingClock,
+                MidiMessage::Start,
+                MidiMessage::TimingClock,
+                MidiMessage::Stop
+            ]
+        );
+        assert_eq!(assembled, [0xF0, 0x01, 0x02, 0xF7]);
+        assert!(!assembler.is_assembling());
+    }
+
+    #[test]
+    fn should_apply_recovery_policy_to_terminated_messages() {
+        let buffers: [&[u8]; 2] = [&[0xF0, 0x01], &[0x02, 0x90, 0x40, 0x7F]];
+
+        let mut assembler = SysExAssembler::<8>::new();
+        assert_eq!(
+            assemble(&mut assembler, &buffers),
+            [(1, std::vec![0xF0, 0x01, 0x02])]
+        );
+
+        assembler.set_recovery(SysExRecovery::Discard);
+        assert_eq!(assemble(&mut assembler, &buffers), []);
+
+        assembler.set_recovery(SysExRecovery::Report);
+        assert_eq!(
+            assemble(&mut assembler, &buffers),
+            [(2, std::vec![SysExFault::Terminated as u8])]
+        );
+    }
+
+    #[test]
+    fn should_report_corrupt_data() {
+        let mut assembler = SysExAssembler::<8>::new();
+        assembler.set_recovery(SysExRecovery::Report);
+
+        // The start of the message was lost
+        let mut events = Vec::new();
+        assembler.push(MidiEvent::SysExChunk(&[0x01, 0x02, 0xF7]), |event| {
+            events.push(event == SysExEvent::Error(SysExFault::Corrupt))
+        });
+        assert_eq!(events, [true]);
+        assert!(!assembler.is_assembling());
+    }
+
+    #[test]
+    fn should_apply_overflow_policy() {
+        let buffers: [&[u8]; 2] = [&[0xF0, 0x01, 0x02], &[0x03, 0x04, 0xF7]];
+
+        let mut assembler = SysExAssembler::<4>::new();
+        assert_eq!(
+            assemble(&mut assembler, &buffers),
+            [(2, std::vec![SysExFault::Overflow as u8])]
+        );
+
+        assembler.set_overflow_policy(SysExOverflow::Truncate);
+        assert_eq!(
+            assemble(&mut assembler, &buffers),
+            [(3, std::vec![0xF0, 0x01, 0x02, 0x03])]
+        );
+
+        assembler.set_overflow_policy(SysExOverflow::Stream);
+        assert_eq!(
+            assemble(&mut assembler, &buffers),
+            [
+                (4, std::vec![0xF0, 0x01, 0x02, 0x03]),
+                (0, std::vec![0x04, 0xF7])
+            ]
+        );
+    }
+}
@@ -0,0 +1,145 @@
+//! Filtering system exclusive messages by device id
+//!
+//! The filter decides whether a message is addressed to this device as soon as the device id has
+//! been received, so messages for other devices on the chain can be dropped before they take up
+//! buffer space.
+
+use super::{ALL_CALL, SYSEX_START, UNIVERSAL_NON_REAL_TIME, UNIVERSAL_REAL_TIME};
+
+/// How to treat manufacturer specific messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManufacturerPolicy {
+    /// Accept all manufacturer specific messages
+    Accept,
+
+    /// Reject all manufacturer specific messages
+    Reject,
+
+    /// Treat the byte following the manufacturer id as the device id, this is the convention most
+    /// manufacturers use
+    MatchDeviceId,
+}
+
+/// The outcome of filtering a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterDecision {
+    /// Not enough bytes were received to decide
+    Pending,
+
+    /// The message is addressed to this device
+    Accept,
+
+    /// The message is addressed to another device
+    Reject,
+}
+
+/// Accepts system exclusive messages addressed to a device id or to all devices
+#[derive(Debug, Clone)]
+pub struct DeviceIdFilter {
+    device_id: u8,
+    manufacturer: ManufacturerPolicy,
+    position: usize,
+    id_position: usize,
+    decision: FilterDecision,
+}
+
+impl DeviceIdFilter {
+    pub fn new(device_id: u8, manufacturer: ManufacturerPolicy) -> Self {
+        DeviceIdFilter {
+            device_id: device_id & 0x7F,
+            manufacturer,
+            position: 0,
+            id_position: 1,
+            decision: FilterDecision::Pending,
+        }
+    }
+
+    pub fn device_id(&self) -> u8 {
+        self.device_id
+    }
+
+    pub fn set_device_id(&mut self, device_id: u8) {
+        self.device_id = device_id & 0x7F;
+    }
+
+    /// Start filtering a new message, call this when the start of a system exclusive message is
+    /// received
+    pub fn start(&mut self) {
+        self.position = 0;
+        self.id_position = 1;
+        self.decision = FilterDecision::Pending;
+    }
+
+    /// Feed the next data byte of the message, the decision stays the same once it is made
+    pub fn feed(&mut self, byte: u8) -> FilterDecision {
+        if self.decision != FilterDecision::Pending {
+            return self.decision;
+        }
+
+        match (self.position, byte) {
+            (0, UNIVERSAL_NON_REAL_TIME) | (0, UNIVERSAL_REAL_TIME) => {}
+            (0, id) => match self.manufacturer {
+                ManufacturerPolicy::Accept => self.decision = FilterDecision::Accept,
+                ManufacturerPolicy::Reject => self.decision = FilterDecision::Reject,
+                // Three byte manufacturer ids start with zero
+                ManufacturerPolicy::MatchDeviceId => self.id_position = if id == 0 { 3 } else { 1 },
+            },
+            (position, device_id) if position == self.id_position => {
+                self.decision = if device_id == ALL_CALL || device_id == self.device_id {
+                    FilterDecision::Accept
+                } else {
+                    FilterDecision::Reject
+                }
+            }
+            _ => {}
+        }
+
+        self.position += 1;
+        self.decision
+    }
+
+    /// Check a complete message, messages that are too short to contain a device id are rejected
+    pub fn accepts(&mut self, message: &[u8]) -> bool {
+        self.start();
+
+        let data = match message {
+            [SYSEX_START, data @ ..] => data,
+            _ => return false,
+        };
+
+        data.iter()
+            .map(|byte| self.feed(*byte))
+            .any(|decision| decision == FilterDecision::Accept)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_accept_own_device_id_and_broadcast() {
+        let mut filter = DeviceIdFilter::new(0x10, ManufacturerPolicy::Reject);
+
+        assert!(filter.accepts(&[0xF0, 0x7E, 0x10, 0x06, 0x01, 0xF7]));
+        assert!(filter.accepts(&[0xF0, 0x7F, 0x7F, 0x06, 0x02, 0xF7]));
+        assert!(!filter.accepts(&[0xF0, 0x7E, 0x11, 0x06, 0x01, 0xF7]));
+        assert!(!filter.accepts(&[0xF0, 0x41, 0x10, 0x42, 0xF7]));
+    }
+
+    #[test]
+    fn should_decide_while_streaming() {
+        let mut filter = DeviceIdFilter::new(0x10, ManufacturerPolicy::MatchDeviceId);
+
+        filter.start();
+        assert_eq!(filter.feed(0x41), FilterDecision::Pending);
+        assert_eq!(filter.feed(0x11), FilterDecision::Reject);
+        assert_eq!(filter.feed(0x10), FilterDecision::Reject);
+
+        filter.start();
+        filter.feed(0x00);
+        filter.feed(0x20);
+        assert_eq!(filter.feed(0x29), FilterDecision::Pending);
+        assert_eq!(filter.feed(0x10), FilterDecision::Accept);
+    }
+}
@@ -0,0 +1,165 @@
+//! Universal non-real-time Identity Request and Identity Reply messages
+
+use super::manufacturer::ManufacturerId;
+use super::{payload, render, SysExError, SYSEX_END, SYSEX_START, UNIVERSAL_NON_REAL_TIME};
+use crate::value::U14;
+
+const GENERAL_INFORMATION: u8 = 0x06;
+const IDENTITY_REQUEST: u8 = 0x01;
+const IDENTITY_REPLY: u8 = 0x02;
+
+/// Asks devices to identify themselves
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdentityRequest {
+    /// The device id of the device that should reply, or `ALL_CALL` for all devices
+    pub device_id: u8,
+}
+
+impl IdentityRequest {
+    pub fn new(device_id: u8) -> Self {
+        IdentityRequest { device_id }
+    }
+
+    /// The complete rendered message
+    pub fn to_bytes(&self) -> [u8; 6] {
+        [
+            SYSEX_START,
+            UNIVERSAL_NON_REAL_TIME,
+            self.device_id & 0x7F,
+            GENERAL_INFORMATION,
+            IDENTITY_REQUEST,
+            SYSEX_END,
+        ]
+    }
+
+    pub fn parse(message: &[u8]) -> Result<Self, SysExError> {
+        match payload(message)? {
+            [UNIVERSAL_NON_REAL_TIME, device_id, GENERAL_INFORMATION, IDENTITY_REQUEST] => {
+                Ok(IdentityRequest {
+                    device_id: *device_id,
+                })
+            }
+            _ => Err(SysExError::Mismatch),
+        }
+    }
+}
+
+/// Identifies a device in reply to an identity request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdentityReply {
+    /// The device id of the replying device
+    pub device_id: u8,
+
+    pub manufacturer: ManufacturerId,
+
+    /// Device family code, 14 bits
+    pub family: u16,
+
+    /// Device family member (model) code, 14 bits
+    pub model: u16,
+
+    /// Software revision level, the format is manufacturer specific
+    pub version: [u8; 4],
+}
+
+impl IdentityReply {
+    /// Render the reply into a buffer, returns the number of bytes written
+    pub fn render(&self, buffer: &mut [u8]) -> Result<usize, SysExError> {
+        let mut version = self.version;
+        version.iter_mut().for_each(|byte| *byte &= 0x7F);
+
+        render(
+            buffer,
+            &[
+                &[
+                    SYSEX_START,
+                    UNIVERSAL_NON_REAL_TIME,
+                    self.device_id & 0x7F,
+                    GENERAL_INFORMATION,
+                    IDENTITY_REPLY,
+                ],
+                self.manufacturer.as_bytes(),
+                &U14::masked(self.family).to_bytes(),
+                &U14::masked(self.model).to_bytes(),
+                &version,
+                &[SYSEX_END],
+            ],
+        )
+    }
+
+    pub fn parse(message: &[u8]) -> Result<Self, SysExError> {
+        let (device_id, data) = match payload(message)? {
+            [UNIVERSAL_NON_REAL_TIME, device_id, GENERAL_INFORMATION, IDENTITY_REPLY, data @ ..] => {
+                (*device_id, data)
+            }
+            _ => return Err(SysExError::Mismatch),
+        };
+
+        let (manufacturer, data) = ManufacturerId::parse(data)?;
+
+        match data {
+            [family_lsb, family_msb, model_lsb, model_msb, v0, v1, v2, v3] => Ok(IdentityReply {
+                device_id,
+                manufacturer,
+                family: U14::from_bytes(*family_lsb, *family_msb).get(),
+                model: U14::from_bytes(*model_lsb, *model_msb).get(),
+                version: [*v0, *v1, *v2, *v3],
+            }),
+            _ => Err(SysExError::Malformed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REPLY: [u8; 17] = [
+        0xF0, 0x7E, 0x10, 0x06, 0x02, 0x00, 0x20, 0x29, 0x13, 0x01, 0x42, 0x00, 0x01, 0x02, 0x03,
+        0x04, 0xF7,
+    ];
+
+    #[test]
+    fn should_render_and_parse_identity_request() {
+        let request = IdentityRequest::new(0x7F);
+
+        assert_eq!(request.to_bytes(), [0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7]);
+        assert_eq!(IdentityRequest::parse(&request.to_bytes()), Ok(request));
+    }
+
+    #[test]
+    fn should_parse_identity_reply() {
+        assert_eq!(
+            IdentityReply::parse(&REPLY),
+            Ok(IdentityReply {
+                device_id: 0x10,
+                manufacturer: ManufacturerId::FOCUSRITE_NOVATION,
+                family: 0x0093,
+                model: 0x0042,
+                version: [1, 2, 3, 4],
+            })
+        );
+    }
+
+    #[test]
+    fn should_render_identity_reply() {
+        let reply = IdentityReply::parse(&REPLY).unwrap();
+        let mut buffer = [0u8; 32];
+
+        assert_eq!(reply.render(&mut buffer), Ok(17));
+        assert_eq!(buffer[..17], REPLY);
+        assert_eq!(
+            reply.render(&mut buffer[..16]),
+            Err(SysExError::BufferTooSmall)
+        );
+    }
+
+    #[test]
+    fn should_reject_other_messages() {
+        assert_eq!(IdentityRequest::parse(&REPLY), Err(SysExError::Mismatch));
+        assert_eq!(
+            IdentityReply::parse(&[0xF0, 0x7E, 0x10]),
+            Err(SysExError::Malformed)
+        );
+    }
+}
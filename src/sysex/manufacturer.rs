@@ -0,0 +1,105 @@
+//! System exclusive manufacturer ids
+
+use super::SysExError;
+
+/// A manufacturer id, either a single byte or three bytes starting with `0x00`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ManufacturerId([u8; 3]);
+
+impl ManufacturerId {
+    pub const SEQUENTIAL: Self = Self::new(0x01);
+    pub const MOOG: Self = Self::new(0x04);
+    pub const KURZWEIL: Self = Self::new(0x07);
+    pub const ENSONIQ: Self = Self::new(0x0F);
+    pub const OBERHEIM: Self = Self::new(0x10);
+    pub const EMU: Self = Self::new(0x18);
+    pub const KAWAI: Self = Self::new(0x40);
+    pub const ROLAND: Self = Self::new(0x41);
+    pub const KORG: Self = Self::new(0x42);
+    pub const YAMAHA: Self = Self::new(0x43);
+    pub const CASIO: Self = Self::new(0x44);
+    pub const AKAI: Self = Self::new(0x47);
+
+    /// Reserved for non-commercial and educational use
+    pub const NON_COMMERCIAL: Self = Self::new(0x7D);
+
+    pub const ALESIS: Self = Self::extended(0x00, 0x0E);
+    pub const FOCUSRITE_NOVATION: Self = Self::extended(0x20, 0x29);
+    pub const BEHRINGER: Self = Self::extended(0x20, 0x32);
+    pub const ELEKTRON: Self = Self::extended(0x20, 0x3C);
+    pub const ARTURIA: Self = Self::extended(0x20, 0x6B);
+    pub const NATIVE_INSTRUMENTS: Self = Self::extended(0x21, 0x09);
+
+    /// Create a single byte manufacturer id
+    ///
+    /// # Note
+    /// * The `id` is masked to 7 bits, a zero id is not a valid single byte id and results in the
+    ///   extended id `0x00 0x00 0x00`
+    pub const fn new(id: u8) -> Self {
+        ManufacturerId([id & 0x7F, 0, 0])
+    }
+
+    /// Create an extended three byte manufacturer id from the two bytes following `0x00`
+    pub const fn extended(first: u8, second: u8) -> Self {
+        ManufacturerId([0x00, first & 0x7F, second & 0x7F])
+    }
+
+    /// Check if this is a three byte manufacturer id
+    pub const fn is_extended(&self) -> bool {
+        self.0[0] == 0x00
+    }
+
+    /// The length of the id when sent
+    #[allow(clippy::len_without_is_empty)]
+    pub const fn len(&self) -> usize {
+        if self.is_extended() {
+            3
+        } else {
+            1
+        }
+    }
+
+    /// The bytes of the id as they are sent
+    pub fn as_bytes(&self) -> &[u8] {
+        self.0.split_at(self.len()).0
+    }
+
+    /// Parse a manufacturer id from the start of a message payload, returns the id and the rest
+    /// of the payload
+    pub fn parse(data: &[u8]) -> Result<(Self, &[u8]), SysExError> {
+        match data {
+            [0x00, first, second, rest @ ..] if *first < 0x80 && *second < 0x80 => {
+                Ok((Self::extended(*first, *second), rest))
+            }
+            [id @ 0x01..=0x7F, rest @ ..] => Ok((Self::new(*id), rest)),
+            _ => Err(SysExError::Malformed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_serialize_standard_and_extended_ids() {
+        assert_eq!(ManufacturerId::ROLAND.as_bytes(), &[0x41]);
+        assert_eq!(ManufacturerId::ALESIS.as_bytes(), &[0x00, 0x00, 0x0E]);
+    }
+
+    #[test]
+    fn should_parse_ids() {
+        assert_eq!(
+            ManufacturerId::parse(&[0x43, 0x10]),
+            Ok((ManufacturerId::YAMAHA, &[0x10][..]))
+        );
+        assert_eq!(
+            ManufacturerId::parse(&[0x00, 0x20, 0x29, 0x01]),
+            Ok((ManufacturerId::FOCUSRITE_NOVATION, &[0x01][..]))
+        );
+        assert_eq!(
+            ManufacturerId::parse(&[0x00, 0x20]),
+            Err(SysExError::Malformed)
+        );
+    }
+}
@@ -0,0 +1,234 @@
+//! Midi Machine Control (MMC) messages
+//!
+//! MMC commands are sent as universal real-time system exclusive messages to control the
+//! transport of recorders and sequencers.
+
+use super::{payload, render, SysExError, SYSEX_END, SYSEX_START, UNIVERSAL_REAL_TIME};
+
+const MMC_COMMAND: u8 = 0x06;
+const LOCATE: u8 = 0x44;
+const LOCATE_TARGET: u8 = 0x01;
+
+/// The longest rendered MMC message, a locate command
+pub const MAX_LEN: usize = 13;
+
+/// SMPTE frame rates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameRate {
+    /// 24 frames per second
+    Fps24,
+
+    /// 25 frames per second
+    Fps25,
+
+    /// 29.97 frames per second drop frame
+    Fps30Drop,
+
+    /// 30 frames per second
+    Fps30,
+}
+
+/// A SMPTE time code position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SmpteTime {
+    pub rate: FrameRate,
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+    pub frames: u8,
+    pub subframes: u8,
+}
+
+impl SmpteTime {
+    pub(crate) fn to_bytes(self) -> [u8; 5] {
+        [
+            (self.rate as u8) << 5 | (self.hours & 0x1F),
+            self.minutes & 0x3F,
+            self.seconds & 0x3F,
+            self.frames & 0x1F,
+            self.subframes & 0x7F,
+        ]
+    }
+
+    pub(crate) fn from_bytes(bytes: [u8; 5]) -> Self {
+        let rate = match (bytes[0] >> 5) & 0x03 {
+            0 => FrameRate::Fps24,
+            1 => FrameRate::Fps25,
+            2 => FrameRate::Fps30Drop,
+            _ => FrameRate::Fps30,
+        };
+
+        SmpteTime {
+            rate,
+            hours: bytes[0] & 0x1F,
+            minutes: bytes[1] & 0x3F,
+            seconds: bytes[2] & 0x3F,
+            frames: bytes[3] & 0x1F,
+            subframes: bytes[4],
+        }
+    }
+}
+
+/// MMC commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MmcCommand {
+    Stop,
+    Play,
+    DeferredPlay,
+    FastForward,
+    Rewind,
+    RecordStrobe,
+    RecordExit,
+    RecordPause,
+    Pause,
+    Eject,
+    Chase,
+    CommandErrorReset,
+    Reset,
+
+    /// Move to a time code position
+    Locate(SmpteTime),
+}
+
+impl MmcCommand {
+    fn code(&self) -> u8 {
+        match self {
+            MmcCommand::Stop => 0x01,
+            MmcCommand::Play => 0x02,
+            MmcCommand::DeferredPlay => 0x03,
+            MmcCommand::FastForward => 0x04,
+            MmcCommand::Rewind => 0x05,
+            MmcCommand::RecordStrobe => 0x06,
+            MmcCommand::RecordExit => 0x07,
+            MmcCommand::RecordPause => 0x08,
+            MmcCommand::Pause => 0x09,
+            MmcCommand::Eject => 0x0A,
+            MmcCommand::Chase => 0x0B,
+            MmcCommand::CommandErrorReset => 0x0C,
+            MmcCommand::Reset => 0x0D,
+            MmcCommand::Locate(_) => LOCATE,
+        }
+    }
+
+    fn from_code(code: u8) -> Option<Self> {
+        Some(match code {
+            0x01 => MmcCommand::Stop,
+            0x02 => MmcCommand::Play,
+            0x03 => MmcCommand::DeferredPlay,
+            0x04 => MmcCommand::FastForward,
+            0x05 => MmcCommand::Rewind,
+            0x06 => MmcCommand::RecordStrobe,
+            0x07 => MmcCommand::RecordExit,
+            0x08 => MmcCommand::RecordPause,
+            0x09 => MmcCommand::Pause,
+            0x0A => MmcCommand::Eject,
+            0x0B => MmcCommand::Chase,
+            0x0C => MmcCommand::CommandErrorReset,
+            0x0D => MmcCommand::Reset,
+            _ => return None,
+        })
+    }
+}
+
+/// An MMC command addressed to a device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MmcMessage {
+    /// The device id of the target device, or `ALL_CALL` for all devices
+    pub device_id: u8,
+    pub command: MmcCommand,
+}
+
+impl MmcMessage {
+    pub fn new(device_id: u8, command: MmcCommand) -> Self {
+        MmcMessage { device_id, command }
+    }
+
+    /// Render the message into a buffer, returns the number of bytes written. A buffer of
+    /// `MAX_LEN` bytes fits every command.
+    pub fn render(&self, buffer: &mut [u8]) -> Result<usize, SysExError> {
+        let header = [
+            SYSEX_START,
+            UNIVERSAL_REAL_TIME,
+            self.device_id & 0x7F,
+            MMC_COMMAND,
+            self.command.code(),
+        ];
+
+        match self.command {
+            MmcCommand::Locate(time) => render(
+                buffer,
+                &[
+                    &header,
+                    &[0x06, LOCATE_TARGET],
+                    &time.to_bytes(),
+                    &[SYSEX_END],
+                ],
+            ),
+            _ => render(buffer, &[&header, &[SYSEX_END]]),
+        }
+    }
+
+    pub fn parse(message: &[u8]) -> Result<Self, SysExError> {
+        let (device_id, command) = match payload(message)? {
+            [UNIVERSAL_REAL_TIME, device_id, MMC_COMMAND, LOCATE, 0x06, LOCATE_TARGET, hr, mn, sc, fr, ff] => {
+                (
+                    *device_id,
+                    MmcCommand::Locate(SmpteTime::from_bytes([*hr, *mn, *sc, *fr, *ff])),
+                )
+            }
+            [UNIVERSAL_REAL_TIME, device_id, MMC_COMMAND, code] => (
+                *device_id,
+                MmcCommand::from_code(*code).ok_or(SysExError::Mismatch)?,
+            ),
+            _ => return Err(SysExError::Mismatch),
+        };
+
+        Ok(MmcMessage { device_id, command })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_render_and_parse_simple_commands() {
+        let message = MmcMessage::new(0x7F, MmcCommand::Play);
+        let mut buffer = [0u8; MAX_LEN];
+
+        assert_eq!(message.render(&mut buffer), Ok(6));
+        assert_eq!(buffer[..6], [0xF0, 0x7F, 0x7F, 0x06, 0x02, 0xF7]);
+        assert_eq!(MmcMessage::parse(&buffer[..6]), Ok(message));
+    }
+
+    #[test]
+    fn should_render_and_parse_locate() {
+        let message = MmcMessage::new(
+            0x01,
+            MmcCommand::Locate(SmpteTime {
+                rate: FrameRate::Fps25,
+                hours: 1,
+                minutes: 2,
+                seconds: 3,
+                frames: 4,
+                subframes: 0,
+            }),
+        );
+        let mut buffer = [0u8; MAX_LEN];
+
+        assert_eq!(message.render(&mut buffer), Ok(13));
+        assert_eq!(
+            buffer,
+            [0xF0, 0x7F, 0x01, 0x06, 0x44, 0x06, 0x01, 0x21, 0x02, 0x03, 0x04, 0x00, 0xF7]
+        );
+        assert_eq!(MmcMessage::parse(&buffer), Ok(message));
+    }
+
+    #[test]
+    fn should_reject_unknown_commands() {
+        assert_eq!(
+            MmcMessage::parse(&[0xF0, 0x7F, 0x7F, 0x06, 0x70, 0xF7]),
+            Err(SysExError::Mismatch)
+        );
+    }
+}
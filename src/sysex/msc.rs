@@ -0,0 +1,404 @@
+//! Midi Show Control (MSC) messages
+//!
+//! MSC commands are sent as universal real-time system exclusive messages to control lighting,
+//! sound and other show equipment. Cues are addressed by cue number, cue list and cue path, each
+//! encoded as ASCII digits and decimal points and separated by `0x00` bytes.
+
+use super::mmc::SmpteTime;
+use super::{payload, render, SysExError, SYSEX_END, SYSEX_START, UNIVERSAL_REAL_TIME};
+use crate::value::U14;
+
+const MSC: u8 = 0x02;
+const CUE_SEPARATOR: u8 = 0x00;
+
+/// The type of equipment a message is addressed to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandFormat {
+    Lighting,
+    MovingLights,
+    Sound,
+    Machinery,
+    Video,
+    Projection,
+    ProcessControl,
+    Pyro,
+    AllTypes,
+
+    /// Any of the other command formats defined by the specification
+    Other(OtherFormat),
+}
+
+/// A command format without a variant of its own, created by converting from its code so it never
+/// holds the code of a named format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OtherFormat(u8);
+
+impl OtherFormat {
+    pub fn get(&self) -> u8 {
+        self.0
+    }
+}
+
+impl From<u8> for CommandFormat {
+    fn from(value: u8) -> Self {
+        match value & 0x7F {
+            0x01 => CommandFormat::Lighting,
+            0x02 => CommandFormat::MovingLights,
+            0x10 => CommandFormat::Sound,
+            0x20 => CommandFormat::Machinery,
+            0x30 => CommandFormat::Video,
+            0x40 => CommandFormat::Projection,
+            0x50 => CommandFormat::ProcessControl,
+            0x60 => CommandFormat::Pyro,
+            0x7F => CommandFormat::AllTypes,
+            other => CommandFormat::Other(OtherFormat(other)),
+        }
+    }
+}
+
+impl From<CommandFormat> for u8 {
+    fn from(format: CommandFormat) -> Self {
+        match format {
+            CommandFormat::Lighting => 0x01,
+            CommandFormat::MovingLights => 0x02,
+            CommandFormat::Sound => 0x10,
+            CommandFormat::Machinery => 0x20,
+            CommandFormat::Video => 0x30,
+            CommandFormat::Projection => 0x40,
+            CommandFormat::ProcessControl => 0x50,
+            CommandFormat::Pyro => 0x60,
+            CommandFormat::AllTypes => 0x7F,
+            CommandFormat::Other(other) => other.get(),
+        }
+    }
+}
+
+/// A cue address, every field consists of ASCII digits and decimal points like `"12.5"`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cue<'a> {
+    pub number: &'a str,
+    pub list: Option<&'a str>,
+
+    /// The cue path, this can only be set when the cue list is set as well
+    pub path: Option<&'a str>,
+}
+
+impl<'a> Cue<'a> {
+    pub fn new(number: &'a str) -> Self {
+        Cue {
+            number,
+            list: None,
+            path: None,
+        }
+    }
+
+    pub fn with_list(self, list: &'a str) -> Self {
+        Cue {
+            list: Some(list),
+            ..self
+        }
+    }
+
+    pub fn with_path(self, path: &'a str) -> Self {
+        Cue {
+            path: Some(path),
+            ..self
+        }
+    }
+
+    fn is_valid(&self) -> bool {
+        is_cue_field(self.number.as_bytes())
+            && self.list.map_or(true, |list| is_cue_field(list.as_bytes()))
+            && self.path.map_or(true, |path| is_cue_field(path.as_bytes()))
+            && !(self.list.is_none() && self.path.is_some())
+    }
+
+    fn parse(data: &'a [u8]) -> Result<Option<Self>, SysExError> {
+        if data.is_empty() {
+            return Ok(None);
+        }
+
+        let mut fields = data.split(|byte| *byte == CUE_SEPARATOR).map(cue_field);
+        let number = fields.next().ok_or(SysExError::Malformed)??;
+        let list = fields.next().transpose()?;
+        let path = fields.next().transpose()?;
+
+        match fields.next() {
+            Some(_) => Err(SysExError::Malformed),
+            None => Ok(Some(Cue { number, list, path })),
+        }
+    }
+}
+
+fn is_cue_field(field: &[u8]) -> bool {
+    !field.is_empty()
+        && field
+            .iter()
+            .all(|byte| byte.is_ascii_digit() || *byte == b'.')
+}
+
+fn cue_field(field: &[u8]) -> Result<&str, SysExError> {
+    if !is_cue_field(field) {
+        return Err(SysExError::Malformed);
+    }
+    core::str::from_utf8(field).map_err(|_| SysExError::Malformed)
+}
+
+/// MSC commands, commands without a cue apply to the current or next cue
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MscCommand<'a> {
+    Go(Option<Cue<'a>>),
+    Stop(Option<Cue<'a>>),
+    Resume(Option<Cue<'a>>),
+    TimedGo(SmpteTime, Option<Cue<'a>>),
+    Load(Cue<'a>),
+
+    /// Set a generic control to a value, both are 14 bits
+    Set {
+        control: u16,
+        value: u16,
+        time: Option<SmpteTime>,
+    },
+
+    /// Fire a macro
+    Fire(u8),
+    AllOff,
+    Restore,
+    Reset,
+    GoOff(Option<Cue<'a>>),
+}
+
+/// An MSC command addressed to a device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MscMessage<'a> {
+    /// The device id of the target device, or `ALL_CALL` for all devices
+    pub device_id: u8,
+    pub format: CommandFormat,
+    pub command: MscCommand<'a>,
+}
+
+impl<'a> MscMessage<'a> {
+    pub fn new(device_id: u8, format: CommandFormat, command: MscCommand<'a>) -> Self {
+        MscMessage {
+            device_id,
+            format,
+            command,
+        }
+    }
+
+    /// Render the message into a buffer, returns the number of bytes written
+    pub fn render(&self, buffer: &mut [u8]) -> Result<usize, SysExError> {
+        let (code, cue, time) = match self.command {
+            MscCommand::Go(cue) => (0x01, cue, None),
+            MscCommand::Stop(cue) => (0x02, cue, None),
+            MscCommand::Resume(cue) => (0x03, cue, None),
+            MscCommand::TimedGo(time, cue) => (0x04, cue, Some(time)),
+            MscCommand::Load(cue) => (0x05, Some(cue), None),
+            MscCommand::GoOff(cue) => (0x0B, cue, None),
+            _ => (0x00, None, None),
+        };
+
+        let header = [
+            SYSEX_START,
+            UNIVERSAL_REAL_TIME,
+            self.device_id & 0x7F,
+            MSC,
+            self.format.into(),
+        ];
+
+        match self.command {
+            MscCommand::Set {
+                control,
+                value,
+                time,
+            } => {
+                let [control_lsb, control_msb] = U14::masked(control).to_bytes();
+                let [value_lsb, value_msb] = U14::masked(value).to_bytes();
+                let data = [0x06, control_lsb, control_msb, value_lsb, value_msb];
+                let time = time.map(SmpteTime::to_bytes);
+
+                render(
+                    buffer,
+                    &[
+                        &header,
+                        &data,
+                        time.as_ref().map_or(&[], |time| &time[..]),
+                        &[SYSEX_END],
+                    ],
+                )
+            }
+            MscCommand::Fire(macro_number) => {
+                render(buffer, &[&header, &[0x07, macro_number & 0x7F, SYSEX_END]])
+            }
+            MscCommand::AllOff => render(buffer, &[&header, &[0x08, SYSEX_END]]),
+            MscCommand::Restore => render(buffer, &[&header, &[0x09, SYSEX_END]]),
+            MscCommand::Reset => render(buffer, &[&header, &[0x0A, SYSEX_END]]),
+            _ => {
+                if !cue.map_or(true, |cue| cue.is_valid()) {
+                    return Err(SysExError::Malformed);
+                }
+
+                let time = time.map(SmpteTime::to_bytes);
+                let field = |field: Option<&'a str>| field.map_or(&[][..], str::as_bytes);
+                let separator = |field: Option<&'a str>| match field {
+                    Some(_) => &[CUE_SEPARATOR][..],
+                    None => &[][..],
+                };
+                let list = cue.and_then(|cue| cue.list);
+                let path = cue.and_then(|cue| cue.path);
+
+                render(
+                    buffer,
+                    &[
+                        &header,
+                        &[code],
+                        time.as_ref().map_or(&[], |time| &time[..]),
+                        field(cue.map(|cue| cue.number)),
+                        separator(list),
+                        field(list),
+                        separator(path),
+                        field(path),
+                        &[SYSEX_END],
+                    ],
+                )
+            }
+        }
+    }
+
+    pub fn parse(message: &'a [u8]) -> Result<Self, SysExError> {
+        let (device_id, format, code, data) = match payload(message)? {
+            [UNIVERSAL_REAL_TIME, device_id, MSC, format, code, data @ ..] => {
+                (*device_id, CommandFormat::from(*format), *code, data)
+            }
+            _ => return Err(SysExError::Mismatch),
+        };
+
+        let command = match (code, data) {
+            (0x01, cue) => MscCommand::Go(Cue::parse(cue)?),
+            (0x02, cue) => MscCommand::Stop(Cue::parse(cue)?),
+            (0x03, cue) => MscCommand::Resume(Cue::parse(cue)?),
+            (0x04, [hr, mn, sc, fr, ff, cue @ ..]) => MscCommand::TimedGo(
+                SmpteTime::from_bytes([*hr, *mn, *sc, *fr, *ff]),
+                Cue::parse(cue)?,
+            ),
+            (0x05, cue) => MscCommand::Load(Cue::parse(cue)?.ok_or(SysExError::Malformed)?),
+            (0x06, [control_lsb, control_msb, value_lsb, value_msb, time @ ..]) => {
+                let time = match time {
+                    [] => None,
+                    [hr, mn, sc, fr, ff] => Some(SmpteTime::from_bytes([*hr, *mn, *sc, *fr, *ff])),
+                    _ => return Err(SysExError::Malformed),
+                };
+                MscCommand::Set {
+                    control: U14::from_bytes(*control_lsb, *control_msb).get(),
+                    value: U14::from_bytes(*value_lsb, *value_msb).get(),
+                    time,
+                }
+            }
+            (0x07, [macro_number]) => MscCommand::Fire(*macro_number),
+            (0x08, []) => MscCommand::AllOff,
+            (0x09, []) => MscCommand::Restore,
+            (0x0A, []) => MscCommand::Reset,
+            (0x0B, cue) => MscCommand::GoOff(Cue::parse(cue)?),
+            (0x04..=0x0A, _) => return Err(SysExError::Malformed),
+            _ => return Err(SysExError::Mismatch),
+        };
+
+        Ok(MscMessage {
+            device_id,
+            format,
+            command,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_render_go_with_cue_list() {
+        let message = MscMessage::new(
+            0x01,
+            CommandFormat::Lighting,
+            MscCommand::Go(Some(Cue::new("23.5").with_list("2"))),
+        );
+        let mut buffer = [0u8; 32];
+
+        assert_eq!(message.render(&mut buffer), Ok(13));
+        assert_eq!(
+            buffer[..13],
+            [0xF0, 0x7F, 0x01, 0x02, 0x01, 0x01, b'2', b'3', b'.', b'5', 0x00, b'2', 0xF7]
+        );
+        assert_eq!(MscMessage::parse(&buffer[..13]), Ok(message));
+    }
+
+    #[test]
+    fn should_parse_go_without_cue() {
+        assert_eq!(
+            MscMessage::parse(&[0xF0, 0x7F, 0x7F, 0x02, 0x10, 0x01, 0xF7]),
+            Ok(MscMessage::new(
+                0x7F,
+                CommandFormat::Sound,
+                MscCommand::Go(None)
+            ))
+        );
+    }
+
+    #[test]
+    fn should_reject_invalid_cue_numbers() {
+        let mut buffer = [0u8; 32];
+
+        assert_eq!(
+            MscMessage::new(
+                0x01,
+                CommandFormat::Lighting,
+                MscCommand::Load(Cue::new("1a"))
+            )
+            .render(&mut buffer),
+            Err(SysExError::Malformed)
+        );
+        assert_eq!(
+            MscMessage::new(
+                0x01,
+                CommandFormat::Lighting,
+                MscCommand::Stop(Some(Cue::new("1").with_path("3")))
+            )
+            .render(&mut buffer),
+            Err(SysExError::Malformed)
+        );
+        assert_eq!(
+            MscMessage::parse(&[0xF0, 0x7F, 0x01, 0x02, 0x01, 0x01, b'1', 0x00, 0xF7]),
+            Err(SysExError::Malformed)
+        );
+    }
+
+    #[test]
+    fn should_convert_command_formats() {
+        for code in 0..=0xFFu8 {
+            let format = CommandFormat::from(code);
+            assert_eq!(u8::from(format), code & 0x7F);
+            assert_eq!(CommandFormat::from(u8::from(format)), format);
+        }
+        assert_eq!(CommandFormat::from(0x81), CommandFormat::Lighting);
+        assert!(
+            matches!(CommandFormat::from(0x03), CommandFormat::Other(other) if other.get() == 0x03)
+        );
+    }
+
+    #[test]
+    fn should_render_and_parse_set() {
+        let message = MscMessage::new(
+            0x01,
+            CommandFormat::Lighting,
+            MscCommand::Set {
+                control: 300,
+                value: 0x3FFF,
+                time: None,
+            },
+        );
+        let mut buffer = [0u8; 32];
+
+        let len = message.render(&mut buffer).unwrap();
+        assert_eq!(MscMessage::parse(&buffer[..len]), Ok(message));
+    }
+}
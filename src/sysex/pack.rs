@@ -0,0 +1,219 @@
+//! Streaming conversion between 8 bit binary data and 7 bit system exclusive data bytes
+//!
+//! Two schemes are supported. Packing groups 7 bytes and precedes them with a byte holding their
+//! most significant bits, bit 0 of this byte holds the msb of the first byte in the group.
+//! Nibbleizing splits every byte into two 4 bit data bytes.
+//!
+//! All converters are streaming, data can be fed in chunks of any size and the output is passed
+//! to an `emit` callback byte by byte.
+
+/// The number of bytes 8 bit data of length `len` packs into
+pub const fn packed_len(len: usize) -> usize {
+    len.saturating_add(len / 7 + (len % 7 != 0) as usize)
+}
+
+/// The number of bytes packed data of length `len` unpacks into
+pub const fn unpacked_len(len: usize) -> usize {
+    len - len / 8 - (len % 8 != 0) as usize
+}
+
+/// Packs 8 bit data into groups of a msb byte followed by up to 7 data bytes
+#[derive(Debug, Clone, Default)]
+pub struct Packer {
+    group: [u8; 7],
+    len: usize,
+}
+
+impl Packer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pack a chunk of data, complete groups are emitted right away
+    pub fn pack(&mut self, data: &[u8], mut emit: impl FnMut(u8)) {
+        for byte in data {
+            if let Some(slot) = self.group.get_mut(self.len) {
+                *slot = *byte;
+                self.len += 1;
+            }
+
+            if self.len == self.group.len() {
+                self.flush(&mut emit);
+            }
+        }
+    }
+
+    /// Emit the last incomplete group, call this after all data is packed
+    pub fn finish(&mut self, mut emit: impl FnMut(u8)) {
+        if self.len > 0 {
+            self.flush(&mut emit);
+        }
+    }
+
+    fn flush(&mut self, emit: &mut impl FnMut(u8)) {
+        let group = self.group.get(..self.len).unwrap_or_default();
+
+        emit(
+            group
+                .iter()
+                .enumerate()
+                .fold(0, |msbs, (index, byte)| msbs | (byte >> 7) << index),
+        );
+        group.iter().for_each(|byte| emit(byte & 0x7F));
+
+        self.len = 0;
+    }
+}
+
+/// Unpacks data packed by the `Packer`
+#[derive(Debug, Clone, Default)]
+pub struct Unpacker {
+    msbs: u8,
+    index: Option<u8>,
+}
+
+impl Unpacker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Unpack a chunk of packed data, data bytes are emitted as soon as they are complete
+    pub fn unpack(&mut self, data: &[u8], mut emit: impl FnMut(u8)) {
+        for byte in data {
+            match self.index {
+                None => {
+                    self.msbs = *byte;
+                    self.index = Some(0);
+                }
+                Some(index) => {
+                    emit((byte & 0x7F) | ((self.msbs >> index) & 0x01) << 7);
+                    self.index = if index < 6 { Some(index + 1) } else { None };
+                }
+            }
+        }
+    }
+
+    /// Start unpacking a new stream
+    pub fn reset(&mut self) {
+        self.index = None;
+    }
+}
+
+/// The order nibbles are sent in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NibbleOrder {
+    LowFirst,
+    HighFirst,
+}
+
+/// Split every byte into two 4 bit data bytes
+pub fn nibbleize(data: &[u8], order: NibbleOrder, mut emit: impl FnMut(u8)) {
+    for byte in data {
+        let (first, second) = match order {
+            NibbleOrder::LowFirst => (byte & 0x0F, byte >> 4),
+            NibbleOrder::HighFirst => (byte >> 4, byte & 0x0F),
+        };
+        emit(first);
+        emit(second);
+    }
+}
+
+/// Joins nibbleized data back into bytes, keeps the first nibble of a byte split across chunks
+#[derive(Debug, Clone)]
+pub struct Denibbleizer {
+    order: NibbleOrder,
+    first: Option<u8>,
+}
+
+impl Denibbleizer {
+    pub fn new(order: NibbleOrder) -> Self {
+        Denibbleizer { order, first: None }
+    }
+
+    /// Join a chunk of nibbles, bytes are emitted as soon as both nibbles are received
+    pub fn denibbleize(&mut self, data: &[u8], mut emit: impl FnMut(u8)) {
+        for nibble in data.iter().map(|byte| byte & 0x0F) {
+            match self.first.take() {
+                None => self.first = Some(nibble),
+                Some(first) => emit(match self.order {
+                    NibbleOrder::LowFirst => nibble << 4 | first,
+                    NibbleOrder::HighFirst => first << 4 | nibble,
+                }),
+            }
+        }
+    }
+
+    /// Start joining a new stream
+    pub fn reset(&mut self) {
+        self.first = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::vec::Vec;
+
+    const DATA: [u8; 10] = [0x80, 0x01, 0xFF, 0x7F, 0x00, 0x81, 0x42, 0xC3, 0x10, 0xFE];
+
+    #[test]
+    fn should_pack_groups_of_seven() {
+        let mut packed = Vec::new();
+        let mut packer = Packer::new();
+        packer.pack(&DATA, |byte| packed.push(byte));
+        packer.finish(|byte| packed.push(byte));
+
+        assert_eq!(packed.len(), packed_len(DATA.len()));
+        assert_eq!(
+            packed,
+            [0x25, 0x00, 0x01, 0x7F, 0x7F, 0x00, 0x01, 0x42, 0x05, 0x43, 0x10, 0x7E]
+        );
+    }
+
+    #[test]
+    fn should_compute_lengths_without_overflow() {
+        assert_eq!(packed_len(0), 0);
+        assert_eq!(packed_len(usize::MAX), usize::MAX);
+        assert_eq!(unpacked_len(0), 0);
+        assert_eq!(unpacked_len(usize::MAX), usize::MAX - usize::MAX / 8 - 1);
+    }
+
+    #[test]
+    fn should_pack_and_unpack_across_chunk_boundaries() {
+        let mut packed = Vec::new();
+        let mut packer = Packer::new();
+        for chunk in DATA.chunks(3) {
+            packer.pack(chunk, |byte| packed.push(byte));
+        }
+        packer.finish(|byte| packed.push(byte));
+
+        let mut unpacked = Vec::new();
+        let mut unpacker = Unpacker::new();
+        for chunk in packed.chunks(5) {
+            unpacker.unpack(chunk, |byte| unpacked.push(byte));
+        }
+
+        assert_eq!(unpacked, DATA);
+        assert_eq!(unpacked_len(packed.len()), DATA.len());
+    }
+
+    #[test]
+    fn should_nibbleize_and_join() {
+        let mut nibbles = Vec::new();
+        nibbleize(&DATA[..2], NibbleOrder::HighFirst, |byte| {
+            nibbles.push(byte)
+        });
+        assert_eq!(nibbles, [0x08, 0x00, 0x00, 0x01]);
+
+        let mut nibbles = Vec::new();
+        nibbleize(&DATA, NibbleOrder::LowFirst, |byte| nibbles.push(byte));
+
+        let mut joined = Vec::new();
+        let mut denibbleizer = Denibbleizer::new(NibbleOrder::LowFirst);
+        for chunk in nibbles.chunks(3) {
+            denibbleizer.denibbleize(chunk, |byte| joined.push(byte));
+        }
+        assert_eq!(joined, DATA);
+    }
+}
@@ -0,0 +1,131 @@
+//! General Midi, Roland GS and Yamaha XG reset messages
+//!
+//! Sound modules reinitialize their state when receiving one of these messages.
+
+use super::roland::ROLAND;
+use super::{payload, render, SysExError, SYSEX_END, SYSEX_START, UNIVERSAL_NON_REAL_TIME};
+
+/// General Midi System On for all devices
+pub const GM_SYSTEM_ON: [u8; 6] = [0xF0, 0x7E, 0x7F, 0x09, 0x01, 0xF7];
+
+/// General Midi System Off for all devices
+pub const GM_SYSTEM_OFF: [u8; 6] = [0xF0, 0x7E, 0x7F, 0x09, 0x02, 0xF7];
+
+/// General Midi 2 System On for all devices
+pub const GM2_SYSTEM_ON: [u8; 6] = [0xF0, 0x7E, 0x7F, 0x09, 0x03, 0xF7];
+
+/// Roland GS Reset for the default device id
+pub const GS_RESET: [u8; 11] = [
+    0xF0, 0x41, 0x10, 0x42, 0x12, 0x40, 0x00, 0x7F, 0x00, 0x41, 0xF7,
+];
+
+/// Yamaha XG System On for the default device number
+pub const XG_SYSTEM_ON: [u8; 9] = [0xF0, 0x43, 0x10, 0x4C, 0x00, 0x00, 0x7E, 0x00, 0xF7];
+
+const GENERAL_MIDI: u8 = 0x09;
+const YAMAHA: u8 = 0x43;
+
+/// The reset messages commonly understood by sound modules
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundModuleReset {
+    GmSystemOn,
+    GmSystemOff,
+    Gm2SystemOn,
+    GsReset,
+    XgSystemOn,
+}
+
+impl SoundModuleReset {
+    /// Render the reset message for a specific device into a buffer, returns the number of bytes
+    /// written. Roland devices use ids `0x10` to `0x1F`, Yamaha devices use `0x10` plus their
+    /// device number.
+    pub fn render(&self, device_id: u8, buffer: &mut [u8]) -> Result<usize, SysExError> {
+        let device_id = device_id & 0x7F;
+
+        match self {
+            SoundModuleReset::GmSystemOn => render_gm(device_id, 0x01, buffer),
+            SoundModuleReset::GmSystemOff => render_gm(device_id, 0x02, buffer),
+            SoundModuleReset::Gm2SystemOn => render_gm(device_id, 0x03, buffer),
+            SoundModuleReset::GsReset => {
+                let mut message = GS_RESET;
+                message[2] = device_id;
+                render(buffer, &[&message])
+            }
+            SoundModuleReset::XgSystemOn => {
+                let mut message = XG_SYSTEM_ON;
+                message[2] = 0x10 | (device_id & 0x0F);
+                render(buffer, &[&message])
+            }
+        }
+    }
+
+    /// Recognize a reset message addressed to any device
+    pub fn classify(message: &[u8]) -> Option<Self> {
+        match payload(message).ok()? {
+            [UNIVERSAL_NON_REAL_TIME, _, GENERAL_MIDI, 0x01] => Some(SoundModuleReset::GmSystemOn),
+            [UNIVERSAL_NON_REAL_TIME, _, GENERAL_MIDI, 0x02] => Some(SoundModuleReset::GmSystemOff),
+            [UNIVERSAL_NON_REAL_TIME, _, GENERAL_MIDI, 0x03] => Some(SoundModuleReset::Gm2SystemOn),
+            [ROLAND, _, 0x42, 0x12, 0x40, 0x00, 0x7F, 0x00, 0x41] => {
+                Some(SoundModuleReset::GsReset)
+            }
+            [YAMAHA, 0x10..=0x1F, 0x4C, 0x00, 0x00, 0x7E, 0x00] => {
+                Some(SoundModuleReset::XgSystemOn)
+            }
+            _ => None,
+        }
+    }
+}
+
+fn render_gm(device_id: u8, sub_id: u8, buffer: &mut [u8]) -> Result<usize, SysExError> {
+    render(
+        buffer,
+        &[&[
+            SYSEX_START,
+            UNIVERSAL_NON_REAL_TIME,
+            device_id,
+            GENERAL_MIDI,
+            sub_id,
+            SYSEX_END,
+        ]],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_classify_reset_constants() {
+        assert_eq!(
+            SoundModuleReset::classify(&GM_SYSTEM_ON),
+            Some(SoundModuleReset::GmSystemOn)
+        );
+        assert_eq!(
+            SoundModuleReset::classify(&GS_RESET),
+            Some(SoundModuleReset::GsReset)
+        );
+        assert_eq!(
+            SoundModuleReset::classify(&XG_SYSTEM_ON),
+            Some(SoundModuleReset::XgSystemOn)
+        );
+        assert_eq!(SoundModuleReset::classify(&[0xF0, 0x7E, 0xF7]), None);
+    }
+
+    #[test]
+    fn should_render_for_device() {
+        let mut buffer = [0u8; 16];
+
+        assert_eq!(
+            SoundModuleReset::GmSystemOn.render(0x7F, &mut buffer),
+            Ok(6)
+        );
+        assert_eq!(buffer[..6], GM_SYSTEM_ON);
+
+        assert_eq!(SoundModuleReset::XgSystemOn.render(3, &mut buffer), Ok(9));
+        assert_eq!(buffer[2], 0x13);
+        assert_eq!(
+            SoundModuleReset::classify(&buffer[..9]),
+            Some(SoundModuleReset::XgSystemOn)
+        );
+    }
+}
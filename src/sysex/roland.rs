@@ -0,0 +1,185 @@
+//! Roland style address based system exclusive messages
+//!
+//! Data set (DT1) messages write data to an address on a device, request data (RQ1) messages ask
+//! a device to send the data at an address. Both end with a checksum over the address and data
+//! bytes. The length of the model id and the address depend on the device.
+
+use super::{payload, render, SysExError, SYSEX_END, SYSEX_START};
+
+/// Roland manufacturer id
+pub const ROLAND: u8 = 0x41;
+
+const RQ1: u8 = 0x11;
+const DT1: u8 = 0x12;
+
+/// Roland address based commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RolandCommand {
+    /// Request data, the data bytes contain the size of the requested data
+    Rq1,
+
+    /// Data set
+    Dt1,
+}
+
+/// An address based Roland message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RolandMessage<'a> {
+    pub device_id: u8,
+    pub model_id: &'a [u8],
+    pub command: RolandCommand,
+    pub address: &'a [u8],
+
+    /// The data for a DT1 message or the requested size for an RQ1 message
+    pub data: &'a [u8],
+}
+
+/// Calculate the Roland checksum over address and data bytes, the checksum makes the sum of the
+/// checked bytes and the checksum itself a multiple of 128
+pub fn checksum<'a>(bytes: impl IntoIterator<Item = &'a u8>) -> u8 {
+    let sum = bytes
+        .into_iter()
+        .fold(0u8, |sum, byte| sum.wrapping_add(*byte & 0x7F));
+    0u8.wrapping_sub(sum) & 0x7F
+}
+
+impl<'a> RolandMessage<'a> {
+    /// Create a data set message
+    pub fn dt1(device_id: u8, model_id: &'a [u8], address: &'a [u8], data: &'a [u8]) -> Self {
+        RolandMessage {
+            device_id,
+            model_id,
+            command: RolandCommand::Dt1,
+            address,
+            data,
+        }
+    }
+
+    /// Create a request data message
+    pub fn rq1(device_id: u8, model_id: &'a [u8], address: &'a [u8], size: &'a [u8]) -> Self {
+        RolandMessage {
+            device_id,
+            model_id,
+            command: RolandCommand::Rq1,
+            address,
+            data: size,
+        }
+    }
+
+    /// Render the message into a buffer including the checksum, returns the number of bytes
+    /// written
+    pub fn render(&self, buffer: &mut [u8]) -> Result<usize, SysExError> {
+        let command = match self.command {
+            RolandCommand::Rq1 => RQ1,
+            RolandCommand::Dt1 => DT1,
+        };
+
+        if [self.model_id, self.address, self.data]
+            .iter()
+            .any(|part| part.iter().any(|byte| *byte > 0x7F))
+        {
+            return Err(SysExError::Malformed);
+        }
+
+        render(
+            buffer,
+            &[
+                &[SYSEX_START, ROLAND, self.device_id & 0x7F],
+                self.model_id,
+                &[command],
+                self.address,
+                self.data,
+                &[checksum(self.address.iter().chain(self.data)), SYSEX_END],
+            ],
+        )
+    }
+
+    /// Parse and validate a message for a device with the given model id and address lengths
+    pub fn parse(
+        message: &'a [u8],
+        model_id_len: usize,
+        address_len: usize,
+    ) -> Result<Self, SysExError> {
+        let (device_id, rest) = match payload(message)? {
+            [ROLAND, device_id, rest @ ..] => (*device_id, rest),
+            _ => return Err(SysExError::Mismatch),
+        };
+
+        if rest.len() < model_id_len.saturating_add(address_len).saturating_add(2) {
+            return Err(SysExError::Mismatch);
+        }
+
+        let (model_id, rest) = rest.split_at(model_id_len);
+        let (command, rest) = rest.split_at(1);
+        let (checked, check) = rest.split_at(rest.len() - 1);
+        let (address, data) = checked.split_at(address_len);
+
+        let command = match command {
+            [RQ1] => RolandCommand::Rq1,
+            [DT1] => RolandCommand::Dt1,
+            _ => return Err(SysExError::Mismatch),
+        };
+
+        if check != [checksum(checked)] {
+            return Err(SysExError::Checksum);
+        }
+
+        Ok(RolandMessage {
+            device_id,
+            model_id,
+            command,
+            address,
+            data,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_calculate_checksum() {
+        // GS reset address and data
+        assert_eq!(checksum(&[0x40, 0x00, 0x7F, 0x00]), 0x41);
+    }
+
+    #[test]
+    fn should_render_and_parse_dt1() {
+        let message = RolandMessage::dt1(0x10, &[0x42], &[0x40, 0x00, 0x7F], &[0x00]);
+        let mut buffer = [0u8; 16];
+
+        assert_eq!(message.render(&mut buffer), Ok(11));
+        assert_eq!(
+            buffer[..11],
+            [0xF0, 0x41, 0x10, 0x42, 0x12, 0x40, 0x00, 0x7F, 0x00, 0x41, 0xF7]
+        );
+        assert_eq!(RolandMessage::parse(&buffer[..11], 1, 3), Ok(message));
+    }
+
+    #[test]
+    fn should_reject_invalid_checksum() {
+        assert_eq!(
+            RolandMessage::parse(
+                &[0xF0, 0x41, 0x10, 0x42, 0x12, 0x40, 0x00, 0x7F, 0x00, 0x40, 0xF7],
+                1,
+                3
+            ),
+            Err(SysExError::Checksum)
+        );
+    }
+
+    #[test]
+    fn should_render_rq1_with_long_model_id() {
+        let message = RolandMessage::rq1(
+            0x10,
+            &[0x00, 0x00, 0x00, 0x0E],
+            &[0x01, 0x00, 0x00, 0x00],
+            &[0x00, 0x00, 0x00, 0x40],
+        );
+        let mut buffer = [0u8; 24];
+
+        let len = message.render(&mut buffer).unwrap();
+        assert_eq!(RolandMessage::parse(&buffer[..len], 4, 4), Ok(message));
+    }
+}
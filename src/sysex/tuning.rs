@@ -0,0 +1,262 @@
+//! Midi Tuning Standard messages
+
+use super::{payload, SysExError, UNIVERSAL_NON_REAL_TIME, UNIVERSAL_REAL_TIME};
+use crate::midi_types::Note;
+use crate::tuning::TuningTable;
+use crate::value::U14;
+
+const MIDI_TUNING: u8 = 0x08;
+const BULK_DUMP_REPLY: u8 = 0x01;
+const SINGLE_NOTE_TUNING_CHANGE: u8 = 0x02;
+const SCALE_OCTAVE_TUNING: u8 = 0x08;
+
+/// The offset in cents of a note tuned to a frequency in the three byte tuning standard format,
+/// `None` for the reserved value meaning no change
+fn frequency_offset(note: u8, frequency: [u8; 3]) -> Option<i16> {
+    let [semitone, msb, lsb] = frequency;
+    if frequency == [0x7F; 3] {
+        return None;
+    }
+
+    // The fraction is in units of 100/16384 cents
+    let fraction = (U14::from_bytes(lsb, msb).get() as i32 * 100 + 8192) / 16384;
+    Some(((semitone as i32 - note as i32) * 100 + fraction) as i16)
+}
+
+/// A bulk tuning dump with the tuning of all notes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BulkTuningDump {
+    pub device_id: u8,
+
+    /// The tuning program number
+    pub program: u8,
+
+    /// The name of the tuning in ASCII
+    pub name: [u8; 16],
+
+    /// The tuning, notes that are not changed by the dump are in equal temperament
+    pub table: TuningTable,
+}
+
+impl BulkTuningDump {
+    pub fn parse(message: &[u8]) -> Result<Self, SysExError> {
+        let (device_id, program, data) = match payload(message)? {
+            [UNIVERSAL_NON_REAL_TIME, device_id, MIDI_TUNING, BULK_DUMP_REPLY, program, data @ ..]
+                if data.len() == 16 + 128 * 3 + 1 =>
+            {
+                (*device_id, *program, data)
+            }
+            [UNIVERSAL_NON_REAL_TIME, _, MIDI_TUNING, BULK_DUMP_REPLY, ..] => {
+                return Err(SysExError::Malformed)
+            }
+            _ => return Err(SysExError::Mismatch),
+        };
+        let (name, data) = data.split_at(16);
+        let (frequencies, checksum) = data.split_at(128 * 3);
+
+        // The checksum covers everything between the start byte and the checksum itself
+        let header = [
+            UNIVERSAL_NON_REAL_TIME,
+            device_id,
+            MIDI_TUNING,
+            BULK_DUMP_REPLY,
+            program,
+        ];
+        let check = header
+            .iter()
+            .chain(name)
+            .chain(frequencies)
+            .fold(0, |check, byte| check ^ byte);
+        if checksum != [check & 0x7F] {
+            return Err(SysExError::Checksum);
+        }
+
+        let mut table = TuningTable::equal();
+        for (note, frequency) in frequencies.chunks_exact(3).enumerate() {
+            if let [semitone, msb, lsb] = *frequency {
+                if let Some(offset) = frequency_offset(note as u8, [semitone, msb, lsb]) {
+                    table.set_offset(Note::new(note as u8), offset);
+                }
+            }
+        }
+
+        let mut dump_name = [0; 16];
+        dump_name.copy_from_slice(name);
+        Ok(BulkTuningDump {
+            device_id,
+            program,
+            name: dump_name,
+            table,
+        })
+    }
+}
+
+/// Real-time changes to the tuning of single notes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SingleNoteTuningChange<'a> {
+    pub device_id: u8,
+
+    /// The tuning program number
+    pub program: u8,
+
+    /// The changes, four bytes for every note
+    changes: &'a [u8],
+}
+
+impl<'a> SingleNoteTuningChange<'a> {
+    pub fn parse(message: &'a [u8]) -> Result<Self, SysExError> {
+        match payload(message)? {
+            [UNIVERSAL_REAL_TIME, device_id, MIDI_TUNING, SINGLE_NOTE_TUNING_CHANGE, program, count, changes @ ..]
+                if changes.len() == *count as usize * 4 =>
+            {
+                Ok(SingleNoteTuningChange {
+                    device_id: *device_id,
+                    program: *program,
+                    changes,
+                })
+            }
+            [UNIVERSAL_REAL_TIME, _, MIDI_TUNING, SINGLE_NOTE_TUNING_CHANGE, ..] => {
+                Err(SysExError::Malformed)
+            }
+            _ => Err(SysExError::Mismatch),
+        }
+    }
+
+    /// The notes that are changed and their new offset in cents
+    pub fn changes(&self) -> impl Iterator<Item = (Note, i16)> + 'a {
+        self.changes
+            .chunks_exact(4)
+            .filter_map(|change| match *change {
+                [note, semitone, msb, lsb] => {
+                    let offset = frequency_offset(note, [semitone, msb, lsb])?;
+                    Some((Note::new(note), offset))
+                }
+                _ => None,
+            })
+    }
+
+    /// Apply the changes to a tuning table
+    pub fn apply(&self, table: &mut TuningTable) {
+        for (note, offset) in self.changes() {
+            table.set_offset(note, offset);
+        }
+    }
+}
+
+/// An octave repeating tuning in the one byte format, tuning each note of the scale to within
+/// -64 and +63 cents
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScaleOctaveTuning {
+    pub device_id: u8,
+
+    /// The channels the tuning applies to, bit `n` is channel `n + 1`
+    pub channels: u16,
+
+    /// Offsets in cents starting at C
+    pub offsets: [i8; 12],
+}
+
+impl ScaleOctaveTuning {
+    pub fn parse(message: &[u8]) -> Result<Self, SysExError> {
+        let (device_id, data) = match payload(message)? {
+            [UNIVERSAL_NON_REAL_TIME | UNIVERSAL_REAL_TIME, device_id, MIDI_TUNING, SCALE_OCTAVE_TUNING, data @ ..] => {
+                (*device_id, data)
+            }
+            _ => return Err(SysExError::Mismatch),
+        };
+
+        match data {
+            [high, middle, low, offsets @ ..] if offsets.len() == 12 => {
+                let mut tuning = ScaleOctaveTuning {
+                    device_id,
+                    channels: (*high as u16 & 0x03) << 14 | (*middle as u16) << 7 | *low as u16,
+                    offsets: [0; 12],
+                };
+                for (offset, byte) in tuning.offsets.iter_mut().zip(offsets) {
+                    *offset = *byte as i8 - 64;
+                }
+                Ok(tuning)
+            }
+            _ => Err(SysExError::Malformed),
+        }
+    }
+
+    /// The tuning as a table
+    pub fn table(&self) -> TuningTable {
+        let mut offsets = [0; 12];
+        for (offset, cents) in offsets.iter_mut().zip(self.offsets.iter()) {
+            *offset = *cents as i16;
+        }
+        TuningTable::from_octave(offsets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::vec::Vec;
+
+    fn bulk_dump(frequency: impl Fn(u8) -> [u8; 3]) -> Vec<u8> {
+        let mut message = std::vec![0xF0, 0x7E, 0x00, 0x08, 0x01, 0x05];
+        message.extend_from_slice(b"Just intonation ");
+        for note in 0..128 {
+            message.extend_from_slice(&frequency(note));
+        }
+        let check = message[1..].iter().fold(0, |check, byte| check ^ byte);
+        message.extend_from_slice(&[check & 0x7F, 0xF7]);
+        message
+    }
+
+    #[test]
+    fn should_parse_bulk_dump() {
+        // Every note is tuned a quarter semitone up, except note 0 which is not changed
+        let message = bulk_dump(|note| match note {
+            0 => [0x7F, 0x7F, 0x7F],
+            note => [note, 0x20, 0x00],
+        });
+
+        let dump = BulkTuningDump::parse(&message).unwrap();
+        assert_eq!(dump.program, 5);
+        assert_eq!(&dump.name, b"Just intonation ");
+        assert_eq!(dump.table.offset(0.into()), 0);
+        assert_eq!(dump.table.offset(60.into()), 25);
+
+        let mut corrupted = message.clone();
+        corrupted[100] ^= 1;
+        assert_eq!(BulkTuningDump::parse(&corrupted), Err(SysExError::Checksum));
+    }
+
+    #[test]
+    fn should_apply_single_note_changes() {
+        let message = [
+            0xF0, 0x7F, 0x00, 0x08, 0x02, 0x00, 0x02, 0x3C, 0x3B, 0x40, 0x00, 0x45, 0x7F, 0x7F,
+            0x7F, 0xF7,
+        ];
+
+        let change = SingleNoteTuningChange::parse(&message).unwrap();
+        let mut table = TuningTable::equal();
+        table.set_offset(0x45.into(), 3);
+        change.apply(&mut table);
+
+        assert_eq!(table.offset(0x3C.into()), -50);
+        assert_eq!(table.offset(0x45.into()), 3);
+        assert_eq!(
+            SingleNoteTuningChange::parse(&message[..15]),
+            Err(SysExError::Malformed)
+        );
+    }
+
+    #[test]
+    fn should_parse_scale_octave_tuning() {
+        let message = [
+            0xF0, 0x7E, 0x7F, 0x08, 0x08, 0x03, 0x7F, 0x7F, 0x40, 0x36, 0x44, 0x40, 0x32, 0x40,
+            0x36, 0x42, 0x40, 0x30, 0x40, 0x34, 0xF7,
+        ];
+
+        let tuning = ScaleOctaveTuning::parse(&message).unwrap();
+        assert_eq!(tuning.channels, 0xFFFF);
+        assert_eq!(tuning.offsets[4], -14);
+        assert_eq!(tuning.table().offset(64.into()), -14);
+    }
+}
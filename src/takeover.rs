@@ -0,0 +1,200 @@
+//! Soft takeover for physical controls sending control changes
+
+use crate::midi_types::{Channel, Control, MidiMessage, Value7};
+use crate::process::Processor;
+
+#[derive(Debug, Clone, Copy)]
+struct Parameter {
+    channel: Channel,
+    control: Control,
+    value: u8,
+    /// The last position of the physical control, if it moved since the value was set
+    position: Option<u8>,
+    picked_up: bool,
+}
+
+/// Suppresses control changes from physical controls until they cross the current value of the
+/// parameter they control, so parameters do not jump after a preset change
+///
+/// Keeps track of up to `N` parameters by channel and controller number, control changes for
+/// parameters that are not tracked are passed unchanged.
+#[derive(Debug, Clone)]
+pub struct SoftTakeover<const N: usize> {
+    parameters: [Option<Parameter>; N],
+}
+
+impl<const N: usize> Default for SoftTakeover<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> SoftTakeover<N> {
+    pub fn new() -> Self {
+        SoftTakeover {
+            parameters: [None; N],
+        }
+    }
+
+    /// Set the value of a parameter when it is changed by something else than its control, like
+    /// a preset change. The control has to cross the new value before it is picked up again.
+    /// Returns false when there is no room to track the parameter.
+    pub fn set_value(&mut self, channel: Channel, control: Control, value: Value7) -> bool {
+        let index = self
+            .find(channel, control)
+            .or_else(|| self.parameters.iter().position(Option::is_none));
+        match index.and_then(|index| self.parameters.get_mut(index)) {
+            Some(slot) => {
+                *slot = Some(Parameter {
+                    channel,
+                    control,
+                    value: value.into(),
+                    position: None,
+                    picked_up: false,
+                });
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The current value of a tracked parameter
+    pub fn value(&self, channel: Channel, control: Control) -> Option<Value7> {
+        self.get(channel, control)
+            .map(|parameter| parameter.value.into())
+    }
+
+    /// True when the control follows the parameter, controls for untracked parameters are always
+    /// picked up
+    pub fn is_picked_up(&self, channel: Channel, control: Control) -> bool {
+        self.get(channel, control)
+            .map_or(true, |parameter| parameter.picked_up)
+    }
+
+    /// Stop tracking a parameter
+    pub fn remove(&mut self, channel: Channel, control: Control) {
+        if let Some(slot) = self
+            .find(channel, control)
+            .and_then(|index| self.parameters.get_mut(index))
+        {
+            *slot = None;
+        }
+    }
+
+    /// Stop tracking all parameters
+    pub fn clear(&mut self) {
+        self.parameters = [None; N];
+    }
+
+    /// Handle the control moving to a new position, returns the value to send once the control
+    /// has picked up the parameter
+    pub fn control(&mut self, channel: Channel, control: Control, value: Value7) -> Option<Value7> {
+        let parameter = match self
+            .find(channel, control)
+            .and_then(|index| self.parameters.get_mut(index))
+            .and_then(Option::as_mut)
+        {
+            Some(parameter) => parameter,
+            None => return Some(value),
+        };
+
+        let position = u8::from(value);
+        if !parameter.picked_up {
+            // The control picks up the parameter when it reaches or moves past its value
+            let previous = parameter.position.unwrap_or(position);
+            let (low, high) = (previous.min(position), previous.max(position));
+            parameter.picked_up = (low..=high).contains(&parameter.value);
+            parameter.position = Some(position);
+        }
+
+        if parameter.picked_up {
+            parameter.value = position;
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    fn find(&self, channel: Channel, control: Control) -> Option<usize> {
+        self.parameters.iter().position(|parameter| {
+            matches!(parameter, Some(p) if p.channel == channel && p.control == control)
+        })
+    }
+
+    fn get(&self, channel: Channel, control: Control) -> Option<&Parameter> {
+        self.parameters
+            .iter()
+            .flatten()
+            .find(|parameter| parameter.channel == channel && parameter.control == control)
+    }
+}
+
+impl<const N: usize> Processor for SoftTakeover<N> {
+    fn process(&mut self, message: MidiMessage, emit: &mut impl FnMut(MidiMessage)) {
+        match message {
+            MidiMessage::ControlChange(channel, control, value) => {
+                if let Some(value) = self.control(channel, control, value) {
+                    emit(MidiMessage::ControlChange(channel, control, value));
+                }
+            }
+            message => emit(message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CUTOFF: Control = Control::new(74);
+
+    #[test]
+    fn should_pass_untracked_controls() {
+        let mut takeover = SoftTakeover::<2>::new();
+        assert_eq!(
+            takeover.control(Channel::C1, CUTOFF, 10.into()),
+            Some(10.into())
+        );
+        assert!(takeover.is_picked_up(Channel::C1, CUTOFF));
+    }
+
+    #[test]
+    fn should_pick_up_when_crossing_value() {
+        let mut takeover = SoftTakeover::<2>::new();
+        assert!(takeover.set_value(Channel::C1, CUTOFF, 64.into()));
+
+        assert_eq!(takeover.control(Channel::C1, CUTOFF, 10.into()), None);
+        assert_eq!(takeover.control(Channel::C1, CUTOFF, 40.into()), None);
+        // Other channels are tracked separately
+        assert_eq!(
+            takeover.control(Channel::C2, CUTOFF, 40.into()),
+            Some(40.into())
+        );
+        // Jumping past the value picks it up
+        assert_eq!(
+            takeover.control(Channel::C1, CUTOFF, 70.into()),
+            Some(70.into())
+        );
+        assert_eq!(
+            takeover.control(Channel::C1, CUTOFF, 20.into()),
+            Some(20.into())
+        );
+        assert_eq!(takeover.value(Channel::C1, CUTOFF), Some(20.into()));
+    }
+
+    #[test]
+    fn should_filter_control_changes() {
+        let mut takeover = SoftTakeover::<1>::new();
+        takeover.set_value(Channel::C1, CUTOFF, 100.into());
+        assert!(!takeover.set_value(Channel::C1, Control::new(1), 0.into()));
+
+        let mut sent = 0;
+        for value in 90..110 {
+            takeover.process(
+                MidiMessage::ControlChange(Channel::C1, CUTOFF, value.into()),
+                &mut |_| sent += 1,
+            );
+        }
+        assert_eq!(sent, 10);
+    }
+}
@@ -0,0 +1,28 @@
+//! Messages and helpers shared by the tests of the processors
+
+extern crate std;
+use crate::midi_types::{Channel, MidiMessage};
+use crate::process::Processor;
+use std::vec::Vec;
+
+/// A note-on on channel 1 with velocity 100
+pub(crate) fn on(note: u8) -> MidiMessage {
+    MidiMessage::NoteOn(Channel::C1, note.into(), 100.into())
+}
+
+/// A note-off on channel 1
+pub(crate) fn off(note: u8) -> MidiMessage {
+    MidiMessage::NoteOff(Channel::C1, note.into(), 0.into())
+}
+
+/// Run messages through a processor, returns the messages it emitted
+pub(crate) fn process(
+    processor: &mut impl Processor,
+    messages: &[MidiMessage],
+) -> Vec<MidiMessage> {
+    let mut output = Vec::new();
+    for message in messages {
+        processor.process(*message, &mut |message| output.push(message));
+    }
+    output
+}
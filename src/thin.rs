@@ -0,0 +1,222 @@
+//! Thinning dense control change streams
+//!
+//! Automation from a sequencer easily sends more control changes than a DIN output can carry.
+//! The thinner limits every controller to one message per interval. Values that arrive within
+//! the interval replace each other and the latest one is sent when the interval ends, so the
+//! controller always ends at the value that was sent last.
+//!
+//! Only continuous controllers are thinned. Bank select, parameter number selects and data entry,
+//! switches like sustain and channel mode messages are passed right away, their order relative to
+//! notes and to each other matters.
+//!
+//! The interval is in the unit used to advance the thinner, for instance milliseconds. The two
+//! halves of 14 bit controllers are thinned independently, leave those out with a `Filter` when
+//! they have to stay in pairs.
+
+use crate::mapping::is_continuous;
+use crate::midi_types::{Channel, Control, MidiMessage, Value7};
+use crate::process::Processor;
+
+#[derive(Debug, Clone, Copy)]
+struct Controller {
+    channel: Channel,
+    control: Control,
+    sent_at: u32,
+    /// The latest value that was held back
+    pending: Option<Value7>,
+}
+
+/// Limits the rate of control changes of up to `N` controllers per channel and controller number
+///
+/// When more than `N` controllers are busy at the same time, control changes for controllers
+/// that are not tracked are passed unchanged.
+#[derive(Debug, Clone)]
+pub struct ControlThinner<const N: usize> {
+    interval: u32,
+    controllers: [Option<Controller>; N],
+    now: u32,
+}
+
+impl<const N: usize> ControlThinner<N> {
+    /// Send every controller at most once per `interval`
+    pub fn new(interval: u32) -> Self {
+        ControlThinner {
+            interval,
+            controllers: [None; N],
+            now: 0,
+        }
+    }
+
+    pub fn interval(&self) -> u32 {
+        self.interval
+    }
+
+    pub fn set_interval(&mut self, interval: u32) {
+        self.interval = interval;
+    }
+
+    /// The number of controllers with a value that was held back
+    pub fn pending(&self) -> usize {
+        self.controllers
+            .iter()
+            .flatten()
+            .filter(|controller| controller.pending.is_some())
+            .count()
+    }
+
+    /// Advance time and emit the held back values of controllers whose interval ended
+    pub fn advance(&mut self, elapsed: u32, mut emit: impl FnMut(MidiMessage)) {
+        self.now = self.now.wrapping_add(elapsed);
+        let (now, interval) = (self.now, self.interval);
+        for controller in self.controllers.iter_mut().flatten() {
+            if now.wrapping_sub(controller.sent_at) >= interval {
+                if let Some(value) = controller.pending.take() {
+                    controller.sent_at = now;
+                    emit(MidiMessage::ControlChange(
+                        controller.channel,
+                        controller.control,
+                        value,
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Emit all held back values right away
+    pub fn flush(&mut self, mut emit: impl FnMut(MidiMessage)) {
+        let now = self.now;
+        for controller in self.controllers.iter_mut().flatten() {
+            if let Some(value) = controller.pending.take() {
+                controller.sent_at = now;
+                emit(MidiMessage::ControlChange(
+                    controller.channel,
+                    controller.control,
+                    value,
+                ));
+            }
+        }
+    }
+
+    fn control_change(
+        &mut self,
+        channel: Channel,
+        control: Control,
+        value: Value7,
+        emit: &mut impl FnMut(MidiMessage),
+    ) {
+        let (now, interval) = (self.now, self.interval);
+        if let Some(controller) = self
+            .controllers
+            .iter_mut()
+            .flatten()
+            .find(|controller| controller.channel == channel && controller.control == control)
+        {
+            if now.wrapping_sub(controller.sent_at) >= interval {
+                controller.sent_at = now;
+                controller.pending = None;
+                emit(MidiMessage::ControlChange(channel, control, value));
+            } else {
+                controller.pending = Some(value);
+            }
+            return;
+        }
+
+        // Track the controller in a free slot or in place of one without a held back value
+        let index = self
+            .controllers
+            .iter()
+            .position(Option::is_none)
+            .or_else(|| {
+                self.controllers
+                    .iter()
+                    .position(|slot| slot.map_or(false, |controller| controller.pending.is_none()))
+            });
+        if let Some(slot) = index.and_then(|index| self.controllers.get_mut(index)) {
+            *slot = Some(Controller {
+                channel,
+                control,
+                sent_at: now,
+                pending: None,
+            });
+        }
+        emit(MidiMessage::ControlChange(channel, control, value));
+    }
+}
+
+impl<const N: usize> Processor for ControlThinner<N> {
+    fn process(&mut self, message: MidiMessage, emit: &mut impl FnMut(MidiMessage)) {
+        match message {
+            MidiMessage::ControlChange(channel, control, value) if is_continuous(control) => {
+                self.control_change(channel, control, value, emit)
+            }
+            message => emit(message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::vec::Vec;
+
+    fn cc(control: u8, value: u8) -> MidiMessage {
+        MidiMessage::ControlChange(Channel::C1, control.into(), value.into())
+    }
+
+    #[test]
+    fn should_limit_rate_and_send_latest_value() {
+        let mut thinner = ControlThinner::<4>::new(10);
+        let mut output = Vec::new();
+
+        for value in 0..5 {
+            thinner.process(cc(74, value), &mut |message| output.push(message));
+            thinner.process(cc(1, value), &mut |message| output.push(message));
+            thinner.advance(3, |message| output.push(message));
+        }
+        assert_eq!(output, [cc(74, 0), cc(1, 0), cc(74, 3), cc(1, 3)]);
+        assert_eq!(thinner.pending(), 2);
+
+        output.clear();
+        thinner.advance(10, |message| output.push(message));
+        assert_eq!(output, [cc(74, 4), cc(1, 4)]);
+        assert_eq!(thinner.pending(), 0);
+    }
+
+    #[test]
+    fn should_pass_parameter_number_sequences() {
+        let mut thinner = ControlThinner::<4>::new(10);
+        let mut output = Vec::new();
+        let messages = [
+            cc(101, 0),
+            cc(100, 0),
+            cc(6, 12),
+            cc(101, 0),
+            cc(100, 1),
+            cc(6, 64),
+            cc(64, 127),
+            cc(64, 0),
+        ];
+
+        for message in messages {
+            thinner.process(message, &mut |message| output.push(message));
+        }
+        assert_eq!(output, messages);
+        assert_eq!(thinner.pending(), 0);
+    }
+
+    #[test]
+    fn should_pass_controllers_that_are_not_tracked() {
+        let mut thinner = ControlThinner::<1>::new(10);
+        let mut output = Vec::new();
+
+        for message in [cc(74, 0), cc(74, 1), cc(1, 0), cc(1, 1)] {
+            thinner.process(message, &mut |message| output.push(message));
+        }
+        assert_eq!(output, [cc(74, 0), cc(1, 0), cc(1, 1)]);
+
+        output.clear();
+        thinner.flush(|message| output.push(message));
+        assert_eq!(output, [cc(74, 1)]);
+    }
+}
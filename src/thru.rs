@@ -0,0 +1,114 @@
+//! Low latency midi thru with a parsing tap
+//!
+//! Received bytes are forwarded as soon as they arrive, the thru output does not wait for a
+//! message to be complete. A parser on the side decodes the same bytes so the application still
+//! receives messages. Messages generated locally are injected between received messages, if a
+//! received message is in progress they are held back until it is complete.
+
+use crate::merge::ByteMerge;
+use crate::midi_types::MidiMessage;
+use crate::packed::PackedMidiEvent;
+use crate::parser::MidiParser;
+
+const RECEIVED: usize = 0;
+const INJECTED: usize = 1;
+
+/// Forwards received bytes right away while parsing them, holding back up to `N` bytes of
+/// injected messages
+///
+/// `N` should be at least 3 to hold back a complete channel message.
+#[derive(Debug, Clone, Default)]
+pub struct ByteThru<const N: usize> {
+    merge: ByteMerge<2, N>,
+    parser: MidiParser,
+}
+
+impl<const N: usize> ByteThru<N> {
+    pub fn new() -> Self {
+        ByteThru {
+            merge: ByteMerge::new(),
+            parser: MidiParser::new(),
+        }
+    }
+
+    /// Forward a received byte to `emit`, returns a message when this byte completes one.
+    /// Injected messages that were held back are passed to `emit` once the received message is
+    /// complete.
+    pub fn receive(&mut self, byte: u8, emit: &mut impl FnMut(u8)) -> Option<MidiMessage> {
+        // Received bytes are never held back, the merge only holds back injected messages
+        let _ = self.merge.push(RECEIVED, byte, emit);
+        self.parser.parse_byte(byte)
+    }
+
+    /// Inject a message, it is passed to `emit` right away when no received message is in
+    /// progress. Returns the message back when there is no room to hold it back.
+    pub fn inject(
+        &mut self,
+        message: &MidiMessage,
+        emit: &mut impl FnMut(u8),
+    ) -> Result<(), MidiMessage> {
+        let packed = PackedMidiEvent::from(*message);
+        if N - self.merge.pending(INJECTED) < packed.len() {
+            return Err(*message);
+        }
+
+        let bytes = packed.to_bytes();
+        for byte in bytes.iter().take(packed.len()) {
+            self.merge
+                .push(INJECTED, *byte, emit)
+                .map_err(|_| *message)?;
+        }
+        Ok(())
+    }
+
+    /// The number of bytes of injected messages that are held back
+    pub fn pending(&self) -> usize {
+        self.merge.pending(INJECTED)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use crate::midi_types::{Channel, Note, Value7};
+    use std::vec::Vec;
+
+    #[test]
+    fn should_forward_bytes_and_parse_them() {
+        let mut thru = ByteThru::<6>::new();
+        let mut output = Vec::new();
+        let messages: Vec<_> = [0x90, 60, 100, 62, 100]
+            .iter()
+            .filter_map(|byte| thru.receive(*byte, &mut |byte| output.push(byte)))
+            .collect();
+
+        assert_eq!(output, [0x90, 60, 100, 62, 100]);
+        assert_eq!(
+            messages,
+            [
+                MidiMessage::NoteOn(Channel::C1, Note::C3, Value7::new(100)),
+                MidiMessage::NoteOn(Channel::C1, Note::D3, Value7::new(100))
+            ]
+        );
+    }
+
+    #[test]
+    fn should_inject_at_message_boundaries() {
+        let mut thru = ByteThru::<3>::new();
+        let mut output = Vec::new();
+        let mut emit = |byte| output.push(byte);
+        let note = MidiMessage::NoteOn(Channel::C2, Note::C3, Value7::new(64));
+
+        thru.receive(0x90, &mut emit);
+        thru.receive(60, &mut emit);
+        assert_eq!(thru.inject(&note, &mut emit), Ok(()));
+        assert_eq!(thru.inject(&note, &mut emit), Err(note));
+        assert_eq!(thru.pending(), 3);
+        thru.receive(100, &mut emit);
+        thru.receive(62, &mut emit);
+        thru.receive(100, &mut emit);
+
+        assert_eq!(output, [0x90, 60, 100, 0x91, 60, 64, 0x90, 62, 100]);
+    }
+}
@@ -0,0 +1,142 @@
+//! Midi messages with the time they were received
+
+use crate::midi_types::MidiMessage;
+use crate::parser::{is_real_time, MidiParser};
+
+/// A midi message with a timestamp, `T` is the type of the clock used to stamp it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimedMidiEvent<T> {
+    pub timestamp: T,
+    pub message: MidiMessage,
+}
+
+impl<T> TimedMidiEvent<T> {
+    pub const fn new(timestamp: T, message: MidiMessage) -> Self {
+        TimedMidiEvent { timestamp, message }
+    }
+}
+
+/// The moment a message is stamped with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Stamp {
+    /// The time of the byte that completed the message
+    #[default]
+    Completion,
+
+    /// The time of the first byte of the message, its status byte or the first data byte when
+    /// running status is used. Real-time messages are always stamped with their own time.
+    Start,
+}
+
+/// Parses midi messages a byte at a time, stamping them with the time passed in with every byte
+#[derive(Debug, Clone, Default)]
+pub struct TimedMidiParser<T> {
+    parser: MidiParser,
+    stamp: Stamp,
+    /// The time the message being received started
+    start: Option<T>,
+}
+
+impl<T: Copy> TimedMidiParser<T> {
+    pub fn new(stamp: Stamp) -> Self {
+        TimedMidiParser {
+            parser: MidiParser::new(),
+            stamp,
+            start: None,
+        }
+    }
+
+    pub fn stamp(&self) -> Stamp {
+        self.stamp
+    }
+
+    /// Parse the next byte received at `now`, returns a timed message when this byte completes one
+    pub fn parse_byte(&mut self, byte: u8, now: T) -> Option<TimedMidiEvent<T>> {
+        let real_time = is_real_time(byte);
+        if !real_time && (byte >= 0x80 || self.start.is_none()) {
+            self.start = Some(now);
+        }
+
+        let message = self.parser.parse_byte(byte)?;
+        let timestamp = match self.stamp {
+            Stamp::Start if !real_time => self.start.take().unwrap_or(now),
+            _ => {
+                if !real_time {
+                    self.start = None;
+                }
+                now
+            }
+        };
+
+        Some(TimedMidiEvent::new(timestamp, message))
+    }
+
+    /// Parse a buffer of bytes received at the same time
+    pub fn parse_buffer(
+        &mut self,
+        buffer: &[u8],
+        now: T,
+        mut handler: impl FnMut(TimedMidiEvent<T>),
+    ) {
+        for byte in buffer {
+            if let Some(event) = self.parse_byte(*byte, now) {
+                handler(event);
+            }
+        }
+    }
+
+    /// The wrapped parser
+    pub fn parser(&self) -> &MidiParser {
+        &self.parser
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use crate::midi_types::Channel;
+    use std::vec::Vec;
+
+    fn parse(parser: &mut TimedMidiParser<u32>, bytes: &[u8]) -> Vec<TimedMidiEvent<u32>> {
+        bytes
+            .iter()
+            .enumerate()
+            .filter_map(|(time, byte)| parser.parse_byte(*byte, time as u32 * 10))
+            .collect()
+    }
+
+    const BYTES: [u8; 7] = [0x90, 0x40, 0xF8, 0x7F, 0x41, 0x7F, 0xFC];
+
+    #[test]
+    fn should_stamp_messages_when_completed() {
+        let mut parser = TimedMidiParser::new(Stamp::Completion);
+
+        assert_eq!(
+            parse(&mut parser, &BYTES),
+            [
+                TimedMidiEvent::new(20, MidiMessage::TimingClock),
+                TimedMidiEvent::new(
+                    30,
+                    MidiMessage::NoteOn(Channel::C1, 0x40.into(), 0x7F.into())
+                ),
+                TimedMidiEvent::new(
+                    50,
+                    MidiMessage::NoteOn(Channel::C1, 0x41.into(), 0x7F.into())
+                ),
+                TimedMidiEvent::new(60, MidiMessage::Stop),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_stamp_messages_when_started() {
+        let mut parser = TimedMidiParser::new(Stamp::Start);
+
+        let timestamps: Vec<u32> = parse(&mut parser, &BYTES)
+            .iter()
+            .map(|event| event.timestamp)
+            .collect();
+        assert_eq!(timestamps, [20, 0, 40, 60]);
+    }
+}
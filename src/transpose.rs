@@ -0,0 +1,203 @@
+//! Chromatic and diatonic transposition of notes
+
+use crate::midi_types::{Channel, MidiMessage, Note, Value7};
+use crate::notes::NoteOffPolicy;
+use crate::process::Processor;
+use crate::scale::Scale;
+
+/// The interval to transpose notes by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interval {
+    /// A number of semitones
+    Chromatic(i8),
+
+    /// A number of degrees of a scale, keeping notes in the key
+    Diatonic(Scale, i8),
+}
+
+impl Interval {
+    /// The transposed note, `None` when it is out of range
+    pub fn apply(&self, note: Note) -> Option<Note> {
+        match *self {
+            Interval::Chromatic(semitones) => {
+                let note = u8::from(note) as i16 + semitones as i16;
+                (0..0x80).contains(&note).then(|| Note::new(note as u8))
+            }
+            Interval::Diatonic(scale, degrees) => scale.transpose(note, degrees),
+        }
+    }
+}
+
+/// Transposes notes, remembering up to `N` held notes so their note-off is transposed like the
+/// note-on even when the interval changes
+///
+/// Notes that are transposed out of range are dropped. The note-off policy decides which press
+/// of a note that is pressed again before it is released a note-off ends. With the `Count`
+/// policy a repeated press plays the note it was transposed to the first time.
+#[derive(Debug, Clone)]
+pub struct Transposer<const N: usize> {
+    interval: Interval,
+    policy: NoteOffPolicy,
+    /// Input note, output note and the number of presses, from the oldest to the most recent
+    held: [Option<(Channel, Note, Note, u8)>; N],
+}
+
+impl<const N: usize> Transposer<N> {
+    pub fn new(interval: Interval) -> Self {
+        Transposer {
+            interval,
+            policy: NoteOffPolicy::FirstOff,
+            held: [None; N],
+        }
+    }
+
+    pub fn interval(&self) -> Interval {
+        self.interval
+    }
+
+    /// Change the interval, held notes keep sounding until they are released
+    pub fn set_interval(&mut self, interval: Interval) {
+        self.interval = interval;
+    }
+
+    pub fn set_note_off_policy(&mut self, policy: NoteOffPolicy) {
+        self.policy = policy;
+    }
+
+    fn note_on(
+        &mut self,
+        channel: Channel,
+        note: Note,
+        velocity: Value7,
+        emit: &mut impl FnMut(MidiMessage),
+    ) {
+        if self.policy == NoteOffPolicy::Count {
+            if let Some((.., output, count)) = self
+                .held
+                .iter_mut()
+                .flatten()
+                .find(|(c, n, ..)| *c == channel && *n == note)
+            {
+                *count = count.saturating_add(1);
+                return emit(MidiMessage::NoteOn(channel, *output, velocity));
+            }
+        }
+
+        if let Some(output) = self.interval.apply(note) {
+            if let Some(slot) = self.held.iter_mut().find(|slot| slot.is_none()) {
+                *slot = Some((channel, note, output, 1));
+            }
+            emit(MidiMessage::NoteOn(channel, output, velocity));
+        }
+    }
+
+    fn note_off(
+        &mut self,
+        channel: Channel,
+        note: Note,
+        velocity: Value7,
+        emit: &mut impl FnMut(MidiMessage),
+    ) {
+        let output = match self.policy.release(
+            &self.held,
+            |slot| matches!(slot, Some((c, n, ..)) if *c == channel && *n == note),
+        ) {
+            Some(index) => match self.release(index) {
+                Some(output) => Some(output),
+                None => return,
+            },
+            None => self.interval.apply(note),
+        };
+
+        if let Some(output) = output {
+            emit(MidiMessage::NoteOff(channel, output, velocity));
+        }
+    }
+
+    /// Release a press, returns the output note when it ends
+    fn release(&mut self, index: usize) -> Option<Note> {
+        let slot = self.held.get_mut(index)?;
+        let (.., output, count) = slot.as_mut()?;
+        *count = count.saturating_sub(1);
+        if *count > 0 {
+            return None;
+        }
+
+        let output = *output;
+        *slot = None;
+        if let Some(held) = self.held.get_mut(index..) {
+            held.rotate_left(1);
+        }
+        Some(output)
+    }
+}
+
+impl<const N: usize> Processor for Transposer<N> {
+    fn process(&mut self, message: MidiMessage, emit: &mut impl FnMut(MidiMessage)) {
+        match message {
+            MidiMessage::NoteOn(channel, note, velocity) if u8::from(velocity) > 0 => {
+                self.note_on(channel, note, velocity, emit)
+            }
+            MidiMessage::NoteOn(channel, note, velocity)
+            | MidiMessage::NoteOff(channel, note, velocity) => {
+                self.note_off(channel, note, velocity, emit)
+            }
+            MidiMessage::KeyPressure(channel, note, pressure) => {
+                if let Some(note) = self.interval.apply(note) {
+                    emit(MidiMessage::KeyPressure(channel, note, pressure));
+                }
+            }
+            message => emit(message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{off, on, process};
+
+    #[test]
+    fn should_transpose_within_key() {
+        let mut transposer =
+            Transposer::<4>::new(Interval::Diatonic(Scale::new(0, Scale::MAJOR), 2));
+
+        assert_eq!(
+            process(&mut transposer, &[on(60), on(62), off(60), off(62)]),
+            [on(64), on(65), off(64), off(65)]
+        );
+    }
+
+    #[test]
+    fn should_release_notes_after_interval_change() {
+        let mut transposer = Transposer::<4>::new(Interval::Chromatic(7));
+
+        let mut output = process(&mut transposer, &[on(60), on(125)]);
+        transposer.set_interval(Interval::Chromatic(-12));
+        output.extend(process(&mut transposer, &[off(60)]));
+
+        assert_eq!(output, [on(67), off(67)]);
+    }
+
+    #[test]
+    fn should_pair_repeated_presses_by_policy() {
+        let play = |policy| {
+            let mut transposer = Transposer::<4>::new(Interval::Chromatic(0));
+            transposer.set_note_off_policy(policy);
+            let mut output = process(&mut transposer, &[on(60)]);
+            transposer.set_interval(Interval::Chromatic(2));
+            output.extend(process(&mut transposer, &[on(60), off(60), off(60)]));
+            output
+        };
+
+        assert_eq!(
+            play(NoteOffPolicy::FirstOff),
+            [on(60), on(62), off(60), off(62)]
+        );
+        assert_eq!(
+            play(NoteOffPolicy::LastOff),
+            [on(60), on(62), off(62), off(60)]
+        );
+        assert_eq!(play(NoteOffPolicy::Count), [on(60), on(60), off(60)]);
+    }
+}
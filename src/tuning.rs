@@ -0,0 +1,161 @@
+//! Microtuning tables with a tuning for every note
+//!
+//! Tables also convert frequencies back to notes, for instance from a tuner or a pitch tracking
+//! input. The conversion only uses integer math so it is fast enough on cores without a floating
+//! point unit.
+
+use crate::cv::exp2_cents;
+use crate::midi_types::Note;
+
+/// The tuning of all 128 notes as offsets in cents from equal temperament
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TuningTable {
+    offsets: [i16; 128],
+}
+
+impl Default for TuningTable {
+    fn default() -> Self {
+        Self::equal()
+    }
+}
+
+impl TuningTable {
+    /// Equal temperament, every note is tuned to its standard pitch
+    pub const fn equal() -> Self {
+        TuningTable { offsets: [0; 128] }
+    }
+
+    /// Create a table from the offset of every note
+    pub const fn from_offsets(offsets: [i16; 128]) -> Self {
+        TuningTable { offsets }
+    }
+
+    /// Create a table repeating a scale every octave, `offsets` starts at C
+    pub fn from_octave(offsets: [i16; 12]) -> Self {
+        let mut table = Self::equal();
+        for (offset, scale) in table.offsets.iter_mut().zip(offsets.iter().cycle()) {
+            *offset = *scale;
+        }
+        table
+    }
+
+    /// The offset of a note in cents
+    pub fn offset(&self, note: Note) -> i16 {
+        self.offsets
+            .get(u8::from(note) as usize)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn set_offset(&mut self, note: Note, cents: i16) {
+        if let Some(offset) = self.offsets.get_mut(u8::from(note) as usize) {
+            *offset = cents;
+        }
+    }
+
+    /// The tuned pitch of a note in cents, note 0 is at 0 cents
+    pub fn cents(&self, note: Note) -> i32 {
+        u8::from(note) as i32 * 100 + self.offset(note) as i32
+    }
+
+    /// The tuned frequency of a note in millihertz, with note 69 tuned to 440 Hz in equal
+    /// temperament
+    pub fn millihertz(&self, note: Note) -> u32 {
+        let ratio = exp2_cents(self.cents(note) as i64 - 6900);
+        (440_000u64.saturating_mul(ratio) >> 16).min(u32::MAX as u64) as u32
+    }
+
+    /// The tuned note nearest to a frequency in millihertz and the offset of the frequency from
+    /// that note in cents, `None` when the frequency is more than half a semitone outside the
+    /// midi range. The offset is accurate to about a cent.
+    pub fn nearest_note(&self, millihertz: u32) -> Option<(Note, i16)> {
+        let pitch = pitch_cents(millihertz)?;
+        let note = (0..128u8)
+            .map(Note::from)
+            .min_by_key(|note| (self.cents(*note) - pitch).abs())?;
+        Some((note, (pitch - self.cents(note)) as i16))
+    }
+}
+
+/// The frequency of a pitch in cents in equal temperament in millihertz times 2^22. Raising the
+/// pitch by six octaves keeps the precision of the ratio for low notes.
+fn scaled_millihertz(cents: i32) -> u64 {
+    440_000 * exp2_cents(cents as i64 - 6900 + 7200)
+}
+
+/// The pitch in cents of a frequency in millihertz in equal temperament, note 0 is at 0 cents.
+/// `None` when the pitch is more than half a semitone outside the midi range.
+pub fn pitch_cents(millihertz: u32) -> Option<i32> {
+    const LOWEST: i32 = -50;
+    const HIGHEST: i32 = 12750;
+
+    let millihertz = (millihertz as u64) << 22;
+    if millihertz < scaled_millihertz(LOWEST) || millihertz >= scaled_millihertz(HIGHEST) {
+        return None;
+    }
+
+    // Frequencies rise with the pitch, search the lowest pitch at or above the frequency
+    let (mut low, mut high) = (LOWEST, HIGHEST);
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if scaled_millihertz(middle) >= millihertz {
+            high = middle;
+        } else {
+            low = middle + 1;
+        }
+    }
+
+    let below = (low - 1).max(LOWEST);
+    if scaled_millihertz(low) - millihertz < millihertz - scaled_millihertz(below) {
+        Some(low)
+    } else {
+        Some(below)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_repeat_octave_scales() {
+        let table = TuningTable::from_octave([0, -10, 4, 0, -14, 0, -10, 2, 0, -16, 0, -12]);
+
+        assert_eq!(table.offset(4.into()), -14);
+        assert_eq!(table.offset(64.into()), -14);
+        assert_eq!(table.cents(64.into()), 6386);
+        assert_eq!(table.cents(127.into()), 12702);
+    }
+
+    #[test]
+    fn should_convert_to_frequency() {
+        let mut table = TuningTable::equal();
+        assert_eq!(table.millihertz(69.into()), 440_000);
+        assert_eq!(table.millihertz(81.into()), 880_000);
+        assert_eq!(table.millihertz(60.into()), 261_625);
+
+        table.set_offset(69.into(), 1200);
+        assert_eq!(table.millihertz(69.into()), 880_000);
+
+        // Extreme offsets saturate
+        table.set_offset(127.into(), i16::MAX);
+        assert_eq!(table.millihertz(127.into()), u32::MAX);
+        table.set_offset(0.into(), i16::MIN);
+        assert_eq!(table.millihertz(0.into()), 0);
+    }
+
+    #[test]
+    fn should_find_nearest_note_with_cents() {
+        let table = TuningTable::equal();
+        assert_eq!(table.nearest_note(440_000), Some((69.into(), 0)));
+        // 19.6 cents, interpolating between semitones is accurate to about a cent
+        assert_eq!(table.nearest_note(445_000), Some((69.into(), 19)));
+        assert_eq!(table.nearest_note(261_000), Some((60.into(), -4)));
+        assert_eq!(table.nearest_note(8_176), Some((0.into(), 0)));
+        assert_eq!(table.nearest_note(7_000), None);
+        assert_eq!(table.nearest_note(13_000_000), None);
+
+        let table = TuningTable::from_octave([0, 0, 0, 0, 0, 0, 0, 0, 0, -30, 0, 0]);
+        assert_eq!(table.nearest_note(440_000), Some((69.into(), 30)));
+    }
+}
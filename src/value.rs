@@ -0,0 +1,280 @@
+//! Data values that can not be out of range
+//!
+//! Midi data bytes carry 7 bits, the most significant bit marks status bytes. A `U7` always has
+//! its most significant bit cleared so it can be written as a data byte without corrupting the
+//! stream. A `U14` holds 14 bit values like pitch bends, song positions and high resolution
+//! controllers that are sent as two data bytes. Both convert to and from the value types of
+//! `midi_types`.
+//!
+//! Values from config files or user interfaces are validated with `TryFrom`, which returns
+//! `OutOfRange` instead of clamping or masking bits like the conversions of `midi_types` do.
+//!
+//! Processors take `U7` for their own 7 bit settings like velocity ranges and button values, the
+//! messages they process keep the value types of `midi_types`.
+
+use crate::midi_types::{Control, Note, Program, Value14, Value7};
+use core::convert::TryFrom;
+use core::fmt;
+
+/// A value is out of range for the type it is converted to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutOfRange;
+
+impl fmt::Display for OutOfRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("value out of range")
+    }
+}
+
+/// A 7 bit value, from 0 to 127
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct U7(u8);
+
+impl U7 {
+    pub const MIN: U7 = U7(0);
+    pub const MAX: U7 = U7(0x7F);
+
+    /// Create a value, values larger than 127 are clamped to 127
+    pub const fn new(value: u8) -> Self {
+        U7(if value > 0x7F { 0x7F } else { value })
+    }
+
+    /// Create a value without checking it, the value must not be larger than 127
+    pub const fn new_unchecked(value: u8) -> Self {
+        debug_assert!(value <= 0x7F);
+        U7(value)
+    }
+
+    /// Create a value, returns `None` for values larger than 127
+    pub const fn checked(value: u8) -> Option<Self> {
+        if value > 0x7F {
+            None
+        } else {
+            Some(U7(value))
+        }
+    }
+
+    /// Create a value from the lower 7 bits of a byte
+    pub const fn masked(value: u8) -> Self {
+        U7(value & 0x7F)
+    }
+
+    pub const fn get(self) -> u8 {
+        self.0
+    }
+
+    /// Add to the value, clamping at 127
+    pub const fn saturating_add(self, value: u8) -> Self {
+        Self::new(self.0.saturating_add(value))
+    }
+
+    /// Subtract from the value, clamping at 0
+    pub const fn saturating_sub(self, value: u8) -> Self {
+        U7(self.0.saturating_sub(value))
+    }
+}
+
+impl TryFrom<u8> for U7 {
+    type Error = OutOfRange;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        U7::checked(value).ok_or(OutOfRange)
+    }
+}
+
+impl From<U7> for u8 {
+    fn from(value: U7) -> Self {
+        value.0
+    }
+}
+
+macro_rules! convert_u7 {
+    ($($type:ty),*) => {
+        $(
+            impl From<U7> for $type {
+                fn from(value: U7) -> Self {
+                    <$type>::new(value.0)
+                }
+            }
+
+            impl From<$type> for U7 {
+                fn from(value: $type) -> Self {
+                    U7::new(value.into())
+                }
+            }
+        )*
+    };
+}
+
+convert_u7!(Value7, Note, Control, Program);
+
+/// A 14 bit value, from 0 to 0x3FFF
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct U14(u16);
+
+impl U14 {
+    pub const MIN: U14 = U14(0);
+    pub const MAX: U14 = U14(0x3FFF);
+
+    /// The center value, no pitch bend
+    pub const CENTER: U14 = U14(0x2000);
+
+    /// Create a value, values larger than 0x3FFF are clamped to 0x3FFF
+    pub const fn new(value: u16) -> Self {
+        U14(if value > 0x3FFF { 0x3FFF } else { value })
+    }
+
+    /// Create a value without checking it, the value must not be larger than 0x3FFF
+    pub const fn new_unchecked(value: u16) -> Self {
+        debug_assert!(value <= 0x3FFF);
+        U14(value)
+    }
+
+    /// Create a value, returns `None` for values larger than 0x3FFF
+    pub const fn checked(value: u16) -> Option<Self> {
+        if value > 0x3FFF {
+            None
+        } else {
+            Some(U14(value))
+        }
+    }
+
+    /// Create a value from the lower 14 bits
+    pub const fn masked(value: u16) -> Self {
+        U14(value & 0x3FFF)
+    }
+
+    /// Join the most and least significant 7 bits
+    pub const fn from_parts(msb: U7, lsb: U7) -> Self {
+        U14((msb.0 as u16) << 7 | lsb.0 as u16)
+    }
+
+    /// Create a value from a signed offset from the center, from -8192 to 8191. Values outside
+    /// that range are clamped.
+    pub const fn from_signed(value: i16) -> Self {
+        let value = if value < -0x2000 {
+            -0x2000
+        } else if value > 0x1FFF {
+            0x1FFF
+        } else {
+            value
+        };
+        U14((value + 0x2000) as u16)
+    }
+
+    pub const fn get(self) -> u16 {
+        self.0
+    }
+
+    /// The most significant 7 bits
+    pub const fn msb(self) -> U7 {
+        U7((self.0 >> 7) as u8)
+    }
+
+    /// The least significant 7 bits
+    pub const fn lsb(self) -> U7 {
+        U7((self.0 & 0x7F) as u8)
+    }
+
+    /// The most and least significant 7 bits
+    pub const fn split(self) -> (U7, U7) {
+        (self.msb(), self.lsb())
+    }
+
+    /// Join the least and most significant 7 bits in the order they are sent, bits that do not
+    /// fit are masked
+    pub const fn from_bytes(lsb: u8, msb: u8) -> Self {
+        Self::from_parts(U7::masked(msb), U7::masked(lsb))
+    }
+
+    /// The least and most significant 7 bits in the order they are sent
+    pub const fn to_bytes(self) -> [u8; 2] {
+        [self.lsb().0, self.msb().0]
+    }
+
+    /// The offset from the center, from -8192 to 8191
+    pub const fn signed(self) -> i16 {
+        self.0 as i16 - 0x2000
+    }
+}
+
+impl TryFrom<u16> for U14 {
+    type Error = OutOfRange;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        U14::checked(value).ok_or(OutOfRange)
+    }
+}
+
+impl From<U14> for u16 {
+    fn from(value: U14) -> Self {
+        value.0
+    }
+}
+
+impl From<U14> for Value14 {
+    fn from(value: U14) -> Self {
+        Value14::from(value.0)
+    }
+}
+
+impl From<Value14> for U14 {
+    fn from(value: Value14) -> Self {
+        U14::new(value.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_keep_values_in_range() {
+        assert_eq!(U7::new(200), U7::MAX);
+        assert_eq!(U7::checked(128), None);
+        assert_eq!(U7::checked(127), Some(U7::MAX));
+        assert_eq!(U7::masked(0x90).get(), 0x10);
+        assert_eq!(U7::new(120).saturating_add(10), U7::MAX);
+        assert_eq!(U7::new(5).saturating_sub(10), U7::MIN);
+    }
+
+    #[test]
+    fn should_convert_to_midi_types() {
+        const VELOCITY: U7 = U7::new(100);
+
+        assert_eq!(Value7::from(VELOCITY), Value7::new(100));
+        assert_eq!(U7::from(Note::C3).get(), 60);
+        assert_eq!(Program::from(U7::new(5)), Program::new(5));
+    }
+
+    #[test]
+    fn should_split_and_join_14_bit_values() {
+        let value = U14::new(0x1234);
+        assert_eq!(value.split(), (U7::new(0x24), U7::new(0x34)));
+        assert_eq!(U14::from_parts(value.msb(), value.lsb()), value);
+        assert_eq!(value.to_bytes(), [0x34, 0x24]);
+        assert_eq!(U14::from_bytes(0xB4, 0x24), value);
+        assert_eq!(U14::new(0xFFFF), U14::MAX);
+        assert_eq!(U14::checked(0x4000), None);
+    }
+
+    #[test]
+    fn should_convert_signed_14_bit_values() {
+        assert_eq!(U14::from_signed(0), U14::CENTER);
+        assert_eq!(U14::from_signed(-10000), U14::MIN);
+        assert_eq!(U14::MAX.signed(), 8191);
+        assert_eq!(
+            i16::from(Value14::from(U14::from_signed(-100))),
+            U14::from_signed(-100).signed()
+        );
+    }
+
+    #[test]
+    fn should_validate_values() {
+        assert_eq!(U7::try_from(127), Ok(U7::MAX));
+        assert_eq!(U7::try_from(128), Err(OutOfRange));
+        assert_eq!(U14::try_from(0x4000), Err(OutOfRange));
+        assert_eq!(U7::try_from(64).map(Note::from), Ok(Note::new(64)));
+        assert_eq!(U14::new_unchecked(0x2000), U14::CENTER);
+    }
+}
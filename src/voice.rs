@@ -0,0 +1,268 @@
+//! Polyphonic voice allocation
+
+use crate::midi_types::{Channel, MidiMessage, Note, Value7};
+
+/// Decides which voice is reused when a note is played while all voices are busy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StealPolicy {
+    /// Steal the voice that started playing first
+    Oldest,
+
+    /// Steal the voice that was started with the lowest velocity
+    Quietest,
+
+    /// Reuse the voice already playing the same note, even when other voices are free, steal the
+    /// oldest voice otherwise
+    SameNote,
+}
+
+/// Voice assignments emitted by the voice allocator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoiceEvent {
+    /// Start playing a note on a voice. When the voice was still playing another note `stolen`
+    /// contains that note
+    On {
+        voice: usize,
+        channel: Channel,
+        note: Note,
+        velocity: Value7,
+        stolen: Option<Note>,
+    },
+
+    /// Stop playing the note on a voice
+    Off {
+        voice: usize,
+        channel: Channel,
+        note: Note,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Voice {
+    playing: Option<(Channel, Note, Value7)>,
+    stamp: u32,
+}
+
+/// Assigns notes to `N` voices
+#[derive(Debug, Clone)]
+pub struct VoiceAllocator<const N: usize> {
+    voices: [Voice; N],
+    policy: StealPolicy,
+    counter: u32,
+}
+
+impl<const N: usize> VoiceAllocator<N> {
+    pub fn new(policy: StealPolicy) -> Self {
+        VoiceAllocator {
+            voices: [Voice {
+                playing: None,
+                stamp: 0,
+            }; N],
+            policy,
+            counter: 0,
+        }
+    }
+
+    pub fn policy(&self) -> StealPolicy {
+        self.policy
+    }
+
+    pub fn set_policy(&mut self, policy: StealPolicy) {
+        self.policy = policy;
+    }
+
+    /// The channel, note and velocity a voice is currently playing
+    pub fn voice(&self, voice: usize) -> Option<(Channel, Note, Value7)> {
+        self.voices.get(voice).and_then(|voice| voice.playing)
+    }
+
+    /// The number of voices that are currently playing
+    pub fn active(&self) -> usize {
+        self.voices.iter().filter(|v| v.playing.is_some()).count()
+    }
+
+    /// Assign a note to a voice, returns `None` only when there are no voices
+    pub fn note_on(
+        &mut self,
+        channel: Channel,
+        note: Note,
+        velocity: Value7,
+    ) -> Option<VoiceEvent> {
+        let voice = self.select(channel, note)?;
+        self.counter = self.counter.wrapping_add(1);
+
+        let slot = self.voices.get_mut(voice)?;
+        let stolen = slot.playing.map(|(_, note, _)| note);
+        slot.playing = Some((channel, note, velocity));
+        slot.stamp = self.counter;
+
+        Some(VoiceEvent::On {
+            voice,
+            channel,
+            note,
+            velocity,
+            stolen,
+        })
+    }
+
+    /// Release the voice playing a note, returns `None` when the note is not playing
+    pub fn note_off(&mut self, channel: Channel, note: Note) -> Option<VoiceEvent> {
+        let voice = self.find(channel, note)?;
+        self.counter = self.counter.wrapping_add(1);
+
+        let slot = self.voices.get_mut(voice)?;
+        slot.playing = None;
+        slot.stamp = self.counter;
+
+        Some(VoiceEvent::Off {
+            voice,
+            channel,
+            note,
+        })
+    }
+
+    /// Release all voices, calls `handler` for every voice that was playing
+    pub fn reset(&mut self, mut handler: impl FnMut(VoiceEvent)) {
+        for (voice, slot) in self.voices.iter_mut().enumerate() {
+            if let Some((channel, note, _)) = slot.playing.take() {
+                handler(VoiceEvent::Off {
+                    voice,
+                    channel,
+                    note,
+                });
+            }
+        }
+    }
+
+    /// Handle a midi message, note-on messages with velocity 0 are treated as note-off. Other
+    /// messages are ignored.
+    pub fn handle(&mut self, message: &MidiMessage) -> Option<VoiceEvent> {
+        match *message {
+            MidiMessage::NoteOn(channel, note, velocity) if u8::from(velocity) > 0 => {
+                self.note_on(channel, note, velocity)
+            }
+            MidiMessage::NoteOn(channel, note, _) | MidiMessage::NoteOff(channel, note, _) => {
+                self.note_off(channel, note)
+            }
+            _ => None,
+        }
+    }
+
+    fn find(&self, channel: Channel, note: Note) -> Option<usize> {
+        self.voices
+            .iter()
+            .position(|voice| matches!(voice.playing, Some((c, n, _)) if c == channel && n == note))
+    }
+
+    fn age(&self, voice: &Voice) -> u32 {
+        self.counter.wrapping_sub(voice.stamp)
+    }
+
+    fn select(&self, channel: Channel, note: Note) -> Option<usize> {
+        if self.policy == StealPolicy::SameNote {
+            if let Some(voice) = self.find(channel, note) {
+                return Some(voice);
+            }
+        }
+
+        // Prefer the free voice that was released the longest ago so release tails can finish,
+        // iterating in reverse makes ties resolve to the lowest voice
+        let voices = self.voices.iter().enumerate();
+        let free = voices
+            .clone()
+            .rev()
+            .filter(|(_, voice)| voice.playing.is_none())
+            .max_by_key(|(_, voice)| self.age(voice));
+
+        free.or_else(|| match self.policy {
+            StealPolicy::Oldest | StealPolicy::SameNote => {
+                voices.rev().max_by_key(|(_, voice)| self.age(voice))
+            }
+            StealPolicy::Quietest => voices.min_by_key(|(_, voice)| {
+                let velocity = voice
+                    .playing
+                    .map_or(0, |(_, _, velocity)| u8::from(velocity));
+                (velocity, u32::MAX - self.age(voice))
+            }),
+        })
+        .map(|(index, _)| index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn on(voice: usize, note: u8, velocity: u8, stolen: Option<u8>) -> Option<VoiceEvent> {
+        Some(VoiceEvent::On {
+            voice,
+            channel: 0.into(),
+            note: note.into(),
+            velocity: velocity.into(),
+            stolen: stolen.map(Into::into),
+        })
+    }
+
+    #[test]
+    fn should_assign_free_voices() {
+        let mut voices = VoiceAllocator::<2>::new(StealPolicy::Oldest);
+
+        assert_eq!(
+            voices.note_on(0.into(), 60.into(), 100.into()),
+            on(0, 60, 100, None)
+        );
+        assert_eq!(
+            voices.note_on(0.into(), 64.into(), 100.into()),
+            on(1, 64, 100, None)
+        );
+        assert_eq!(
+            voices.note_off(0.into(), 60.into()),
+            Some(VoiceEvent::Off {
+                voice: 0,
+                channel: 0.into(),
+                note: 60.into()
+            })
+        );
+        assert_eq!(
+            voices.note_on(0.into(), 67.into(), 100.into()),
+            on(0, 67, 100, None)
+        );
+    }
+
+    #[test]
+    fn should_steal_oldest_voice() {
+        let mut voices = VoiceAllocator::<2>::new(StealPolicy::Oldest);
+
+        voices.note_on(0.into(), 60.into(), 100.into());
+        voices.note_on(0.into(), 64.into(), 100.into());
+        assert_eq!(
+            voices.note_on(0.into(), 67.into(), 100.into()),
+            on(0, 67, 100, Some(60))
+        );
+    }
+
+    #[test]
+    fn should_steal_quietest_voice() {
+        let mut voices = VoiceAllocator::<2>::new(StealPolicy::Quietest);
+
+        voices.note_on(0.into(), 60.into(), 100.into());
+        voices.note_on(0.into(), 64.into(), 20.into());
+        assert_eq!(
+            voices.note_on(0.into(), 67.into(), 100.into()),
+            on(1, 67, 100, Some(64))
+        );
+    }
+
+    #[test]
+    fn should_reuse_voice_for_same_note() {
+        let mut voices = VoiceAllocator::<4>::new(StealPolicy::SameNote);
+
+        voices.note_on(0.into(), 60.into(), 100.into());
+        voices.note_on(0.into(), 64.into(), 100.into());
+        assert_eq!(
+            voices.note_on(0.into(), 60.into(), 90.into()),
+            on(0, 60, 90, Some(60))
+        );
+        assert_eq!(voices.active(), 2);
+    }
+}
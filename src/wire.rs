@@ -0,0 +1,170 @@
+//! Fixed layout binary encoding of events for links between cores or controllers
+//!
+//! Events are encoded with their timestamp and cable number so they can be passed through a
+//! shared memory queue between cores or over an spi link between a user interface controller and
+//! a dsp. Every event starts with an eight byte header:
+//!
+//! | bytes | contents                                                             |
+//! |-------|----------------------------------------------------------------------|
+//! | 0..4  | timestamp, little endian                                             |
+//! | 4     | cable number                                                         |
+//! | 5     | status byte, `0xF0` for a system exclusive message, `0xF7` for a chunk |
+//! | 6..8  | the data bytes, or the little endian length of the bytes that follow |
+//!
+//! System exclusive messages and chunks are followed by their bytes, so decoding borrows them
+//! from the encoded buffer.
+
+use crate::error::Error;
+use crate::packed::PackedMidiEvent;
+use crate::parser::MidiEvent;
+use crate::sysex::{SYSEX_END, SYSEX_START};
+use core::convert::TryFrom;
+
+/// An event with its timestamp and the cable number of the port it was received on or is sent to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WireEvent<'a> {
+    pub timestamp: u32,
+    pub cable: u8,
+    pub event: MidiEvent<'a>,
+}
+
+impl<'a> WireEvent<'a> {
+    /// The length of the header every event starts with
+    pub const HEADER_LEN: usize = 8;
+
+    pub fn new(timestamp: u32, cable: u8, event: MidiEvent<'a>) -> Self {
+        WireEvent {
+            timestamp,
+            cable,
+            event,
+        }
+    }
+
+    /// The number of bytes of the encoded event
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        Self::HEADER_LEN + self.payload().map_or(0, |(_, bytes)| bytes.len())
+    }
+
+    /// Encode the event into a buffer, returns the number of bytes written
+    pub fn encode(&self, buffer: &mut [u8]) -> Result<usize, Error> {
+        let (status, data, payload) = match (self.event, self.payload()) {
+            (_, Some((status, bytes))) => {
+                let len = u16::try_from(bytes.len()).map_err(|_| Error::InvalidValue)?;
+                (status, len.to_le_bytes(), bytes)
+            }
+            (MidiEvent::Message(message), None) => {
+                let [status, first, second] = PackedMidiEvent::from(message).to_bytes();
+                (status, [first, second], &[][..])
+            }
+            _ => return Err(Error::InvalidValue),
+        };
+
+        let len = self.len();
+        let target = buffer.get_mut(..len).ok_or(Error::BufferTooSmall)?;
+        let (header, target) = target.split_at_mut(Self::HEADER_LEN);
+        let timestamp = self.timestamp.to_le_bytes();
+        header.copy_from_slice(&[
+            timestamp[0],
+            timestamp[1],
+            timestamp[2],
+            timestamp[3],
+            self.cable,
+            status,
+            data[0],
+            data[1],
+        ]);
+        target.copy_from_slice(payload);
+
+        Ok(len)
+    }
+
+    /// Decode an event from the start of a buffer, returns the event and the number of bytes
+    /// it took
+    pub fn decode(buffer: &'a [u8]) -> Result<(Self, usize), Error> {
+        let header = buffer
+            .get(..Self::HEADER_LEN)
+            .ok_or(Error::BufferTooSmall)?;
+        let (timestamp, rest) = header.split_at(4);
+        let (cable, status, first, second) = match *rest {
+            [cable, status, first, second] => (cable, status, first, second),
+            _ => return Err(Error::BufferTooSmall),
+        };
+        let timestamp = timestamp
+            .iter()
+            .rev()
+            .fold(0, |value, byte| value << 8 | *byte as u32);
+
+        let payload = || {
+            let end = Self::HEADER_LEN + u16::from_le_bytes([first, second]) as usize;
+            buffer
+                .get(Self::HEADER_LEN..end)
+                .map(|bytes| (bytes, end))
+                .ok_or(Error::BufferTooSmall)
+        };
+
+        let (event, len) = match status {
+            SYSEX_START => {
+                let (bytes, len) = payload()?;
+                (MidiEvent::SysEx(bytes), len)
+            }
+            SYSEX_END => {
+                let (bytes, len) = payload()?;
+                (MidiEvent::SysExChunk(bytes), len)
+            }
+            #[cfg(feature = "unknown-messages")]
+            0xF4 | 0xF5 | 0xF9 | 0xFD => {
+                let (data, len) = payload()?;
+                (MidiEvent::Unknown { status, data }, len)
+            }
+            _ => {
+                let message = PackedMidiEvent::from_bytes(status, first, second).message()?;
+                (MidiEvent::Message(message), Self::HEADER_LEN)
+            }
+        };
+
+        Ok((WireEvent::new(timestamp, cable, event), len))
+    }
+
+    /// The status byte and bytes following the header of events with a variable length
+    fn payload(&self) -> Option<(u8, &'a [u8])> {
+        match self.event {
+            MidiEvent::Message(_) => None,
+            MidiEvent::SysEx(bytes) => Some((SYSEX_START, bytes)),
+            MidiEvent::SysExChunk(bytes) => Some((SYSEX_END, bytes)),
+            #[cfg(feature = "unknown-messages")]
+            MidiEvent::Unknown { status, data } => Some((status, data)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::midi_types::{Channel, MidiMessage, Note, Value7};
+
+    #[test]
+    fn should_encode_messages_with_timestamp_and_cable() {
+        let event = WireEvent::new(
+            0x12345678,
+            3,
+            MidiEvent::Message(MidiMessage::NoteOn(Channel::C2, Note::C3, Value7::new(100))),
+        );
+
+        let mut buffer = [0; 8];
+        assert_eq!(event.encode(&mut buffer), Ok(8));
+        assert_eq!(buffer, [0x78, 0x56, 0x34, 0x12, 3, 0x91, 60, 100]);
+        assert_eq!(WireEvent::decode(&buffer), Ok((event, 8)));
+    }
+
+    #[test]
+    fn should_encode_system_exclusive_messages() {
+        let event = WireEvent::new(7, 0, MidiEvent::SysEx(&[0xF0, 0x7E, 0x01, 0xF7]));
+
+        let mut buffer = [0; 16];
+        assert_eq!(event.encode(&mut buffer), Ok(12));
+        assert_eq!(WireEvent::decode(&buffer), Ok((event, 12)));
+        assert_eq!(event.encode(&mut buffer[..11]), Err(Error::BufferTooSmall));
+        assert_eq!(WireEvent::decode(&buffer[..10]), Err(Error::BufferTooSmall));
+    }
+}